@@ -0,0 +1,154 @@
+//! Gas benchmarks of the packet hot path, run with `cargo test --features bench`.
+//!
+//! cw-multi-test doesn't meter gas, so the contract runs against a storage
+//! charging the KV store costs of the SDK. Storage dominates the cost of a
+//! packet, so regressions in the number or the size of the entries touched
+//! show up here. Wasm execution isn't metered.
+#![cfg(test)]
+use std::cell::Cell;
+use std::marker::PhantomData;
+
+use cosmwasm_std::testing::{mock_env, mock_info, MockApi, MockQuerier, MockStorage};
+use cosmwasm_std::{Addr, Env, Order, OwnedDeps, Record, Storage};
+
+use crate::contract::{execute, instantiate};
+use crate::msg::{InstantiateMsg, PathMsg, QuotaMsg};
+use crate::state::tests::RESET_TIME_DAILY;
+use crate::test_msg_send;
+
+// The default KVGasConfig of the SDK
+const READ_COST_FLAT: u64 = 1000;
+const READ_COST_PER_BYTE: u64 = 3;
+const WRITE_COST_FLAT: u64 = 2000;
+const WRITE_COST_PER_BYTE: u64 = 30;
+const DELETE_COST: u64 = 1000;
+const ITER_NEXT_COST_FLAT: u64 = 30;
+
+const BRIDGE_CONTRACT: &str = "BRIDGE_CONTRACT";
+
+// A MockStorage adding up the gas the SDK would charge for each access
+#[derive(Default)]
+struct MeteredStorage {
+    storage: MockStorage,
+    gas: Cell<u64>,
+}
+
+impl MeteredStorage {
+    fn charge(&self, gas: u64) {
+        self.gas.set(self.gas.get() + gas);
+    }
+}
+
+impl Storage for MeteredStorage {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        let value = self.storage.get(key);
+        let len = key.len() + value.as_ref().map_or(0, Vec::len);
+        self.charge(READ_COST_FLAT + READ_COST_PER_BYTE * len as u64);
+        value
+    }
+
+    fn range<'a>(
+        &'a self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = Record> + 'a> {
+        Box::new(
+            self.storage
+                .range(start, end, order)
+                .inspect(|(key, value)| {
+                    let len = key.len() + value.len();
+                    self.charge(ITER_NEXT_COST_FLAT + READ_COST_PER_BYTE * len as u64);
+                }),
+        )
+    }
+
+    fn set(&mut self, key: &[u8], value: &[u8]) {
+        self.charge(WRITE_COST_FLAT + WRITE_COST_PER_BYTE * (key.len() + value.len()) as u64);
+        self.storage.set(key, value);
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        self.charge(DELETE_COST);
+        self.storage.remove(key);
+    }
+}
+
+type MeteredDeps = OwnedDeps<MeteredStorage, MockApi, MockQuerier>;
+
+// A contract with a single path limited by `quotas` daily quotas
+fn setup(quotas: usize) -> MeteredDeps {
+    let mut deps = OwnedDeps {
+        storage: MeteredStorage::default(),
+        api: MockApi::default(),
+        querier: MockQuerier::default(),
+        custom_query_type: PhantomData,
+    };
+    let quotas = (0..quotas)
+        .map(|i| {
+            let duration = RESET_TIME_DAILY * (i as u64 + 1);
+            QuotaMsg::new(
+                &format!("quota_{i}"),
+                duration,
+                10_u32.into(),
+                10_u32.into(),
+            )
+        })
+        .collect();
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas,
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+    deps
+}
+
+// The gas charged for sending a packet on the path
+fn send_gas(deps: &mut MeteredDeps, env: Env) -> u64 {
+    let msg = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 1_u32.into()
+    );
+    let before = deps.storage.gas.get();
+    execute(deps.as_mut(), env, mock_info(BRIDGE_CONTRACT, &[]), msg).unwrap();
+    deps.storage.gas.get() - before
+}
+
+// The gas of the first packet of a period, which rolls the flows over, and of
+// a second packet in the same period
+fn cold_and_warm_gas(quotas: usize) -> (u64, u64) {
+    let mut deps = setup(quotas);
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(RESET_TIME_DAILY * 1000);
+    let cold = send_gas(&mut deps, env.clone());
+    let warm = send_gas(&mut deps, env);
+    (cold, warm)
+}
+
+#[test] // Fails when sending a packet costs more gas than the recorded thresholds
+fn packet_gas() {
+    // (quotas, cold threshold, warm threshold), about 15% above the measured gas
+    let thresholds = [
+        (1, 76_000, 66_000),
+        (3, 120_000, 98_000),
+        (10, 275_000, 210_000),
+    ];
+    for (quotas, max_cold, max_warm) in thresholds {
+        let (cold, warm) = cold_and_warm_gas(quotas);
+        assert!(
+            cold <= max_cold,
+            "{quotas} quotas: cold {cold} > {max_cold}"
+        );
+        assert!(
+            warm <= max_warm,
+            "{quotas} quotas: warm {warm} > {max_warm}"
+        );
+    }
+}
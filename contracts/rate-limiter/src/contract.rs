@@ -1,29 +1,51 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
-use cosmwasm_std::{Binary, Deps, DepsMut, Env, MessageInfo, Response, StdResult};
+use cosmwasm_std::{
+    to_json_binary, Binary, Deps, DepsMut, Env, MessageInfo, Reply, Response, StdResult,
+};
 use cw2::set_contract_version;
 
 use crate::error::ContractError;
-use crate::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg};
-use crate::state::FlowType;
+use crate::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg, SudoMsg};
+use crate::packet::Packet;
+use crate::state::{
+    continue_migration, load_config, load_key_spec, load_tenant_config, record_idempotency_key,
+    Config, FlowType, MigrationProgress, Path, PauseScope, CONFIG, KEY_SPEC, MAINTENANCE,
+    MIGRATION_BATCH, MIGRATION_PROGRESS,
+};
+use crate::time::{config_clock, contract_clock};
 use crate::{execute, query};
 
+// The last attribute of every execute and sudo response, naming the layout the
+// attributes before it follow
+const ATTRIBUTE_SCHEMA_KEY: &str = "attribute_schema_version";
+
 // version info for migration info
 const CONTRACT_NAME: &str = "crates.io:rate-limiter";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
-    deps: DepsMut,
+    mut deps: DepsMut,
     env: Env,
-    _info: MessageInfo,
+    info: MessageInfo,
     msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    cw_ownable::initialize_owner(deps.storage, deps.api, Some(info.sender.as_str()))?;
+    CONFIG.save(deps.storage, &Config::default())?;
+    let key_spec = msg.key_spec.unwrap_or_default();
+    key_spec.validate()?;
+    KEY_SPEC.save(deps.storage, &key_spec)?;
 
-    execute::add_new_paths(deps, msg.paths, env.block.time)?;
+    let clock = contract_clock(deps.storage, &env)?;
+    let seeded = msg.seed_flows.len();
+    execute::add_new_paths(deps.branch(), msg.paths, &info.sender, &clock)?;
+    execute::seed_flows(deps, msg.seed_flows, &clock)?;
 
-    Ok(Response::new().add_attribute("method", "instantiate"))
+    Ok(Response::new()
+        .add_attribute("method", "instantiate")
+        .add_attribute("seeded_flows", seeded.to_string()))
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
@@ -33,49 +55,639 @@ pub fn execute(
     info: MessageInfo,
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
-    match msg {
+    // The config as it applies to the sender, which is the tenant of the packets
+    // it sends
+    let config = load_tenant_config(deps.storage, &info.sender)?;
+    let clock = config_clock(deps.storage, &env, &config)?;
+    let schema_version = config.attribute_schema_version();
+    // Migrations left in maintenance are still continued by anyone
+    if !matches!(msg, ExecuteMsg::ContinueMigration { .. }) {
+        execute::check_maintenance(deps.storage, &info.sender)?;
+    }
+    if let Some(key) = msg.idempotency_key() {
+        if !record_idempotency_key(deps.storage, &info.sender, key)? {
+            return Ok(Response::new()
+                .add_attribute("method", "execute")
+                .add_attribute("idempotent_replay", key)
+                .add_attribute(ATTRIBUTE_SCHEMA_KEY, schema_version.to_string()));
+        }
+    }
+    let response = match msg {
         ExecuteMsg::AddPath {
             channel_id,
             denom,
             quotas,
-        } => execute::try_add_path(deps, info.sender, channel_id, denom, quotas, env.block.time),
-        ExecuteMsg::RemovePath { channel_id, denom } => {
-            execute::try_remove_path(deps, info.sender, channel_id, denom)
-        }
+            ..
+        } => execute::try_add_path(
+            deps,
+            &info.sender,
+            info.sender.clone(),
+            channel_id,
+            denom,
+            quotas,
+            &clock,
+        ),
+        ExecuteMsg::RemovePath {
+            channel_id, denom, ..
+        } => execute::try_remove_path(
+            deps,
+            &info.sender,
+            info.sender.clone(),
+            channel_id,
+            denom,
+            &clock,
+        ),
+        ExecuteMsg::RenameQuota {
+            channel_id,
+            denom,
+            from,
+            to,
+            ..
+        } => execute::try_rename_quota(deps, info.sender, channel_id, denom, from, to, &clock),
         ExecuteMsg::ResetPathQuota {
             channel_id,
             denom,
             quota_id,
+            memo,
+            mode,
+            ..
         } => execute::try_reset_path_quota(
             deps,
-            info.sender,
+            &info.sender,
+            info.sender.clone(),
             channel_id,
             denom,
             quota_id,
-            env.block.time,
+            memo,
+            mode,
+            &clock,
         ),
+        ExecuteMsg::ResetAllQuotas { scope, memo, .. } => {
+            execute::try_reset_all_quotas(deps, info.sender, scope, memo, &clock)
+        }
         ExecuteMsg::SendPacket { packet } => {
-            execute::process_packet(deps, info.sender, packet, FlowType::Out, env.block.time)
+            execute::process_packet(deps, info, &config, packet, FlowType::Out, &clock)
+        }
+        ExecuteMsg::ProcessPacket { packet } => {
+            let direction = execute::infer_direction(deps.as_ref(), &info.sender, &packet)?;
+            execute::process_packet(deps, info, &config, packet, direction, &clock)
+        }
+        ExecuteMsg::SendIcaPacket { packet } => {
+            execute::process_ica_packet(deps, info, &config, packet, &clock)
+        }
+        ExecuteMsg::Ics20TransferHook(transfer) => {
+            execute::process_ics20_transfer(deps, info, &config, transfer, &clock)
         }
         ExecuteMsg::RecvPacket { packet } => {
-            execute::process_packet(deps, info.sender, packet, FlowType::In, env.block.time)
+            execute::process_packet(deps, info, &config, packet, FlowType::In, &clock)
         }
-        ExecuteMsg::UndoSend { packet } => execute::undo_send(deps, info.sender, packet),
-    }
+        ExecuteMsg::SendIcs20Packet {
+            channel_id,
+            sequence,
+            data,
+        } => {
+            let packet = Packet::from_ics20(channel_id, sequence, data)?;
+            execute::process_packet(deps, info, &config, packet, FlowType::Out, &clock)
+        }
+        ExecuteMsg::RecvIcs20Packet {
+            channel_id,
+            sequence,
+            data,
+        } => {
+            let packet = Packet::from_ics20(channel_id, sequence, data)?;
+            execute::process_packet(deps, info, &config, packet, FlowType::In, &clock)
+        }
+        ExecuteMsg::UndoSend { packet } => execute::undo_send(deps, info.sender, packet, &clock),
+        ExecuteMsg::AckPacket { packet, success } => {
+            execute::ack_packet(deps, info.sender, packet, success, &clock)
+        }
+        ExecuteMsg::SetPathConfig {
+            channel_id,
+            denom,
+            config,
+            ..
+        } => execute::try_set_path_config(deps, info.sender, channel_id, denom, config),
+        ExecuteMsg::MigratePath { from, to, .. } => {
+            execute::try_migrate_path(deps, info.sender, from, to, &clock)
+        }
+        ExecuteMsg::SetClassQuotas {
+            channel_id,
+            denom,
+            class,
+            quotas,
+            ..
+        } => execute::try_set_class_quotas(
+            deps,
+            info.sender,
+            channel_id,
+            denom,
+            class.as_str(),
+            quotas,
+            &clock,
+        ),
+        ExecuteMsg::SetReceiverClassQuotas {
+            channel_id,
+            denom,
+            class,
+            quotas,
+            ..
+        } => execute::try_set_class_quotas(
+            deps,
+            info.sender,
+            channel_id,
+            denom,
+            class.as_str(),
+            quotas,
+            &clock,
+        ),
+        ExecuteMsg::SetTagQuotas {
+            channel_id,
+            denom,
+            tag,
+            quotas,
+            ..
+        } => execute::try_set_tag_quotas(deps, info.sender, channel_id, denom, tag, quotas, &clock),
+        ExecuteMsg::SetChainQuotas {
+            chain_id,
+            denom,
+            quotas,
+            ..
+        } => execute::try_set_chain_quotas(deps, info.sender, chain_id, denom, quotas, &clock),
+        ExecuteMsg::SetReceiverClass { address, class } => {
+            execute::try_set_receiver_class(deps, info.sender, address, class)
+        }
+        ExecuteMsg::SetChannelDefaultQuotas {
+            channel_id, quotas, ..
+        } => execute::try_set_channel_default_quotas(deps, info.sender, channel_id, quotas, &clock),
+        ExecuteMsg::SetChannelAssets { channel_id, denoms } => {
+            execute::try_set_channel_assets(deps, info.sender, channel_id, denoms)
+        }
+        ExecuteMsg::Tick { paths, limit } => execute::try_tick(deps, paths, limit, &clock),
+        ExecuteMsg::ContinueMigration { limit } => execute::try_continue_migration(deps, limit),
+        ExecuteMsg::UpdateConfig { config, .. } => {
+            execute::try_update_config(deps, info.sender, config, &clock)
+        }
+        ExecuteMsg::RefreshChannelValue {
+            contract,
+            channel_id,
+            denom,
+        } => {
+            let path = load_key_spec(deps.storage)?.path(&contract, &channel_id, &denom);
+            execute::try_refresh_channel_value(deps, path)
+        }
+        ExecuteMsg::Heartbeat {} => execute::try_heartbeat(deps, info.sender, &clock),
+        ExecuteMsg::SetTenantConfig { contract, config } => {
+            execute::try_set_tenant_config(deps, info.sender, contract, config)
+        }
+        ExecuteMsg::PurgeQuarantined {
+            contract,
+            channel_id,
+            denom,
+        } => execute::try_purge_quarantined(deps, info.sender, contract, channel_id, denom),
+        ExecuteMsg::PruneChannelDenoms {
+            contract,
+            channel_id,
+            limit,
+        } => execute::try_prune_channel_denoms(deps, info.sender, contract, channel_id, limit),
+        ExecuteMsg::EscrowBalanceCallback {
+            contract,
+            channel_id,
+            denom,
+            balance,
+        } => {
+            let path = load_key_spec(deps.storage)?.path(&contract, &channel_id, &denom);
+            execute::try_escrow_balance_callback(deps, info.sender, path, balance, &clock)
+        }
+        ExecuteMsg::RegisterBridge { bridge, registered } => {
+            execute::try_register_bridge(deps, info.sender, bridge, registered)
+        }
+        ExecuteMsg::SetPaused {
+            paused,
+            auto_unpause_at,
+        } => {
+            cw_ownable::assert_owner(deps.storage, &info.sender)?;
+            execute::try_set_paused(deps, paused, auto_unpause_at, &clock)
+        }
+        ExecuteMsg::SetDirectionPaused { direction, paused } => {
+            execute::try_set_direction_paused(deps, info.sender, direction, paused)
+        }
+        ExecuteMsg::SetContractPaused { contract, paused } => {
+            let scope = PauseScope::Contract { contract };
+            execute::try_set_scope_paused(deps, info.sender, scope, paused)
+        }
+        ExecuteMsg::SetChannelPaused {
+            contract,
+            channel_id,
+            paused,
+        } => {
+            let scope = PauseScope::Channel {
+                contract,
+                channel_id,
+            };
+            execute::try_set_scope_paused(deps, info.sender, scope, paused)
+        }
+        ExecuteMsg::SetPathPaused { path, paused } => {
+            let path = Path::new(&path.contract, path.channel_id, path.denom);
+            execute::try_set_scope_paused(deps, info.sender, PauseScope::Path(path), paused)
+        }
+        ExecuteMsg::SetPathFee { path, fee_bps } => {
+            execute::try_set_path_fee(deps, info.sender, path, fee_bps)
+        }
+        ExecuteMsg::PayFees {} => execute::try_pay_fees(deps, info),
+        ExecuteMsg::WithdrawFees {
+            contract,
+            denom,
+            recipient,
+        } => execute::try_withdraw_fees(deps, info.sender, contract, denom, recipient),
+        ExecuteMsg::Sweep { denom, to } => {
+            execute::try_sweep(deps, &env.contract.address, info.sender, denom, to)
+        }
+        ExecuteMsg::RepairInvariants { start_after, limit } => {
+            execute::try_repair_invariants(deps, info.sender, start_after, limit)
+        }
+        ExecuteMsg::PurgeSenderData {
+            address,
+            start_after,
+            limit,
+        } => execute::try_purge_sender_data(deps, info.sender, address, start_after, limit),
+        ExecuteMsg::SaveFilter { filter_id, filter } => {
+            execute::try_save_filter(deps, info.sender, filter_id, filter)
+        }
+        ExecuteMsg::RemoveFilter { filter_id } => {
+            execute::try_remove_filter(deps, info.sender, filter_id)
+        }
+        ExecuteMsg::SetMaintenance { enabled } => {
+            execute::try_set_maintenance(deps, info.sender, enabled)
+        }
+        ExecuteMsg::SetMultisig { owners, threshold } => {
+            execute::try_set_multisig(deps, info.sender, &env.contract.address, owners, threshold)
+        }
+        ExecuteMsg::Propose { msg, expires_in } => {
+            execute::try_propose(deps, info.sender, *msg, expires_in, &clock)
+        }
+        ExecuteMsg::Confirm { id } => execute::try_confirm(deps, info.sender, id, &clock),
+        ExecuteMsg::ExecuteProposal { id } => {
+            execute::try_execute_proposal(deps, &env.contract.address, id, &clock)
+        }
+        ExecuteMsg::ReserveCapacity {
+            path,
+            direction,
+            amount,
+            window,
+            beneficiary,
+            role,
+        } => execute::try_reserve_capacity(
+            deps,
+            info.sender,
+            &env.contract.address,
+            path,
+            direction,
+            amount,
+            window,
+            beneficiary,
+            role,
+            &clock,
+        ),
+        ExecuteMsg::SyncCounterpartyState {
+            path,
+            usage,
+            reported_at,
+        } => execute::try_sync_counterparty_state(
+            deps,
+            info.sender,
+            path,
+            usage,
+            reported_at,
+            &clock,
+        ),
+        ExecuteMsg::PreApproveTransfer {
+            path,
+            amount,
+            window,
+            approver_role,
+        } => execute::try_pre_approve_transfer(
+            deps,
+            info.sender,
+            &env.contract.address,
+            path,
+            amount,
+            window,
+            approver_role,
+            &clock,
+        ),
+        #[cfg(feature = "mock-time")]
+        ExecuteMsg::SetMockTime { time } => execute::try_set_mock_time(deps, info.sender, time),
+        ExecuteMsg::UpdateOwnership(action) => {
+            let ownership = cw_ownable::update_ownership(deps, &env.block, &info.sender, action)?;
+            Ok(Response::new().add_attributes(ownership.into_attributes()))
+        }
+    }?;
+    Ok(response.add_attribute(ATTRIBUTE_SCHEMA_KEY, schema_version.to_string()))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn sudo(deps: DepsMut, env: Env, msg: SudoMsg) -> Result<Response, ContractError> {
+    let clock = contract_clock(deps.storage, &env)?;
+    let schema_version = load_config(deps.storage)?.attribute_schema_version();
+    let response = match msg {
+        SudoMsg::AddPath {
+            contract,
+            channel_id,
+            denom,
+            quotas,
+        } => execute::try_add_path(
+            deps,
+            &env.contract.address,
+            contract,
+            channel_id,
+            denom,
+            quotas,
+            &clock,
+        ),
+        SudoMsg::RemovePath {
+            contract,
+            channel_id,
+            denom,
+        } => execute::try_remove_path(
+            deps,
+            &env.contract.address,
+            contract,
+            channel_id,
+            denom,
+            &clock,
+        ),
+        SudoMsg::ResetPathQuota {
+            contract,
+            channel_id,
+            denom,
+            quota_id,
+            memo,
+            mode,
+        } => execute::try_reset_path_quota(
+            deps,
+            &env.contract.address,
+            contract,
+            channel_id,
+            denom,
+            quota_id,
+            memo,
+            mode,
+            &clock,
+        ),
+        SudoMsg::SetPaused {
+            paused,
+            auto_unpause_at,
+        } => execute::try_set_paused(deps, paused, auto_unpause_at, &clock),
+    }?;
+    Ok(response.add_attribute(ATTRIBUTE_SCHEMA_KEY, schema_version.to_string()))
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
         QueryMsg::GetQuotas {
             contract,
             channel_id,
             denom,
-        } => query::get_quotas(deps, contract, channel_id, denom),
+        } => {
+            let clock = contract_clock(deps.storage, &env)?;
+            query::get_quotas(deps, &clock, contract, channel_id, denom)
+        }
+        QueryMsg::GetQuotasBatch { paths } => {
+            let clock = contract_clock(deps.storage, &env)?;
+            query::get_quotas_batch(deps, &clock, paths)
+        }
+        QueryMsg::GetContractUsage {
+            contract,
+            start_after,
+            limit,
+        } => {
+            let clock = contract_clock(deps.storage, &env)?;
+            query::get_contract_usage(deps, &clock, contract, start_after, limit)
+        }
+        QueryMsg::GetConfig {} => query::get_config(deps),
+        QueryMsg::GetHeartbeat {} => {
+            let clock = contract_clock(deps.storage, &env)?;
+            query::get_heartbeat(deps, &clock)
+        }
+        QueryMsg::GetTenantConfig { contract } => query::get_tenant_config(deps, contract),
+        QueryMsg::GetKeySpec {} => query::get_key_spec(deps),
+        QueryMsg::GetClassQuotas {
+            contract,
+            channel_id,
+            denom,
+            class,
+        } => {
+            let clock = contract_clock(deps.storage, &env)?;
+            query::get_class_quotas(deps, &clock, contract, channel_id, denom, class.as_str())
+        }
+        QueryMsg::GetReceiverClassQuotas {
+            contract,
+            channel_id,
+            denom,
+            class,
+        } => {
+            let clock = contract_clock(deps.storage, &env)?;
+            query::get_class_quotas(deps, &clock, contract, channel_id, denom, class.as_str())
+        }
+        QueryMsg::GetTagQuotas {
+            contract,
+            channel_id,
+            denom,
+            tag,
+        } => {
+            let clock = contract_clock(deps.storage, &env)?;
+            query::get_tag_quotas(deps, &clock, contract, channel_id, denom, tag)
+        }
+        QueryMsg::GetChainQuotas {
+            contract,
+            chain_id,
+            denom,
+        } => {
+            let clock = contract_clock(deps.storage, &env)?;
+            query::get_chain_quotas(deps, &clock, contract, chain_id, denom)
+        }
+        QueryMsg::GetReceiverClass { address } => query::get_receiver_class(deps, address),
+        QueryMsg::GetAuditLog {
+            contract,
+            channel_id,
+            denom,
+            start_after,
+            limit,
+        } => query::get_audit_log(deps, contract, channel_id, denom, start_after, limit),
+        QueryMsg::GetHistory {
+            contract,
+            channel_id,
+            denom,
+        } => query::get_history(deps, contract, channel_id, denom),
+        QueryMsg::GetPeriodReport {
+            contract,
+            channel_id,
+            denom,
+            period_index,
+        } => query::get_period_report(deps, contract, channel_id, denom, period_index),
+        QueryMsg::GetQuotasRaw { key_base64 } => query::get_quotas_raw(deps, key_base64),
+        QueryMsg::GetRemainingCapacity {
+            contract,
+            channel_id,
+            denom,
+        } => {
+            let clock = contract_clock(deps.storage, &env)?;
+            let path = load_key_spec(deps.storage)?.path(&contract, &channel_id, &denom);
+            query::get_remaining_capacity(deps, &clock, path)
+        }
+        QueryMsg::GetAverageFlow {
+            path,
+            lookback_periods,
+        } => query::get_average_flow(deps, path, lookback_periods),
+        QueryMsg::GetInFlight {
+            contract,
+            channel_id,
+            denom,
+        } => query::get_in_flight(deps, contract, channel_id, denom),
+        QueryMsg::GetChannelDefaultQuotas {
+            contract,
+            channel_id,
+        } => query::get_channel_default_quotas(deps, contract, channel_id),
+        QueryMsg::GetChannelAssets {
+            contract,
+            channel_id,
+        } => query::get_channel_assets(deps, contract, channel_id),
+        QueryMsg::GetQuarantined { start_after, limit } => {
+            query::get_quarantined(deps, start_after, limit)
+        }
+        QueryMsg::CanSend {
+            contract,
+            channel_id,
+            denom,
+            amount,
+        } => {
+            let clock = contract_clock(deps.storage, &env)?;
+            query::can_send(deps, &clock, contract, channel_id, denom, amount)
+        }
+        QueryMsg::ChangesSince { seq, limit } => {
+            let clock = contract_clock(deps.storage, &env)?;
+            query::changes_since(deps, &clock, seq, limit)
+        }
+        QueryMsg::GetMultisig {} => query::get_multisig(deps),
+        QueryMsg::GetProposal { id } => query::get_proposal(deps, id),
+        QueryMsg::GetStreak { path } => query::get_streak(deps, path),
+        QueryMsg::GetRecentRejections { path, limit } => {
+            query::get_recent_rejections(deps, path, limit)
+        }
+        QueryMsg::GetPathOverview {
+            contract,
+            channel_id,
+            denom,
+        } => {
+            let clock = contract_clock(deps.storage, &env)?;
+            query::get_path_overview(deps, &clock, contract, channel_id, denom)
+        }
+        QueryMsg::GetChannelPair {
+            contract,
+            channel_id,
+            denom,
+        } => {
+            let clock = contract_clock(deps.storage, &env)?;
+            query::get_channel_pair(deps, &clock, contract, channel_id, denom)
+        }
+        QueryMsg::GetQuotaAcrossPaths {
+            quota_name,
+            start_after,
+            limit,
+        } => {
+            let clock = contract_clock(deps.storage, &env)?;
+            query::get_quota_across_paths(deps, &clock, quota_name, start_after, limit)
+        }
+        QueryMsg::PreviewPathChange { path_msg } => {
+            let clock = contract_clock(deps.storage, &env)?;
+            query::preview_path_change(deps, &clock, path_msg)
+        }
+        QueryMsg::SimulateSequence {
+            path,
+            transfers,
+            proposed_quotas,
+        } => query::simulate_sequence(deps, path, transfers, proposed_quotas),
+        QueryMsg::GetFees { contract, denom } => query::get_fees(deps, contract, denom),
+        QueryMsg::CheckInvariants { start_after, limit } => {
+            let clock = contract_clock(deps.storage, &env)?;
+            query::check_invariants(deps, &clock, start_after, limit)
+        }
+        QueryMsg::GetFilter { filter_id } => query::get_filter(deps, filter_id),
+        QueryMsg::GetFilteredState {
+            filter_id,
+            start_after,
+            limit,
+        } => {
+            let clock = contract_clock(deps.storage, &env)?;
+            query::get_filtered_state(deps, &clock, filter_id, start_after, limit)
+        }
+        QueryMsg::ListProposals { start_after, limit } => {
+            query::list_proposals(deps, start_after, limit)
+        }
+        QueryMsg::ListPreApprovals {
+            path,
+            start_after,
+            limit,
+        } => query::list_pre_approvals(deps, path, start_after, limit),
+        QueryMsg::ListReservations {
+            path,
+            start_after,
+            limit,
+        } => query::list_reservations(deps, path, start_after, limit),
+        QueryMsg::Metrics {} => query::get_metrics(deps),
+        QueryMsg::StorageLayout {} => query::get_storage_layout(),
+        QueryMsg::Diagnose {
+            contract,
+            channel_id,
+            denom,
+        } => query::diagnose(deps, contract, channel_id, denom),
+        QueryMsg::Ownership {} => to_json_binary(&cw_ownable::get_ownership(deps.storage)?),
     }
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn migrate(_deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
-    unimplemented!()
+pub fn reply(_deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, ContractError> {
+    execute::notification_failed(msg)
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
+    // Flow and quota amounts went from Uint128 to Uint256. Both are stored as
+    // decimal strings, so trackers saved by older versions load unchanged
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    MAINTENANCE.save(deps.storage, &true)?;
+
+    // Large states don't fit in a single transaction. The first batch is moved
+    // here, ContinueMigration moves the rest
+    let progress = MigrationProgress {
+        namespace: 0,
+        moved: 0,
+    };
+    MIGRATION_PROGRESS.save(deps.storage, &progress)?;
+    let (moved, done) = continue_migration(deps.storage, MIGRATION_BATCH)?;
+    execute::index_open_pre_approvals(deps.storage)?;
+    let clock = contract_clock(deps.storage, &env)?;
+    execute::index_active_reservations(deps.storage, &clock)?;
+    execute::index_pending_in_flight(deps.storage)?;
+    // The denoms are normalized once all the trackers are in their namespace
+    let (normalized, conflicts) = match done {
+        true => execute::normalize_path_denoms(deps.storage)?,
+        false => (0, 0),
+    };
+    if done {
+        execute::seed_path_gauge(deps.storage)?;
+    }
+
+    if !msg.keep_maintenance {
+        MAINTENANCE.save(deps.storage, &false)?;
+    }
+
+    Ok(Response::new()
+        .add_attribute("method", "migrate")
+        .add_attribute("moved_entries", moved.to_string())
+        .add_attribute("done", done.to_string())
+        .add_attribute("normalized_paths", normalized.to_string())
+        .add_attribute("denom_conflicts", conflicts.to_string())
+        .add_attribute("maintenance", msg.keep_maintenance.to_string()))
 }
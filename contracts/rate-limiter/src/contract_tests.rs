@@ -1,23 +1,60 @@
 #![cfg(test)]
 
-use crate::packet::Packet;
-use crate::{contract::*, test_msg_recv, test_msg_send, ContractError};
-use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
-use cosmwasm_std::{from_json, Addr, Attribute, Uint128};
+use crate::execute::{ALERT_REPLY_ID, ESCROW_QUERY_REPLY_ID, REJECT_HOOK_REPLY_ID};
+use crate::packet::{
+    IcaPacket, Ics20PacketData, Ics20Transfer, Packet, PacketClass, PacketEndpoints, ReceiverClass,
+};
+use crate::{contract::*, test_msg_recv, test_msg_send, test_msg_undo, ContractError};
+use cosmwasm_std::testing::{
+    mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage, MOCK_CONTRACT_ADDR,
+};
+use cosmwasm_std::{
+    coin, coins, from_json, to_json_binary, Addr, Attribute, BankMsg, Binary, Coin, ContractResult,
+    CosmosMsg, Decimal, Deps, Empty, Env, Order, OwnedDeps, Record, Reply, ReplyOn, Response,
+    Storage, SubMsg, SubMsgResult, SystemResult, Timestamp, Uint128, Uint256, WasmMsg, WasmQuery,
+};
 
 use crate::helpers::tests::verify_query_response;
-use crate::msg::{ExecuteMsg, InstantiateMsg, PathMsg, QueryMsg, QuotaMsg};
-use crate::state::tests::RESET_TIME_WEEKLY;
-use crate::state::{RateLimit, RATE_LIMIT_TRACKERS};
+use crate::msg::{
+    AlertMsg, AverageFlow, CanSendResponse, ChangesResponse, ChannelPairResponse,
+    ContractUsageResponse, DiagnoseResponse, EscrowQuerierMsg, ExecuteMsg, FilteredStateResponse,
+    HeartbeatResponse, InstantiateMsg, InvariantViolation, InvariantsResponse, Metric, MetricKind,
+    MigrateMsg, PathChangePreview, PathId, PathMsg, PathOverviewResponse, PeriodReport,
+    PolicyQueryMsg, PolicyResponse, QuarantinedEntry, QueryMsg, QuotaAcrossPathsResponse, QuotaMsg,
+    QuotaResponse, QuotaUsage, QuotaViolation, RateLimitExceededData, RateLimitResponse,
+    RawQuotasResponse, RejectHookMsg, RemainingCapacity, ResetScope, SeedFlow, Severity,
+    SimulatedTransfer, SimulationResponse, StorageLayoutResponse, SudoMsg, TenantConfigResponse,
+    TransferData, Versioned, API_VERSION,
+};
+use crate::state::tests::{RESET_TIME_DAILY, RESET_TIME_WEEKLY};
+use crate::state::{
+    ApproverRole, AuditAction, AuditEntry, Config, CounterpartyPath, DayPartition,
+    EscalationPolicy, EvaluationMode, FeeAccount, FlowType, HeartbeatPolicy, HierarchyStrictness,
+    HistoryEntry, KeyDimension, KeySpec, LastPacket, LocalChannel, OnExceed, OnMissedHeartbeat,
+    OnStaleChannelValue, PathConfig, PathFilter, PathKey, PendingPeriod, PendingSend,
+    PercentageLimit, PreApproval, ProbationPolicy, Proposal, Quota, RateLimit, Rejection,
+    Reservation, ResetMode, Rounding, Streak, TenantConfig, UnconfiguredBehavior,
+    ACTIVE_RESERVATIONS, ATTRIBUTE_SCHEMA_VERSION, CHANNEL_TRACKERS, CONFIG, DAILY_STATS,
+    DEGRADED_TRACKERS, ESCROW_VALUES, IN_FLIGHT, MAX_ACTIVE_RESERVATIONS, MAX_PRE_APPROVAL_WINDOW,
+    MAX_PROPOSAL_EXPIRY, MAX_QUOTA_DURATION, MAX_RESERVATION_WINDOW, NANOS_PER_SECOND, PAUSED,
+    PAUSE_EXPIRY, PENDING_IN_FLIGHT, PENDING_SENDS, RATE_LIMIT_TRACKERS, REJECTIONS,
+    REJECTIONS_LENGTH, WILDCARD,
+};
+use cw_storage_plus::Map;
+use std::marker::PhantomData;
 
 const BRIDGE_CONTRACT: &str = "BRIDGE_CONTRACT";
-const OWNER: &str = "Owner";
+const OWNER: &str = "owner";
 
 #[test] // Tests we ccan instantiate the contract and that the owners are set correctly
 fn proper_instantiation() {
     let mut deps = mock_dependencies();
 
-    let msg = InstantiateMsg { paths: vec![] };
+    let msg = InstantiateMsg {
+        paths: vec![],
+        key_spec: None,
+        seed_flows: vec![],
+    };
     let info = mock_info(OWNER, &vec![]);
 
     // we can just call .unwrap() to assert this was a success
@@ -32,10 +69,12 @@ fn consume_allowance() {
     let quota = QuotaMsg::new(
         "weekly",
         RESET_TIME_WEEKLY,
-        Uint128::new(1000000),
-        Uint128::new(1000000),
+        Uint256::from(1000000_u128),
+        Uint256::from(1000000_u128),
     );
     let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
         paths: vec![PathMsg {
             contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
             channel_id: format!("channel"),
@@ -75,10 +114,12 @@ fn symetric_flows_dont_consume_allowance() {
     let quota = QuotaMsg::new(
         "weekly",
         RESET_TIME_WEEKLY,
-        Uint128::new(1000000),
-        Uint128::new(1000000),
+        Uint256::from(1000000_u128),
+        Uint256::from(1000000_u128),
     );
     let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
         paths: vec![PathMsg {
             contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
             channel_id: format!("channel"),
@@ -143,10 +184,12 @@ fn asymetric_quotas() {
     let quota = QuotaMsg::new(
         "weekly",
         RESET_TIME_WEEKLY,
-        Uint128::new(400000),
-        Uint128::new(100000),
+        Uint256::from(400000_u128),
+        Uint256::from(100000_u128),
     );
     let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
         paths: vec![PathMsg {
             contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
             channel_id: format!("channel"),
@@ -226,10 +269,12 @@ fn query_state() {
     let quota = QuotaMsg::new(
         "weekly",
         RESET_TIME_WEEKLY,
-        Uint128::new(1000000),
-        Uint128::new(1000000),
+        Uint256::from(1000000_u128),
+        Uint256::from(1000000_u128),
     );
     let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
         paths: vec![PathMsg {
             contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
             channel_id: format!("channel"),
@@ -248,13 +293,18 @@ fn query_state() {
     };
 
     let res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
-    let value: Vec<RateLimit> = from_json(&res).unwrap();
+    let response: Versioned<Vec<RateLimitResponse>> = from_json(&res).unwrap();
+    assert_eq!(response.api_version, API_VERSION);
+    let value = response.data;
     assert_eq!(value[0].quota.name, "weekly");
-    assert_eq!(value[0].quota.max_recv, Uint128::new(1000000));
-    assert_eq!(value[0].quota.max_send, Uint128::new(1000000));
-    assert_eq!(value[0].quota.duration, RESET_TIME_WEEKLY);
-    assert_eq!(value[0].flow.inflow, Uint128::from(0_u32));
-    assert_eq!(value[0].flow.outflow, Uint128::from(0_u32));
+    assert_eq!(value[0].quota.max_recv, Uint256::from(1000000_u128));
+    assert_eq!(value[0].quota.max_send, Uint256::from(1000000_u128));
+    assert_eq!(
+        value[0].quota.duration_nanos,
+        RESET_TIME_WEEKLY * NANOS_PER_SECOND
+    );
+    assert_eq!(value[0].flow.inflow, Uint256::from(0_u32));
+    assert_eq!(value[0].flow.outflow, Uint256::from(0_u32));
     assert_eq!(
         value[0].flow.period_end,
         env.block.time.plus_seconds(RESET_TIME_WEEKLY)
@@ -277,12 +327,12 @@ fn query_state() {
 
     // Query
     let res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
-    let value: Vec<RateLimit> = from_json(&res).unwrap();
+    let value: Vec<RateLimitResponse> = from_json::<Versioned<_>>(&res).unwrap().data;
     verify_query_response(
         &value[0],
         "weekly",
-        Uint128::new(1000000),
-        Uint128::new(1000000),
+        Uint256::from(1000000_u128),
+        Uint256::from(1000000_u128),
         RESET_TIME_WEEKLY,
         30_u32.into(),
         300_u32.into(),
@@ -297,10 +347,12 @@ fn undo_send() {
     let quota = QuotaMsg::new(
         "weekly",
         RESET_TIME_WEEKLY,
-        Uint128::new(1000000),
-        Uint128::new(1000000),
+        Uint256::from(1000000_u128),
+        Uint256::from(1000000_u128),
     );
     let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
         paths: vec![PathMsg {
             contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
             channel_id: format!("channel"),
@@ -314,11 +366,15 @@ fn undo_send() {
     let send_msg = test_msg_send!(
         channel_id: format!("channel"),
         denom: format!("denom"),
-        funds: 300_u32.into()
+        funds: 300_u32.into(),
+        sequence: 1
+    );
+    let undo_msg = test_msg_undo!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 300_u32.into(),
+        sequence: 1
     );
-    let undo_msg = ExecuteMsg::UndoSend {
-        packet: Packet::mock(format!("channel"), format!("denom"), 300_u32.into()),
-    };
     let info = mock_info(BRIDGE_CONTRACT, &[]);
 
     execute(deps.as_mut(), mock_env(), info.clone(), send_msg.clone()).unwrap();
@@ -335,7 +391,7 @@ fn undo_send() {
         .unwrap();
     assert_eq!(
         trackers.first().unwrap().flow.outflow,
-        Uint128::from(300_u32)
+        Uint256::from(300_u32)
     );
     let period_end = trackers.first().unwrap().flow.period_end;
 
@@ -351,6 +407,8324 @@ fn undo_send() {
             ),
         )
         .unwrap();
-    assert_eq!(trackers.first().unwrap().flow.outflow, Uint128::from(0_u32));
+    assert_eq!(trackers.first().unwrap().flow.outflow, Uint256::from(0_u32));
     assert_eq!(trackers.first().unwrap().flow.period_end, period_end);
+
+    // Sends are only undone once, and only when they were recorded
+    let err = execute(deps.as_mut(), mock_env(), info.clone(), undo_msg).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::PendingSendNotFound {
+            channel_id: format!("channel"),
+            sequence: Some(1),
+        }
+    );
+    let msg = ExecuteMsg::UndoSend {
+        packet: Packet::mock(format!("channel"), format!("denom"), 300_u32.into()),
+    };
+    let err = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::PendingSendNotFound { sequence: None, .. }
+    ));
+
+    // The denom has to be the one of the send
+    let send_msg = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 300_u32.into(),
+        sequence: 2
+    );
+    execute(deps.as_mut(), mock_env(), info.clone(), send_msg).unwrap();
+    let msg = test_msg_undo!(
+        channel_id: format!("channel"),
+        denom: format!("other"),
+        funds: 300_u32.into(),
+        sequence: 2
+    );
+    let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::PendingSendMismatch {
+            sequence: 2,
+            denom: format!("other"),
+            expected: format!("denom"),
+        }
+    );
+}
+
+#[test] // Tests that the usage of all the paths of a contract is aggregated and paginated
+fn contract_usage() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new(
+        "weekly",
+        RESET_TIME_WEEKLY,
+        Uint256::from(1000_u128),
+        Uint256::from(1000_u128),
+    );
+    let bridge = Addr::unchecked(BRIDGE_CONTRACT);
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![
+            PathMsg::new(&bridge, "channel", "denom", vec![quota.clone()]),
+            PathMsg::new(&bridge, "channel2", "denom", vec![quota.clone()]),
+            PathMsg::new(&Addr::unchecked("other"), "channel", "denom", vec![quota]),
+        ],
+    };
+    let info = mock_info(OWNER, &vec![]);
+    instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let info = mock_info(BRIDGE_CONTRACT, &[]);
+    let send_msg = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 300_u32.into()
+    );
+    execute(deps.as_mut(), mock_env(), info.clone(), send_msg).unwrap();
+    let recv_msg = test_msg_recv!(
+        channel_id: format!("channel2"),
+        denom: format!("denom"),
+        funds: 200_u32.into()
+    );
+    execute(deps.as_mut(), mock_env(), info.clone(), recv_msg).unwrap();
+
+    let query_msg = QueryMsg::GetContractUsage {
+        contract: bridge.clone(),
+        start_after: None,
+        limit: Some(1),
+    };
+    let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+    let usage: ContractUsageResponse = from_json::<Versioned<_>>(&res).unwrap().data;
+    assert_eq!(usage.totals.len(), 1);
+    assert_eq!(usage.totals[0].name, "weekly");
+    assert_eq!(usage.totals[0].inflow, Uint256::from(200_u128));
+    assert_eq!(usage.totals[0].outflow, Uint256::from(300_u128));
+    assert_eq!(usage.totals[0].max_out, Uint256::from(2000_u128));
+    assert!(usage.totals_complete);
+    assert_eq!(usage.paths.len(), 1);
+    assert_eq!(usage.paths[0].channel_id, "channel");
+
+    let query_msg = QueryMsg::GetContractUsage {
+        contract: bridge,
+        start_after: Some(("channel".to_string(), "denom".to_string())),
+        limit: None,
+    };
+    let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+    let usage: ContractUsageResponse = from_json::<Versioned<_>>(&res).unwrap().data;
+    assert_eq!(usage.paths.len(), 1);
+    assert_eq!(usage.paths[0].channel_id, "channel2");
+    assert_eq!(usage.paths[0].quotas[0].inflow, Uint256::from(200_u128));
+}
+
+#[test] // Tests that a manual reset records who did it, when and why
+fn reset_path_quota_records_reset() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new(
+        "weekly",
+        RESET_TIME_WEEKLY,
+        Uint256::from(1000_u128),
+        Uint256::from(1000_u128),
+    );
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg::new(
+            &Addr::unchecked(BRIDGE_CONTRACT),
+            "channel",
+            "denom",
+            vec![quota],
+        )],
+    };
+    let info = mock_info(OWNER, &[]);
+    instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let info = mock_info(BRIDGE_CONTRACT, &[]);
+    let send_msg = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 300_u32.into()
+    );
+    execute(deps.as_mut(), mock_env(), info.clone(), send_msg).unwrap();
+
+    let reset_msg = ExecuteMsg::ResetPathQuota {
+        channel_id: "channel".to_string(),
+        denom: "denom".to_string(),
+        quota_id: "weekly".to_string(),
+        memo: Some("incident 42".to_string()),
+        mode: ResetMode::Full,
+        idempotency_key: None,
+    };
+    let env = mock_env();
+    execute(deps.as_mut(), env.clone(), info, reset_msg).unwrap();
+
+    let query_msg = QueryMsg::GetQuotas {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+    };
+    let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+    let value: Vec<RateLimitResponse> = from_json::<Versioned<_>>(&res).unwrap().data;
+    assert_eq!(value[0].flow.outflow, Uint256::zero());
+    let last_reset = value[0].last_reset.clone().unwrap();
+    assert_eq!(last_reset.by, Addr::unchecked(BRIDGE_CONTRACT));
+    assert_eq!(last_reset.at, env.block.time);
+    assert_eq!(last_reset.memo, Some("incident 42".to_string()));
+}
+
+#[test] // Tests that bulk resets only affect the paths matching the scope
+fn reset_all_quotas() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new(
+        "weekly",
+        RESET_TIME_WEEKLY,
+        Uint256::from(1000_u128),
+        Uint256::from(1000_u128),
+    );
+    let bridge = Addr::unchecked(BRIDGE_CONTRACT);
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![
+            PathMsg::new(&bridge, "channel", "denom", vec![quota.clone()]),
+            PathMsg::new(&bridge, "channel", "denom2", vec![quota.clone()]),
+            PathMsg::new(&bridge, "channel2", "denom", vec![quota]),
+        ],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let info = mock_info(BRIDGE_CONTRACT, &[]);
+    for (channel, denom) in [
+        ("channel", "denom"),
+        ("channel", "denom2"),
+        ("channel2", "denom"),
+    ] {
+        let send_msg = test_msg_send!(
+            channel_id: channel.to_string(),
+            denom: denom.to_string(),
+            funds: 300_u32.into()
+        );
+        execute(deps.as_mut(), mock_env(), info.clone(), send_msg).unwrap();
+    }
+
+    let reset_msg = ExecuteMsg::ResetAllQuotas {
+        scope: ResetScope::Channel("channel".to_string()),
+        memo: None,
+        idempotency_key: None,
+    };
+    let res = execute(deps.as_mut(), mock_env(), info, reset_msg).unwrap();
+    assert_eq!(res.events.len(), 2);
+    assert!(res.events.iter().all(|e| e.ty == "reset_path"));
+
+    let outflow = |channel: &str, denom: &str| {
+        RATE_LIMIT_TRACKERS
+            .load(
+                &deps.storage,
+                (bridge.clone(), channel.to_string(), denom.to_string()),
+            )
+            .unwrap()[0]
+            .flow
+            .outflow
+    };
+    assert_eq!(outflow("channel", "denom"), Uint256::zero());
+    assert_eq!(outflow("channel", "denom2"), Uint256::zero());
+    assert_eq!(outflow("channel2", "denom"), Uint256::from(300_u128));
+
+    // The periods cut short are archived
+    let history = |channel: &str| {
+        let msg = QueryMsg::GetHistory {
+            contract: bridge.clone(),
+            channel_id: channel.to_string(),
+            denom: format!("denom"),
+        };
+        let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+        from_json::<Versioned<Vec<HistoryEntry>>>(&res)
+            .unwrap()
+            .data
+    };
+    let archived = history("channel");
+    assert_eq!(archived.len(), 1);
+    assert_eq!(archived[0].outflow, Uint256::from(300_u128));
+    assert_eq!(archived[0].period_end, mock_env().block.time);
+    assert!(history("channel2").is_empty());
+}
+
+#[test] // Tests that packets with an already processed sequence are rejected
+fn duplicate_packet_sequence() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new(
+        "weekly",
+        RESET_TIME_WEEKLY,
+        Uint256::from(1000_u128),
+        Uint256::from(1000_u128),
+    );
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg::new(
+            &Addr::unchecked(BRIDGE_CONTRACT),
+            "channel",
+            "denom",
+            vec![quota],
+        )],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let info = mock_info(BRIDGE_CONTRACT, &[]);
+    let packet = Packet::mock(format!("channel"), format!("denom"), 300_u32.into());
+    let send_msg = ExecuteMsg::SendPacket {
+        packet: packet.clone().with_sequence(5),
+    };
+    execute(deps.as_mut(), mock_env(), info.clone(), send_msg.clone()).unwrap();
+
+    let err = execute(deps.as_mut(), mock_env(), info.clone(), send_msg).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::DuplicatePacket {
+            channel: "channel".to_string(),
+            sequence: 5,
+            last_sequence: 5,
+        }
+    );
+
+    // Sequences are tracked per direction
+    let recv_msg = ExecuteMsg::RecvPacket {
+        packet: packet.clone().with_sequence(5),
+    };
+    execute(deps.as_mut(), mock_env(), info.clone(), recv_msg).unwrap();
+
+    let send_msg = ExecuteMsg::SendPacket {
+        packet: packet.with_sequence(6),
+    };
+    execute(deps.as_mut(), mock_env(), info, send_msg).unwrap();
+}
+
+#[test] // Tests that escrow paths hold the sent funds and refund them on failed sends
+fn escrow_mode() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new(
+        "weekly",
+        RESET_TIME_WEEKLY,
+        Uint256::from(1000_u128),
+        Uint256::from(1000_u128),
+    );
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg::new(
+            &Addr::unchecked(BRIDGE_CONTRACT),
+            "channel",
+            "orai",
+            vec![quota],
+        )],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let info = mock_info(BRIDGE_CONTRACT, &[]);
+    let config_msg = ExecuteMsg::SetPathConfig {
+        channel_id: "channel".to_string(),
+        denom: "orai".to_string(),
+        config: PathConfig {
+            escrow: true,
+            ..PathConfig::default()
+        },
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), info.clone(), config_msg).unwrap();
+
+    let send_msg = |sequence| {
+        test_msg_send!(
+            channel_id: format!("channel"),
+            denom: format!("orai"),
+            funds: 300_u32.into(),
+            sequence: sequence
+        )
+    };
+    let err = execute(deps.as_mut(), mock_env(), info, send_msg(1)).unwrap_err();
+    assert!(matches!(err, ContractError::Payment(_)));
+
+    let info = mock_info(BRIDGE_CONTRACT, &coins(200, "orai"));
+    let err = execute(deps.as_mut(), mock_env(), info, send_msg(2)).unwrap_err();
+    assert!(matches!(err, ContractError::EscrowMismatch { .. }));
+
+    let info = mock_info(BRIDGE_CONTRACT, &coins(300, "orai"));
+    let msg = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("orai"),
+        funds: 300_u32.into()
+    );
+    let err = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+    assert_eq!(err, ContractError::EscrowWithoutSequence {});
+    execute(deps.as_mut(), mock_env(), info, send_msg(3)).unwrap();
+
+    // The path can't be removed while it holds funds
+    let info = mock_info(BRIDGE_CONTRACT, &[]);
+    let remove_msg = ExecuteMsg::RemovePath {
+        channel_id: "channel".to_string(),
+        denom: "orai".to_string(),
+        idempotency_key: None,
+    };
+    let err = execute(deps.as_mut(), mock_env(), info.clone(), remove_msg.clone()).unwrap_err();
+    assert!(matches!(err, ContractError::EscrowNotEmpty { .. }));
+
+    let ack_msg = |sequence, success| ExecuteMsg::AckPacket {
+        packet: Packet::mock(format!("channel"), format!("orai"), 300_u32.into())
+            .with_sequence(sequence),
+        success,
+    };
+    let released = vec![SubMsg::new(BankMsg::Send {
+        to_address: BRIDGE_CONTRACT.to_string(),
+        amount: coins(300, "orai"),
+    })];
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), ack_msg(3, false)).unwrap();
+    assert_eq!(res.messages, released);
+
+    // The refund can't be claimed twice
+    let msg = ExecuteMsg::UndoSend {
+        packet: Packet::mock(format!("channel"), format!("orai"), 300_u32.into()).with_sequence(3),
+    };
+    let err = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+    assert!(matches!(err, ContractError::PendingSendNotFound { .. }));
+
+    // Delivered sends release their escrow too
+    let funded = mock_info(BRIDGE_CONTRACT, &coins(300, "orai"));
+    execute(deps.as_mut(), mock_env(), funded.clone(), send_msg(4)).unwrap();
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), ack_msg(4, true)).unwrap();
+    assert_eq!(res.messages, released);
+
+    // Receives don't take funds
+    let msg = test_msg_recv!(
+        channel_id: format!("channel"),
+        denom: format!("orai"),
+        funds: 300_u32.into()
+    );
+    let err = execute(deps.as_mut(), mock_env(), funded, msg).unwrap_err();
+    assert!(matches!(err, ContractError::Payment(_)));
+
+    execute(deps.as_mut(), mock_env(), info, remove_msg).unwrap();
+}
+
+#[test] // Tests that the policy contract can veto packets and that only the owner can set it
+fn policy_contract() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new(
+        "weekly",
+        RESET_TIME_WEEKLY,
+        Uint256::from(1000_u128),
+        Uint256::from(1000_u128),
+    );
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg::new(
+            &Addr::unchecked(BRIDGE_CONTRACT),
+            "channel",
+            "denom",
+            vec![quota],
+        )],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    // The policy contract denies any transfer above 500
+    deps.querier.update_wasm(|query| match query {
+        WasmQuery::Smart { msg, .. } => {
+            let PolicyQueryMsg::CheckPolicy { packet, .. } = from_json(msg).unwrap();
+            let response = PolicyResponse {
+                allowed: packet.amount <= Uint128::new(500),
+                reason: Some("too large".to_string()),
+            };
+            SystemResult::Ok(ContractResult::Ok(to_json_binary(&response).unwrap()))
+        }
+        _ => panic!("unexpected query"),
+    });
+
+    let config_msg = ExecuteMsg::UpdateConfig {
+        config: Config {
+            policy_contract: Some(Addr::unchecked("policy")),
+            ..Config::default()
+        },
+        idempotency_key: None,
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(BRIDGE_CONTRACT, &[]),
+        config_msg.clone(),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::Ownership(_)));
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), config_msg).unwrap();
+
+    let info = mock_info(BRIDGE_CONTRACT, &[]);
+    let send_msg = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 300_u32.into()
+    );
+    execute(deps.as_mut(), mock_env(), info.clone(), send_msg).unwrap();
+
+    let send_msg = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 600_u32.into()
+    );
+    let err = execute(deps.as_mut(), mock_env(), info, send_msg).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::PolicyDenied {
+            reason: "too large".to_string()
+        }
+    );
+}
+
+#[test] // Tests that corrupt tracker entries are quarantined instead of blocking the path
+fn corrupt_entry_quarantine() {
+    let mut deps = mock_dependencies();
+
+    let msg = InstantiateMsg {
+        paths: vec![],
+        key_spec: None,
+        seed_flows: vec![],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let key = (
+        Addr::unchecked(BRIDGE_CONTRACT),
+        "channel".to_string(),
+        "denom".to_string(),
+    );
+    deps.storage
+        .set(&RATE_LIMIT_TRACKERS.key(key.clone()), b"not a tracker");
+
+    let info = mock_info(BRIDGE_CONTRACT, &[]);
+    let send_msg = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 300_u32.into()
+    );
+    let res = execute(deps.as_mut(), mock_env(), info, send_msg.clone()).unwrap();
+    assert_eq!(res.events[0].ty, "quarantine");
+    assert!(!RATE_LIMIT_TRACKERS.has(&deps.storage, key.clone()));
+
+    let query_msg = QueryMsg::GetQuarantined {
+        start_after: None,
+        limit: None,
+    };
+    let res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
+    let entries: Vec<QuarantinedEntry> = from_json::<Versioned<_>>(&res).unwrap().data;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].raw.as_slice(), b"not a tracker");
+
+    // Only the owner can purge
+    let purge_msg = ExecuteMsg::PurgeQuarantined {
+        contract: key.0,
+        channel_id: key.1,
+        denom: key.2,
+    };
+    let info = mock_info(BRIDGE_CONTRACT, &[]);
+    execute(deps.as_mut(), mock_env(), info, purge_msg.clone()).unwrap_err();
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), purge_msg).unwrap();
+    let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+    let entries: Vec<QuarantinedEntry> = from_json::<Versioned<_>>(&res).unwrap().data;
+    assert!(entries.is_empty());
+
+    // Unconfigured paths can be rejected
+    let config_msg = ExecuteMsg::UpdateConfig {
+        config: Config {
+            unconfigured_behavior: UnconfiguredBehavior::Reject,
+            ..Config::default()
+        },
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), config_msg).unwrap();
+    let info = mock_info(BRIDGE_CONTRACT, &[]);
+    let err = execute(deps.as_mut(), mock_env(), info.clone(), send_msg.clone()).unwrap_err();
+    assert!(matches!(err, ContractError::PathNotConfigured { .. }));
+
+    // Corrupted paths are told apart from unconfigured ones
+    let key = (
+        Addr::unchecked(BRIDGE_CONTRACT),
+        "channel".to_string(),
+        "denom".to_string(),
+    );
+    deps.storage
+        .set(&RATE_LIMIT_TRACKERS.key(key), b"not a tracker");
+    let err = execute(deps.as_mut(), mock_env(), info, send_msg).unwrap_err();
+    assert!(matches!(err, ContractError::PathQuarantined { .. }));
+}
+
+#[test] // Tests that paths can be managed through sudo on behalf of any contract
+fn sudo_path_management() {
+    let mut deps = mock_dependencies();
+    let info = mock_info(OWNER, &[]);
+    instantiate(
+        deps.as_mut(),
+        mock_env(),
+        info,
+        InstantiateMsg {
+            paths: vec![],
+            key_spec: None,
+            seed_flows: vec![],
+        },
+    )
+    .unwrap();
+
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 10_u32.into(), 10_u32.into());
+    let msg = SudoMsg::AddPath {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        quotas: vec![quota],
+    };
+    sudo(deps.as_mut(), mock_env(), msg).unwrap();
+
+    let send_msg = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 10_u32.into()
+    );
+    let info = mock_info(BRIDGE_CONTRACT, &[]);
+    execute(deps.as_mut(), mock_env(), info.clone(), send_msg.clone()).unwrap();
+    let err = execute(deps.as_mut(), mock_env(), info.clone(), send_msg.clone()).unwrap_err();
+    assert!(matches!(err, ContractError::RateLimitExceded { .. }));
+
+    let msg = SudoMsg::ResetPathQuota {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        quota_id: format!("weekly"),
+        memo: Some(format!("governance")),
+        mode: ResetMode::Full,
+    };
+    sudo(deps.as_mut(), mock_env(), msg).unwrap();
+    execute(deps.as_mut(), mock_env(), info.clone(), send_msg.clone()).unwrap();
+
+    let msg = SudoMsg::RemovePath {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+    };
+    sudo(deps.as_mut(), mock_env(), msg).unwrap();
+
+    let query_msg = QueryMsg::GetQuotas {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+    };
+    query(deps.as_ref(), mock_env(), query_msg).unwrap_err();
+}
+
+#[test] // Tests that a pause blocks every packet and can be set by the owner or sudo
+fn pause() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 10_u32.into(), 10_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+    };
+    let info = mock_info(OWNER, &[]);
+    instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let send_msg = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 1_u32.into()
+    );
+    let recv_msg = test_msg_recv!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 1_u32.into()
+    );
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+
+    // Only the owner can pause
+    let pause_msg = ExecuteMsg::SetPaused {
+        paused: true,
+        auto_unpause_at: None,
+    };
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), pause_msg.clone()).unwrap_err();
+    assert!(matches!(err, ContractError::Ownership(_)));
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), pause_msg).unwrap();
+
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), send_msg.clone()).unwrap_err();
+    assert_eq!(err, ContractError::Paused {});
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), recv_msg.clone()).unwrap_err();
+    assert_eq!(err, ContractError::Paused {});
+
+    sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::SetPaused {
+            paused: false,
+            auto_unpause_at: None,
+        },
+    )
+    .unwrap();
+    execute(deps.as_mut(), mock_env(), bridge.clone(), send_msg).unwrap();
+    execute(deps.as_mut(), mock_env(), bridge, recv_msg).unwrap();
+}
+
+#[test] // Tests that the metrics follow the packets and the paths
+fn metrics() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 10_u32.into(), 10_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+    };
+    let info = mock_info(OWNER, &[]);
+    instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let send_msg = |sequence| {
+        test_msg_send!(
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            funds: 3_u32.into(),
+            sequence: sequence
+        )
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), send_msg(1)).unwrap();
+    execute(deps.as_mut(), mock_env(), bridge.clone(), send_msg(2)).unwrap();
+
+    let recv_msg = test_msg_recv!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 3_u32.into()
+    );
+    execute(deps.as_mut(), mock_env(), bridge.clone(), recv_msg).unwrap();
+
+    let undo_msg = test_msg_undo!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 3_u32.into(),
+        sequence: 2
+    );
+    execute(deps.as_mut(), mock_env(), bridge.clone(), undo_msg).unwrap();
+
+    // Sends rejected with data are counted, unlike the aborted ones
+    let msg = ExecuteMsg::SetPathConfig {
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        config: PathConfig {
+            reject_with_data: true,
+            ..PathConfig::default()
+        },
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+    let msg = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 11_u32.into()
+    );
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+
+    // Re-adding an existing path doesn't count it twice
+    let add_msg = ExecuteMsg::AddPath {
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        quotas: vec![],
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), add_msg).unwrap();
+    let add_msg = ExecuteMsg::AddPath {
+        channel_id: format!("channel2"),
+        denom: format!("denom"),
+        quotas: vec![],
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), bridge, add_msg).unwrap();
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::Metrics {}).unwrap();
+    let metrics: Vec<Metric> = from_json::<Versioned<_>>(&res).unwrap().data;
+    let value = |name: &str| metrics.iter().find(|m| m.name == name).unwrap().value;
+    assert_eq!(value("packets_processed"), 3);
+    assert_eq!(value("packets_out"), 2);
+    assert_eq!(value("packets_in"), 1);
+    assert_eq!(value("packets_undone"), 1);
+    assert_eq!(value("rejected"), 1);
+    assert_eq!(value("paths"), 2);
+    assert_eq!(value("quarantined_paths"), 0);
+    assert_eq!(
+        metrics.iter().find(|m| m.name == "paths").unwrap().kind,
+        MetricKind::Gauge
+    );
+}
+
+#[test] // Tests that paths fall back to the channel defaults, then to the global defaults
+fn quota_inheritance() {
+    let mut deps = mock_dependencies();
+
+    let quota = |max: u32| QuotaMsg::new("weekly", RESET_TIME_WEEKLY, max.into(), max.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("exact"),
+            quotas: vec![quota(10)],
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let msg = ExecuteMsg::SetChannelDefaultQuotas {
+        channel_id: format!("channel"),
+        quotas: vec![quota(5)],
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::UpdateConfig {
+        config: Config {
+            default_quotas: vec![quota(2)],
+            ..Config::default()
+        },
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let send = |channel: &str, denom: &str, funds: u32| {
+        test_msg_send!(
+            channel_id: channel.to_string(),
+            denom: denom.to_string(),
+            funds: funds.into()
+        )
+    };
+
+    // The exact path takes precedence over the defaults
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send("channel", "exact", 8),
+    )
+    .unwrap();
+
+    // Other denoms of the channel use the channel defaults
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send("channel", "other", 6),
+    );
+    assert!(matches!(err, Err(ContractError::RateLimitExceded { .. })));
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send("channel", "other", 5),
+    )
+    .unwrap();
+    let event = &res.events[0];
+    assert_eq!(event.ty, "inherit_quotas");
+    assert!(event
+        .attributes
+        .contains(&Attribute::new("source", "channel")));
+
+    // The inherited quotas are now tracked for the path
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send("channel", "other", 1),
+    );
+    assert!(matches!(err, Err(ContractError::RateLimitExceded { .. })));
+
+    // Channels without defaults use the global defaults
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send("channel2", "denom", 3),
+    );
+    assert!(matches!(err, Err(ContractError::RateLimitExceded { .. })));
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send("channel2", "denom", 2),
+    )
+    .unwrap();
+    assert!(res.events[0]
+        .attributes
+        .contains(&Attribute::new("source", "global")));
+
+    // Without any defaults, the unconfigured behavior applies
+    let msg = ExecuteMsg::UpdateConfig {
+        config: Config::default(),
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send("channel3", "denom", 100),
+    )
+    .unwrap();
+    assert!(res.attributes.contains(&Attribute::new("quota", "none")));
+}
+
+#[test] // Tests that Tick rolls over idle paths and that expired periods are archived
+fn tick_and_history() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 10_u32.into(), 10_u32.into());
+    let path = |channel: &str| PathMsg {
+        contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: channel.to_string(),
+        denom: format!("denom"),
+        quotas: vec![quota.clone()],
+    };
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![path("channel1"), path("channel2"), path("channel3")],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let send_msg = test_msg_send!(
+        channel_id: format!("channel1"),
+        denom: format!("denom"),
+        funds: 4_u32.into()
+    );
+    execute(deps.as_mut(), mock_env(), bridge.clone(), send_msg.clone()).unwrap();
+
+    let history = |deps: &cosmwasm_std::OwnedDeps<_, _, _>, channel: &str| {
+        let msg = QueryMsg::GetHistory {
+            contract: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: channel.to_string(),
+            denom: format!("denom"),
+        };
+        let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+        from_json::<Versioned<Vec<HistoryEntry>>>(&res)
+            .unwrap()
+            .data
+    };
+
+    // Nothing expired yet
+    let tick = ExecuteMsg::Tick {
+        paths: None,
+        limit: None,
+    };
+    let anyone = mock_info("anyone", &[]);
+    let res = execute(deps.as_mut(), mock_env(), anyone.clone(), tick.clone()).unwrap();
+    assert!(res.attributes.contains(&Attribute::new("expired", "0")));
+
+    // A week passes. Ticking two paths at a time covers them all in two calls
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(RESET_TIME_WEEKLY + 1);
+    let tick = ExecuteMsg::Tick {
+        paths: None,
+        limit: Some(2),
+    };
+    let res = execute(deps.as_mut(), env.clone(), anyone.clone(), tick.clone()).unwrap();
+    assert!(res.attributes.contains(&Attribute::new("expired", "2")));
+    let res = execute(deps.as_mut(), env.clone(), anyone.clone(), tick).unwrap();
+    assert!(res.attributes.contains(&Attribute::new("expired", "1")));
+
+    let entries = history(&deps, "channel1");
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].index, 0);
+    assert_eq!(entries[0].outflow, Uint256::from(4_u128));
+    assert_eq!(
+        entries[0].period_end,
+        mock_env().block.time.plus_seconds(RESET_TIME_WEEKLY)
+    );
+
+    let msg = QueryMsg::GetPeriodReport {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel1"),
+        denom: format!("denom"),
+        period_index: 0,
+    };
+    let report: PeriodReport =
+        from_json::<Versioned<_>>(&query(deps.as_ref(), env.clone(), msg).unwrap())
+            .unwrap()
+            .data;
+    assert_eq!(report.period, entries[0]);
+    assert_eq!(report.period.stats.packets_out, 1);
+    assert_eq!(report.period.stats.largest_transfer, Uint128::from(4_u32));
+    let msg = QueryMsg::GetPeriodReport {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel1"),
+        denom: format!("denom"),
+        period_index: 1,
+    };
+    query(deps.as_ref(), env.clone(), msg).unwrap_err();
+
+    let msg = QueryMsg::GetQuotas {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel3"),
+        denom: format!("denom"),
+    };
+    let res = query(deps.as_ref(), env.clone(), msg).unwrap();
+    let value: Vec<RateLimitResponse> = from_json::<Versioned<_>>(&res).unwrap().data;
+    assert_eq!(
+        value[0].flow.period_end,
+        env.block.time.plus_seconds(RESET_TIME_WEEKLY)
+    );
+
+    // Periods rolled over by traffic are archived too
+    execute(deps.as_mut(), env.clone(), bridge.clone(), send_msg.clone()).unwrap();
+    env.block.time = env.block.time.plus_seconds(RESET_TIME_WEEKLY + 1);
+    execute(deps.as_mut(), env.clone(), bridge, send_msg).unwrap();
+
+    let entries = history(&deps, "channel1");
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[1].index, 1);
+    assert_eq!(entries[1].outflow, Uint256::from(4_u128));
+
+    // Explicit paths are ticked regardless of the cursor
+    env.block.time = env.block.time.plus_seconds(RESET_TIME_WEEKLY + 1);
+    let tick = ExecuteMsg::Tick {
+        paths: Some(vec![PathId {
+            contract: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel2"),
+            denom: format!("denom"),
+        }]),
+        limit: None,
+    };
+    let res = execute(deps.as_mut(), env, anyone, tick).unwrap();
+    assert!(res.attributes.contains(&Attribute::new("expired", "1")));
+    assert_eq!(history(&deps, "channel2").len(), 2);
+}
+
+#[test] // Tests that zero quotas are rejected and that blocked quotas reject everything
+fn blocked_quota() {
+    let mut deps = mock_dependencies();
+    instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(OWNER, &[]),
+        InstantiateMsg {
+            paths: vec![],
+            key_spec: None,
+            seed_flows: vec![],
+        },
+    )
+    .unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let msg = ExecuteMsg::AddPath {
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        quotas: vec![QuotaMsg::new(
+            "weekly",
+            RESET_TIME_WEEKLY,
+            10_u32.into(),
+            0_u32.into(),
+        )],
+        idempotency_key: None,
+    };
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::ZeroQuota {
+            name: format!("weekly")
+        }
+    );
+
+    let msg = ExecuteMsg::AddPath {
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        quotas: vec![QuotaMsg::blocked("weekly", RESET_TIME_WEEKLY)],
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+
+    let send_msg = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 1_u32.into()
+    );
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), send_msg).unwrap_err();
+    assert!(matches!(err, ContractError::RateLimitExceded { .. }));
+
+    let recv_msg = test_msg_recv!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 1_u32.into()
+    );
+    let err = execute(deps.as_mut(), mock_env(), bridge, recv_msg).unwrap_err();
+    assert!(matches!(err, ContractError::RateLimitExceded { .. }));
+}
+
+#[test] // Tests that relayed ICS-20 packets are accounted by their string amounts
+fn ics20_packets() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new(
+        "weekly",
+        RESET_TIME_WEEKLY,
+        Uint256::from(3_000_000_000_000_000_000_u128),
+        Uint256::from(3_000_000_000_000_000_000_u128),
+    );
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg::new(
+            &Addr::unchecked(BRIDGE_CONTRACT),
+            "channel",
+            "weth-wei",
+            vec![quota],
+        )],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let data = |amount: &str| Ics20PacketData {
+        denom: format!("weth-wei"),
+        amount: amount.to_string(),
+        sender: format!("axelar1sender"),
+        receiver: format!("orai1receiver"),
+        memo: None,
+    };
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let msg = ExecuteMsg::RecvIcs20Packet {
+        channel_id: format!("channel"),
+        sequence: None,
+        data: data("2500000000000000000"),
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+    let msg = ExecuteMsg::SendIcs20Packet {
+        channel_id: format!("channel"),
+        sequence: Some(1),
+        data: data("6500000000000000000"),
+    };
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap_err();
+    assert!(matches!(err, ContractError::RateLimitExceded { .. }));
+
+    // Malformed and overflowing amounts are refused before any accounting
+    let send = |amount: &str| ExecuteMsg::SendIcs20Packet {
+        channel_id: format!("channel"),
+        sequence: None,
+        data: data(amount),
+    };
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), send("")).unwrap_err();
+    assert_eq!(err, ContractError::EmptyAmount {});
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), send("1e18")).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::MalformedAmount {
+            amount: format!("1e18")
+        }
+    );
+    let beyond = (Uint256::from(u128::MAX) + Uint256::from(1_u32)).to_string();
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), send(&beyond)).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::AmountOverflow {
+            amount: beyond,
+            max: format!("uint128"),
+        }
+    );
+
+    let query_msg = QueryMsg::GetQuotas {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("weth-wei"),
+    };
+    let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+    let quotas: Versioned<Vec<RateLimitResponse>> = from_json(&res).unwrap();
+    assert_eq!(
+        quotas.data[0].flow.inflow,
+        Uint256::from(2_500_000_000_000_000_000_u128)
+    );
+    assert_eq!(quotas.data[0].flow.outflow, Uint256::zero());
+}
+
+#[test] // Tests that CanSend reports whether a send would pass without accounting it
+fn can_send() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 10_u32.into(), 10_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let can_send = |deps: &cosmwasm_std::OwnedDeps<_, _, _>, amount: u32| {
+        let msg = QueryMsg::CanSend {
+            contract: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            amount: amount.into(),
+        };
+        from_json::<Versioned<CanSendResponse>>(&query(deps.as_ref(), mock_env(), msg).unwrap())
+            .unwrap()
+            .data
+    };
+
+    assert!(can_send(&deps, 10).allowed);
+    // Querying doesn't consume the allowance
+    assert!(can_send(&deps, 10).allowed);
+
+    let res = can_send(&deps, 11);
+    assert!(!res.allowed);
+    assert!(res.reason.unwrap().contains("IBC Rate Limit exceeded"));
+    let exceeded = res.exceeded.unwrap();
+    assert_eq!(exceeded.quota_name, "weekly");
+    assert_eq!(exceeded.max, Uint256::from(10_u128));
+
+    let send_msg = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 6_u32.into()
+    );
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(BRIDGE_CONTRACT, &[]),
+        send_msg,
+    )
+    .unwrap();
+    assert!(can_send(&deps, 4).allowed);
+    assert!(!can_send(&deps, 5).allowed);
+
+    let msg = ExecuteMsg::SetPaused {
+        paused: true,
+        auto_unpause_at: None,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+    let res = can_send(&deps, 1);
+    assert!(!res.allowed);
+    assert_eq!(res.reason, Some(ContractError::Paused {}.to_string()));
+}
+
+#[test] // Tests that percentage quotas follow the channel value sent with the packets
+fn percentage_quota() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::percentage(
+        "weekly",
+        RESET_TIME_WEEKLY,
+        PercentageLimit {
+            send: Decimal::percent(10),
+            recv: Decimal::percent(10),
+            rounding: Rounding::Floor,
+            min_capacity: Uint256::from(2_u128),
+        },
+    );
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let send = |funds: u32, channel_value: Option<u32>| {
+        let packet = Packet::mock(format!("channel"), format!("denom"), funds.into());
+        ExecuteMsg::SendPacket {
+            packet: match channel_value {
+                Some(value) => packet.with_channel_value(value.into()),
+                None => packet,
+            },
+        }
+    };
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+
+    // Without a channel value, only the floor is available
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), send(3, None)).unwrap_err();
+    assert!(
+        matches!(err, ContractError::RateLimitExceded { max, .. } if max == Uint256::from(2_u128))
+    );
+
+    // 10% of 1000
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send(60, Some(1000)),
+    )
+    .unwrap();
+    assert_eq!(res.attributes[6].value, "100");
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send(41, Some(1000)),
+    )
+    .unwrap_err();
+    assert!(
+        matches!(err, ContractError::RateLimitExceded { max, .. } if max == Uint256::from(100_u128))
+    );
+
+    // The channel value is frozen for the rest of the period
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send(41, Some(5000)),
+    )
+    .unwrap_err();
+    assert!(
+        matches!(err, ContractError::RateLimitExceded { max, .. } if max == Uint256::from(100_u128))
+    );
+    execute(deps.as_mut(), mock_env(), bridge.clone(), send(40, None)).unwrap();
+
+    // A new period takes the channel value of its first packet
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(RESET_TIME_WEEKLY + 1);
+    let res = execute(deps.as_mut(), env, bridge, send(1, Some(5000))).unwrap();
+    assert_eq!(res.attributes[6].value, "500");
+}
+
+#[test] // Tests that undoing a send only reverts the period it was accounted in
+fn undo_send_targets_period() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 100_u32.into(), 100_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let packet = |sequence: u64, funds: u32| {
+        Packet::mock(format!("channel"), format!("denom"), funds.into()).with_sequence(sequence)
+    };
+    let outflow = |deps: &cosmwasm_std::OwnedDeps<_, _, _>| {
+        let msg = QueryMsg::GetQuotas {
+            contract: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+        };
+        let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+        from_json::<Versioned<Vec<RateLimitResponse>>>(&res)
+            .unwrap()
+            .data[0]
+            .flow
+            .outflow
+    };
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let period_end = mock_env().block.time.plus_seconds(RESET_TIME_WEEKLY);
+
+    for sequence in 1..=3 {
+        let msg = ExecuteMsg::SendPacket {
+            packet: packet(sequence, 10),
+        };
+        execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+    }
+
+    // Undone on the last instant of the period
+    let mut env = mock_env();
+    env.block.time = period_end;
+    let msg = ExecuteMsg::UndoSend {
+        packet: packet(1, 10),
+    };
+    let res = execute(deps.as_mut(), env.clone(), bridge.clone(), msg).unwrap();
+    // A week later, the undo also rolls up the day of the sends
+    assert!(!res.events.iter().any(|event| event.ty == "undo_skipped"));
+    assert_eq!(outflow(&deps), Uint256::from(20_u128));
+
+    // Right after the period expired, even if the flow was not rolled over yet
+    env.block.time = period_end.plus_nanos(1);
+    let msg = ExecuteMsg::UndoSend {
+        packet: packet(2, 10),
+    };
+    let res = execute(deps.as_mut(), env.clone(), bridge.clone(), msg).unwrap();
+    assert_eq!(res.events[0].ty, "undo_skipped");
+    assert_eq!(outflow(&deps), Uint256::from(20_u128));
+
+    // Once a new period started, its accounting is left untouched
+    let msg = ExecuteMsg::SendPacket {
+        packet: packet(4, 5),
+    };
+    execute(deps.as_mut(), env.clone(), bridge.clone(), msg).unwrap();
+    let msg = ExecuteMsg::AckPacket {
+        packet: packet(3, 10),
+        success: false,
+    };
+    let res = execute(deps.as_mut(), env, bridge, msg).unwrap();
+    assert_eq!(res.events[0].ty, "undo_skipped");
+    assert_eq!(outflow(&deps), Uint256::from(5_u128));
+}
+
+#[test] // Tests that the rate limit details can be recovered from the error message
+fn rate_limit_error_data() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 10_u32.into(), 10_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let send_msg = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 11_u32.into()
+    );
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(BRIDGE_CONTRACT, &[]),
+        send_msg,
+    )
+    .unwrap_err();
+
+    let data = RateLimitExceededData::from_error_message(&err.to_string()).unwrap();
+    assert_eq!(
+        data,
+        RateLimitExceededData {
+            contract: BRIDGE_CONTRACT.to_string(),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            amount: Uint128::new(11),
+            quota_name: format!("weekly"),
+            used: Uint256::zero(),
+            max: Uint256::from(10_u128),
+            reset: mock_env().block.time.plus_seconds(RESET_TIME_WEEKLY),
+            violations: vec![QuotaViolation {
+                quota_name: format!("weekly"),
+                used: Uint256::zero(),
+                max: Uint256::from(10_u128),
+                reset: mock_env().block.time.plus_seconds(RESET_TIME_WEEKLY),
+            }],
+        }
+    );
+    assert_eq!(Some(data), err.rate_limit_data());
+
+    assert!(
+        RateLimitExceededData::from_error_message(&ContractError::Paused {}.to_string()).is_none()
+    );
+}
+
+#[test] // Tests that every change to a path is recorded in its audit log
+fn audit_log() {
+    let mut deps = mock_dependencies();
+    instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(OWNER, &[]),
+        InstantiateMsg {
+            paths: vec![],
+            key_spec: None,
+            seed_flows: vec![],
+        },
+    )
+    .unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 10_u32.into(), 10_u32.into());
+    let add_msg = ExecuteMsg::AddPath {
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        quotas: vec![quota],
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), add_msg.clone()).unwrap();
+    execute(deps.as_mut(), mock_env(), bridge.clone(), add_msg).unwrap();
+
+    let msg = ExecuteMsg::ResetPathQuota {
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        quota_id: format!("weekly"),
+        memo: Some(format!("incident 42")),
+        mode: ResetMode::Full,
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+
+    let msg = SudoMsg::RemovePath {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+    };
+    sudo(deps.as_mut(), mock_env(), msg).unwrap();
+
+    let audit_log = |start_after: Option<u64>, limit: Option<u32>| {
+        let msg = QueryMsg::GetAuditLog {
+            contract: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            start_after,
+            limit,
+        };
+        from_json::<Versioned<Vec<AuditEntry>>>(&query(deps.as_ref(), mock_env(), msg).unwrap())
+            .unwrap()
+            .data
+    };
+
+    let entries = audit_log(None, None);
+    let actions: Vec<AuditAction> = entries.iter().map(|e| e.action.clone()).collect();
+    assert_eq!(
+        actions,
+        vec![
+            AuditAction::AddPath,
+            AuditAction::EditQuota,
+            AuditAction::Reset,
+            AuditAction::RemovePath
+        ]
+    );
+    assert_eq!(entries[2].memo, Some(format!("incident 42")));
+    assert_eq!(entries[2].actor, Addr::unchecked(BRIDGE_CONTRACT));
+    assert_eq!(entries[3].actor, mock_env().contract.address);
+    assert_eq!(entries[3].at, mock_env().block.time);
+
+    let page = audit_log(Some(1), Some(1));
+    assert_eq!(page.len(), 1);
+    assert_eq!(page[0].index, 2);
+}
+
+#[test] // Tests that the configured caps on quotas and paths are enforced
+fn quota_and_path_caps() {
+    let mut deps = mock_dependencies();
+    instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(OWNER, &[]),
+        InstantiateMsg {
+            paths: vec![],
+            key_spec: None,
+            seed_flows: vec![],
+        },
+    )
+    .unwrap();
+
+    let msg = ExecuteMsg::UpdateConfig {
+        config: Config {
+            max_quotas_per_path: Some(2),
+            max_paths_per_contract: Some(2),
+            ..Config::default()
+        },
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let quota = |name: &str| QuotaMsg::new(name, RESET_TIME_WEEKLY, 10_u32.into(), 10_u32.into());
+    let add_path = |channel: &str, quotas: Vec<QuotaMsg>| ExecuteMsg::AddPath {
+        channel_id: channel.to_string(),
+        denom: format!("denom"),
+        quotas,
+        idempotency_key: None,
+    };
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+
+    let msg = add_path("channel1", vec![quota("a"), quota("b"), quota("c")]);
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap_err();
+    assert_eq!(err, ContractError::TooManyQuotas { max: 2, count: 3 });
+
+    let msg = add_path("channel1", vec![quota("a"), quota("b")]);
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+    let msg = add_path("channel2", vec![quota("a")]);
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+
+    let msg = add_path("channel3", vec![quota("a")]);
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::TooManyPaths {
+            contract: BRIDGE_CONTRACT.to_string(),
+            max: 2
+        }
+    );
+
+    // Existing paths can still be edited, and other contracts have their own cap
+    let msg = add_path("channel2", vec![quota("b")]);
+    execute(deps.as_mut(), mock_env(), bridge, msg).unwrap();
+    let msg = add_path("channel3", vec![quota("a")]);
+    execute(deps.as_mut(), mock_env(), mock_info("other", &[]), msg).unwrap();
+}
+
+#[test] // Tests that packets with ibc-hooks memos are also checked against their class quotas
+fn hooked_packet_quotas() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 100_u32.into(), 100_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let msg = ExecuteMsg::SetClassQuotas {
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        class: PacketClass::Hooked,
+        quotas: vec![QuotaMsg::new(
+            "weekly",
+            RESET_TIME_WEEKLY,
+            10_u32.into(),
+            10_u32.into(),
+        )],
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+
+    let hooks_memo = r#"{"wasm":{"contract":"osmo1contract","msg":{"swap":{}}}}"#;
+    let packet = |funds: u32, memo: Option<&str>| {
+        let packet = Packet::mock(format!("channel"), format!("denom"), funds.into());
+        match memo {
+            Some(memo) => packet.with_memo(memo),
+            None => packet,
+        }
+    };
+    assert_eq!(packet(1, Some(hooks_memo)).class(), PacketClass::Hooked);
+    assert_eq!(
+        packet(1, Some(r#"{"forward":{}}"#)).class(),
+        PacketClass::Plain
+    );
+    assert_eq!(packet(1, Some("not json")).class(), PacketClass::Plain);
+
+    let send = |packet: Packet| ExecuteMsg::SendPacket { packet };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send(packet(11, Some(hooks_memo))),
+    )
+    .unwrap_err();
+    assert!(
+        matches!(err, ContractError::RateLimitExceded { max, .. } if max == Uint256::from(10_u128))
+    );
+
+    // Plain packets only use the path quotas
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send(packet(50, Some(r#"{"forward":{}}"#))),
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send(packet(10, Some(hooks_memo))),
+    )
+    .unwrap();
+
+    let msg = QueryMsg::GetClassQuotas {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        class: PacketClass::Hooked,
+    };
+    let res = query(deps.as_ref(), mock_env(), msg.clone()).unwrap();
+    let value: Vec<RateLimitResponse> = from_json::<Versioned<_>>(&res).unwrap().data;
+    assert_eq!(value[0].flow.outflow, Uint256::from(10_u128));
+
+    // Hooked packets still count against the path quotas
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send(packet(41, None)),
+    )
+    .unwrap_err();
+    assert!(
+        matches!(err, ContractError::RateLimitExceded { max, .. } if max == Uint256::from(100_u128))
+    );
+
+    // Undoing a send of a past period leaves the class flow of the current one
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(RESET_TIME_WEEKLY + 1);
+    let sequenced =
+        |funds: u32, sequence: u64| send(packet(funds, Some(hooks_memo)).with_sequence(sequence));
+    execute(deps.as_mut(), env.clone(), bridge.clone(), sequenced(4, 1)).unwrap();
+    env.block.time = env.block.time.plus_seconds(RESET_TIME_WEEKLY + 1);
+    execute(deps.as_mut(), env.clone(), bridge.clone(), sequenced(6, 2)).unwrap();
+    let undo = ExecuteMsg::UndoSend {
+        packet: packet(4, Some(hooks_memo)).with_sequence(1),
+    };
+    execute(deps.as_mut(), env.clone(), bridge, undo).unwrap();
+    let res = query(deps.as_ref(), env, msg).unwrap();
+    let value: Vec<RateLimitResponse> = from_json::<Versioned<_>>(&res).unwrap().data;
+    assert_eq!(value[0].flow.outflow, Uint256::from(6_u128));
+}
+
+#[test] // Tests that migrating a path moves its state and redirects queries against the old path
+fn migrate_path() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 100_u32.into(), 100_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![
+            PathMsg {
+                contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+                channel_id: format!("channel-5"),
+                denom: format!("denom"),
+                quotas: vec![quota.clone()],
+            },
+            PathMsg {
+                contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+                channel_id: format!("channel-7"),
+                denom: format!("denom"),
+                quotas: vec![quota],
+            },
+        ],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let send = ExecuteMsg::SendPacket {
+        packet: Packet::mock(format!("channel-5"), format!("denom"), 60_u32.into()),
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), send).unwrap();
+
+    let migrate = |from: &str, to: &str| ExecuteMsg::MigratePath {
+        from: (from.to_string(), format!("denom")),
+        to: (to.to_string(), format!("denom")),
+        idempotency_key: None,
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        migrate("channel-5", "channel-7"),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::PathAlreadyExists { .. }));
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        migrate("channel-1", "channel-92"),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::PathNotConfigured { .. }));
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        migrate("channel-5", "channel-92"),
+    )
+    .unwrap();
+
+    // The flow carried over to the new channel
+    let send = ExecuteMsg::SendPacket {
+        packet: Packet::mock(format!("channel-92"), format!("denom"), 41_u32.into()),
+    };
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), send).unwrap_err();
+    assert!(matches!(err, ContractError::RateLimitExceded { .. }));
+
+    // The old channel is no longer configured, but queries are redirected
+    let send = ExecuteMsg::SendPacket {
+        packet: Packet::mock(format!("channel-5"), format!("denom"), 50_u32.into()),
+    };
+    execute(deps.as_mut(), mock_env(), bridge, send).unwrap();
+
+    let query_quotas = |deps: Deps, channel: &str| {
+        let msg = QueryMsg::GetQuotas {
+            contract: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: channel.to_string(),
+            denom: format!("denom"),
+        };
+        from_json::<Versioned<Vec<RateLimitResponse>>>(&query(deps, mock_env(), msg).unwrap())
+            .unwrap()
+            .data
+    };
+    assert_eq!(
+        query_quotas(deps.as_ref(), "channel-5")[0].flow.outflow,
+        Uint256::from(60_u128)
+    );
+    assert_eq!(
+        query_quotas(deps.as_ref(), "channel-5"),
+        query_quotas(deps.as_ref(), "channel-92")
+    );
+
+    let msg = QueryMsg::GetAuditLog {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel-92"),
+        denom: format!("denom"),
+        start_after: None,
+        limit: None,
+    };
+    let log: Vec<AuditEntry> =
+        from_json::<Versioned<_>>(&query(deps.as_ref(), mock_env(), msg).unwrap())
+            .unwrap()
+            .data;
+    assert_eq!(log.len(), 1);
+    assert_eq!(log[0].action, AuditAction::MigratePath);
+
+    // A paused path stays paused on its new channel
+    let msg = ExecuteMsg::SetPathPaused {
+        path: PathId {
+            contract: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel-7"),
+            denom: format!("denom"),
+        },
+        paused: true,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(BRIDGE_CONTRACT, &[]),
+        migrate("channel-7", "channel-93"),
+    )
+    .unwrap();
+    let send = ExecuteMsg::SendPacket {
+        packet: Packet::mock(format!("channel-93"), format!("denom"), 1_u32.into()),
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(BRIDGE_CONTRACT, &[]),
+        send,
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::PathPaused {
+            channel_id: format!("channel-93"),
+            denom: format!("denom"),
+        }
+    );
+}
+
+#[test] // Tests that unconfigured paths get the new denom quota when failing open
+fn new_denom_quota() {
+    let mut deps = mock_dependencies();
+
+    let msg = InstantiateMsg {
+        paths: vec![],
+        key_spec: None,
+        seed_flows: vec![],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let config = Config {
+        default_new_denom_quota: Some(QuotaMsg::new(
+            "new_denom",
+            RESET_TIME_WEEKLY,
+            10_u32.into(),
+            10_u32.into(),
+        )),
+        ..Config::default()
+    };
+    let msg = ExecuteMsg::UpdateConfig {
+        config,
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let send = |funds: u32| ExecuteMsg::SendPacket {
+        packet: Packet::mock(format!("channel"), format!("new_denom"), funds.into()),
+    };
+    let res = execute(deps.as_mut(), mock_env(), bridge.clone(), send(6)).unwrap();
+    let event = res
+        .events
+        .iter()
+        .find(|e| e.ty == "inherit_quotas")
+        .unwrap();
+    assert!(event
+        .attributes
+        .contains(&Attribute::new("source", "new_denom")));
+
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), send(5)).unwrap_err();
+    assert!(matches!(err, ContractError::RateLimitExceded { .. }));
+
+    // Failing closed still rejects unconfigured paths
+    let config = Config {
+        unconfigured_behavior: UnconfiguredBehavior::Reject,
+        default_new_denom_quota: Some(QuotaMsg::new(
+            "new_denom",
+            RESET_TIME_WEEKLY,
+            10_u32.into(),
+            10_u32.into(),
+        )),
+        ..Config::default()
+    };
+    let msg = ExecuteMsg::UpdateConfig {
+        config,
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let send = ExecuteMsg::SendPacket {
+        packet: Packet::mock(format!("channel"), format!("other_denom"), 1_u32.into()),
+    };
+    let err = execute(deps.as_mut(), mock_env(), bridge, send).unwrap_err();
+    assert!(matches!(err, ContractError::PathNotConfigured { .. }));
+}
+
+#[test] // Tests that trackers stored with Uint128 amounts load as Uint256 after migrating
+fn migrate_uint128_trackers() {
+    let mut deps = mock_dependencies();
+
+    let msg = InstantiateMsg {
+        paths: vec![],
+        key_spec: None,
+        seed_flows: vec![],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let key = (
+        Addr::unchecked(BRIDGE_CONTRACT),
+        "channel".to_string(),
+        "denom".to_string(),
+    );
+    let period_end = mock_env().block.time.plus_seconds(RESET_TIME_WEEKLY);
+    let stored = format!(
+        r#"[{{"quota":{{"name":"weekly","max_send":"340282366920938463463374607431768211455","max_recv":"1000","duration":{RESET_TIME_WEEKLY}}},"flow":{{"inflow":"5","outflow":"340282366920938463463374607431768211455","period_end":"{}"}},"last_reset":null}}]"#,
+        period_end.nanos()
+    );
+    // Older versions stored the trackers under the "flow" namespace
+    let legacy: Map<PathKey, Vec<RateLimit>> = Map::new("flow");
+    deps.storage
+        .set(&legacy.key(key.clone()), stored.as_bytes());
+
+    let res = migrate(deps.as_mut(), mock_env(), MigrateMsg::default()).unwrap();
+    assert!(res
+        .attributes
+        .contains(&Attribute::new("moved_entries", "1")));
+    assert!(!legacy.has(&deps.storage, key.clone()));
+
+    let trackers = RATE_LIMIT_TRACKERS.load(&deps.storage, key).unwrap();
+    assert_eq!(trackers[0].quota.max_send, Uint256::from(Uint128::MAX));
+    assert_eq!(trackers[0].quota.max_recv, Uint256::from(1000_u128));
+    assert_eq!(trackers[0].flow.inflow, Uint256::from(5_u128));
+    assert_eq!(trackers[0].flow.outflow, Uint256::from(Uint128::MAX));
+    assert_eq!(trackers[0].flow.period_end, period_end);
+    assert_eq!(
+        trackers[0].quota.duration_nanos,
+        RESET_TIME_WEEKLY * NANOS_PER_SECOND
+    );
+
+    // The paths gauge counts the paths stored before the metrics
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::Metrics {}).unwrap();
+    let metrics: Vec<Metric> = from_json::<Versioned<_>>(&res).unwrap().data;
+    let paths = metrics.iter().find(|m| m.name == "paths").unwrap();
+    assert_eq!(paths.value, 1);
+}
+
+#[test] // Tests that only contracts with paths or registered bridges can send packets when required
+fn registered_senders() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 100_u32.into(), 100_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let config = Config {
+        require_registered_sender: true,
+        ..Config::default()
+    };
+    let msg = ExecuteMsg::UpdateConfig {
+        config,
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let send = |denom: &str| ExecuteMsg::SendPacket {
+        packet: Packet::mock(format!("channel"), denom.to_string(), 10_u32.into()),
+    };
+    // The configured bridge can send on any of its paths, even unconfigured ones
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(BRIDGE_CONTRACT, &[]),
+        send("denom"),
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(BRIDGE_CONTRACT, &[]),
+        send("other_denom"),
+    )
+    .unwrap();
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("other_bridge", &[]),
+        send("denom"),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::UnregisteredSender { .. }));
+
+    let msg = ExecuteMsg::RegisterBridge {
+        bridge: format!("other_bridge"),
+        registered: true,
+    };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("other_bridge", &[]),
+        msg.clone(),
+    )
+    .unwrap_err();
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("other_bridge", &[]),
+        send("denom"),
+    )
+    .unwrap();
+
+    // The owner can turn the check off
+    let msg = ExecuteMsg::UpdateConfig {
+        config: Config::default(),
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("unknown_bridge", &[]),
+        send("denom"),
+    )
+    .unwrap();
+}
+
+#[test] // Tests that rollovers request the escrow balance and the reported balance becomes the channel value
+fn escrow_balance_queries() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::percentage(
+        "weekly",
+        RESET_TIME_WEEKLY,
+        PercentageLimit {
+            send: Decimal::percent(10),
+            recv: Decimal::percent(10),
+            rounding: Rounding::Floor,
+            min_capacity: Uint256::zero(),
+        },
+    );
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let config = Config {
+        escrow_querier: Some(Addr::unchecked("querier")),
+        ..Config::default()
+    };
+    let msg = ExecuteMsg::UpdateConfig {
+        config,
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let send = |funds: u32| {
+        test_msg_send!(
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            funds: funds.into(),
+            channel_value: 1000_u32.into()
+        )
+    };
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let res = execute(deps.as_mut(), mock_env(), bridge.clone(), send(50)).unwrap();
+    assert!(res.messages.is_empty());
+
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(RESET_TIME_WEEKLY + 1);
+    let res = execute(deps.as_mut(), env.clone(), bridge.clone(), send(50)).unwrap();
+    assert_eq!(res.messages.len(), 1);
+    let expected_msg = to_json_binary(&EscrowQuerierMsg::QueryEscrowBalance {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+    })
+    .unwrap();
+    assert!(matches!(
+        &res.messages[0].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, msg, .. })
+            if contract_addr == "querier" && msg == &expected_msg
+    ));
+    // A failing query doesn't fail the packet
+    assert_eq!(res.messages[0].reply_on, ReplyOn::Error);
+    assert_eq!(res.messages[0].id, ESCROW_QUERY_REPLY_ID);
+
+    let callback = ExecuteMsg::EscrowBalanceCallback {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        balance: 5000_u32.into(),
+    };
+    let err = execute(deps.as_mut(), env.clone(), bridge.clone(), callback.clone()).unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized {}));
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("querier", &[]),
+        callback,
+    )
+    .unwrap();
+
+    // The current period keeps the channel value it started with
+    let err = execute(deps.as_mut(), env.clone(), bridge.clone(), send(51)).unwrap_err();
+    assert!(
+        matches!(err, ContractError::RateLimitExceded { max, .. } if max == Uint256::from(100_u128))
+    );
+
+    // 10% of the reported balance from the next period on
+    env.block.time = env.block.time.plus_seconds(RESET_TIME_WEEKLY + 1);
+    execute(deps.as_mut(), env.clone(), bridge.clone(), send(500)).unwrap();
+
+    // The reported balance goes with the path
+    let msg = ExecuteMsg::RemovePath {
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), env, bridge, msg).unwrap();
+    let key: PathKey = (
+        Addr::unchecked(BRIDGE_CONTRACT),
+        format!("channel"),
+        format!("denom"),
+    );
+    assert!(!ESCROW_VALUES.has(&deps.storage, key));
+}
+
+#[test] // Tests that a path in Any mode accepts transfers allowed by at least one quota
+fn any_evaluation_mode() {
+    let mut deps = mock_dependencies();
+
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![
+                QuotaMsg::new("daily", RESET_TIME_DAILY, 10_u32.into(), 10_u32.into()),
+                QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 100_u32.into(), 100_u32.into()),
+            ],
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let send = |funds: u32| ExecuteMsg::SendPacket {
+        packet: Packet::mock(format!("channel"), format!("denom"), funds.into()),
+    };
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), send(20)).unwrap_err();
+    assert!(
+        matches!(err, ContractError::RateLimitExceded { quota_name, .. } if quota_name == "daily")
+    );
+
+    let msg = ExecuteMsg::SetPathConfig {
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        config: PathConfig {
+            evaluation_mode: EvaluationMode::Any,
+            ..PathConfig::default()
+        },
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+
+    let msg = QueryMsg::CanSend {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        amount: 20_u32.into(),
+    };
+    let res: CanSendResponse =
+        from_json::<Versioned<_>>(&query(deps.as_ref(), mock_env(), msg).unwrap())
+            .unwrap()
+            .data;
+    assert!(res.allowed);
+
+    // The weekly quota lets it through, and both quotas account for it
+    execute(deps.as_mut(), mock_env(), bridge.clone(), send(20)).unwrap();
+    let trackers = RATE_LIMIT_TRACKERS
+        .load(
+            &deps.storage,
+            (
+                Addr::unchecked(BRIDGE_CONTRACT),
+                format!("channel"),
+                format!("denom"),
+            ),
+        )
+        .unwrap();
+    assert!(trackers
+        .iter()
+        .all(|limit| limit.flow.outflow == Uint256::from(20_u128)));
+
+    // Rejected once every quota is exceeded
+    let err = execute(deps.as_mut(), mock_env(), bridge, send(81)).unwrap_err();
+    assert!(
+        matches!(err, ContractError::RateLimitExceded { quota_name, .. } if quota_name == "daily")
+    );
+}
+
+#[test] // Tests that every write to the trackers is listed by ChangesSince
+fn changes_since() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 100_u32.into(), 100_u32.into());
+    let path = |denom: &str| PathMsg {
+        contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: denom.to_string(),
+        quotas: vec![quota.clone()],
+    };
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![path("denom"), path("other_denom")],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let changes = |deps: Deps, seq: u64| {
+        let msg = QueryMsg::ChangesSince { seq, limit: None };
+        from_json::<Versioned<ChangesResponse>>(&query(deps, mock_env(), msg).unwrap())
+            .unwrap()
+            .data
+    };
+    let res = changes(deps.as_ref(), 0);
+    assert_eq!(res.last_seq, 2);
+    assert_eq!(res.oldest_seq, 1);
+    assert_eq!(
+        res.changes
+            .iter()
+            .map(|c| c.denom.as_str())
+            .collect::<Vec<_>>(),
+        vec!["denom", "other_denom"]
+    );
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let send = ExecuteMsg::SendPacket {
+        packet: Packet::mock(format!("channel"), format!("denom"), 30_u32.into()),
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), send).unwrap();
+    let msg = ExecuteMsg::RemovePath {
+        channel_id: format!("channel"),
+        denom: format!("other_denom"),
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), bridge, msg).unwrap();
+
+    let res = changes(deps.as_ref(), 2);
+    assert_eq!(res.last_seq, 4);
+    assert_eq!(res.changes.len(), 2);
+    assert_eq!(res.changes[0].seq, 3);
+    assert_eq!(res.changes[0].denom, "denom");
+    assert_eq!(
+        res.changes[0].trackers[0].flow.outflow,
+        Uint256::from(30_u128)
+    );
+    assert_eq!(res.changes[1].denom, "other_denom");
+    assert!(res.changes[1].trackers.is_empty());
+
+    assert!(changes(deps.as_ref(), 4).changes.is_empty());
+}
+
+#[test] // Tests that owner messages go through proposals confirmed by the multisig owners
+fn multisig_proposals() {
+    let mut deps = mock_dependencies();
+    let env = mock_env();
+
+    let msg = InstantiateMsg {
+        paths: vec![],
+        key_spec: None,
+        seed_flows: vec![],
+    };
+    instantiate(deps.as_mut(), env.clone(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let msg = ExecuteMsg::SetMultisig {
+        owners: vec![format!("alice"), format!("bob"), format!("carol")],
+        threshold: 4,
+    };
+    let err = execute(deps.as_mut(), env.clone(), mock_info(OWNER, &[]), msg).unwrap_err();
+    assert!(matches!(err, ContractError::InvalidThreshold { .. }));
+    let msg = ExecuteMsg::SetMultisig {
+        owners: vec![format!("alice"), format!("bob"), format!("carol")],
+        threshold: 2,
+    };
+    execute(deps.as_mut(), env.clone(), mock_info(OWNER, &[]), msg).unwrap();
+
+    // The previous owner lost its rights
+    let pause = ExecuteMsg::SetPaused {
+        paused: true,
+        auto_unpause_at: None,
+    };
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(OWNER, &[]),
+        pause.clone(),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::Ownership(_)));
+
+    let propose = |expires_in: u64| ExecuteMsg::Propose {
+        msg: Box::new(ExecuteMsg::SetPaused {
+            paused: true,
+            auto_unpause_at: None,
+        }),
+        expires_in,
+    };
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(OWNER, &[]),
+        propose(60),
+    )
+    .unwrap_err();
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("alice", &[]),
+        propose(u64::MAX),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::ProposalExpiryTooLong {
+            expires_in: u64::MAX,
+            max: MAX_PROPOSAL_EXPIRY,
+        }
+    );
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("alice", &[]),
+        propose(60),
+    )
+    .unwrap();
+
+    let run = ExecuteMsg::ExecuteProposal { id: 0 };
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("alice", &[]),
+        run.clone(),
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::ThresholdNotMet {
+            confirmations: 1,
+            threshold: 2,
+            ..
+        }
+    ));
+
+    let confirm = ExecuteMsg::Confirm { id: 0 };
+    execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), confirm).unwrap();
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("alice", &[]),
+        run.clone(),
+    )
+    .unwrap();
+    let expected = to_json_binary(&pause).unwrap();
+    assert!(matches!(
+        &res.messages[0].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, msg, .. })
+            if contract_addr == env.contract.address.as_str() && msg == &expected
+    ));
+    let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), run).unwrap_err();
+    assert!(matches!(err, ContractError::ProposalExecuted { id: 0 }));
+
+    // The message is then executed by the contract, as its own owner
+    let contract = mock_info(env.contract.address.as_str(), &[]);
+    execute(deps.as_mut(), env.clone(), contract, pause).unwrap();
+
+    // Expired proposals can't be confirmed
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("alice", &[]),
+        propose(60),
+    )
+    .unwrap();
+    let mut later = env.clone();
+    later.block.time = later.block.time.plus_seconds(61);
+    let confirm = ExecuteMsg::Confirm { id: 1 };
+    let err = execute(deps.as_mut(), later, mock_info("bob", &[]), confirm).unwrap_err();
+    assert!(matches!(err, ContractError::ProposalExpired { id: 1 }));
+
+    let msg = QueryMsg::ListProposals {
+        start_after: None,
+        limit: None,
+    };
+    let proposals: Vec<Proposal> =
+        from_json::<Versioned<_>>(&query(deps.as_ref(), env, msg).unwrap())
+            .unwrap()
+            .data;
+    assert_eq!(proposals.len(), 2);
+    assert!(proposals[0].executed);
+    assert!(!proposals[1].executed);
+}
+
+#[test] // Tests that pausing a direction only halts the packets flowing that way
+fn direction_paused() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 10_u32.into(), 10_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let send_msg = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 1_u32.into()
+    );
+    let recv_msg = test_msg_recv!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 1_u32.into()
+    );
+
+    let pause_msg = |paused| ExecuteMsg::SetDirectionPaused {
+        direction: FlowType::Out,
+        paused,
+    };
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), pause_msg(true)).unwrap_err();
+    assert!(matches!(err, ContractError::Ownership(_)));
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(OWNER, &[]),
+        pause_msg(true),
+    )
+    .unwrap();
+
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), send_msg.clone()).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::DirectionPaused {
+            direction: format!("out")
+        }
+    );
+    execute(deps.as_mut(), mock_env(), bridge.clone(), recv_msg).unwrap();
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(OWNER, &[]),
+        pause_msg(false),
+    )
+    .unwrap();
+    execute(deps.as_mut(), mock_env(), bridge, send_msg).unwrap();
+}
+
+#[test] // Tests that unsettled sends are capped by max_in_flight until acked or undone
+fn max_in_flight() {
+    let mut deps = mock_dependencies();
+
+    let msg = InstantiateMsg {
+        paths: vec![],
+        key_spec: None,
+        seed_flows: vec![],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let msg = ExecuteMsg::SetPathConfig {
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        config: PathConfig {
+            max_in_flight: Some(Uint256::from(10_u32)),
+            ..PathConfig::default()
+        },
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+
+    let packet = |sequence, amount: u32| {
+        Packet::mock(format!("channel"), format!("denom"), amount.into()).with_sequence(sequence)
+    };
+    let send = |sequence, amount| ExecuteMsg::SendPacket {
+        packet: packet(sequence, amount),
+    };
+    let in_flight = |deps: Deps| -> Uint256 {
+        let msg = QueryMsg::GetInFlight {
+            contract: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+        };
+        from_json::<Versioned<_>>(&query(deps, mock_env(), msg).unwrap())
+            .unwrap()
+            .data
+    };
+
+    execute(deps.as_mut(), mock_env(), bridge.clone(), send(1, 6)).unwrap();
+    execute(deps.as_mut(), mock_env(), bridge.clone(), send(2, 4)).unwrap();
+    assert_eq!(in_flight(deps.as_ref()), Uint256::from(10_u32));
+
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), send(3, 1)).unwrap_err();
+    assert!(matches!(err, ContractError::InFlightExceeded { .. }));
+
+    // Acks and undos settle the sends
+    let msg = ExecuteMsg::AckPacket {
+        packet: packet(1, 6),
+        success: true,
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+    assert_eq!(in_flight(deps.as_ref()), Uint256::from(4_u32));
+    let msg = ExecuteMsg::UndoSend {
+        packet: packet(2, 4),
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+    assert_eq!(in_flight(deps.as_ref()), Uint256::zero());
+
+    execute(deps.as_mut(), mock_env(), bridge, send(4, 10)).unwrap();
+    assert_eq!(in_flight(deps.as_ref()), Uint256::from(10_u32));
+}
+
+#[test] // Tests that packets match paths whatever the form of their IBC denom
+fn denom_normalization() {
+    let mut deps = mock_dependencies();
+
+    let atom = "ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2";
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 10_u32.into(), 10_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: atom.to_string(),
+            quotas: vec![quota],
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let send = |denom: &str| ExecuteMsg::SendPacket {
+        packet: Packet::mock(format!("channel"), denom.to_string(), 5_u32.into()),
+    };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send(&atom.to_lowercase()),
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send("transfer/channel-0/uatom"),
+    )
+    .unwrap();
+    let err = execute(deps.as_mut(), mock_env(), bridge, send(atom)).unwrap_err();
+    assert!(matches!(err, ContractError::RateLimitExceded { .. }));
+
+    // Queries normalize the denom too
+    let msg = QueryMsg::GetQuotas {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: atom.to_lowercase(),
+    };
+    let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+    let quotas = from_json::<Versioned<Vec<RateLimitResponse>>>(&res)
+        .unwrap()
+        .data;
+    assert_eq!(quotas[0].flow.outflow, Uint256::from(10_u32));
+}
+
+#[test] // Tests that paths truncating exceeded transfers accept the part that fits
+fn truncate_on_exceed() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 10_u32.into(), 10_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let msg = ExecuteMsg::SetPathConfig {
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        config: PathConfig {
+            on_exceed: OnExceed::Truncate,
+            ..PathConfig::default()
+        },
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+
+    let send = |amount: u32| {
+        test_msg_send!(
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            funds: amount.into()
+        )
+    };
+    let data = |res: Response| from_json::<TransferData>(&res.data.unwrap()).unwrap();
+
+    let res = execute(deps.as_mut(), mock_env(), bridge.clone(), send(8)).unwrap();
+    assert_eq!(
+        data(res),
+        TransferData {
+            accepted: 8_u32.into(),
+            remainder: Uint128::zero(),
+            fee: Uint128::zero(),
+        }
+    );
+    let res = execute(deps.as_mut(), mock_env(), bridge.clone(), send(5)).unwrap();
+    let consumed = res
+        .events
+        .iter()
+        .find(|event| event.ty == "quota_consumed")
+        .unwrap();
+    assert!(consumed
+        .attributes
+        .contains(&Attribute::new("consumed_from", "base")));
+    assert!(consumed.attributes.contains(&Attribute::new("amount", "2")));
+    assert_eq!(
+        data(res),
+        TransferData {
+            accepted: 2_u32.into(),
+            remainder: 3_u32.into(),
+            fee: Uint128::zero(),
+        }
+    );
+
+    // Nothing fits anymore
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), send(1)).unwrap_err();
+    assert!(matches!(err, ContractError::RateLimitExceded { .. }));
+
+    // Receives can't be split, so they're rejected whole
+    let recv_msg = test_msg_recv!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 21_u32.into()
+    );
+    let err = execute(deps.as_mut(), mock_env(), bridge, recv_msg).unwrap_err();
+    assert!(matches!(err, ContractError::RateLimitExceded { .. }));
+
+    let msg = QueryMsg::GetRemainingCapacity {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+    };
+    let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+    let remaining = from_json::<Versioned<Vec<RemainingCapacity>>>(&res)
+        .unwrap()
+        .data;
+    assert_eq!(remaining[0].send, Uint256::zero());
+    assert_eq!(remaining[0].recv, Uint256::from(20_u32));
+
+    let msg = QueryMsg::GetQuotas {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+    };
+    let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+    let quotas = from_json::<Versioned<Vec<RateLimitResponse>>>(&res)
+        .unwrap()
+        .data;
+    assert_eq!(quotas[0].flow.outflow, Uint256::from(10_u32));
+}
+
+#[test] // Tests that the storage layout matches the namespaces used by the contract
+fn storage_layout() {
+    let mut deps = mock_dependencies();
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::StorageLayout {}).unwrap();
+    let layout = from_json::<Versioned<StorageLayoutResponse>>(&res)
+        .unwrap()
+        .data;
+    let namespace = |name: &str| {
+        layout
+            .entries
+            .iter()
+            .find(|entry| entry.name == name)
+            .unwrap()
+            .namespace
+            .clone()
+    };
+
+    let key = (
+        Addr::unchecked(BRIDGE_CONTRACT),
+        "channel".to_string(),
+        "denom".to_string(),
+    );
+    let trackers = namespace("RATE_LIMIT_TRACKERS");
+    let raw = RATE_LIMIT_TRACKERS.key(key);
+    assert_eq!(&raw[2..2 + trackers.len()], trackers.as_bytes());
+    CONFIG.save(&mut deps.storage, &Config::default()).unwrap();
+    assert!(deps.storage.get(namespace("CONFIG").as_bytes()).is_some());
+    assert_eq!(
+        layout.legacy_namespaces,
+        vec![
+            (format!("class_flow"), format!("class_flow_v2")),
+            (format!("flow"), format!("flow_v3")),
+            (format!("flow_v2"), format!("flow_v3")),
+        ]
+    );
+}
+
+#[test] // Tests that the quotas can be queried by their raw storage key
+fn quotas_by_raw_key() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 10_u32.into(), 10_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("ibc/ABC"),
+            quotas: vec![quota],
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let key = (
+        Addr::unchecked(BRIDGE_CONTRACT),
+        format!("channel"),
+        format!("ibc/ABC"),
+    );
+    let raw = RATE_LIMIT_TRACKERS.key(key.clone()).to_vec();
+    let msg = QueryMsg::GetQuotasRaw {
+        key_base64: Binary::from(raw.clone()),
+    };
+    let res: RawQuotasResponse =
+        from_json::<Versioned<_>>(&query(deps.as_ref(), mock_env(), msg).unwrap())
+            .unwrap()
+            .data;
+    assert_eq!(res.contract, key.0);
+    assert_eq!(res.channel_id, key.1);
+    assert_eq!(res.denom, key.2);
+    assert_eq!(
+        res.rate_limits,
+        RATE_LIMIT_TRACKERS
+            .load(&deps.storage, key)
+            .unwrap()
+            .iter()
+            .map(RateLimitResponse::from)
+            .collect::<Vec<_>>()
+    );
+
+    // Keys of other maps or truncated keys are rejected
+    let msg = QueryMsg::GetQuotasRaw {
+        key_base64: Binary::from(&raw[..12]),
+    };
+    query(deps.as_ref(), mock_env(), msg).unwrap_err();
+    let msg = QueryMsg::GetQuotasRaw {
+        key_base64: Binary::from(b"config".as_slice()),
+    };
+    query(deps.as_ref(), mock_env(), msg).unwrap_err();
+}
+
+#[test] // Tests that the stats of a day are rolled up by the first packet or tick of the next
+fn daily_rollup() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 100_u32.into(), 100_u32.into());
+    let path = |channel: &str| PathMsg {
+        contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: channel.to_string(),
+        denom: format!("denom"),
+        quotas: vec![quota.clone()],
+    };
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![path("channel1"), path("channel2")],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let send = |channel: &str, amount: u32| {
+        test_msg_send!(
+            channel_id: channel.to_string(),
+            denom: format!("denom"),
+            funds: amount.into()
+        )
+    };
+    let recv_msg = test_msg_recv!(
+        channel_id: format!("channel1"),
+        denom: format!("denom"),
+        funds: 2_u32.into()
+    );
+    let rollups = |res: &Response| {
+        res.events
+            .iter()
+            .filter(|event| event.ty == "daily_rollup")
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send("channel1", 5),
+    )
+    .unwrap();
+    assert!(rollups(&res).is_empty());
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send("channel1", 3),
+    )
+    .unwrap();
+    execute(deps.as_mut(), mock_env(), bridge.clone(), recv_msg).unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send("channel2", 1),
+    )
+    .unwrap();
+    // Sends rejected with data are counted too
+    let msg = ExecuteMsg::SetPathConfig {
+        channel_id: format!("channel1"),
+        denom: format!("denom"),
+        config: PathConfig {
+            reject_with_data: true,
+            ..PathConfig::default()
+        },
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send("channel1", 100),
+    )
+    .unwrap();
+
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(RESET_TIME_DAILY);
+    let res = execute(deps.as_mut(), env.clone(), bridge, send("channel1", 1)).unwrap();
+    let events = rollups(&res);
+    assert_eq!(events.len(), 1);
+    let day = (mock_env().block.time.seconds() / RESET_TIME_DAILY).to_string();
+    for attribute in [
+        Attribute::new("channel_id", "channel1"),
+        Attribute::new("day", day),
+        Attribute::new("outflow", "8"),
+        Attribute::new("inflow", "2"),
+        Attribute::new("packets_out", "2"),
+        Attribute::new("rejections", "1"),
+    ] {
+        assert!(events[0].attributes.contains(&attribute));
+    }
+
+    // The idle path is rolled up by the keeper
+    let tick = ExecuteMsg::Tick {
+        paths: None,
+        limit: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("anyone", &[]),
+        tick.clone(),
+    )
+    .unwrap();
+    let events = rollups(&res);
+    assert_eq!(events.len(), 1);
+    assert!(events[0]
+        .attributes
+        .contains(&Attribute::new("channel_id", "channel2")));
+    let res = execute(deps.as_mut(), env, mock_info("anyone", &[]), tick).unwrap();
+    assert!(rollups(&res).is_empty());
+}
+
+// Storage recording the keys written, to check which packets hit the trackers
+#[derive(Default)]
+struct WriteCountingStorage {
+    inner: MockStorage,
+    writes: Vec<Vec<u8>>,
+}
+
+impl Storage for WriteCountingStorage {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.inner.get(key)
+    }
+
+    fn range<'a>(
+        &'a self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = Record> + 'a> {
+        self.inner.range(start, end, order)
+    }
+
+    fn set(&mut self, key: &[u8], value: &[u8]) {
+        self.writes.push(key.to_vec());
+        self.inner.set(key, value)
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        self.writes.push(key.to_vec());
+        self.inner.remove(key)
+    }
+}
+
+#[test] // Tests that packets leaving the trackers unchanged don't rewrite them
+fn unchanged_trackers_not_saved() {
+    let mut deps = OwnedDeps {
+        storage: WriteCountingStorage::default(),
+        api: MockApi::default(),
+        querier: MockQuerier::default(),
+        custom_query_type: PhantomData::<Empty>,
+    };
+
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 100_u32.into(), 100_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let send = |amount: u32| {
+        test_msg_send!(
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            funds: amount.into()
+        )
+    };
+    let key = RATE_LIMIT_TRACKERS
+        .key((
+            Addr::unchecked(BRIDGE_CONTRACT),
+            format!("channel"),
+            format!("denom"),
+        ))
+        .to_vec();
+
+    deps.storage.writes.clear();
+    execute(deps.as_mut(), mock_env(), bridge.clone(), send(5)).unwrap();
+    let writes = std::mem::take(&mut deps.storage.writes);
+    assert!(writes.contains(&key));
+
+    execute(deps.as_mut(), mock_env(), bridge, send(0)).unwrap();
+    let zero_writes = std::mem::take(&mut deps.storage.writes);
+    assert!(!zero_writes.contains(&key));
+    // The tracker write and its change log entries are saved
+    assert_eq!(writes.len() - zero_writes.len(), 3);
+}
+
+#[test] // Tests that quotas with split durations roll each direction over on its own
+fn split_durations() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new("split", RESET_TIME_DAILY, 10_u32.into(), 10_u32.into())
+        .with_durations(RESET_TIME_DAILY, 3600);
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let send_msg = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 10_u32.into()
+    );
+    let recv_msg = test_msg_recv!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 20_u32.into()
+    );
+
+    // The receive fills its window on top of the netted send
+    execute(deps.as_mut(), mock_env(), bridge.clone(), send_msg.clone()).unwrap();
+    execute(deps.as_mut(), mock_env(), bridge.clone(), recv_msg.clone()).unwrap();
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), recv_msg.clone()).unwrap_err();
+    let ContractError::RateLimitExceded { reset, .. } = err else {
+        panic!("unexpected error {err}");
+    };
+    assert_eq!(reset, mock_env().block.time.plus_seconds(3600));
+
+    // An hour later, only the receive window started over
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(3601);
+    execute(deps.as_mut(), env.clone(), bridge.clone(), recv_msg).unwrap();
+
+    let msg = QueryMsg::GetQuotas {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+    };
+    let res = query(deps.as_ref(), env.clone(), msg).unwrap();
+    let quotas = from_json::<Versioned<Vec<RateLimitResponse>>>(&res)
+        .unwrap()
+        .data;
+    assert_eq!(
+        quotas[0].quota.send_duration_nanos,
+        RESET_TIME_DAILY * NANOS_PER_SECOND
+    );
+    assert_eq!(quotas[0].quota.recv_duration_nanos, 3600 * NANOS_PER_SECOND);
+    assert_eq!(quotas[0].flow.inflow, Uint256::from(20_u32));
+    assert_eq!(quotas[0].flow.outflow, Uint256::from(10_u32));
+    assert_eq!(
+        quotas[0].flow.period_end,
+        mock_env().block.time.plus_seconds(RESET_TIME_DAILY)
+    );
+    assert_eq!(
+        quotas[0].flow.recv_period_end,
+        env.block.time.plus_seconds(3600)
+    );
+}
+
+#[test] // Tests that a management message retried with the same idempotency key is a no-op
+fn idempotent_replay() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 10_u32.into(), 10_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota.clone()],
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let add_msg = ExecuteMsg::AddPath {
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        quotas: vec![quota],
+        idempotency_key: Some(format!("add-1")),
+    };
+    let res = execute(deps.as_mut(), mock_env(), bridge.clone(), add_msg.clone()).unwrap();
+    assert!(!res.attributes.iter().any(|a| a.key == "idempotent_replay"));
+
+    let send_msg = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 3_u32.into()
+    );
+    execute(deps.as_mut(), mock_env(), bridge.clone(), send_msg).unwrap();
+
+    // The retry doesn't re-add the path, so the flow is kept
+    let res = execute(deps.as_mut(), mock_env(), bridge.clone(), add_msg).unwrap();
+    assert!(res
+        .attributes
+        .iter()
+        .any(|a| a.key == "idempotent_replay" && a.value == "add-1"));
+
+    let msg = QueryMsg::GetQuotas {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+    };
+    let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+    let quotas = from_json::<Versioned<Vec<RateLimitResponse>>>(&res)
+        .unwrap()
+        .data;
+    assert_eq!(quotas[0].flow.outflow, Uint256::from(3_u32));
+
+    // The key is scoped to its sender
+    let add_msg = ExecuteMsg::AddPath {
+        channel_id: format!("channel2"),
+        denom: format!("denom"),
+        quotas: vec![],
+        idempotency_key: Some(format!("add-1")),
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), add_msg).unwrap();
+    assert!(!res.attributes.iter().any(|a| a.key == "idempotent_replay"));
+}
+
+#[test] // Tests that packets sent to registered contracts are also accounted on the receiver class quotas
+fn receiver_class_quotas() {
+    let mut deps = mock_dependencies();
+
+    let quota = |max: u32| QuotaMsg::new("weekly", RESET_TIME_WEEKLY, max.into(), max.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota(100)],
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let msg = ExecuteMsg::SetReceiverClassQuotas {
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        class: ReceiverClass::Contract,
+        quotas: vec![quota(10)],
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+
+    // Only the owner registers receivers
+    let register = ExecuteMsg::SetReceiverClass {
+        address: format!("osmo1contract"),
+        class: ReceiverClass::Contract,
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), register.clone()).unwrap_err();
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), register).unwrap();
+
+    let msg = QueryMsg::GetReceiverClass {
+        address: format!("osmo1contract"),
+    };
+    let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+    assert_eq!(
+        from_json::<Versioned<ReceiverClass>>(&res).unwrap().data,
+        ReceiverClass::Contract
+    );
+    let msg = QueryMsg::GetReceiverClass {
+        address: format!("osmo1user"),
+    };
+    let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+    assert_eq!(
+        from_json::<Versioned<ReceiverClass>>(&res).unwrap().data,
+        ReceiverClass::Account
+    );
+
+    let send = |funds: u32, receiver: &str| ExecuteMsg::SendPacket {
+        packet: Packet::mock(format!("channel"), format!("denom"), funds.into())
+            .with_receiver(receiver),
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send(11, "osmo1contract"),
+    )
+    .unwrap_err();
+    assert!(
+        matches!(err, ContractError::RateLimitExceded { max, .. } if max == Uint256::from(10_u128))
+    );
+
+    // Accounts only use the path quotas
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send(50, "osmo1user"),
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send(10, "osmo1contract"),
+    )
+    .unwrap();
+
+    let msg = QueryMsg::GetReceiverClassQuotas {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        class: ReceiverClass::Contract,
+    };
+    let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+    let value: Vec<RateLimitResponse> = from_json::<Versioned<_>>(&res).unwrap().data;
+    assert_eq!(value[0].flow.outflow, Uint256::from(10_u128));
+}
+
+#[test] // Tests that a migration left in maintenance rejects packets, but not its continuation, until the owner clears it
+fn maintenance_after_migration() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 100_u32.into(), 100_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let send_msg = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 10_u32.into()
+    );
+    execute(deps.as_mut(), mock_env(), bridge.clone(), send_msg.clone()).unwrap();
+
+    // A regular migration leaves maintenance when done
+    migrate(deps.as_mut(), mock_env(), MigrateMsg::default()).unwrap();
+    execute(deps.as_mut(), mock_env(), bridge.clone(), send_msg.clone()).unwrap();
+
+    let msg = MigrateMsg {
+        keep_maintenance: true,
+    };
+    migrate(deps.as_mut(), mock_env(), msg).unwrap();
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), send_msg.clone()).unwrap_err();
+    assert_eq!(err, ContractError::Maintenance {});
+    // Continuing the migration stays open to anyone
+    let msg = ExecuteMsg::ContinueMigration { limit: None };
+    execute(deps.as_mut(), mock_env(), mock_info("anyone", &[]), msg).unwrap();
+
+    // Queries keep serving the state
+    let msg = QueryMsg::GetQuotas {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+    };
+    let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+    let quotas = from_json::<Versioned<Vec<RateLimitResponse>>>(&res)
+        .unwrap()
+        .data;
+    assert_eq!(quotas[0].flow.outflow, Uint256::from(20_u32));
+
+    let msg = ExecuteMsg::SetMaintenance { enabled: false };
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), msg.clone()).unwrap_err();
+    assert_eq!(err, ContractError::Maintenance {});
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+    execute(deps.as_mut(), mock_env(), bridge, send_msg).unwrap();
+}
+
+#[test] // Tests that migrations too large for one transaction are continued in batches
+fn chunked_migration() {
+    let mut deps = mock_dependencies();
+
+    let msg = InstantiateMsg {
+        paths: vec![],
+        key_spec: None,
+        seed_flows: vec![],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let period_end = mock_env().block.time.plus_seconds(RESET_TIME_WEEKLY);
+    let stored = format!(
+        r#"[{{"quota":{{"name":"weekly","max_send":"100","max_recv":"100","duration":{RESET_TIME_WEEKLY}}},"flow":{{"inflow":"0","outflow":"0","period_end":"{}"}},"last_reset":null}}]"#,
+        period_end.nanos()
+    );
+    let legacy: Map<PathKey, Vec<RateLimit>> = Map::new("flow");
+    for i in 0..105 {
+        let key = (
+            Addr::unchecked(BRIDGE_CONTRACT),
+            format!("channel-{i:03}"),
+            "denom".to_string(),
+        );
+        deps.storage.set(&legacy.key(key), stored.as_bytes());
+    }
+
+    let res = migrate(deps.as_mut(), mock_env(), MigrateMsg::default()).unwrap();
+    assert!(res
+        .attributes
+        .contains(&Attribute::new("moved_entries", "100")));
+    assert!(res.attributes.contains(&Attribute::new("done", "false")));
+
+    // Packets on the paths left behind wait for the migration
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let send = |channel: &str| {
+        test_msg_send!(
+            channel_id: channel.to_string(),
+            denom: format!("denom"),
+            funds: 1_u32.into()
+        )
+    };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send("channel-000"),
+    )
+    .unwrap();
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send("channel-104"),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::PathNotMigrated {
+            channel_id: format!("channel-104"),
+            denom: format!("denom"),
+        }
+    );
+
+    let msg = ExecuteMsg::ContinueMigration { limit: Some(2) };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("anyone", &[]), msg).unwrap();
+    assert!(res
+        .attributes
+        .contains(&Attribute::new("moved_entries", "2")));
+    assert!(res.attributes.contains(&Attribute::new("done", "false")));
+
+    let msg = ExecuteMsg::ContinueMigration { limit: None };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("anyone", &[]), msg).unwrap();
+    assert!(res
+        .attributes
+        .contains(&Attribute::new("moved_entries", "3")));
+    assert!(res.attributes.contains(&Attribute::new("done", "true")));
+
+    execute(deps.as_mut(), mock_env(), bridge, send("channel-104")).unwrap();
+    let moved = RATE_LIMIT_TRACKERS
+        .keys(&deps.storage, None, None, Order::Ascending)
+        .count();
+    assert_eq!(moved, 105);
+}
+
+#[test] // Tests that the diagnose query reports redundant and unlimited quotas and unconfigured paths
+fn diagnose_path() {
+    let mut deps = mock_dependencies();
+
+    let unlimited = Uint256::from(Uint128::MAX);
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![
+                QuotaMsg::new("daily", RESET_TIME_DAILY, 10_u32.into(), 10_u32.into()),
+                QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 10_u32.into(), 10_u32.into()),
+                QuotaMsg::new("open", RESET_TIME_DAILY, unlimited, unlimited),
+            ],
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let diagnose = |deps: Deps, denom: &str| {
+        let msg = QueryMsg::Diagnose {
+            contract: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: denom.to_string(),
+        };
+        let res = query(deps, mock_env(), msg).unwrap();
+        from_json::<Versioned<DiagnoseResponse>>(&res)
+            .unwrap()
+            .data
+            .diagnostics
+    };
+    let codes = |deps: Deps, denom: &str| {
+        diagnose(deps, denom)
+            .into_iter()
+            .map(|d| (d.code, d.quota))
+            .collect::<Vec<_>>()
+    };
+
+    // The daily quota can't run out before the weekly one, nor can the open
+    // one, which isn't capped anyway
+    assert_eq!(
+        codes(deps.as_ref(), "denom"),
+        vec![
+            (format!("unlimited"), Some(format!("open"))),
+            (format!("redundant"), Some(format!("daily"))),
+            (format!("redundant"), Some(format!("open"))),
+        ]
+    );
+
+    assert_eq!(
+        codes(deps.as_ref(), "other"),
+        vec![(format!("no_quotas"), None)]
+    );
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let packet = Packet::mock(format!("channel"), format!("other"), 1_u32.into()).with_sequence(1);
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge,
+        ExecuteMsg::SendPacket { packet },
+    )
+    .unwrap();
+    let diagnostics = diagnose(deps.as_ref(), "other");
+    assert_eq!(diagnostics[0].code, "missing_path");
+    assert_eq!(diagnostics[0].severity, Severity::Warning);
+}
+
+#[test] // Tests that channels track a limited number of denoms until their stats are pruned
+fn max_denoms_per_channel() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 100_u32.into(), 100_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let msg = ExecuteMsg::UpdateConfig {
+        config: Config {
+            max_denoms_per_channel: Some(2),
+            ..Config::default()
+        },
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let send = |denom: &str| {
+        test_msg_send!(
+            channel_id: format!("channel"),
+            denom: denom.to_string(),
+            funds: 1_u32.into()
+        )
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), send("denom")).unwrap();
+    execute(deps.as_mut(), mock_env(), bridge.clone(), send("garbage")).unwrap();
+    let tracked = |deps: Deps, denom: &str| {
+        let key = (
+            Addr::unchecked(BRIDGE_CONTRACT),
+            format!("channel"),
+            denom.to_string(),
+        );
+        DAILY_STATS.has(deps.storage, key)
+    };
+    // Denoms past the cap go through untracked
+    execute(deps.as_mut(), mock_env(), bridge.clone(), send("other")).unwrap();
+    assert!(!tracked(deps.as_ref(), "other"));
+
+    // Denoms already tracked keep being tracked
+    execute(deps.as_mut(), mock_env(), bridge.clone(), send("garbage")).unwrap();
+    assert!(tracked(deps.as_ref(), "garbage"));
+
+    // Pruning keeps the denoms with quotas
+    let msg = ExecuteMsg::PruneChannelDenoms {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        limit: None,
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg.clone()).unwrap_err();
+    let res = execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+    assert!(res.attributes.contains(&Attribute::new("pruned", "1")));
+
+    execute(deps.as_mut(), mock_env(), bridge, send("other")).unwrap();
+    assert!(tracked(deps.as_ref(), "other"));
+}
+
+#[test] // Tests that quotas can have periods shorter than a second
+fn sub_second_duration() {
+    let mut deps = mock_dependencies();
+
+    let quota =
+        QuotaMsg::new("burst", 0, 10_u32.into(), 10_u32.into()).with_duration_nanos(500_000_000);
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let send_msg = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 10_u32.into()
+    );
+    execute(deps.as_mut(), mock_env(), bridge.clone(), send_msg.clone()).unwrap();
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), send_msg.clone()).unwrap_err();
+    let ContractError::RateLimitExceded { reset, .. } = err else {
+        panic!("unexpected error {err}");
+    };
+    assert_eq!(reset, mock_env().block.time.plus_nanos(500_000_000));
+
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_nanos(600_000_000);
+    execute(deps.as_mut(), env, bridge, send_msg).unwrap();
+}
+
+#[test] // Tests that sends rejected on paths with an on_reject_contract notify it instead of failing
+fn on_reject_contract() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 10_u32.into(), 10_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let set_config = |reject_with_data| ExecuteMsg::SetPathConfig {
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        config: PathConfig {
+            reject_with_data,
+            on_reject_contract: Some(Addr::unchecked("hook")),
+            ..PathConfig::default()
+        },
+        idempotency_key: None,
+    };
+    // The hook can't be notified of rejections answered with an error
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), set_config(false)).unwrap_err();
+    assert!(matches!(err, ContractError::RejectHookWithoutData {}));
+    execute(deps.as_mut(), mock_env(), bridge.clone(), set_config(true)).unwrap();
+
+    let send_msg = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 11_u32.into()
+    );
+    let res = execute(deps.as_mut(), mock_env(), bridge.clone(), send_msg.clone()).unwrap();
+    assert_eq!(
+        from_json::<TransferData>(&res.data.unwrap()).unwrap(),
+        TransferData {
+            accepted: Uint128::zero(),
+            remainder: 11_u32.into(),
+            fee: Uint128::zero(),
+        }
+    );
+    // The notification is fire-and-forget
+    assert_eq!(res.messages[0].reply_on, ReplyOn::Error);
+    assert_eq!(res.messages[0].id, REJECT_HOOK_REPLY_ID);
+    let CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr, msg, ..
+    }) = &res.messages[0].msg
+    else {
+        panic!("unexpected message {:?}", res.messages[0]);
+    };
+    assert_eq!(contract_addr, "hook");
+    let RejectHookMsg::RateLimitRejected { packet, exceeded } = from_json(msg).unwrap();
+    assert_eq!(packet.amount, Uint128::from(11_u32));
+    assert_eq!(exceeded.quota_name, "weekly");
+
+    let failed = Reply {
+        id: REJECT_HOOK_REPLY_ID,
+        result: SubMsgResult::Err(format!("hook failed")),
+    };
+    let res = reply(deps.as_mut(), mock_env(), failed).unwrap();
+    assert!(res
+        .attributes
+        .contains(&Attribute::new("error", "hook failed")));
+
+    // Nothing was accounted
+    let msg = QueryMsg::GetQuotas {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+    };
+    let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+    let quotas = from_json::<Versioned<Vec<RateLimitResponse>>>(&res)
+        .unwrap()
+        .data;
+    assert_eq!(quotas[0].flow.outflow, Uint256::zero());
+    // But the rejection is counted in the stats of the period
+    let key: PathKey = (
+        Addr::unchecked(BRIDGE_CONTRACT),
+        format!("channel"),
+        format!("denom"),
+    );
+    let trackers = RATE_LIMIT_TRACKERS.load(&deps.storage, key).unwrap();
+    assert_eq!(trackers[0].flow.stats.rejections, 1);
+
+    // Receives keep failing
+    let recv_msg = test_msg_recv!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 11_u32.into()
+    );
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), recv_msg).unwrap_err();
+    assert!(matches!(err, ContractError::RateLimitExceded { .. }));
+
+    // A rejection doesn't archive the expired period, so the send accepted
+    // after it archives it once
+    let history = |deps: &OwnedDeps<_, _, _>| {
+        let msg = QueryMsg::GetHistory {
+            contract: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+        };
+        let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+        from_json::<Versioned<Vec<HistoryEntry>>>(&res)
+            .unwrap()
+            .data
+    };
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(RESET_TIME_WEEKLY + 1);
+    execute(deps.as_mut(), env.clone(), bridge.clone(), send_msg).unwrap();
+    assert!(history(&deps).is_empty());
+    let send_msg = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 1_u32.into()
+    );
+    execute(deps.as_mut(), env, bridge, send_msg).unwrap();
+    assert_eq!(history(&deps).len(), 1);
+}
+
+#[test] // Tests that the average flow spreads the archived periods over the time they cover
+fn average_flow() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new("daily", RESET_TIME_DAILY, 100_u32.into(), 100_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let send = |funds: u32| {
+        test_msg_send!(
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            funds: funds.into()
+        )
+    };
+    let at = |days: u64| {
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(RESET_TIME_DAILY * days);
+        env
+    };
+    // Two periods over four days, the traffic stopped in between
+    execute(deps.as_mut(), at(0), bridge.clone(), send(30)).unwrap();
+    execute(deps.as_mut(), at(3), bridge.clone(), send(20)).unwrap();
+    execute(deps.as_mut(), at(5), bridge, send(1)).unwrap();
+
+    let average = |lookback_periods: Option<u32>| {
+        let msg = QueryMsg::GetAverageFlow {
+            path: PathId {
+                contract: Addr::unchecked(BRIDGE_CONTRACT),
+                channel_id: format!("channel"),
+                denom: format!("denom"),
+            },
+            lookback_periods,
+        };
+        let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+        from_json::<Versioned<Vec<AverageFlow>>>(&res).unwrap().data
+    };
+    assert_eq!(
+        average(None),
+        vec![AverageFlow {
+            quota: format!("daily"),
+            periods: 2,
+            inflow: Uint256::zero(),
+            outflow: Uint256::from(12_u32),
+        }]
+    );
+    assert_eq!(average(Some(1))[0].outflow, Uint256::from(20_u32));
+}
+
+#[test] // Tests that a pre-approved transfer skips the quotas exactly once
+fn pre_approved_transfer() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 10_u32.into(), 10_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let path = PathId {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+    };
+    let pre_approve = |window: u64, approver_role: ApproverRole| ExecuteMsg::PreApproveTransfer {
+        path: path.clone(),
+        amount: 500_u32.into(),
+        window,
+        approver_role,
+    };
+    let send = |funds: u32| {
+        test_msg_send!(
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            funds: funds.into()
+        )
+    };
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+
+    // Only the owner, or the multisig owners once there's a multisig
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        pre_approve(60, ApproverRole::Owner),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::Ownership(_)));
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(OWNER, &[]),
+        pre_approve(60, ApproverRole::Multisig),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(OWNER, &[]),
+        pre_approve(u64::MAX, ApproverRole::Owner),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::PreApprovalWindowTooLong {
+            window: u64::MAX,
+            max: MAX_PRE_APPROVAL_WINDOW,
+        }
+    );
+
+    // The first approval expires unused
+    let owner = mock_info(OWNER, &[]);
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        owner.clone(),
+        pre_approve(60, ApproverRole::Owner),
+    )
+    .unwrap();
+    let mut later = mock_env();
+    later.block.time = later.block.time.plus_seconds(61);
+    let err = execute(deps.as_mut(), later.clone(), bridge.clone(), send(500)).unwrap_err();
+    assert!(matches!(err, ContractError::RateLimitExceded { .. }));
+
+    execute(
+        deps.as_mut(),
+        later.clone(),
+        owner,
+        pre_approve(60, ApproverRole::Owner),
+    )
+    .unwrap();
+    // Other amounts go through the quotas
+    let err = execute(deps.as_mut(), later.clone(), bridge.clone(), send(499)).unwrap_err();
+    assert!(matches!(err, ContractError::RateLimitExceded { .. }));
+    let res = execute(deps.as_mut(), later.clone(), bridge.clone(), send(500)).unwrap();
+    assert!(res
+        .attributes
+        .iter()
+        .any(|attr| attr.key == "pre_approval" && attr.value == "1"));
+    // Once
+    let err = execute(deps.as_mut(), later.clone(), bridge, send(500)).unwrap_err();
+    assert!(matches!(err, ContractError::RateLimitExceded { .. }));
+
+    let msg = QueryMsg::ListPreApprovals {
+        path: path.clone(),
+        start_after: None,
+        limit: None,
+    };
+    let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+    let approvals = from_json::<Versioned<Vec<PreApproval>>>(&res).unwrap().data;
+    assert_eq!(approvals.len(), 2);
+    assert_eq!(approvals[0].consumed_at, None);
+    assert_eq!(approvals[1].consumed_at, Some(later.block.time));
+    assert_eq!(approvals[1].approver, Addr::unchecked(OWNER));
+
+    // The quotas weren't charged
+    let msg = QueryMsg::GetQuotas {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+    };
+    let res = query(deps.as_ref(), mock_env(), msg.clone()).unwrap();
+    let quotas = from_json::<Versioned<Vec<RateLimitResponse>>>(&res)
+        .unwrap()
+        .data;
+    assert_eq!(quotas[0].flow.outflow, Uint256::zero());
+
+    // Undoing a pre-approved send leaves the quotas charged by the others
+    let sequenced = |funds: u32, sequence: u64| ExecuteMsg::SendPacket {
+        packet: Packet::mock(format!("channel"), format!("denom"), funds.into())
+            .with_channel_value(10_000_u32.into())
+            .with_sequence(sequence),
+    };
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    execute(
+        deps.as_mut(),
+        later.clone(),
+        bridge.clone(),
+        sequenced(300, 1),
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        later.clone(),
+        mock_info(OWNER, &[]),
+        pre_approve(60, ApproverRole::Owner),
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        later.clone(),
+        bridge.clone(),
+        sequenced(500, 2),
+    )
+    .unwrap();
+    let undo = test_msg_undo!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 500_u32.into(),
+        sequence: 2
+    );
+    execute(deps.as_mut(), later.clone(), bridge, undo).unwrap();
+    let res = query(deps.as_ref(), later.clone(), msg).unwrap();
+    let quotas = from_json::<Versioned<Vec<RateLimitResponse>>>(&res)
+        .unwrap()
+        .data;
+    assert_eq!(quotas[0].flow.outflow, Uint256::from(300_u32));
+
+    // The multisig approves through its proposals, which the contract executes,
+    // not through any of its owners
+    let msg = ExecuteMsg::SetMultisig {
+        owners: vec![format!("alice"), format!("bob")],
+        threshold: 2,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[]),
+        pre_approve(60, ApproverRole::Multisig),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(MOCK_CONTRACT_ADDR, &[]),
+        pre_approve(60, ApproverRole::Multisig),
+    )
+    .unwrap();
+}
+
+#[test] // Tests that the overview gathers the quotas, usage and status of a path
+fn path_overview() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 10_u32.into(), 20_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let send_msg = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 5_u32.into()
+    );
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    execute(deps.as_mut(), mock_env(), bridge, send_msg).unwrap();
+    let msg = ExecuteMsg::SetDirectionPaused {
+        direction: FlowType::In,
+        paused: true,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let msg = QueryMsg::GetPathOverview {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+    };
+    let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+    let overview = from_json::<Versioned<PathOverviewResponse>>(&res)
+        .unwrap()
+        .data;
+    assert_eq!(overview.quotas.len(), 1);
+    let quota = &overview.quotas[0];
+    assert_eq!(quota.rate_limit.quota.name, "weekly");
+    assert_eq!(quota.usage.outflow, Uint256::from(5_u32));
+    assert_eq!(quota.send_utilization_bps, 5_000);
+    assert_eq!(quota.recv_utilization_bps, 0);
+    assert!(!overview.paused);
+    assert_eq!(overview.paused_directions, vec![FlowType::In]);
+    assert!(!overview.quarantined);
+    assert_eq!(overview.config, PathConfig::default());
+    let last_packet = LastPacket {
+        direction: FlowType::Out,
+        amount: Uint128::new(5),
+        timestamp: mock_env().block.time,
+        sequence: None,
+    };
+    assert_eq!(overview.last_packet, Some(last_packet.clone()));
+
+    // The last packet outlives the day it was sent on
+    let mut later = mock_env();
+    later.block.time = later.block.time.plus_seconds(RESET_TIME_DAILY * 2);
+    let msg = ExecuteMsg::Tick {
+        paths: None,
+        limit: None,
+    };
+    execute(deps.as_mut(), later.clone(), mock_info("anyone", &[]), msg).unwrap();
+    let msg = QueryMsg::GetPathOverview {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+    };
+    let res = query(deps.as_ref(), later, msg).unwrap();
+    let overview = from_json::<Versioned<PathOverviewResponse>>(&res)
+        .unwrap()
+        .data;
+    assert_eq!(overview.last_packet, Some(last_packet));
+}
+
+#[test] // Tests that packets within the grace before the end of a period count in the next one
+fn expiry_grace() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new("daily", RESET_TIME_DAILY, 10_u32.into(), 10_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let update_config = |grace_nanos: u64| ExecuteMsg::UpdateConfig {
+        config: Config {
+            grace_nanos,
+            ..Config::default()
+        },
+        idempotency_key: None,
+    };
+    let owner = mock_info(OWNER, &[]);
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        owner.clone(),
+        update_config(61 * NANOS_PER_SECOND),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::GraceTooLong { .. }));
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        owner,
+        update_config(2 * NANOS_PER_SECOND),
+    )
+    .unwrap();
+
+    let send = |funds: u32| {
+        test_msg_send!(
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            funds: funds.into()
+        )
+    };
+    let at = |seconds: u64| {
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(seconds);
+        env
+    };
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    execute(deps.as_mut(), at(0), bridge.clone(), send(10)).unwrap();
+
+    // Before the grace, the period is still full
+    let err = execute(
+        deps.as_mut(),
+        at(RESET_TIME_DAILY - 3),
+        bridge.clone(),
+        send(1),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::RateLimitExceded { .. }));
+
+    // A second before the end, the packet opens the next period
+    execute(deps.as_mut(), at(RESET_TIME_DAILY - 1), bridge, send(10)).unwrap();
+    let msg = QueryMsg::GetQuotas {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+    };
+    let res = query(deps.as_ref(), at(RESET_TIME_DAILY - 1), msg).unwrap();
+    let quotas = from_json::<Versioned<Vec<RateLimitResponse>>>(&res)
+        .unwrap()
+        .data;
+    assert_eq!(quotas[0].flow.outflow, Uint256::from(10_u32));
+    assert_eq!(
+        quotas[0].flow.period_end,
+        at(2 * RESET_TIME_DAILY - 1).block.time
+    );
+}
+
+#[test] // Tests that the rejections answered through an on_reject_contract are kept, newest first
+fn recent_rejections() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 10_u32.into(), 10_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let msg = ExecuteMsg::SetPathConfig {
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        config: PathConfig {
+            reject_with_data: true,
+            on_reject_contract: Some(Addr::unchecked("hook")),
+            ..PathConfig::default()
+        },
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+
+    for funds in 11_u32..11 + REJECTIONS_LENGTH as u32 + 2 {
+        let msg = ExecuteMsg::SendPacket {
+            packet: Packet::mock(format!("channel"), format!("denom"), funds.into())
+                .with_sender("alice"),
+        };
+        execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+    }
+
+    let rejections = |limit: Option<u32>| {
+        let msg = QueryMsg::GetRecentRejections {
+            path: PathId {
+                contract: Addr::unchecked(BRIDGE_CONTRACT),
+                channel_id: format!("channel"),
+                denom: format!("denom"),
+            },
+            limit,
+        };
+        let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+        from_json::<Versioned<Vec<Rejection>>>(&res).unwrap().data
+    };
+    let all = rejections(None);
+    assert_eq!(all.len(), REJECTIONS_LENGTH);
+    assert_eq!(
+        all[0],
+        Rejection {
+            at: mock_env().block.time,
+            amount: Uint128::from(12_u32 + REJECTIONS_LENGTH as u32),
+            direction: FlowType::Out,
+            quota: format!("weekly"),
+            sender: Some(format!("alice")),
+        }
+    );
+    // The oldest ones were dropped
+    assert_eq!(all[REJECTIONS_LENGTH - 1].amount, Uint128::from(13_u32));
+    assert_eq!(rejections(Some(2)).len(), 2);
+}
+
+#[test] // Tests that renaming a quota keeps its flow and is audited
+fn rename_quota() {
+    let mut deps = mock_dependencies();
+
+    let quotas = vec![
+        QuotaMsg::new("daily", RESET_TIME_DAILY, 100_u32.into(), 100_u32.into()),
+        QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 100_u32.into(), 100_u32.into()),
+    ];
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas,
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let sent = Packet::mock(format!("channel"), format!("denom"), 30_u32.into()).with_sequence(1);
+    let send_msg = ExecuteMsg::SendPacket {
+        packet: sent.clone(),
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), send_msg).unwrap();
+
+    let rename = |from: &str, to: &str| ExecuteMsg::RenameQuota {
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        from: from.to_string(),
+        to: to.to_string(),
+        idempotency_key: None,
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        rename("weekly", "daily"),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::QuotaAlreadyExists { .. }));
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        rename("monthly", "30d"),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::QuotaNotFound { .. }));
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        rename("weekly", ""),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::EmptyQuotaName {});
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        rename("weekly", "7d"),
+    )
+    .unwrap();
+
+    let msg = QueryMsg::GetQuotas {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+    };
+    let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+    let quotas = from_json::<Versioned<Vec<RateLimitResponse>>>(&res)
+        .unwrap()
+        .data;
+    assert_eq!(quotas[1].quota.name, "7d");
+    assert_eq!(quotas[1].flow.outflow, Uint256::from(30_u32));
+
+    let msg = QueryMsg::GetAuditLog {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        start_after: None,
+        limit: None,
+    };
+    let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+    let log = from_json::<Versioned<Vec<AuditEntry>>>(&res).unwrap().data;
+    let last = log.last().unwrap();
+    assert_eq!(last.action, AuditAction::RenameQuota);
+    assert_eq!(last.memo, Some(format!("weekly -> 7d")));
+
+    // The pending send is undone from the renamed quota too
+    let msg = ExecuteMsg::UndoSend { packet: sent };
+    let res = execute(deps.as_mut(), mock_env(), bridge, msg).unwrap();
+    assert!(!res.events.iter().any(|event| event.ty == "undo_skipped"));
+    let msg = QueryMsg::GetQuotas {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+    };
+    let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+    let quotas = from_json::<Versioned<Vec<RateLimitResponse>>>(&res)
+        .unwrap()
+        .data;
+    assert_eq!(quotas[1].flow.outflow, Uint256::zero());
+}
+
+// Response attribute keys of version 1 of the attribute schema, by message.
+// Changing any of them requires a new ATTRIBUTE_SCHEMA_VERSION
+const PATH_KEYS: &[&str] = &[
+    "method",
+    "contract",
+    "channel_id",
+    "denom",
+    "attribute_schema_version",
+];
+const PACKET_KEYS: &[&str] = &[
+    "method",
+    "channel_id",
+    "denom",
+    "weekly_used_in",
+    "weekly_used_out",
+    "weekly_max_in",
+    "weekly_max_out",
+    "weekly_period_end",
+    "attribute_schema_version",
+];
+const ATTRIBUTE_LAYOUTS_V1: &[(&str, &[&str])] = &[
+    ("add_path", PATH_KEYS),
+    ("send_packet", PACKET_KEYS),
+    ("recv_packet", PACKET_KEYS),
+    ("undo_send", PATH_KEYS),
+    (
+        "reset_path_quota",
+        &[
+            "method",
+            "contract",
+            "denom",
+            "channel_id",
+            "memo",
+            "attribute_schema_version",
+        ],
+    ),
+    (
+        "set_path_config",
+        &[
+            "method",
+            "contract",
+            "channel_id",
+            "denom",
+            "escrow",
+            "attribute_schema_version",
+        ],
+    ),
+    (
+        "remove_path",
+        &[
+            "method",
+            "contract",
+            "denom",
+            "channel_id",
+            "attribute_schema_version",
+        ],
+    ),
+    ("sudo_add_path", PATH_KEYS),
+];
+
+// The debug_math attributes aren't part of the schema
+#[cfg(not(feature = "debug_math"))]
+#[test] // Snapshots the attribute layout of each message for the current schema version
+fn attribute_layouts() {
+    let mut deps = mock_dependencies();
+    instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(OWNER, &[]),
+        InstantiateMsg {
+            paths: vec![],
+            key_spec: None,
+            seed_flows: vec![],
+        },
+    )
+    .unwrap();
+
+    let quotas = vec![QuotaMsg::new(
+        "weekly",
+        RESET_TIME_WEEKLY,
+        100_u32.into(),
+        100_u32.into(),
+    )];
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let mut execute =
+        |msg: ExecuteMsg| execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+    let responses = vec![
+        (
+            "add_path",
+            execute(ExecuteMsg::AddPath {
+                channel_id: format!("channel"),
+                denom: format!("denom"),
+                quotas: quotas.clone(),
+                idempotency_key: None,
+            }),
+        ),
+        (
+            "send_packet",
+            execute(test_msg_send!(
+                channel_id: format!("channel"),
+                denom: format!("denom"),
+                funds: 10_u32.into(),
+                sequence: 1
+            )),
+        ),
+        (
+            "recv_packet",
+            execute(test_msg_recv!(
+                channel_id: format!("channel"),
+                denom: format!("denom"),
+                funds: 10_u32.into()
+            )),
+        ),
+        (
+            "undo_send",
+            execute(test_msg_undo!(
+                channel_id: format!("channel"),
+                denom: format!("denom"),
+                funds: 10_u32.into(),
+                sequence: 1
+            )),
+        ),
+        (
+            "reset_path_quota",
+            execute(ExecuteMsg::ResetPathQuota {
+                channel_id: format!("channel"),
+                denom: format!("denom"),
+                quota_id: format!("weekly"),
+                memo: None,
+                mode: ResetMode::Full,
+                idempotency_key: None,
+            }),
+        ),
+        (
+            "set_path_config",
+            execute(ExecuteMsg::SetPathConfig {
+                channel_id: format!("channel"),
+                denom: format!("denom"),
+                config: PathConfig::default(),
+                idempotency_key: None,
+            }),
+        ),
+        (
+            "remove_path",
+            execute(ExecuteMsg::RemovePath {
+                channel_id: format!("channel"),
+                denom: format!("denom"),
+                idempotency_key: None,
+            }),
+        ),
+    ];
+    let sudo_msg = SudoMsg::AddPath {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        quotas,
+    };
+    let sudo_response = sudo(deps.as_mut(), mock_env(), sudo_msg).unwrap();
+
+    let layouts: Vec<(&str, Vec<String>)> = responses
+        .into_iter()
+        .chain([("sudo_add_path", sudo_response)])
+        .map(|(name, res)| {
+            let keys = res.attributes.into_iter().map(|attr| attr.key).collect();
+            (name, keys)
+        })
+        .collect();
+    let expected: Vec<(&str, Vec<String>)> = ATTRIBUTE_LAYOUTS_V1
+        .iter()
+        .map(|(name, keys)| (*name, keys.iter().map(|key| key.to_string()).collect()))
+        .collect();
+    assert_eq!(layouts, expected);
+}
+
+#[test] // Tests that only known attribute schema versions can be pinned
+fn attribute_schema_version() {
+    let mut deps = mock_dependencies();
+    instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(OWNER, &[]),
+        InstantiateMsg {
+            paths: vec![],
+            key_spec: None,
+            seed_flows: vec![],
+        },
+    )
+    .unwrap();
+
+    let update_config = |version: u32| ExecuteMsg::UpdateConfig {
+        config: Config {
+            attribute_schema_version: Some(version),
+            ..Config::default()
+        },
+        idempotency_key: None,
+    };
+    let owner = mock_info(OWNER, &[]);
+    let err = execute(deps.as_mut(), mock_env(), owner.clone(), update_config(2)).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::UnsupportedAttributeSchema {
+            version: 2,
+            latest: 1
+        }
+    );
+    let res = execute(deps.as_mut(), mock_env(), owner, update_config(1)).unwrap();
+    let last = res.attributes.last().unwrap();
+    assert_eq!(last.key, "attribute_schema_version");
+    assert_eq!(last.value, "1");
+}
+
+#[test] // Tests that paths keyed by denom only share their limits across channels
+fn denom_key_spec() {
+    let mut deps = mock_dependencies();
+    let key_spec = KeySpec {
+        dimensions: vec![KeyDimension::Denom],
+    };
+    let quota = QuotaMsg::new(
+        "weekly",
+        RESET_TIME_WEEKLY,
+        Uint256::from(1000_u32),
+        Uint256::from(1000_u32),
+    );
+    let msg = InstantiateMsg {
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel1"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+        key_spec: Some(key_spec.clone()),
+        seed_flows: vec![],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::GetKeySpec {}).unwrap();
+    assert_eq!(
+        from_json::<Versioned<KeySpec>>(&res).unwrap().data,
+        key_spec
+    );
+
+    // The path is stored with a wildcard channel
+    let query_msg = QueryMsg::GetQuotas {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: WILDCARD.to_string(),
+        denom: format!("denom"),
+    };
+    let res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
+    let quotas: Versioned<Vec<RateLimitResponse>> = from_json(&res).unwrap();
+    assert_eq!(quotas.data.len(), 1);
+
+    let info = mock_info(BRIDGE_CONTRACT, &[]);
+    for channel in ["channel1", "channel2"] {
+        let msg = test_msg_send!(
+            channel_id: format!("{channel}"),
+            denom: format!("denom"),
+            funds: 400_u32.into()
+        );
+        execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+    }
+    let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+    let quotas: Versioned<Vec<RateLimitResponse>> = from_json(&res).unwrap();
+    assert_eq!(quotas.data[0].flow.outflow, Uint256::from(800_u32));
+
+    // A third channel still draws from the same quota
+    let msg = test_msg_send!(
+        channel_id: format!("channel3"),
+        denom: format!("denom"),
+        funds: 400_u32.into()
+    );
+    let err = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+    assert!(matches!(err, ContractError::RateLimitExceded { .. }));
+
+    // Channel defaults are keyed like the paths, so all the channels inherit them
+    let msg = ExecuteMsg::SetChannelDefaultQuotas {
+        channel_id: format!("channel1"),
+        quotas: vec![QuotaMsg::new(
+            "weekly",
+            RESET_TIME_WEEKLY,
+            Uint256::from(100_u32),
+            Uint256::from(100_u32),
+        )],
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+    let msg = test_msg_send!(
+        channel_id: format!("channel2"),
+        denom: format!("other"),
+        funds: 101_u32.into()
+    );
+    let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+    assert!(matches!(err, ContractError::RateLimitExceded { .. }));
+
+    // So are the escrow balances reported for them
+    let msg = ExecuteMsg::UpdateConfig {
+        config: Config {
+            escrow_querier: Some(Addr::unchecked("querier")),
+            ..Config::default()
+        },
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+    let callback = ExecuteMsg::EscrowBalanceCallback {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel1"),
+        denom: format!("denom"),
+        balance: 5000_u32.into(),
+    };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("querier", &[]),
+        callback,
+    )
+    .unwrap();
+    let key: PathKey = (
+        Addr::unchecked(BRIDGE_CONTRACT),
+        WILDCARD.to_string(),
+        format!("denom"),
+    );
+    assert!(ESCROW_VALUES.has(&deps.storage, key));
+}
+
+#[test] // Tests that messages are keyed by the key spec and that escrow keeps the denom of the funds
+fn channel_key_spec() {
+    let mut deps = mock_dependencies();
+    let quota = QuotaMsg::new(
+        "weekly",
+        RESET_TIME_WEEKLY,
+        Uint256::from(1000_u32),
+        Uint256::from(1000_u32),
+    );
+    let instantiate_msg = |dimensions| InstantiateMsg {
+        paths: vec![PathMsg::new(
+            &Addr::unchecked(BRIDGE_CONTRACT),
+            "channel",
+            "uorai",
+            vec![quota.clone()],
+        )],
+        key_spec: Some(KeySpec { dimensions }),
+        seed_flows: vec![],
+    };
+    let owner = mock_info(OWNER, &[]);
+    let msg = instantiate_msg(vec![KeyDimension::Channel, KeyDimension::Channel]);
+    let err = instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::DuplicateKeyDimension {
+            dimension: KeyDimension::Channel,
+        }
+    );
+    let msg = instantiate_msg(vec![KeyDimension::Channel]);
+    instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+
+    // Any denom names the path of the channel
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let msg = ExecuteMsg::SetPathConfig {
+        channel_id: format!("channel"),
+        denom: format!("uatom"),
+        config: PathConfig {
+            escrow: true,
+            ..PathConfig::default()
+        },
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+    let send_msg = |sequence| {
+        test_msg_send!(
+            channel_id: format!("channel"),
+            denom: format!("uosmo"),
+            funds: 300_u32.into(),
+            sequence: sequence
+        )
+    };
+    let funded = mock_info(BRIDGE_CONTRACT, &coins(300, "uosmo"));
+    execute(deps.as_mut(), mock_env(), funded.clone(), send_msg(1)).unwrap();
+
+    let remove_msg = ExecuteMsg::RemovePath {
+        channel_id: format!("channel"),
+        denom: format!("uatom"),
+        idempotency_key: None,
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        remove_msg.clone(),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::EscrowNotEmpty { .. }));
+
+    let pause_path = |paused| ExecuteMsg::SetPathPaused {
+        path: PathId {
+            contract: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("uatom"),
+        },
+        paused,
+    };
+    execute(deps.as_mut(), mock_env(), owner.clone(), pause_path(true)).unwrap();
+    let err = execute(deps.as_mut(), mock_env(), funded, send_msg(2)).unwrap_err();
+    assert!(matches!(err, ContractError::PathPaused { .. }));
+    execute(deps.as_mut(), mock_env(), owner, pause_path(false)).unwrap();
+
+    // The escrow is released in the denom it was paid in
+    let msg = ExecuteMsg::AckPacket {
+        packet: Packet::mock(format!("channel"), format!("uosmo"), 300_u32.into()).with_sequence(1),
+        success: true,
+    };
+    let res = execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(BankMsg::Send {
+            to_address: BRIDGE_CONTRACT.to_string(),
+            amount: coins(300, "uosmo"),
+        })]
+    );
+    execute(deps.as_mut(), mock_env(), bridge, remove_msg).unwrap();
+}
+
+#[test] // Tests that the coins moved by ICA packets are accounted on the path of their denom
+fn ica_packets() {
+    let mut deps = mock_dependencies();
+    let path = |denom: &str| PathMsg {
+        contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: denom.to_string(),
+        quotas: vec![QuotaMsg::new(
+            "weekly",
+            RESET_TIME_WEEKLY,
+            100_u32.into(),
+            100_u32.into(),
+        )],
+    };
+    let msg = InstantiateMsg {
+        paths: vec![path("denom1"), path("denom2")],
+        key_spec: None,
+        seed_flows: vec![],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let ica_packet = |sequence: u64, msgs_value: Vec<Coin>| ExecuteMsg::SendIcaPacket {
+        packet: IcaPacket {
+            channel: format!("channel"),
+            msgs_value,
+            sequence: Some(sequence),
+            sender: None,
+        },
+    };
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let msgs_value = vec![coin(50, "denom1"), coin(20, "denom2"), coin(30, "denom1")];
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        ica_packet(1, msgs_value),
+    )
+    .unwrap();
+    let outflow = |deps: Deps, denom: &str| {
+        let msg = QueryMsg::GetQuotas {
+            contract: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: denom.to_string(),
+        };
+        let res = query(deps, mock_env(), msg).unwrap();
+        let quotas: Versioned<Vec<RateLimitResponse>> = from_json(&res).unwrap();
+        quotas.data[0].flow.outflow
+    };
+    assert_eq!(outflow(deps.as_ref(), "denom1"), Uint256::from(80_u32));
+    assert_eq!(outflow(deps.as_ref(), "denom2"), Uint256::from(20_u32));
+
+    // The sequence is checked once for the whole packet
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        ica_packet(1, vec![coin(1, "denom2")]),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::DuplicatePacket { .. }));
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        ica_packet(2, vec![coin(30, "denom1")]),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::RateLimitExceded { .. }));
+
+    // Truncated coins fail the packet instead
+    let msg = ExecuteMsg::SetPathConfig {
+        channel_id: format!("channel"),
+        denom: format!("denom2"),
+        config: PathConfig {
+            on_exceed: OnExceed::Truncate,
+            ..PathConfig::default()
+        },
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        ica_packet(3, vec![coin(90, "denom2")]),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::IcaPacketNotAccepted {
+            denom: format!("denom2"),
+            amount: Uint128::new(90),
+            accepted: Uint128::new(80),
+        }
+    );
+
+    // Each coin of the packet is undone on its own
+    let coin_packet = |denom: &str| {
+        Packet::mock(format!("channel"), denom.to_string(), 0_u32.into()).with_sequence(1)
+    };
+    let sent = outflow(deps.as_ref(), "denom1");
+    let msg = ExecuteMsg::UndoSend {
+        packet: coin_packet("denom1"),
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg.clone()).unwrap();
+    assert_eq!(
+        outflow(deps.as_ref(), "denom1"),
+        sent - Uint256::from(80_u32)
+    );
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap_err();
+    assert!(matches!(err, ContractError::PendingSendNotFound { .. }));
+
+    let sent = outflow(deps.as_ref(), "denom2");
+    let msg = ExecuteMsg::AckPacket {
+        packet: coin_packet("denom2"),
+        success: false,
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+    assert_eq!(
+        outflow(deps.as_ref(), "denom2"),
+        sent - Uint256::from(20_u32)
+    );
+
+    // The coins of a denom can't add up beyond a uint128
+    let msgs_value = vec![coin(u128::MAX, "denom1"), coin(1, "denom1")];
+    let err = execute(deps.as_mut(), mock_env(), bridge, ica_packet(4, msgs_value)).unwrap_err();
+    assert!(matches!(err, ContractError::AmountOverflow { .. }));
+}
+
+#[test] // Tests that reserved capacity is only used by the beneficiary of the reservation
+fn capacity_reservation() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 100_u32.into(), 100_u32.into());
+    let msg = InstantiateMsg {
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+        key_spec: None,
+        seed_flows: vec![],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let path = PathId {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+    };
+    let reserve = |amount: u32, window: u64| ExecuteMsg::ReserveCapacity {
+        path: path.clone(),
+        direction: FlowType::Out,
+        amount: amount.into(),
+        window,
+        beneficiary: format!("treasury"),
+        role: ApproverRole::Owner,
+    };
+    let send = |funds: u32, sender: &str| ExecuteMsg::SendPacket {
+        packet: Packet::mock(format!("channel"), format!("denom"), funds.into())
+            .with_sender(sender),
+    };
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let owner = mock_info(OWNER, &[]);
+
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), reserve(60, 3600)).unwrap_err();
+    assert!(matches!(err, ContractError::Ownership(_)));
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        owner.clone(),
+        reserve(60, u64::MAX),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::ReservationWindowTooLong {
+            window: u64::MAX,
+            max: MAX_RESERVATION_WINDOW,
+        }
+    );
+    execute(deps.as_mut(), mock_env(), owner.clone(), reserve(60, 3600)).unwrap();
+
+    // Routine traffic can't eat into the reserved slice
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), send(50, "user")).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::CapacityReserved {
+            quota_name: format!("weekly"),
+            amount: Uint128::new(50),
+            reserved: Uint256::from(60_u32),
+            remaining: Uint256::from(100_u32),
+        }
+    );
+    let can_send = |deps: Deps, amount: u32| {
+        let msg = QueryMsg::CanSend {
+            contract: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            amount: amount.into(),
+        };
+        from_json::<Versioned<CanSendResponse>>(&query(deps, mock_env(), msg).unwrap())
+            .unwrap()
+            .data
+    };
+    assert_eq!(can_send(deps.as_ref(), 50).reason, Some(err.to_string()));
+    assert!(can_send(deps.as_ref(), 40).allowed);
+    execute(deps.as_mut(), mock_env(), bridge.clone(), send(40, "user")).unwrap();
+
+    // The beneficiary draws from it
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send(25, "treasury"),
+    )
+    .unwrap();
+    assert!(res
+        .attributes
+        .iter()
+        .any(|attr| attr.key == "reservation" && attr.value == "0"));
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send(35, "treasury"),
+    )
+    .unwrap();
+
+    let msg = QueryMsg::ListReservations {
+        path: path.clone(),
+        start_after: None,
+        limit: None,
+    };
+    let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+    let reservations: Versioned<Vec<Reservation>> = from_json(&res).unwrap();
+    assert_eq!(reservations.data[0].consumed, Uint128::new(60));
+    assert_eq!(reservations.data[0].left(), Uint128::zero());
+    // Used up reservations are no longer gone through
+    let active = |deps: &OwnedDeps<_, _, _>| {
+        let key: PathKey = (
+            Addr::unchecked(BRIDGE_CONTRACT),
+            format!("channel"),
+            format!("denom"),
+        );
+        ACTIVE_RESERVATIONS
+            .prefix(key)
+            .keys(&deps.storage, None, None, Order::Ascending)
+            .map(|id| id.unwrap())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(active(&deps), Vec::<u64>::new());
+
+    // Expired reservations no longer hold capacity back
+    let mut later = mock_env();
+    later.block.time = later.block.time.plus_seconds(RESET_TIME_WEEKLY + 1);
+    execute(deps.as_mut(), later.clone(), owner.clone(), reserve(90, 60)).unwrap();
+    let err = execute(
+        deps.as_mut(),
+        later.clone(),
+        bridge.clone(),
+        send(20, "user"),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::CapacityReserved { .. }));
+    assert_eq!(active(&deps), vec![1]);
+    later.block.time = later.block.time.plus_seconds(61);
+    execute(deps.as_mut(), later.clone(), bridge, send(20, "user")).unwrap();
+    assert_eq!(active(&deps), Vec::<u64>::new());
+
+    // Paths hold a bounded number of active reservations, expired ones aside
+    for _ in 0..MAX_ACTIVE_RESERVATIONS {
+        execute(deps.as_mut(), later.clone(), owner.clone(), reserve(1, 60)).unwrap();
+    }
+    let err = execute(deps.as_mut(), later.clone(), owner.clone(), reserve(1, 60)).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::TooManyReservations {
+            max: MAX_ACTIVE_RESERVATIONS
+        }
+    );
+    later.block.time = later.block.time.plus_seconds(61);
+    execute(deps.as_mut(), later, owner, reserve(1, 60)).unwrap();
+    assert_eq!(active(&deps).len(), 1);
+}
+
+#[cfg(feature = "debug_math")]
+#[test] // Tests that the intermediate values of the quota math are reported
+fn debug_math_attributes() {
+    let mut deps = mock_dependencies();
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 100_u32.into(), 100_u32.into());
+    let msg = InstantiateMsg {
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+        key_spec: None,
+        seed_flows: vec![],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let send = |funds: u32| {
+        test_msg_send!(
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            funds: funds.into()
+        )
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), send(30)).unwrap();
+
+    let mut later = mock_env();
+    later.block.time = later.block.time.plus_seconds(RESET_TIME_WEEKLY + 1);
+    let res = execute(deps.as_mut(), later, bridge, send(20)).unwrap();
+    let value = |key: &str| {
+        res.attributes
+            .iter()
+            .find(|attr| attr.key == key)
+            .map(|attr| attr.value.as_str())
+    };
+    assert_eq!(value("weekly_debug_pre_out"), Some("30"));
+    assert_eq!(value("weekly_debug_expired"), Some("true"));
+    assert_eq!(value("weekly_debug_post_out"), Some("20"));
+    assert_eq!(value("weekly_debug_channel_value"), Some("none"));
+    assert_eq!(value("weekly_debug_capacity"), Some("100"));
+}
+
+#[test] // Tests that the relayer syncs the counterparty state shown next to the path
+fn channel_pair() {
+    let mut deps = mock_dependencies();
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 100_u32.into(), 100_u32.into());
+    let msg = InstantiateMsg {
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+        key_spec: None,
+        seed_flows: vec![],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let path = PathId {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+    };
+    let counterparty_usage = |inflow: u32| QuotaUsage {
+        name: format!("weekly"),
+        inflow: inflow.into(),
+        outflow: Uint256::zero(),
+        max_in: 100_u32.into(),
+        max_out: 100_u32.into(),
+        period_end: None,
+        recv_period_end: None,
+    };
+    let now = mock_env().block.time;
+    let sync = |inflow: u32, reported_at: Timestamp| ExecuteMsg::SyncCounterpartyState {
+        path: path.clone(),
+        usage: vec![counterparty_usage(inflow)],
+        reported_at,
+    };
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let relayer = mock_info("relayer", &[]);
+
+    // Paths need a counterparty link first
+    let err = execute(deps.as_mut(), mock_env(), relayer.clone(), sync(30, now)).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::NoCounterparty {
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+        }
+    );
+    let counterparty = CounterpartyPath {
+        chain_id: format!("osmosis-1"),
+        contract: format!("osmo1ratelimiter"),
+        channel_id: format!("channel-216"),
+        denom: format!("ibc/denom"),
+        relayer: Addr::unchecked("relayer"),
+    };
+    let msg = ExecuteMsg::SetPathConfig {
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        config: PathConfig {
+            counterparty: Some(counterparty.clone()),
+            ..PathConfig::default()
+        },
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), sync(30, now)).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    execute(deps.as_mut(), mock_env(), relayer.clone(), sync(30, now)).unwrap();
+    let err = execute(deps.as_mut(), mock_env(), relayer, sync(20, now)).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::StaleCounterpartyState {
+            reported_at: now,
+            last_reported_at: now,
+        }
+    );
+
+    let msg = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 30_u32.into()
+    );
+    execute(deps.as_mut(), mock_env(), bridge, msg).unwrap();
+
+    let msg = QueryMsg::GetChannelPair {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+    };
+    let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+    let pair: Versioned<ChannelPairResponse> = from_json(&res).unwrap();
+    let pair = pair.data;
+    assert_eq!(pair.usage[0].outflow, Uint256::from(30_u32));
+    assert_eq!(pair.counterparty, Some(counterparty));
+    let state = pair.counterparty_state.unwrap();
+    assert_eq!(state.usage, vec![counterparty_usage(30)]);
+    assert_eq!(state.synced_at, now);
+}
+
+#[test] // Tests that packets are held back by the pauses of their contract, channel and path
+fn scoped_pauses() {
+    let mut deps = mock_dependencies();
+    let path = |channel: &str, denom: &str| PathMsg {
+        contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: channel.to_string(),
+        denom: denom.to_string(),
+        quotas: vec![QuotaMsg::new(
+            "weekly",
+            RESET_TIME_WEEKLY,
+            100_u32.into(),
+            100_u32.into(),
+        )],
+    };
+    let msg = InstantiateMsg {
+        paths: vec![
+            path("channel1", "denom1"),
+            path("channel1", "denom2"),
+            path("channel2", "denom1"),
+        ],
+        key_spec: None,
+        seed_flows: vec![],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let owner = mock_info(OWNER, &[]);
+    let send =
+        |deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>, channel: &str, denom: &str| {
+            let msg = test_msg_send!(
+                channel_id: channel.to_string(),
+                denom: denom.to_string(),
+                funds: 1_u32.into()
+            );
+            execute(deps.as_mut(), mock_env(), bridge.clone(), msg)
+        };
+
+    let pause_path = |paused| ExecuteMsg::SetPathPaused {
+        path: PathId {
+            contract: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel1"),
+            denom: format!("denom1"),
+        },
+        paused,
+    };
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), pause_path(true)).unwrap_err();
+    assert!(matches!(err, ContractError::Ownership(_)));
+    execute(deps.as_mut(), mock_env(), owner.clone(), pause_path(true)).unwrap();
+    assert_eq!(
+        send(&mut deps, "channel1", "denom1").unwrap_err(),
+        ContractError::PathPaused {
+            channel_id: format!("channel1"),
+            denom: format!("denom1"),
+        }
+    );
+    send(&mut deps, "channel1", "denom2").unwrap();
+
+    // The broader pauses are checked first
+    let pause_channel = |paused| ExecuteMsg::SetChannelPaused {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel1"),
+        paused,
+    };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        owner.clone(),
+        pause_channel(true),
+    )
+    .unwrap();
+    let channel_paused = ContractError::ChannelPaused {
+        contract: BRIDGE_CONTRACT.to_string(),
+        channel_id: format!("channel1"),
+    };
+    assert_eq!(
+        send(&mut deps, "channel1", "denom1").unwrap_err(),
+        channel_paused
+    );
+    assert_eq!(
+        send(&mut deps, "channel1", "denom2").unwrap_err(),
+        channel_paused
+    );
+    send(&mut deps, "channel2", "denom1").unwrap();
+
+    let pause_contract = |paused| ExecuteMsg::SetContractPaused {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        paused,
+    };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        owner.clone(),
+        pause_contract(true),
+    )
+    .unwrap();
+    let contract_paused = ContractError::ContractPaused {
+        contract: BRIDGE_CONTRACT.to_string(),
+    };
+    assert_eq!(
+        send(&mut deps, "channel1", "denom1").unwrap_err(),
+        contract_paused
+    );
+    assert_eq!(
+        send(&mut deps, "channel2", "denom1").unwrap_err(),
+        contract_paused
+    );
+
+    let msg = ExecuteMsg::SetPaused {
+        paused: true,
+        auto_unpause_at: None,
+    };
+    execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+    assert_eq!(
+        send(&mut deps, "channel2", "denom1").unwrap_err(),
+        ContractError::Paused {}
+    );
+
+    // Lifting the pauses one by one
+    let msg = ExecuteMsg::SetPaused {
+        paused: false,
+        auto_unpause_at: None,
+    };
+    execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        owner.clone(),
+        pause_contract(false),
+    )
+    .unwrap();
+    send(&mut deps, "channel2", "denom1").unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        owner.clone(),
+        pause_channel(false),
+    )
+    .unwrap();
+    send(&mut deps, "channel1", "denom2").unwrap();
+    assert!(matches!(
+        send(&mut deps, "channel1", "denom1").unwrap_err(),
+        ContractError::PathPaused { .. }
+    ));
+    execute(deps.as_mut(), mock_env(), owner, pause_path(false)).unwrap();
+    send(&mut deps, "channel1", "denom1").unwrap();
+}
+
+#[test] // Tests that streaks of rejected sends escalate once past the threshold
+fn rejection_streak() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 10_u32.into(), 10_u32.into());
+    let msg = InstantiateMsg {
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+        key_spec: None,
+        seed_flows: vec![],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let msg = ExecuteMsg::SetPathConfig {
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        config: PathConfig {
+            reject_with_data: true,
+            on_reject_contract: Some(Addr::unchecked("hook")),
+            escalation: Some(EscalationPolicy {
+                threshold: 3,
+                window: 60,
+                freeze: true,
+                alert_contract: Some(Addr::unchecked("alerts")),
+            }),
+            ..PathConfig::default()
+        },
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+
+    let path = PathId {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+    };
+    let streak = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>| {
+        let msg = QueryMsg::GetStreak { path: path.clone() };
+        let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+        from_json::<Versioned<Option<Streak>>>(&res).unwrap().data
+    };
+    let send = |funds: u32| {
+        test_msg_send!(
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            funds: funds.into()
+        )
+    };
+
+    // A rejection outside of the window starts a new streak
+    let mut env = mock_env();
+    execute(deps.as_mut(), env.clone(), bridge.clone(), send(11)).unwrap();
+    env.block.time = env.block.time.plus_seconds(61);
+    execute(deps.as_mut(), env.clone(), bridge.clone(), send(11)).unwrap();
+    let current = streak(&deps).unwrap();
+    assert_eq!(current.count, 1);
+    assert_eq!(current.started_at, env.block.time);
+
+    // An accepted packet ends it
+    execute(deps.as_mut(), env.clone(), bridge.clone(), send(1)).unwrap();
+    assert_eq!(streak(&deps), None);
+
+    let res = execute(deps.as_mut(), env.clone(), bridge.clone(), send(11)).unwrap();
+    assert!(res.events.iter().all(|event| event.ty != "escalation"));
+    execute(deps.as_mut(), env.clone(), bridge.clone(), send(11)).unwrap();
+    let res = execute(deps.as_mut(), env.clone(), bridge.clone(), send(11)).unwrap();
+    let escalation = res
+        .events
+        .iter()
+        .find(|event| event.ty == "escalation")
+        .unwrap();
+    assert!(escalation
+        .attributes
+        .iter()
+        .any(|attr| attr.key == "count" && attr.value == "3"));
+    let current = streak(&deps).unwrap();
+    assert!(current.escalated);
+    let alert = to_json_binary(&AlertMsg::Escalation {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        streak: current,
+        frozen: true,
+    })
+    .unwrap();
+    // A failing alert doesn't revert the rejection
+    let alert = res
+        .messages
+        .iter()
+        .find(|msg| {
+            msg.msg
+                == CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: format!("alerts"),
+                    msg: alert.clone(),
+                    funds: vec![],
+                })
+        })
+        .unwrap();
+    assert_eq!(alert.reply_on, ReplyOn::Error);
+    assert_eq!(alert.id, ALERT_REPLY_ID);
+    let failed = Reply {
+        id: ALERT_REPLY_ID,
+        result: SubMsgResult::Err(format!("alert failed")),
+    };
+    let res = reply(deps.as_mut(), mock_env(), failed).unwrap();
+    assert!(res
+        .attributes
+        .contains(&Attribute::new("notification", "alert_contract")));
+
+    // The path was frozen
+    let err = execute(deps.as_mut(), env, bridge, send(1)).unwrap_err();
+    assert!(matches!(err, ContractError::PathPaused { .. }));
+}
+
+#[test] // Tests that the usage of another limiter can be carried over at instantiate
+fn seeded_flows() {
+    let mut deps = mock_dependencies();
+    let env = mock_env();
+    let path = PathMsg {
+        contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        quotas: vec![QuotaMsg::new(
+            "weekly",
+            RESET_TIME_WEEKLY,
+            100_u32.into(),
+            100_u32.into(),
+        )],
+    };
+    let seed = |quota_id: &str, period_end: Timestamp| SeedFlow {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        quota_id: quota_id.to_string(),
+        inflow: 10_u32.into(),
+        outflow: 70_u32.into(),
+        period_end,
+    };
+    let instantiate_msg = |seed_flows| InstantiateMsg {
+        paths: vec![path.clone()],
+        key_spec: None,
+        seed_flows,
+    };
+
+    let owner = mock_info(OWNER, &[]);
+    let err = instantiate(
+        deps.as_mut(),
+        env.clone(),
+        owner.clone(),
+        instantiate_msg(vec![seed("daily", env.block.time)]),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::QuotaNotFound { .. }));
+    let too_far = env.block.time.plus_seconds(RESET_TIME_WEEKLY + 1);
+    let err = instantiate(
+        deps.as_mut(),
+        env.clone(),
+        owner.clone(),
+        instantiate_msg(vec![seed("weekly", too_far)]),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::SeedPeriodTooLong { .. }));
+
+    let period_end = env.block.time.plus_seconds(3600);
+    let mut deps = mock_dependencies();
+    let res = instantiate(
+        deps.as_mut(),
+        env.clone(),
+        owner,
+        instantiate_msg(vec![seed("weekly", period_end)]),
+    )
+    .unwrap();
+    assert!(res
+        .attributes
+        .iter()
+        .any(|attr| attr.key == "seeded_flows" && attr.value == "1"));
+
+    let msg = QueryMsg::GetQuotas {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+    };
+    let res = query(deps.as_ref(), env.clone(), msg).unwrap();
+    let flow = &from_json::<Versioned<Vec<RateLimitResponse>>>(&res)
+        .unwrap()
+        .data[0]
+        .flow;
+    assert_eq!(flow.inflow, Uint256::from(10_u32));
+    assert_eq!(flow.outflow, Uint256::from(70_u32));
+    assert_eq!(flow.period_end, period_end);
+
+    // The carried over usage counts against the quota until the period ends
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let send = |funds: u32| {
+        test_msg_send!(
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            funds: funds.into()
+        )
+    };
+    let err = execute(deps.as_mut(), env.clone(), bridge.clone(), send(41)).unwrap_err();
+    assert!(matches!(err, ContractError::RateLimitExceded { .. }));
+    execute(deps.as_mut(), env.clone(), bridge.clone(), send(40)).unwrap();
+    let mut later = env;
+    later.block.time = period_end.plus_seconds(1);
+    execute(deps.as_mut(), later, bridge, send(100)).unwrap();
+}
+
+#[test] // Tests that a rejection lists every violated quota, not only the first
+fn all_violations() {
+    let mut deps = mock_dependencies();
+    let env = mock_env();
+
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![
+                QuotaMsg::new("daily", RESET_TIME_DAILY, 10_u32.into(), 10_u32.into()),
+                QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 50_u32.into(), 50_u32.into()),
+                QuotaMsg::new(
+                    "monthly",
+                    RESET_TIME_WEEKLY * 4,
+                    100_u32.into(),
+                    100_u32.into(),
+                ),
+            ],
+        }],
+    };
+    instantiate(deps.as_mut(), env.clone(), mock_info(OWNER, &[]), msg).unwrap();
+
+    // Only the monthly quota still has room for the transfer
+    let send_msg = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 60_u32.into()
+    );
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(BRIDGE_CONTRACT, &[]),
+        send_msg,
+    )
+    .unwrap_err();
+
+    let data = RateLimitExceededData::from_error_message(&err.to_string()).unwrap();
+    assert_eq!(data.quota_name, "daily");
+    let names: Vec<_> = data
+        .violations
+        .iter()
+        .map(|v| v.quota_name.as_str())
+        .collect();
+    assert_eq!(names, vec!["daily", "weekly"]);
+    assert_eq!(data.violations[1].max, Uint256::from(50_u128));
+    // The transfer can only go through once the weekly quota resets too
+    assert_eq!(
+        data.retry_after(),
+        env.block.time.plus_seconds(RESET_TIME_WEEKLY)
+    );
+    assert_eq!(Some(data), err.rate_limit_data());
+}
+
+#[test] // Tests that observers can page through the paths matching their saved filters
+fn filtered_state() {
+    let mut deps = mock_dependencies();
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 100_u32.into(), 100_u32.into());
+    let path_msg = |channel_id: &str, denom: &str| PathMsg {
+        contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: channel_id.to_string(),
+        denom: denom.to_string(),
+        quotas: vec![quota.clone()],
+    };
+    let msg = InstantiateMsg {
+        paths: vec![
+            path_msg("channel-0", "uusdc"),
+            path_msg("channel-1", "uatom"),
+            path_msg("channel-1", "uusdt"),
+        ],
+        key_spec: None,
+        seed_flows: vec![],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    for (channel_id, denom) in [("channel-0", "uusdc"), ("channel-1", "uusdt")] {
+        let msg = ExecuteMsg::SetPathConfig {
+            channel_id: channel_id.to_string(),
+            denom: denom.to_string(),
+            config: PathConfig {
+                tags: vec![format!("stablecoin")],
+                ..PathConfig::default()
+            },
+            idempotency_key: None,
+        };
+        execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+    }
+
+    let indexer = mock_info("indexer", &[]);
+    let save = |filter_id: &str, filter: PathFilter| ExecuteMsg::SaveFilter {
+        filter_id: filter_id.to_string(),
+        filter,
+    };
+    let stablecoins = PathFilter::Tag {
+        tag: format!("stablecoin"),
+    };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        indexer.clone(),
+        save("stables", stablecoins),
+    )
+    .unwrap();
+    let channel = PathFilter::Channel {
+        channel_id: format!("channel-1"),
+    };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        indexer.clone(),
+        save("channel-1", channel.clone()),
+    )
+    .unwrap();
+
+    // Only the observer who saved a filter can replace it
+    let other = mock_info("other", &[]);
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        other.clone(),
+        save("stables", channel),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        other,
+        save(
+            "",
+            PathFilter::Denom {
+                denom: format!("uatom"),
+            },
+        ),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::InvalidFilterId { .. }));
+
+    let query_page = |deps: Deps, filter_id: &str, start_after, limit| {
+        let msg = QueryMsg::GetFilteredState {
+            filter_id: filter_id.to_string(),
+            start_after,
+            limit,
+        };
+        let res = query(deps, mock_env(), msg).unwrap();
+        from_json::<Versioned<FilteredStateResponse>>(&res)
+            .unwrap()
+            .data
+    };
+    let names = |page: &FilteredStateResponse| -> Vec<String> {
+        page.paths
+            .iter()
+            .map(|path| format!("{}/{}", path.channel_id, path.denom))
+            .collect()
+    };
+
+    let page = query_page(deps.as_ref(), "stables", None, None);
+    assert_eq!(names(&page), vec!["channel-0/uusdc", "channel-1/uusdt"]);
+    assert_eq!(page.paths[0].tags, vec![format!("stablecoin")]);
+    assert_eq!(page.paths[0].usage[0].name, "weekly");
+    assert_eq!(page.next_start_after, None);
+
+    // Paging resumes after the last path looked at
+    let page = query_page(deps.as_ref(), "channel-1", None, Some(1));
+    assert_eq!(names(&page), vec!["channel-1/uatom"]);
+    let start_after = page.next_start_after.clone();
+    assert!(start_after.is_some());
+    let page = query_page(deps.as_ref(), "channel-1", start_after, Some(1));
+    assert_eq!(names(&page), vec!["channel-1/uusdt"]);
+    assert_eq!(page.paths[0].tags, vec![format!("stablecoin")]);
+    assert_eq!(page.next_start_after, None);
+
+    // Filters are removed by the observer who saved them or by the owner
+    let remove = ExecuteMsg::RemoveFilter {
+        filter_id: format!("channel-1"),
+    };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(OWNER, &[]),
+        remove.clone(),
+    )
+    .unwrap();
+    let err = execute(deps.as_mut(), mock_env(), indexer, remove).unwrap_err();
+    assert!(matches!(err, ContractError::FilterNotFound { .. }));
+    let msg = QueryMsg::GetFilteredState {
+        filter_id: format!("channel-1"),
+        start_after: None,
+        limit: None,
+    };
+    assert!(query(deps.as_ref(), mock_env(), msg).is_err());
+}
+
+#[test] // Tests that the direction of packets is verified, or inferred, from their endpoints
+fn packet_direction() {
+    let mut deps = mock_dependencies();
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 100_u32.into(), 100_u32.into());
+    let msg = InstantiateMsg {
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel-0"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+        key_spec: None,
+        seed_flows: vec![],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let outgoing = PacketEndpoints {
+        source_port: format!("transfer"),
+        source_channel: format!("channel-0"),
+        destination_port: format!("transfer"),
+        destination_channel: format!("channel-42"),
+    };
+    let incoming = PacketEndpoints {
+        source_port: format!("transfer"),
+        source_channel: format!("channel-42"),
+        destination_port: format!("transfer"),
+        destination_channel: format!("channel-0"),
+    };
+    let packet = |amount: u32, endpoints: &PacketEndpoints| {
+        Packet::mock(format!("channel-0"), format!("denom"), amount.into())
+            .with_endpoints(endpoints.clone())
+    };
+
+    // Without a local channel, the declared direction is trusted
+    let msg = ExecuteMsg::RecvPacket {
+        packet: packet(10, &outgoing),
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+    let msg = ExecuteMsg::ProcessPacket {
+        packet: packet(10, &outgoing),
+    };
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap_err();
+    assert!(matches!(err, ContractError::CannotInferDirection { .. }));
+
+    let msg = ExecuteMsg::SetPathConfig {
+        channel_id: format!("channel-0"),
+        denom: format!("denom"),
+        config: PathConfig {
+            local_channel: Some(LocalChannel {
+                port_id: format!("transfer"),
+                channel_id: format!("channel-0"),
+            }),
+            ..PathConfig::default()
+        },
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+
+    // A flipped packet is refused
+    let msg = ExecuteMsg::RecvPacket {
+        packet: packet(10, &outgoing),
+    };
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::DirectionMismatch {
+            channel_id: format!("channel-0"),
+            denom: format!("denom"),
+            declared: format!("in"),
+            inferred: format!("out"),
+        }
+    );
+    let msg = ExecuteMsg::SendPacket {
+        packet: packet(10, &incoming),
+    };
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap_err();
+    assert!(matches!(err, ContractError::DirectionMismatch { .. }));
+
+    // Packets that didn't go through the local channel are refused too
+    let elsewhere = PacketEndpoints {
+        source_channel: format!("channel-7"),
+        ..outgoing.clone()
+    };
+    let msg = ExecuteMsg::ProcessPacket {
+        packet: packet(10, &elsewhere),
+    };
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap_err();
+    assert!(matches!(err, ContractError::UnknownPacketEndpoints { .. }));
+
+    // The direction is inferred from the endpoints. Flows are netted against
+    // the 10 received first
+    let used_out = |res: &Response| {
+        let attribute = res.attributes.iter().find(|a| a.key == "weekly_used_out");
+        attribute.unwrap().value.clone()
+    };
+    let msg = ExecuteMsg::ProcessPacket {
+        packet: packet(30, &outgoing),
+    };
+    let res = execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+    assert_eq!(used_out(&res), "20");
+    let msg = ExecuteMsg::ProcessPacket {
+        packet: packet(5, &incoming),
+    };
+    let res = execute(deps.as_mut(), mock_env(), bridge, msg).unwrap();
+    assert_eq!(used_out(&res), "15");
+}
+
+#[test] // Tests that the channel defaults cap the quotas of the paths of the channel
+fn quota_hierarchy() {
+    let mut deps = mock_dependencies();
+    let msg = InstantiateMsg {
+        paths: vec![],
+        key_spec: None,
+        seed_flows: vec![],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let owner = mock_info(OWNER, &[]);
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let weekly = |max: u32| {
+        vec![QuotaMsg::new(
+            "weekly",
+            RESET_TIME_WEEKLY,
+            max.into(),
+            max.into(),
+        )]
+    };
+    let set_strictness = |strictness| ExecuteMsg::UpdateConfig {
+        config: Config {
+            quota_hierarchy: strictness,
+            ..Config::default()
+        },
+        idempotency_key: None,
+    };
+    let set_defaults = |max: u32| ExecuteMsg::SetChannelDefaultQuotas {
+        channel_id: format!("channel"),
+        quotas: weekly(max),
+        idempotency_key: None,
+    };
+    let add_path = |denom: &str, max: u32| ExecuteMsg::AddPath {
+        channel_id: format!("channel"),
+        denom: denom.to_string(),
+        quotas: weekly(max),
+        idempotency_key: None,
+    };
+    let send = |denom: &str, amount: u32| {
+        test_msg_send!(
+            channel_id: format!("channel"),
+            denom: denom.to_string(),
+            funds: amount.into()
+        )
+    };
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        owner.clone(),
+        set_strictness(HierarchyStrictness::Max),
+    )
+    .unwrap();
+    execute(deps.as_mut(), mock_env(), bridge.clone(), set_defaults(100)).unwrap();
+
+    // With Max, each path quota has to fit in the channel default
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        add_path("uatom", 150),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::ChildQuotaExceedsParent {
+            channel_id: format!("channel"),
+            denom: format!("uatom"),
+            quota_name: format!("weekly"),
+            child: Uint256::from(150_u32),
+            parent: Uint256::from(100_u32),
+        }
+    );
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        add_path("uatom", 80),
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        add_path("uosmo", 80),
+    )
+    .unwrap();
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), set_defaults(50)).unwrap_err();
+    assert!(matches!(err, ContractError::ChildQuotaExceedsParent { .. }));
+
+    // Transfers have to fit in the channel defaults too, across the denoms
+    execute(deps.as_mut(), mock_env(), bridge.clone(), send("uatom", 70)).unwrap();
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), send("uosmo", 70)).unwrap_err();
+    assert!(
+        matches!(err, ContractError::RateLimitExceded { max, .. } if max == Uint256::from(100_u32))
+    );
+    let osmo_send =
+        Packet::mock(format!("channel"), format!("uosmo"), 30_u32.into()).with_sequence(1);
+    let msg = ExecuteMsg::SendPacket {
+        packet: osmo_send.clone(),
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+    execute(deps.as_mut(), mock_env(), bridge.clone(), send("uatom", 1)).unwrap_err();
+
+    // Undone sends are given back to the channel flows
+    let msg = ExecuteMsg::UndoSend { packet: osmo_send };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+    execute(deps.as_mut(), mock_env(), bridge.clone(), send("uosmo", 30)).unwrap();
+
+    // Editing the defaults keeps the flows of the quotas of the same name
+    execute(deps.as_mut(), mock_env(), bridge.clone(), set_defaults(90)).unwrap();
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), send("uatom", 1)).unwrap_err();
+    assert!(
+        matches!(err, ContractError::RateLimitExceded { max, .. } if max == Uint256::from(90_u32))
+    );
+    execute(deps.as_mut(), mock_env(), bridge.clone(), set_defaults(101)).unwrap();
+    execute(deps.as_mut(), mock_env(), bridge.clone(), send("uatom", 1)).unwrap();
+
+    // With Sum, the path quotas have to fit in the channel default together
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        owner,
+        set_strictness(HierarchyStrictness::Sum),
+    )
+    .unwrap();
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        add_path("uusdc", 10),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::ChildQuotasExceedParent {
+            channel_id: format!("channel"),
+            quota_name: format!("weekly"),
+            total: Uint256::from(170_u32),
+            parent: Uint256::from(101_u32),
+        }
+    );
+    execute(deps.as_mut(), mock_env(), bridge.clone(), set_defaults(200)).unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        add_path("uusdc", 10),
+    )
+    .unwrap();
+
+    // Sends are only given back to the channel flows of their period
+    let usdc_send =
+        Packet::mock(format!("channel"), format!("uusdc"), 5_u32.into()).with_sequence(2);
+    let msg = ExecuteMsg::SendPacket {
+        packet: usdc_send.clone(),
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(RESET_TIME_WEEKLY + 1);
+    execute(deps.as_mut(), env.clone(), bridge.clone(), send("uatom", 8)).unwrap();
+    let msg = ExecuteMsg::UndoSend { packet: usdc_send };
+    execute(deps.as_mut(), env, bridge, msg).unwrap();
+    let key = (Addr::unchecked(BRIDGE_CONTRACT), format!("channel"));
+    let trackers = CHANNEL_TRACKERS.load(&deps.storage, key).unwrap();
+    assert_eq!(trackers[0].flow.outflow, Uint256::from(8_u32));
+}
+
+#[test] // Tests that the paths having a quota of a name can be listed with its usage
+fn quota_across_paths() {
+    let mut deps = mock_dependencies();
+    let daily = QuotaMsg::new("daily", RESET_TIME_DAILY, 100_u32.into(), 100_u32.into());
+    let weekly = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 500_u32.into(), 500_u32.into());
+    let path_msg = |channel_id: &str, quotas: Vec<QuotaMsg>| PathMsg {
+        contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: channel_id.to_string(),
+        denom: format!("denom"),
+        quotas,
+    };
+    let msg = InstantiateMsg {
+        paths: vec![
+            path_msg("channel-0", vec![daily.clone(), weekly.clone()]),
+            path_msg("channel-1", vec![weekly]),
+            path_msg("channel-2", vec![daily]),
+        ],
+        key_spec: None,
+        seed_flows: vec![],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let send_msg = test_msg_send!(
+        channel_id: format!("channel-2"),
+        denom: format!("denom"),
+        funds: 40_u32.into()
+    );
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(BRIDGE_CONTRACT, &[]),
+        send_msg,
+    )
+    .unwrap();
+
+    let query_page = |start_after, limit| {
+        let msg = QueryMsg::GetQuotaAcrossPaths {
+            quota_name: format!("daily"),
+            start_after,
+            limit,
+        };
+        let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+        from_json::<Versioned<QuotaAcrossPathsResponse>>(&res)
+            .unwrap()
+            .data
+    };
+
+    let page = query_page(None, None);
+    let channels: Vec<_> = page.paths.iter().map(|p| p.channel_id.as_str()).collect();
+    assert_eq!(channels, vec!["channel-0", "channel-2"]);
+    assert_eq!(page.paths[1].usage.name, "daily");
+    assert_eq!(page.paths[1].usage.outflow, Uint256::from(40_u32));
+    assert_eq!(page.next_start_after, None);
+
+    let page = query_page(None, Some(1));
+    assert_eq!(page.paths.len(), 1);
+    let page = query_page(page.next_start_after, Some(1));
+    assert_eq!(page.paths[0].channel_id, "channel-2");
+    assert_eq!(page.next_start_after, None);
+}
+
+#[test] // Tests that the changes of an AddPath can be previewed without applying them
+fn preview_path_change() {
+    let mut deps = mock_dependencies();
+    let daily = QuotaMsg::new("daily", RESET_TIME_DAILY, 100_u32.into(), 100_u32.into());
+    let weekly = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 500_u32.into(), 500_u32.into());
+    let path_msg = |quotas: Vec<QuotaMsg>| PathMsg {
+        contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        quotas,
+    };
+    let msg = InstantiateMsg {
+        paths: vec![path_msg(vec![daily.clone(), weekly])],
+        key_spec: None,
+        seed_flows: vec![],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+    let send_msg = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 40_u32.into()
+    );
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(BRIDGE_CONTRACT, &[]),
+        send_msg,
+    )
+    .unwrap();
+
+    let preview = |deps: Deps, path_msg: PathMsg| {
+        let msg = QueryMsg::PreviewPathChange { path_msg };
+        let res = query(deps, mock_env(), msg).unwrap();
+        from_json::<Versioned<PathChangePreview>>(&res)
+            .unwrap()
+            .data
+    };
+
+    let monthly = QuotaMsg::new(
+        "monthly",
+        RESET_TIME_WEEKLY * 4,
+        900_u32.into(),
+        900_u32.into(),
+    );
+    let stricter_daily = QuotaMsg::new("daily", RESET_TIME_DAILY, 50_u32.into(), 50_u32.into());
+    let data = preview(
+        deps.as_ref(),
+        path_msg(vec![daily, stricter_daily.clone(), monthly.clone()]),
+    );
+    assert!(!data.new_path);
+    assert_eq!(
+        data.added,
+        vec![QuotaResponse::from(&Quota::from(&monthly))]
+    );
+    assert_eq!(data.replaced.len(), 1);
+    assert_eq!(
+        data.replaced[0].after,
+        QuotaResponse::from(&Quota::from(&stricter_daily))
+    );
+    let removed: Vec<_> = data.removed.iter().map(|q| q.name.as_str()).collect();
+    assert_eq!(removed, vec!["weekly"]);
+    let reset: Vec<_> = data.reset_flows.iter().map(|u| u.name.as_str()).collect();
+    assert_eq!(reset, vec!["daily", "weekly"]);
+    assert_eq!(data.reset_flows[0].outflow, Uint256::from(40_u32));
+    assert_eq!(data.error, None);
+
+    // Nothing was applied
+    let msg = QueryMsg::GetQuotas {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+    };
+    let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+    let trackers = from_json::<Versioned<Vec<RateLimitResponse>>>(&res)
+        .unwrap()
+        .data;
+    assert_eq!(trackers.len(), 2);
+
+    // New paths, and the errors AddPath would fail with, are reported
+    let invalid = QuotaMsg::new("zero", RESET_TIME_DAILY, 0_u32.into(), 0_u32.into());
+    let data = preview(
+        deps.as_ref(),
+        PathMsg {
+            channel_id: format!("channel-1"),
+            ..path_msg(vec![invalid])
+        },
+    );
+    assert!(data.new_path);
+    assert_eq!(data.added.len(), 1);
+    assert!(data.reset_flows.is_empty());
+    assert!(data.error.is_some());
+}
+
+#[test] // Tests that the fee of a path is charged on accepted transfers and withdrawn by the owner
+fn path_fees() {
+    let mut deps = mock_dependencies();
+    let quota = QuotaMsg::new(
+        "weekly",
+        RESET_TIME_WEEKLY,
+        10_000_u32.into(),
+        10_000_u32.into(),
+    );
+    let msg = InstantiateMsg {
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+        key_spec: None,
+        seed_flows: vec![],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let owner = mock_info(OWNER, &[]);
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let path = PathId {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+    };
+    let set_fee = |fee_bps| ExecuteMsg::SetPathFee {
+        path: path.clone(),
+        fee_bps,
+    };
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), set_fee(50)).unwrap_err();
+    assert!(matches!(err, ContractError::Ownership(_)));
+    let err = execute(deps.as_mut(), mock_env(), owner.clone(), set_fee(2_000)).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::FeeTooHigh {
+            fee_bps: 2_000,
+            max: 1_000
+        }
+    );
+    execute(deps.as_mut(), mock_env(), owner.clone(), set_fee(50)).unwrap();
+
+    // The bridge can't clear the fee through the path config
+    let msg = ExecuteMsg::SetPathConfig {
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        config: PathConfig::default(),
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+
+    // Nor by removing and adding the path again
+    let msg = ExecuteMsg::RemovePath {
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+    let msg = ExecuteMsg::AddPath {
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        quotas: vec![QuotaMsg::new(
+            "weekly",
+            RESET_TIME_WEEKLY,
+            10_000_u32.into(),
+            10_000_u32.into(),
+        )],
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+
+    let send_msg = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 1_000_u32.into()
+    );
+    let res = execute(deps.as_mut(), mock_env(), bridge.clone(), send_msg.clone()).unwrap();
+    let data: TransferData = from_json(&res.data.unwrap()).unwrap();
+    assert_eq!(data.accepted, Uint128::new(1_000));
+    assert_eq!(data.fee, Uint128::new(5));
+
+    let fees = |deps: Deps| {
+        let msg = QueryMsg::GetFees {
+            contract: Addr::unchecked(BRIDGE_CONTRACT),
+            denom: format!("denom"),
+        };
+        from_json::<Versioned<FeeAccount>>(&query(deps, mock_env(), msg).unwrap())
+            .unwrap()
+            .data
+    };
+    assert_eq!(fees(deps.as_ref()).owed, Uint128::new(5));
+
+    // Sends wait for the fees to be paid, receives don't
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), send_msg.clone()).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::FeesOwed {
+            denom: format!("denom"),
+            owed: Uint128::new(5),
+        }
+    );
+    let msg = QueryMsg::CanSend {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        amount: 1_000_u32.into(),
+    };
+    let res: CanSendResponse =
+        from_json::<Versioned<_>>(&query(deps.as_ref(), mock_env(), msg).unwrap())
+            .unwrap()
+            .data;
+    assert!(!res.allowed);
+    assert_eq!(res.reason, Some(err.to_string()));
+    let recv_msg = test_msg_recv!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 200_u32.into()
+    );
+    execute(deps.as_mut(), mock_env(), bridge.clone(), recv_msg).unwrap();
+    assert_eq!(fees(deps.as_ref()).owed, Uint128::new(6));
+
+    // The bridge pays what it owes, and no more
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(BRIDGE_CONTRACT, &coins(7, "denom")),
+        ExecuteMsg::PayFees {},
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::FeeOverpaid { .. }));
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(BRIDGE_CONTRACT, &coins(6, "denom")),
+        ExecuteMsg::PayFees {},
+    )
+    .unwrap();
+    assert_eq!(
+        fees(deps.as_ref()),
+        FeeAccount {
+            owed: Uint128::zero(),
+            collected: Uint128::new(6),
+        }
+    );
+    execute(deps.as_mut(), mock_env(), bridge.clone(), send_msg).unwrap();
+
+    let withdraw = ExecuteMsg::WithdrawFees {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        denom: format!("denom"),
+        recipient: Some(format!("treasury")),
+    };
+    let err = execute(deps.as_mut(), mock_env(), bridge, withdraw.clone()).unwrap_err();
+    assert!(matches!(err, ContractError::Ownership(_)));
+    let res = execute(deps.as_mut(), mock_env(), owner, withdraw).unwrap();
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: format!("treasury"),
+            amount: coins(6, "denom"),
+        })
+    );
+    assert!(fees(deps.as_ref()).collected.is_zero());
+}
+
+#[test] // Tests that the invariant check reports corrupted entries and that the index is repaired
+fn invariants() {
+    let mut deps = mock_dependencies();
+    let quota = QuotaMsg::new("daily", RESET_TIME_DAILY, 100_u32.into(), 100_u32.into());
+    let msg = InstantiateMsg {
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+        key_spec: None,
+        seed_flows: vec![],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let msg = ExecuteMsg::SetPathConfig {
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        config: PathConfig {
+            max_in_flight: Some(Uint256::from(100_u32)),
+            ..PathConfig::default()
+        },
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+    for (sequence, amount) in [(1, 6_u32), (2, 4)] {
+        let msg = ExecuteMsg::SendPacket {
+            packet: Packet::mock(format!("channel"), format!("denom"), amount.into())
+                .with_sequence(sequence),
+        };
+        execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+    }
+
+    let check = |deps: Deps, limit| {
+        let mut violations = vec![];
+        let mut start_after = None;
+        loop {
+            let msg = QueryMsg::CheckInvariants { start_after, limit };
+            let res: Versioned<InvariantsResponse> =
+                from_json(&query(deps, mock_env(), msg).unwrap()).unwrap();
+            violations.extend(res.data.violations);
+            match res.data.next {
+                Some(next) => start_after = Some(next),
+                None => return violations,
+            }
+        }
+    };
+    assert_eq!(check(deps.as_ref(), None), vec![]);
+
+    // Corrupts the index, the stats and adds a send on a path that doesn't exist
+    let key: PathKey = (
+        Addr::unchecked(BRIDGE_CONTRACT),
+        format!("channel"),
+        format!("denom"),
+    );
+    IN_FLIGHT
+        .save(deps.as_mut().storage, key.clone(), &Uint256::from(3_u32))
+        .unwrap();
+    let mut stats = DAILY_STATS.load(&deps.storage, key.clone()).unwrap();
+    stats.outflow = Uint256::from(5_u32);
+    DAILY_STATS
+        .save(deps.as_mut().storage, key.clone(), &stats)
+        .unwrap();
+    let orphan = PendingSend {
+        denom: format!("denom"),
+        amount: Uint128::new(1),
+        periods: vec![PendingPeriod {
+            quota: format!("daily"),
+            period_end: Timestamp::from_nanos(0),
+        }],
+        in_flight: false,
+        escrowed: false,
+        tag: None,
+        pre_approved: false,
+        class_periods: vec![],
+        channel_periods: vec![],
+        chain_periods: vec![],
+        degraded_periods: vec![],
+    };
+    let orphan_key = (Addr::unchecked(BRIDGE_CONTRACT), format!("other"), 9);
+    PENDING_SENDS
+        .save(deps.as_mut().storage, orphan_key, &orphan)
+        .unwrap();
+
+    let invariants = |violations: Vec<InvariantViolation>| -> Vec<String> {
+        violations.into_iter().map(|v| v.invariant).collect()
+    };
+    let expected = vec![
+        "stats_cover_flows",
+        "in_flight_index",
+        "pending_send_path",
+        "period_end",
+    ];
+    assert_eq!(invariants(check(deps.as_ref(), None)), expected);
+    // Pages of a single entry go from the paths to the pending sends
+    assert_eq!(invariants(check(deps.as_ref(), Some(1))), expected);
+
+    let repair = ExecuteMsg::RepairInvariants {
+        start_after: None,
+        limit: None,
+    };
+    let err = execute(deps.as_mut(), mock_env(), bridge, repair.clone()).unwrap_err();
+    assert!(matches!(err, ContractError::Ownership(_)));
+    let res = execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), repair).unwrap();
+    assert!(res.attributes.contains(&Attribute::new("repaired", "1")));
+    let next: Option<PathKey> = from_json(&res.data.unwrap()).unwrap();
+    assert_eq!(next, None);
+
+    assert_eq!(
+        invariants(check(deps.as_ref(), None)),
+        vec!["stats_cover_flows", "pending_send_path", "period_end"]
+    );
+    let in_flight = IN_FLIGHT.load(&deps.storage, key.clone()).unwrap();
+    assert_eq!(in_flight, Uint256::from(10_u32));
+
+    // Sends recorded before the pending sends were indexed are on migration
+    PENDING_IN_FLIGHT.remove(deps.as_mut().storage, key.clone());
+    let msg = MigrateMsg {
+        keep_maintenance: false,
+    };
+    migrate(deps.as_mut(), mock_env(), msg).unwrap();
+    let pending = PENDING_IN_FLIGHT.load(&deps.storage, key).unwrap();
+    assert_eq!(pending, Uint256::from(10_u32));
+}
+
+#[test] // Tests that tagged transfers are accounted on the quotas of their tag instead of the path quotas
+fn tag_quotas() {
+    let mut deps = mock_dependencies();
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 100_u32.into(), 100_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let set_tag_quotas = |tag: &str, max: u32| ExecuteMsg::SetTagQuotas {
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        tag: tag.to_string(),
+        quotas: vec![QuotaMsg::new(
+            "weekly",
+            RESET_TIME_WEEKLY,
+            max.into(),
+            max.into(),
+        )],
+        idempotency_key: None,
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        set_tag_quotas("", 10),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::InvalidTag { .. }));
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        set_tag_quotas("user", 10),
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        set_tag_quotas("protocol", 150),
+    )
+    .unwrap();
+
+    let packet = |funds: u32, tag: &str| {
+        Packet::mock(format!("channel"), format!("denom"), funds.into()).with_tag(tag)
+    };
+    let send = |packet: Packet| ExecuteMsg::SendPacket { packet };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send(packet(11, "user")),
+    )
+    .unwrap_err();
+    assert!(
+        matches!(err, ContractError::RateLimitExceded { max, .. } if max == Uint256::from(10_u128))
+    );
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send(packet(10, "user").with_sequence(1)),
+    )
+    .unwrap();
+    // Protocol transfers have their own cap, even above the path quotas, and
+    // tags without quotas use the path quotas
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send(packet(120, "protocol")),
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send(packet(15, "liquidation")),
+    )
+    .unwrap();
+
+    let tag_outflow = |deps: Deps, tag: &str| {
+        let msg = QueryMsg::GetTagQuotas {
+            contract: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            tag: tag.to_string(),
+        };
+        let res = query(deps, mock_env(), msg).unwrap();
+        let value: Vec<RateLimitResponse> = from_json::<Versioned<_>>(&res).unwrap().data;
+        value[0].flow.outflow
+    };
+    assert_eq!(tag_outflow(deps.as_ref(), "user"), Uint256::from(10_u32));
+    assert_eq!(
+        tag_outflow(deps.as_ref(), "protocol"),
+        Uint256::from(120_u32)
+    );
+
+    // Undoing a tagged send gives its capacity back to the tag
+    let msg = ExecuteMsg::UndoSend {
+        packet: packet(10, "user").with_sequence(1),
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+    assert_eq!(tag_outflow(deps.as_ref(), "user"), Uint256::zero());
+
+    // Transfers accounted on their tag quotas don't use the path quotas
+    let msg = send(packet(86, "liquidation"));
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap_err();
+    assert!(
+        matches!(err, ContractError::RateLimitExceded { max, .. } if max == Uint256::from(100_u128))
+    );
+    let msg = send(packet(85, "liquidation"));
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+
+    // The tag quotas go with the path
+    let msg = ExecuteMsg::RemovePath {
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), bridge, msg).unwrap();
+    let msg = QueryMsg::GetTagQuotas {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        tag: format!("protocol"),
+    };
+    let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+    let quotas: Vec<RateLimitResponse> = from_json::<Versioned<_>>(&res).unwrap().data;
+    assert!(quotas.is_empty());
+}
+
+#[test] // Tests that quotas can't outlast the max duration and that longer stored ones are clamped
+fn max_quota_duration() {
+    let mut deps = mock_dependencies();
+    let weekly = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 100_u32.into(), 100_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![weekly.clone()],
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let add_path = |quota: QuotaMsg| ExecuteMsg::AddPath {
+        channel_id: format!("channel"),
+        denom: format!("other"),
+        quotas: vec![quota],
+        idempotency_key: None,
+    };
+    let endless = QuotaMsg::new("endless", u64::MAX, 100_u32.into(), 100_u32.into());
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), add_path(endless)).unwrap_err();
+    assert!(matches!(err, ContractError::QuotaDurationTooLong { .. }));
+
+    let update_config = |max_quota_duration| ExecuteMsg::UpdateConfig {
+        config: Config {
+            max_quota_duration,
+            ..Config::default()
+        },
+        idempotency_key: None,
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(OWNER, &[]),
+        update_config(Some(0)),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::InvalidMaxQuotaDuration {
+            max: MAX_QUOTA_DURATION
+        }
+    );
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(OWNER, &[]),
+        update_config(Some(u64::MAX)),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::InvalidMaxQuotaDuration { .. }));
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(OWNER, &[]),
+        update_config(Some(RESET_TIME_DAILY)),
+    )
+    .unwrap();
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), add_path(weekly)).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::QuotaDurationTooLong {
+            name: format!("weekly"),
+            duration_nanos: RESET_TIME_WEEKLY * NANOS_PER_SECOND,
+            max_nanos: RESET_TIME_DAILY * NANOS_PER_SECOND,
+        }
+    );
+
+    // The weekly quota stored before is clamped on the next packet
+    let msg = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 10_u32.into()
+    );
+    let res = execute(deps.as_mut(), mock_env(), bridge, msg).unwrap();
+    let event = res
+        .events
+        .iter()
+        .find(|event| event.ty == "clamp_quota_duration")
+        .unwrap();
+    assert!(event
+        .attributes
+        .contains(&Attribute::new("quotas", "weekly")));
+
+    let msg = QueryMsg::GetQuotas {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+    };
+    let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+    let value: Vec<RateLimitResponse> = from_json::<Versioned<_>>(&res).unwrap().data;
+    assert_eq!(
+        value[0].quota.duration_nanos,
+        RESET_TIME_DAILY * NANOS_PER_SECOND
+    );
+    assert_eq!(
+        value[0].flow.period_end,
+        mock_env().block.time.plus_seconds(RESET_TIME_DAILY)
+    );
+    assert_eq!(value[0].flow.outflow, Uint256::from(10_u32));
+}
+
+#[test] // Tests that a sequence of transfers is replayed against proposed quotas without storing anything
+fn simulate_sequence() {
+    let mut deps = mock_dependencies();
+    let quota = QuotaMsg::new("daily", RESET_TIME_DAILY, 100_u32.into(), 100_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg {
+            contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota],
+        }],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let start = mock_env().block.time;
+    let transfer = |amount: u32, seconds: u64| SimulatedTransfer {
+        direction: FlowType::Out,
+        amount: amount.into(),
+        at: start.plus_seconds(seconds),
+        channel_value: None,
+    };
+    let simulate = |deps: Deps, transfers, max_send: u32| {
+        let msg = QueryMsg::SimulateSequence {
+            path: PathId {
+                contract: Addr::unchecked(BRIDGE_CONTRACT),
+                channel_id: format!("channel"),
+                denom: format!("denom"),
+            },
+            transfers,
+            proposed_quotas: vec![QuotaMsg::new(
+                "daily",
+                RESET_TIME_DAILY,
+                max_send.into(),
+                100_u32.into(),
+            )],
+        };
+        query(deps, mock_env(), msg).map(|res| {
+            from_json::<Versioned<SimulationResponse>>(&res)
+                .unwrap()
+                .data
+        })
+    };
+
+    let transfers = vec![
+        transfer(30, 0),
+        transfer(30, 3_600),
+        transfer(20, 7_200),
+        transfer(40, RESET_TIME_DAILY + 1),
+    ];
+    let res = simulate(deps.as_ref(), transfers.clone(), 50).unwrap();
+    let accepted: Vec<Uint128> = res.outcomes.iter().map(|o| o.accepted).collect();
+    assert_eq!(
+        accepted,
+        vec![30_u32, 0, 20, 40]
+            .into_iter()
+            .map(Uint128::from)
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(res.rejected, 1);
+    assert_eq!(res.outcomes[1].violations[0].quota_name, "daily");
+    assert_eq!(res.outcomes[1].violations[0].max, Uint256::from(50_u32));
+    assert_eq!(res.usage[0].outflow, Uint256::from(40_u32));
+    assert_eq!(res.error, None);
+
+    // The current quotas of the path are left untouched
+    let msg = QueryMsg::GetQuotas {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+    };
+    let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+    let value: Vec<RateLimitResponse> = from_json::<Versioned<_>>(&res).unwrap().data;
+    assert_eq!(value[0].flow.outflow, Uint256::zero());
+    assert_eq!(value[0].quota.max_send, Uint256::from(100_u32));
+
+    // Invalid quotas are reported instead of simulated
+    let res = simulate(deps.as_ref(), transfers.clone(), 0).unwrap();
+    assert!(res.outcomes.is_empty());
+    assert!(res.error.is_some());
+
+    let mut unordered = transfers;
+    unordered.swap(0, 1);
+    simulate(deps.as_ref(), unordered, 50).unwrap_err();
+}
+
+#[test] // Tests that the settings overridden by a tenant only apply to its paths
+fn tenant_config() {
+    let mut deps = mock_dependencies();
+    let msg = InstantiateMsg {
+        paths: vec![],
+        key_spec: None,
+        seed_flows: vec![],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let owner = mock_info(OWNER, &[]);
+    let set_tenant = |config| ExecuteMsg::SetTenantConfig {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        config,
+    };
+    let strict = TenantConfig {
+        unconfigured_behavior: Some(UnconfiguredBehavior::Reject),
+        ..TenantConfig::default()
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(BRIDGE_CONTRACT, &[]),
+        set_tenant(Some(strict.clone())),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::Ownership(_)));
+    let invalid = TenantConfig {
+        attribute_schema_version: Some(ATTRIBUTE_SCHEMA_VERSION + 1),
+        ..TenantConfig::default()
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        owner.clone(),
+        set_tenant(Some(invalid)),
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::UnsupportedAttributeSchema { .. }
+    ));
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        owner.clone(),
+        set_tenant(Some(strict.clone())),
+    )
+    .unwrap();
+
+    let msg = QueryMsg::GetTenantConfig {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+    };
+    let res: TenantConfigResponse =
+        from_json::<Versioned<_>>(&query(deps.as_ref(), mock_env(), msg).unwrap())
+            .unwrap()
+            .data;
+    assert_eq!(res.overrides, Some(strict));
+    assert_eq!(
+        res.config.unconfigured_behavior,
+        UnconfiguredBehavior::Reject
+    );
+
+    // Only the tenant rejects packets on paths without quotas
+    let send = || {
+        test_msg_send!(
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            funds: 10_u32.into()
+        )
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(BRIDGE_CONTRACT, &[]),
+        send(),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::PathNotConfigured { .. }));
+    execute(deps.as_mut(), mock_env(), mock_info("other", &[]), send()).unwrap();
+
+    // Tenant default quotas become the trackers of its new paths
+    let quota = QuotaMsg::new("daily", RESET_TIME_DAILY, 5_u32.into(), 5_u32.into());
+    let defaults = TenantConfig {
+        default_quotas: Some(vec![quota]),
+        ..TenantConfig::default()
+    };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        owner.clone(),
+        set_tenant(Some(defaults)),
+    )
+    .unwrap();
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(BRIDGE_CONTRACT, &[]),
+        send(),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::RateLimitExceded { .. }));
+
+    execute(deps.as_mut(), mock_env(), owner, set_tenant(None)).unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(BRIDGE_CONTRACT, &[]),
+        send(),
+    )
+    .unwrap();
+}
+
+#[test] // Tests that packets of denoms outside of the allowed assets of a channel are rejected
+fn channel_assets() {
+    let mut deps = mock_dependencies();
+    let msg = InstantiateMsg {
+        paths: vec![],
+        key_spec: None,
+        seed_flows: vec![],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let set_assets = |denoms: Vec<&str>| ExecuteMsg::SetChannelAssets {
+        channel_id: format!("channel"),
+        denoms: denoms.into_iter().map(String::from).collect(),
+    };
+    let msg = set_assets(vec![
+        "uosmo",
+        "ibc/27394fb092d2eccd56123c74f36e4c1f926001ceada9ca97ea622b25f41e5eb2",
+    ]);
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+
+    let send = |channel: &str, denom: &str| {
+        test_msg_send!(
+            channel_id: channel.to_string(),
+            denom: denom.to_string(),
+            funds: 10_u32.into()
+        )
+    };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send("channel", "uosmo"),
+    )
+    .unwrap();
+    // Denoms are compared once normalized
+    let ibc = "ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2";
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send("channel", ibc),
+    )
+    .unwrap();
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send("channel", "uspoofed"),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::DenomNotAllowed {
+            channel_id: format!("channel"),
+            denom: format!("uspoofed"),
+        }
+    );
+    // Other channels and other contracts aren't restricted
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send("other", "uspoofed"),
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("other_bridge", &[]),
+        send("channel", "uspoofed"),
+    )
+    .unwrap();
+
+    let msg = QueryMsg::CanSend {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("uspoofed"),
+        amount: Uint128::new(1),
+    };
+    let res: CanSendResponse =
+        from_json::<Versioned<_>>(&query(deps.as_ref(), mock_env(), msg).unwrap())
+            .unwrap()
+            .data;
+    assert!(!res.allowed);
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        set_assets(vec![]),
+    )
+    .unwrap();
+    let msg = QueryMsg::GetChannelAssets {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+    };
+    let denoms: Versioned<Vec<String>> =
+        from_json(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+    assert!(denoms.data.is_empty());
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge,
+        send("channel", "uspoofed"),
+    )
+    .unwrap();
+}
+
+#[test] // Tests that the remaining capacity and the flows are bucketed for everyone
+fn capacity_redaction() {
+    let mut deps = mock_dependencies();
+    let quota = QuotaMsg::new("daily", RESET_TIME_DAILY, 1000_u32.into(), 1000_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg::new(
+            &Addr::unchecked(BRIDGE_CONTRACT),
+            "channel",
+            "denom",
+            vec![quota],
+        )],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+    let msg = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 137_u32.into()
+    );
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(BRIDGE_CONTRACT, &[]),
+        msg,
+    )
+    .unwrap();
+
+    let update_config = |capacity_bucket_bps| ExecuteMsg::UpdateConfig {
+        config: Config {
+            capacity_bucket_bps,
+            ..Config::default()
+        },
+        idempotency_key: None,
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(OWNER, &[]),
+        update_config(Some(0)),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::InvalidCapacityBucket { bps: 0 });
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(OWNER, &[]),
+        update_config(Some(1000)),
+    )
+    .unwrap();
+
+    let remaining = |deps: &OwnedDeps<_, _, _>| {
+        let msg = QueryMsg::GetRemainingCapacity {
+            contract: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+        };
+        let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let remaining = from_json::<Versioned<Vec<RemainingCapacity>>>(&res)
+            .unwrap()
+            .data;
+        (remaining[0].send, remaining[0].recv)
+    };
+    let outflow = |deps: &OwnedDeps<_, _, _>| {
+        let msg = QueryMsg::GetQuotas {
+            contract: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+        };
+        let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+        from_json::<Versioned<Vec<RateLimitResponse>>>(&res)
+            .unwrap()
+            .data[0]
+            .flow
+            .outflow
+    };
+    // Rounded down to 10% of the capacity, the send netting the receive
+    assert_eq!(
+        remaining(&deps),
+        (Uint256::from(800_u32), Uint256::from(1100_u32))
+    );
+    // The flows don't give the exact capacity away either
+    assert_eq!(outflow(&deps), Uint256::from(200_u32));
+    let msg = QueryMsg::GetContractUsage {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        start_after: None,
+        limit: None,
+    };
+    let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+    let usage: ContractUsageResponse = from_json::<Versioned<_>>(&res).unwrap().data;
+    assert_eq!(usage.paths[0].quotas[0].outflow, Uint256::from(200_u32));
+    assert_eq!(usage.totals[0].outflow, Uint256::from(200_u32));
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(OWNER, &[]),
+        update_config(None),
+    )
+    .unwrap();
+    assert_eq!(remaining(&deps).0, Uint256::from(863_u32));
+    assert_eq!(outflow(&deps), Uint256::from(137_u32));
+}
+
+#[test] // Tests that a pause with an auto_unpause_at is lifted by the first packet after it
+fn auto_unpause() {
+    let mut deps = mock_dependencies();
+    let quota = QuotaMsg::new("daily", RESET_TIME_DAILY, 100_u32.into(), 100_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg::new(
+            &Addr::unchecked(BRIDGE_CONTRACT),
+            "channel",
+            "denom",
+            vec![quota],
+        )],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let msg = ExecuteMsg::SetPaused {
+        paused: true,
+        auto_unpause_at: Some(mock_env().block.time),
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::PauseExpiryInPast {
+            auto_unpause_at: mock_env().block.time
+        }
+    );
+
+    let expiry = mock_env().block.time.plus_seconds(3600);
+    let msg = ExecuteMsg::SetPaused {
+        paused: true,
+        auto_unpause_at: Some(expiry),
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let send = |deps: &mut OwnedDeps<_, _, _>, env: Env| {
+        let msg = test_msg_send!(
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            funds: 1_u32.into()
+        );
+        execute(deps.as_mut(), env, mock_info(BRIDGE_CONTRACT, &[]), msg)
+    };
+    let mut before = mock_env();
+    before.block.time = expiry.minus_seconds(1);
+    assert_eq!(
+        send(&mut deps, before).unwrap_err(),
+        ContractError::Paused {}
+    );
+
+    let mut after = mock_env();
+    after.block.time = expiry;
+    let overview = |deps: &OwnedDeps<_, _, _>, env: Env| {
+        let msg = QueryMsg::GetPathOverview {
+            contract: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+        };
+        let res = query(deps.as_ref(), env, msg).unwrap();
+        from_json::<Versioned<PathOverviewResponse>>(&res)
+            .unwrap()
+            .data
+    };
+    // Expired already before a packet lifts it
+    assert!(!overview(&deps, after.clone()).paused);
+    let res = send(&mut deps, after.clone()).unwrap();
+    let event = res
+        .events
+        .iter()
+        .find(|event| event.ty == "auto_unpaused")
+        .unwrap();
+    assert!(event
+        .attributes
+        .contains(&Attribute::new("auto_unpause_at", expiry.to_string())));
+    assert!(!PAUSED.load(&deps.storage).unwrap());
+    assert_eq!(PAUSE_EXPIRY.may_load(&deps.storage).unwrap(), None);
+    let res = send(&mut deps, after).unwrap();
+    assert!(!res.events.iter().any(|event| event.ty == "auto_unpaused"));
+
+    // Unpausing by hand drops the schedule
+    let msg = ExecuteMsg::SetPaused {
+        paused: true,
+        auto_unpause_at: Some(expiry),
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+    let msg = ExecuteMsg::SetPaused {
+        paused: false,
+        auto_unpause_at: Some(expiry),
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+    assert_eq!(PAUSE_EXPIRY.may_load(&deps.storage).unwrap(), None);
+}
+
+#[test] // Tests that the chain quotas cap the paths of a denom linked to a counterparty chain together
+fn chain_quotas() {
+    let mut deps = mock_dependencies();
+    let quota = QuotaMsg::new("daily", RESET_TIME_DAILY, 100_u32.into(), 100_u32.into());
+    let bridge_addr = Addr::unchecked(BRIDGE_CONTRACT);
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![
+            PathMsg::new(&bridge_addr, "channel-a", "denom", vec![quota.clone()]),
+            PathMsg::new(&bridge_addr, "channel-b", "denom", vec![quota.clone()]),
+            PathMsg::new(&bridge_addr, "channel-b", "other", vec![quota.clone()]),
+            PathMsg::new(&bridge_addr, "channel-c", "denom", vec![quota]),
+        ],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let linked = [
+        ("channel-a", "denom"),
+        ("channel-b", "denom"),
+        ("channel-b", "other"),
+    ];
+    for (channel_id, denom) in linked {
+        let msg = ExecuteMsg::SetPathConfig {
+            channel_id: channel_id.to_string(),
+            denom: denom.to_string(),
+            config: PathConfig {
+                counterparty: Some(CounterpartyPath {
+                    chain_id: format!("osmosis-1"),
+                    contract: format!("osmo1ratelimiter"),
+                    channel_id: format!("channel-216"),
+                    denom: format!("ibc/denom"),
+                    relayer: Addr::unchecked("relayer"),
+                }),
+                ..PathConfig::default()
+            },
+            idempotency_key: None,
+        };
+        execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+    }
+    let set_chain_quotas = |chain_id: &str| ExecuteMsg::SetChainQuotas {
+        chain_id: chain_id.to_string(),
+        denom: format!("denom"),
+        quotas: vec![QuotaMsg::new(
+            "daily",
+            RESET_TIME_DAILY,
+            50_u32.into(),
+            50_u32.into(),
+        )],
+        idempotency_key: None,
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        set_chain_quotas(""),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::InvalidChainId { .. }));
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        set_chain_quotas("osmosis-1"),
+    )
+    .unwrap();
+
+    let packet = |channel_id: &str, denom: &str, funds: u32| {
+        Packet::mock(channel_id.to_string(), denom.to_string(), funds.into())
+    };
+    let send = |packet: Packet| ExecuteMsg::SendPacket { packet };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send(packet("channel-a", "denom", 30)),
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send(packet("channel-b", "denom", 20).with_sequence(1)),
+    )
+    .unwrap();
+    // Both paths fit their own quotas, but not the chain's together
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send(packet("channel-b", "denom", 1)),
+    )
+    .unwrap_err();
+    assert!(
+        matches!(err, ContractError::RateLimitExceded { max, .. } if max == Uint256::from(50_u32))
+    );
+    let msg = QueryMsg::CanSend {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel-b"),
+        denom: format!("denom"),
+        amount: 1_u32.into(),
+    };
+    let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+    assert!(
+        !from_json::<Versioned<CanSendResponse>>(&res)
+            .unwrap()
+            .data
+            .allowed
+    );
+    // Other denoms don't add up with it
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send(packet("channel-b", "other", 60)),
+    )
+    .unwrap();
+    // Paths without a counterparty aren't linked to the chain
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send(packet("channel-c", "denom", 60)),
+    )
+    .unwrap();
+
+    let chain_outflow = |deps: Deps| {
+        let msg = QueryMsg::GetChainQuotas {
+            contract: Addr::unchecked(BRIDGE_CONTRACT),
+            chain_id: format!("osmosis-1"),
+            denom: format!("denom"),
+        };
+        let res = query(deps, mock_env(), msg).unwrap();
+        let value: Vec<RateLimitResponse> = from_json::<Versioned<_>>(&res).unwrap().data;
+        value[0].flow.outflow
+    };
+    assert_eq!(chain_outflow(deps.as_ref()), Uint256::from(50_u32));
+
+    // Undone sends give their capacity back to the chain
+    let msg = ExecuteMsg::UndoSend {
+        packet: packet("channel-b", "denom", 20).with_sequence(1),
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+    assert_eq!(chain_outflow(deps.as_ref()), Uint256::from(30_u32));
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        bridge.clone(),
+        send(packet("channel-b", "denom", 20)),
+    )
+    .unwrap();
+
+    // But only to the period they were accounted in
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(RESET_TIME_DAILY + 1);
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        bridge.clone(),
+        send(packet("channel-a", "denom", 10).with_sequence(2)),
+    )
+    .unwrap();
+    env.block.time = env.block.time.plus_seconds(RESET_TIME_DAILY + 1);
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        bridge.clone(),
+        send(packet("channel-b", "denom", 15)),
+    )
+    .unwrap();
+    let msg = ExecuteMsg::UndoSend {
+        packet: packet("channel-a", "denom", 10).with_sequence(2),
+    };
+    execute(deps.as_mut(), env, bridge, msg).unwrap();
+    assert_eq!(chain_outflow(deps.as_ref()), Uint256::from(15_u32));
+}
+
+#[test] // Tests that sends are frozen or degraded once the keeper misses a heartbeat
+fn heartbeat() {
+    let mut deps = mock_dependencies();
+    let quota = QuotaMsg::new("daily", RESET_TIME_DAILY, 100_u32.into(), 100_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg::new(
+            &Addr::unchecked(BRIDGE_CONTRACT),
+            "channel",
+            "denom",
+            vec![quota],
+        )],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let update_config = |on_missed: OnMissedHeartbeat| ExecuteMsg::UpdateConfig {
+        config: Config {
+            heartbeat: Some(HeartbeatPolicy {
+                keeper: Addr::unchecked("keeper"),
+                interval: 3600,
+                on_missed,
+            }),
+            ..Config::default()
+        },
+        idempotency_key: None,
+    };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(OWNER, &[]),
+        update_config(OnMissedHeartbeat::Freeze),
+    )
+    .unwrap();
+
+    let at = |seconds: u64| {
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(seconds);
+        env
+    };
+    let send = |deps: &mut OwnedDeps<_, _, _>, env: Env, funds: u32| {
+        let msg = test_msg_send!(
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            funds: funds.into()
+        );
+        execute(deps.as_mut(), env, mock_info(BRIDGE_CONTRACT, &[]), msg)
+    };
+    // The keeper has a full interval from the config update
+    send(&mut deps, at(3600), 1).unwrap();
+    let err = send(&mut deps, at(3601), 1).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::HeartbeatMissed {
+            last_heartbeat: mock_env().block.time
+        }
+    );
+    let can_send = |deps: &OwnedDeps<_, _, _>, env: Env, amount: u32| {
+        let msg = QueryMsg::CanSend {
+            contract: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            amount: amount.into(),
+        };
+        from_json::<Versioned<CanSendResponse>>(&query(deps.as_ref(), env, msg).unwrap())
+            .unwrap()
+            .data
+            .allowed
+    };
+    assert!(!can_send(&deps, at(3601), 1));
+    // Receives still go through
+    let msg = test_msg_recv!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 1_u32.into()
+    );
+    execute(
+        deps.as_mut(),
+        at(3601),
+        mock_info(BRIDGE_CONTRACT, &[]),
+        msg,
+    )
+    .unwrap();
+
+    let err = execute(
+        deps.as_mut(),
+        at(3601),
+        mock_info("anyone", &[]),
+        ExecuteMsg::Heartbeat {},
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    execute(
+        deps.as_mut(),
+        at(3601),
+        mock_info("keeper", &[]),
+        ExecuteMsg::Heartbeat {},
+    )
+    .unwrap();
+    send(&mut deps, at(3601), 1).unwrap();
+
+    // Degraded sends fit the stricter quotas on top of the path quotas
+    let degraded = vec![QuotaMsg::new(
+        "degraded",
+        RESET_TIME_DAILY,
+        5_u32.into(),
+        5_u32.into(),
+    )];
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(OWNER, &[]),
+        update_config(OnMissedHeartbeat::Degrade { quotas: degraded }),
+    )
+    .unwrap();
+    let res = send(&mut deps, at(7300), 5).unwrap();
+    assert!(res
+        .events
+        .iter()
+        .any(|event| event.ty == "heartbeat_missed"));
+    let err = send(&mut deps, at(7300), 1).unwrap_err();
+    assert!(
+        matches!(err, ContractError::RateLimitExceded { max, .. } if max == Uint256::from(5_u32))
+    );
+    assert!(!can_send(&deps, at(7300), 1));
+
+    let res = query(deps.as_ref(), at(7300), QueryMsg::GetHeartbeat {}).unwrap();
+    let heartbeat: HeartbeatResponse = from_json::<Versioned<_>>(&res).unwrap().data;
+    assert_eq!(
+        heartbeat,
+        HeartbeatResponse {
+            last_heartbeat: Some(at(3601).block.time),
+            missed: true,
+        }
+    );
+
+    // A new policy starts the degraded flows over
+    let degraded = vec![QuotaMsg::new(
+        "degraded",
+        RESET_TIME_DAILY,
+        10_u32.into(),
+        10_u32.into(),
+    )];
+    execute(
+        deps.as_mut(),
+        at(7300),
+        mock_info(OWNER, &[]),
+        update_config(OnMissedHeartbeat::Degrade { quotas: degraded }),
+    )
+    .unwrap();
+    assert!(can_send(&deps, at(7300), 10));
+    send(&mut deps, at(7300), 10).unwrap();
+
+    // Undoing a send made with the heartbeats on leaves the degraded flows
+    execute(
+        deps.as_mut(),
+        at(7300),
+        mock_info("keeper", &[]),
+        ExecuteMsg::Heartbeat {},
+    )
+    .unwrap();
+    let msg = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 1_u32.into(),
+        sequence: 1
+    );
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    execute(deps.as_mut(), at(7300), bridge.clone(), msg).unwrap();
+    let msg = test_msg_undo!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 1_u32.into(),
+        sequence: 1
+    );
+    execute(deps.as_mut(), at(7300), bridge, msg).unwrap();
+    let key = (
+        Addr::unchecked(BRIDGE_CONTRACT),
+        format!("channel"),
+        format!("denom"),
+    );
+    let trackers = DEGRADED_TRACKERS.load(&deps.storage, key).unwrap();
+    assert_eq!(trackers[0].flow.outflow, Uint256::from(10_u32));
+
+    let msg = ExecuteMsg::UpdateConfig {
+        config: Config {
+            heartbeat: Some(HeartbeatPolicy {
+                keeper: Addr::unchecked("keeper"),
+                interval: u64::MAX,
+                on_missed: OnMissedHeartbeat::Freeze,
+            }),
+            ..Config::default()
+        },
+        idempotency_key: None,
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::InvalidHeartbeatInterval {
+            max: 365 * RESET_TIME_DAILY
+        }
+    );
+}
+
+#[test] // Tests that sweeping forwards what the contract holds beyond the escrow and the fees
+fn sweep() {
+    let mut deps = mock_dependencies();
+    let quota = QuotaMsg::new("daily", RESET_TIME_DAILY, 1000_u32.into(), 1000_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg::new(
+            &Addr::unchecked(BRIDGE_CONTRACT),
+            "channel",
+            "orai",
+            vec![quota],
+        )],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+    let msg = ExecuteMsg::SetPathConfig {
+        channel_id: format!("channel"),
+        denom: format!("orai"),
+        config: PathConfig {
+            escrow: true,
+            ..PathConfig::default()
+        },
+        idempotency_key: None,
+    };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(BRIDGE_CONTRACT, &[]),
+        msg,
+    )
+    .unwrap();
+    let msg = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("orai"),
+        funds: 300_u32.into(),
+        sequence: 1
+    );
+    let info = mock_info(BRIDGE_CONTRACT, &coins(300, "orai"));
+    execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    // 50 orai were sent to the contract by mistake on top of the escrow
+    deps.querier
+        .update_balance(MOCK_CONTRACT_ADDR, coins(350, "orai"));
+
+    let sweep = |denom: &str| ExecuteMsg::Sweep {
+        denom: denom.to_string(),
+        to: format!("user"),
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("anyone", &[]),
+        sweep("orai"),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::Ownership(_)));
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(OWNER, &[]),
+        sweep("orai"),
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(BankMsg::Send {
+            to_address: format!("user"),
+            amount: coins(50, "orai"),
+        })]
+    );
+    assert!(res
+        .events
+        .iter()
+        .any(|event| event.ty == "swept"
+            && event.attributes.contains(&Attribute::new("amount", "50"))));
+
+    // Nothing to sweep beyond the escrow
+    deps.querier
+        .update_balance(MOCK_CONTRACT_ADDR, coins(300, "orai"));
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(OWNER, &[]),
+        sweep("orai"),
+    )
+    .unwrap();
+    assert!(res.messages.is_empty());
+
+    deps.querier.update_wasm(|query| match query {
+        WasmQuery::Smart { contract_addr, .. } if contract_addr == "token" => {
+            let response = cw20::BalanceResponse {
+                balance: Uint128::new(40),
+            };
+            SystemResult::Ok(ContractResult::Ok(to_json_binary(&response).unwrap()))
+        }
+        _ => panic!("unexpected query"),
+    });
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(OWNER, &[]),
+        sweep("cw20:token"),
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(WasmMsg::Execute {
+            contract_addr: format!("token"),
+            msg: to_json_binary(&cw20::Cw20ExecuteMsg::Transfer {
+                recipient: format!("user"),
+                amount: Uint128::new(40),
+            })
+            .unwrap(),
+            funds: vec![],
+        })]
+    );
+}
+
+#[test] // Tests that stale channel values fall back to the floor of percentage quotas or reject packets
+fn stale_channel_value() {
+    let mut deps = mock_dependencies();
+    let quota = QuotaMsg::percentage(
+        "daily",
+        RESET_TIME_DAILY,
+        PercentageLimit {
+            send: Decimal::percent(10),
+            recv: Decimal::percent(10),
+            rounding: Rounding::Floor,
+            min_capacity: Uint256::from(20_u32),
+        },
+    );
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg::new(
+            &Addr::unchecked(BRIDGE_CONTRACT),
+            "channel",
+            "denom",
+            vec![quota],
+        )],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+    let update_config = |on_stale_channel_value| ExecuteMsg::UpdateConfig {
+        config: Config {
+            escrow_querier: Some(Addr::unchecked("querier")),
+            channel_value_max_age: Some(3600),
+            on_stale_channel_value,
+            ..Config::default()
+        },
+        idempotency_key: None,
+    };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(OWNER, &[]),
+        update_config(OnStaleChannelValue::Floor),
+    )
+    .unwrap();
+
+    let at = |seconds: u64| {
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(seconds);
+        env
+    };
+    let report = |deps: &mut OwnedDeps<_, _, _>, env: Env| {
+        let msg = ExecuteMsg::EscrowBalanceCallback {
+            contract: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            balance: 5000_u32.into(),
+        };
+        execute(deps.as_mut(), env, mock_info("querier", &[]), msg).unwrap();
+    };
+    let send = |deps: &mut OwnedDeps<_, _, _>, env: Env, funds: u32| {
+        let msg = test_msg_send!(
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            funds: funds.into()
+        );
+        execute(deps.as_mut(), env, mock_info(BRIDGE_CONTRACT, &[]), msg)
+    };
+    report(&mut deps, mock_env());
+
+    // Two hours later, the 5000 reported is stale and the quota has its floor
+    let err = send(&mut deps, at(7200), 21).unwrap_err();
+    assert!(
+        matches!(err, ContractError::RateLimitExceded { max, .. } if max == Uint256::from(20_u32))
+    );
+    send(&mut deps, at(7200), 20).unwrap();
+
+    // Anyone can ask for a fresh value
+    let msg = ExecuteMsg::RefreshChannelValue {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+    };
+    let res = execute(deps.as_mut(), at(7200), mock_info("anyone", &[]), msg).unwrap();
+    assert!(matches!(
+        &res.messages[0].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, .. }) if contract_addr == "querier"
+    ));
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(OWNER, &[]),
+        update_config(OnStaleChannelValue::Reject),
+    )
+    .unwrap();
+    let next_day = RESET_TIME_DAILY + 7200;
+    let err = send(&mut deps, at(next_day), 1).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::StaleChannelValue {
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            updated_at: mock_env().block.time,
+        }
+    );
+    // 10% of a fresh value
+    report(&mut deps, at(next_day));
+    send(&mut deps, at(next_day), 500).unwrap();
+
+    // A max age past the end of time keeps the value fresh
+    let msg = ExecuteMsg::UpdateConfig {
+        config: Config {
+            escrow_querier: Some(Addr::unchecked("querier")),
+            channel_value_max_age: Some(u64::MAX),
+            on_stale_channel_value: OnStaleChannelValue::Reject,
+            ..Config::default()
+        },
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+    send(&mut deps, at(3 * RESET_TIME_DAILY), 500).unwrap();
+}
+
+#[test] // Tests that partitioned quotas get the share of the partition of the day
+fn day_partitions() {
+    let mut deps = mock_dependencies();
+    let partition = |start: u32, end: u32, share_bps: u32| DayPartition {
+        start,
+        end,
+        share_bps,
+    };
+    let quota = |partitions: Vec<DayPartition>| {
+        QuotaMsg::new("daily", RESET_TIME_DAILY, 1000_u32.into(), 1000_u32.into())
+            .with_partitions(partitions)
+    };
+    let add_path = |deps: &mut OwnedDeps<_, _, _>, partitions: Vec<DayPartition>| {
+        let msg = SudoMsg::AddPath {
+            contract: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            quotas: vec![quota(partitions)],
+        };
+        sudo(deps.as_mut(), mock_env(), msg)
+    };
+
+    // Partitions must cover the day from midnight to midnight, without gaps
+    // or overlaps
+    let invalid = ContractError::InvalidDayPartitions {
+        name: format!("daily"),
+    };
+    for partitions in [
+        vec![partition(0, 28_800, 3000)],
+        vec![
+            partition(0, 28_800, 3000),
+            partition(28_801, 86_400, 10_000),
+        ],
+        vec![
+            partition(0, 28_800, 3000),
+            partition(28_000, 86_400, 10_000),
+        ],
+        vec![partition(3600, 86_400, 3000)],
+        vec![partition(0, 0, 3000), partition(0, 86_400, 10_000)],
+        vec![partition(0, 86_400, 10_001)],
+    ] {
+        assert_eq!(add_path(&mut deps, partitions).unwrap_err(), invalid);
+    }
+
+    // 00:00-08:00 UTC gets 30% of the cap
+    add_path(
+        &mut deps,
+        vec![
+            partition(0, 28_800, 3000),
+            partition(28_800, 86_400, 10_000),
+        ],
+    )
+    .unwrap();
+
+    let midnight = mock_env().block.time.seconds() / 86_400 * 86_400;
+    let at = |second_of_day: u64| {
+        let mut env = mock_env();
+        env.block.time = Timestamp::from_seconds(midnight + second_of_day);
+        env
+    };
+    let send = |deps: &mut OwnedDeps<_, _, _>, env: Env, funds: u32| {
+        let msg = test_msg_send!(
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            funds: funds.into()
+        );
+        execute(deps.as_mut(), env, mock_info(BRIDGE_CONTRACT, &[]), msg)
+    };
+
+    let last_off_hours_second = 28_799;
+    send(&mut deps, at(last_off_hours_second), 300).unwrap();
+    let err = send(&mut deps, at(last_off_hours_second), 1).unwrap_err();
+    assert!(
+        matches!(err, ContractError::RateLimitExceded { max, .. } if max == Uint256::from(300_u32))
+    );
+
+    // The full cap is back at 08:00, for the same period
+    send(&mut deps, at(28_800), 700).unwrap();
+    let err = send(&mut deps, at(28_800), 1).unwrap_err();
+    assert!(
+        matches!(err, ContractError::RateLimitExceded { max, .. } if max == Uint256::from(1000_u32))
+    );
+}
+
+#[test] // Tests that the quotas of many paths are returned in order, None for missing paths
+fn quotas_batch() {
+    let mut deps = mock_dependencies();
+    let bridge = Addr::unchecked(BRIDGE_CONTRACT);
+    let quota = |max: u32| QuotaMsg::new("daily", RESET_TIME_DAILY, max.into(), max.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![
+            PathMsg::new(&bridge, "channel", "denom1", vec![quota(10)]),
+            PathMsg::new(&bridge, "channel", "denom2", vec![quota(20)]),
+        ],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let path_id = |denom: &str| PathId {
+        contract: bridge.clone(),
+        channel_id: format!("channel"),
+        denom: denom.to_string(),
+    };
+    let batch = |deps: Deps, paths: Vec<PathId>| {
+        query(deps, mock_env(), QueryMsg::GetQuotasBatch { paths })
+            .map(|res| from_json::<Versioned<Vec<Option<Vec<RateLimitResponse>>>>>(&res).unwrap())
+    };
+
+    let res = batch(
+        deps.as_ref(),
+        vec![path_id("denom2"), path_id("missing"), path_id("denom1")],
+    )
+    .unwrap();
+    let max_sends: Vec<_> = res
+        .data
+        .iter()
+        .map(|quotas| quotas.as_ref().map(|quotas| quotas[0].quota.max_send))
+        .collect();
+    assert_eq!(
+        max_sends,
+        vec![
+            Some(Uint256::from(20_u32)),
+            None,
+            Some(Uint256::from(10_u32))
+        ]
+    );
+
+    let too_many = vec![path_id("denom1"); 101];
+    assert!(batch(deps.as_ref(), too_many).is_err());
+}
+
+#[test] // Tests that cw-ics20 transfer hooks are accounted on the path of their local denom
+fn ics20_transfer_hook() {
+    let mut deps = mock_dependencies();
+    let quota = QuotaMsg::new("daily", RESET_TIME_DAILY, 10_u32.into(), 10_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg::new(
+            &Addr::unchecked(BRIDGE_CONTRACT),
+            "channel",
+            "cw20:token",
+            vec![quota],
+        )],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let hook = |amount: u32, is_receive: bool| {
+        ExecuteMsg::Ics20TransferHook(Ics20Transfer {
+            channel: format!("channel"),
+            remote_denom: format!("uatom"),
+            local_denom: format!("cw20:token"),
+            amount: amount.into(),
+            is_receive,
+        })
+    };
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let res = execute(deps.as_mut(), mock_env(), bridge.clone(), hook(10, true)).unwrap();
+    assert!(res
+        .attributes
+        .contains(&Attribute::new("remote_denom", "uatom")));
+    let err = execute(deps.as_mut(), mock_env(), bridge.clone(), hook(1, true)).unwrap_err();
+    assert!(matches!(err, ContractError::RateLimitExceded { .. }));
+    execute(deps.as_mut(), mock_env(), bridge, hook(11, false)).unwrap();
+
+    let key = (
+        Addr::unchecked(BRIDGE_CONTRACT),
+        format!("channel"),
+        format!("cw20:token"),
+    );
+    let flow = &RATE_LIMIT_TRACKERS.load(&deps.storage, key).unwrap()[0].flow;
+    assert_eq!(flow.inflow, Uint256::from(10_u32));
+    assert_eq!(flow.outflow, Uint256::from(11_u32));
+}
+
+#[test] // Tests that new paths run at a share of their quotas until they graduate
+fn probation() {
+    let mut deps = mock_dependencies();
+    let quota = || QuotaMsg::new("daily", RESET_TIME_DAILY, 1000_u32.into(), 1000_u32.into());
+    let bridge = Addr::unchecked(BRIDGE_CONTRACT);
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg::new(&bridge, "channel", "existing", vec![quota()])],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let update_config = |deps: &mut OwnedDeps<_, _, _>, probation| {
+        let msg = ExecuteMsg::UpdateConfig {
+            config: Config {
+                probation,
+                ..Config::default()
+            },
+            idempotency_key: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg)
+    };
+    let err = update_config(
+        &mut deps,
+        Some(ProbationPolicy {
+            periods: 0,
+            share_bps: 2500,
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::InvalidProbation {});
+    update_config(
+        &mut deps,
+        Some(ProbationPolicy {
+            periods: 2,
+            share_bps: 2500,
+        }),
+    )
+    .unwrap();
+
+    let at = |seconds: u64| {
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(seconds);
+        env
+    };
+    let add_path = |deps: &mut OwnedDeps<_, _, _>, env: Env| {
+        let msg = ExecuteMsg::AddPath {
+            channel_id: format!("channel"),
+            denom: format!("new"),
+            quotas: vec![quota()],
+            idempotency_key: None,
+        };
+        execute(deps.as_mut(), env, mock_info(BRIDGE_CONTRACT, &[]), msg).unwrap();
+    };
+    let send = |deps: &mut OwnedDeps<_, _, _>, env: Env, denom: &str, funds: u32| {
+        let msg = test_msg_send!(
+            channel_id: format!("channel"),
+            denom: denom.to_string(),
+            funds: funds.into()
+        );
+        execute(deps.as_mut(), env, mock_info(BRIDGE_CONTRACT, &[]), msg)
+    };
+    let overview = |deps: &OwnedDeps<_, _, _>, env: Env| {
+        let msg = QueryMsg::GetPathOverview {
+            contract: Addr::unchecked(BRIDGE_CONTRACT),
+            channel_id: format!("channel"),
+            denom: format!("new"),
+        };
+        let res = query(deps.as_ref(), env, msg).unwrap();
+        from_json::<Versioned<PathOverviewResponse>>(&res)
+            .unwrap()
+            .data
+            .quotas
+            .remove(0)
+    };
+
+    // Paths added before the policy keep their full capacity
+    send(&mut deps, mock_env(), "existing", 1000).unwrap();
+
+    add_path(&mut deps, mock_env());
+    let graduation = mock_env().block.time.plus_seconds(2 * RESET_TIME_DAILY);
+    let quota_overview = overview(&deps, mock_env());
+    assert_eq!(quota_overview.probation_end, Some(graduation));
+    assert_eq!(quota_overview.usage.max_out, Uint256::from(250_u32));
+
+    send(&mut deps, mock_env(), "new", 250).unwrap();
+    let err = send(&mut deps, mock_env(), "new", 1).unwrap_err();
+    assert!(
+        matches!(err, ContractError::RateLimitExceded { max, .. } if max == Uint256::from(250_u32))
+    );
+
+    // Editing the quotas of the path doesn't graduate it
+    add_path(&mut deps, at(RESET_TIME_DAILY + 1));
+    let edited = at(RESET_TIME_DAILY + 1);
+    send(&mut deps, edited.clone(), "new", 250).unwrap();
+    send(&mut deps, edited, "new", 1).unwrap_err();
+
+    // After two periods the path has its full capacity, in the period started
+    // by the edit
+    let graduated = 2 * RESET_TIME_DAILY;
+    assert_eq!(overview(&deps, at(graduated)).probation_end, None);
+    send(&mut deps, at(graduated), "new", 750).unwrap();
+    send(&mut deps, at(graduated), "new", 1).unwrap_err();
+
+    // Paths inheriting the channel defaults start on probation too
+    let msg = ExecuteMsg::SetChannelDefaultQuotas {
+        channel_id: format!("channel"),
+        quotas: vec![quota()],
+        idempotency_key: None,
+    };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(BRIDGE_CONTRACT, &[]),
+        msg,
+    )
+    .unwrap();
+    send(&mut deps, mock_env(), "inherited", 250).unwrap();
+    let err = send(&mut deps, mock_env(), "inherited", 1).unwrap_err();
+    assert!(
+        matches!(err, ContractError::RateLimitExceded { max, .. } if max == Uint256::from(250_u32))
+    );
+
+    // A probation too long to end in representable time never graduates
+    update_config(
+        &mut deps,
+        Some(ProbationPolicy {
+            periods: u32::MAX,
+            share_bps: 2500,
+        }),
+    )
+    .unwrap();
+    let msg = ExecuteMsg::RemovePath {
+        channel_id: format!("channel"),
+        denom: format!("new"),
+        idempotency_key: None,
+    };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(BRIDGE_CONTRACT, &[]),
+        msg,
+    )
+    .unwrap();
+    add_path(&mut deps, at(3 * RESET_TIME_DAILY));
+    let probation_end = overview(&deps, at(3 * RESET_TIME_DAILY)).probation_end;
+    assert_eq!(probation_end, Some(Timestamp::from_nanos(u64::MAX)));
+}
+
+#[test] // Tests that sends past the rejections accepted per block are dropped until the next block
+fn max_rejections_per_block() {
+    let mut deps = mock_dependencies();
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 10_u32.into(), 10_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![PathMsg::new(
+            &Addr::unchecked(BRIDGE_CONTRACT),
+            "channel",
+            "denom",
+            vec![quota],
+        )],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+    let msg = ExecuteMsg::UpdateConfig {
+        config: Config {
+            max_rejections_per_block: Some(2),
+            ..Config::default()
+        },
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let msg = ExecuteMsg::SetPathConfig {
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        config: PathConfig {
+            reject_with_data: true,
+            on_reject_contract: Some(Addr::unchecked("hook")),
+            ..PathConfig::default()
+        },
+        idempotency_key: None,
+    };
+    execute(deps.as_mut(), mock_env(), bridge.clone(), msg).unwrap();
+
+    let block = |height: u64| {
+        let mut env = mock_env();
+        env.block.height = height;
+        env
+    };
+    let send = |deps: &mut OwnedDeps<_, _, _>, env: Env, funds: u32| {
+        let msg = test_msg_send!(
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            funds: funds.into()
+        );
+        execute(deps.as_mut(), env, bridge.clone(), msg).unwrap()
+    };
+    let dropped = |res: &Response| {
+        res.attributes.contains(&Attribute::new(
+            "rejection_dropped",
+            "max_rejections_per_block",
+        ))
+    };
+
+    // The first two rejections of the block notify the hook
+    for _ in 0..2 {
+        let res = send(&mut deps, block(100), 11);
+        assert!(!dropped(&res));
+        assert_eq!(res.messages.len(), 1);
+    }
+
+    // The next rejections of the block are dropped
+    let res = send(&mut deps, block(100), 11);
+    assert!(dropped(&res));
+    assert!(res.messages.is_empty());
+    assert_eq!(
+        from_json::<TransferData>(&res.data.unwrap()).unwrap(),
+        TransferData::rejected(11_u32.into())
+    );
+    let key = (
+        Addr::unchecked(BRIDGE_CONTRACT),
+        format!("channel"),
+        format!("denom"),
+    );
+    assert_eq!(
+        REJECTIONS.load(&deps.storage, key.clone()).unwrap().len(),
+        2
+    );
+
+    // Sends fitting the quota still go through
+    let res = send(&mut deps, block(100), 5);
+    assert!(!dropped(&res));
+    let trackers = RATE_LIMIT_TRACKERS.load(&deps.storage, key).unwrap();
+    assert_eq!(trackers[0].flow.outflow, Uint256::from(5_u32));
+
+    // The next block starts over
+    let res = send(&mut deps, block(101), 11);
+    assert!(!dropped(&res));
+    assert_eq!(res.messages.len(), 1);
+
+    let msg = ExecuteMsg::UpdateConfig {
+        config: Config {
+            max_rejections_per_block: Some(0),
+            ..Config::default()
+        },
+        idempotency_key: None,
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::InvalidMaxRejectionsPerBlock {}
+    ));
+}
+
+#[test] // Tests that the owner can purge the rejections recorded for a sender across paths
+fn purge_sender_data() {
+    let mut deps = mock_dependencies();
+    let bridge = Addr::unchecked(BRIDGE_CONTRACT);
+    let quota = || QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 10_u32.into(), 10_u32.into());
+    let msg = InstantiateMsg {
+        key_spec: None,
+        seed_flows: vec![],
+        paths: vec![
+            PathMsg::new(&bridge, "channel", "denom1", vec![quota()]),
+            PathMsg::new(&bridge, "channel", "denom2", vec![quota()]),
+        ],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    for denom in ["denom1", "denom2"] {
+        let msg = ExecuteMsg::SetPathConfig {
+            channel_id: format!("channel"),
+            denom: denom.to_string(),
+            config: PathConfig {
+                reject_with_data: true,
+                on_reject_contract: Some(Addr::unchecked("hook")),
+                ..PathConfig::default()
+            },
+            idempotency_key: None,
+        };
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(BRIDGE_CONTRACT, &[]),
+            msg,
+        )
+        .unwrap();
+    }
+    let reject = |deps: &mut OwnedDeps<_, _, _>, denom: &str, sender: &str| {
+        let mut packet = Packet::mock(format!("channel"), denom.to_string(), 11_u32.into());
+        packet.sender = Some(sender.to_string());
+        let msg = ExecuteMsg::SendPacket { packet };
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(BRIDGE_CONTRACT, &[]),
+            msg,
+        )
+        .unwrap();
+    };
+    reject(&mut deps, "denom1", "alice");
+    reject(&mut deps, "denom1", "bob");
+    reject(&mut deps, "denom2", "alice");
+
+    let purge = |deps: &mut OwnedDeps<_, _, _>, sender: &str| {
+        let msg = ExecuteMsg::PurgeSenderData {
+            address: format!("alice"),
+            start_after: None,
+            limit: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info(sender, &[]), msg)
+    };
+    let err = purge(&mut deps, "anyone").unwrap_err();
+    assert!(matches!(err, ContractError::Ownership(_)));
+
+    let res = purge(&mut deps, OWNER).unwrap();
+    let purged: Vec<_> = res
+        .events
+        .iter()
+        .filter(|event| event.ty == "sender_data_purged")
+        .map(|event| event.attributes[3].value.as_str())
+        .collect();
+    assert_eq!(purged, vec!["denom1", "denom2"]);
+
+    let key = |denom: &str| (bridge.clone(), format!("channel"), denom.to_string());
+    let remaining = REJECTIONS.load(&deps.storage, key("denom1")).unwrap();
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].sender, Some(format!("bob")));
+    assert!(!REJECTIONS.has(&deps.storage, key("denom2")));
+}
+
+#[test] // Tests that migrating moves the paths stored under lowercase ibc/ hashes to the normalized denom
+fn migrate_ibc_denoms() {
+    let mut deps = mock_dependencies();
+
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 100_u32.into(), 100_u32.into());
+    let path = |denom: &str| PathMsg {
+        contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: denom.to_string(),
+        quotas: vec![quota.clone()],
+    };
+    let msg = InstantiateMsg {
+        paths: vec![path("ibc/ABC"), path("ibc/DEF")],
+        key_spec: None,
+        seed_flows: vec![],
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let bridge = mock_info(BRIDGE_CONTRACT, &[]);
+    let send = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("ibc/ABC"),
+        funds: 30_u32.into()
+    );
+    execute(deps.as_mut(), mock_env(), bridge, send).unwrap();
+
+    // Older versions stored the denoms as given
+    let key = |denom: &str| -> PathKey {
+        (
+            Addr::unchecked(BRIDGE_CONTRACT),
+            format!("channel"),
+            denom.to_string(),
+        )
+    };
+    let trackers = RATE_LIMIT_TRACKERS
+        .load(&deps.storage, key("ibc/ABC"))
+        .unwrap();
+    RATE_LIMIT_TRACKERS.remove(deps.as_mut().storage, key("ibc/ABC"));
+    RATE_LIMIT_TRACKERS
+        .save(deps.as_mut().storage, key("ibc/abc"), &trackers)
+        .unwrap();
+    // Both forms of the same denom can't be merged
+    RATE_LIMIT_TRACKERS
+        .save(deps.as_mut().storage, key("ibc/def"), &trackers)
+        .unwrap();
+
+    let res = migrate(deps.as_mut(), mock_env(), MigrateMsg::default()).unwrap();
+    assert!(res
+        .attributes
+        .contains(&Attribute::new("normalized_paths", "1")));
+    assert!(res
+        .attributes
+        .contains(&Attribute::new("denom_conflicts", "1")));
+    assert!(!RATE_LIMIT_TRACKERS.has(&deps.storage, key("ibc/abc")));
+    assert!(RATE_LIMIT_TRACKERS.has(&deps.storage, key("ibc/def")));
+
+    let msg = QueryMsg::GetQuotas {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("ibc/abc"),
+    };
+    let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+    let quotas = from_json::<Versioned<Vec<RateLimitResponse>>>(&res)
+        .unwrap()
+        .data;
+    assert_eq!(quotas[0].flow.outflow, Uint256::from(30_u32));
 }
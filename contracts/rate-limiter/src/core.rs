@@ -0,0 +1,268 @@
+//! The quota logic of the limiter, for contracts embedding it instead of
+//! calling a deployed limiter. The contract evaluates its quotas here too,
+//! the rest is exposed by the `library` feature.
+//!
+//! The trackers of a key are loaded and saved through a [`LimitStore`], so
+//! the embedding contract decides where they live: [`MapStore`] keeps them in
+//! a map of its own storage, and a `BTreeMap` keeps them in memory. Only the
+//! quotas themselves are applied here. Pauses, class quotas, escrow and the
+//! other features of the contract stay with the contract, which builds the
+//! embedding API only with the `library` feature.
+#[cfg(any(test, feature = "library"))]
+use std::collections::BTreeMap;
+
+use cosmwasm_std::Uint128;
+#[cfg(any(test, feature = "library"))]
+use cosmwasm_std::{StdResult, Storage};
+#[cfg(any(test, feature = "library"))]
+use cw_storage_plus::{Map, PrimaryKey};
+
+use crate::state::EvaluationMode;
+
+pub use crate::msg::QuotaMsg;
+#[cfg(any(test, feature = "library"))]
+pub use crate::state::{DayPartition, PercentageLimit, QuotaMode};
+pub use crate::state::{Flow, FlowType, Path, Quota, RateLimit};
+pub use crate::time::TimeProvider;
+pub use crate::ContractError;
+
+/// LimitStore loads and saves the trackers of the quotas under a key, a path
+/// or anything else the embedding contract limits
+#[cfg(any(test, feature = "library"))]
+pub trait LimitStore {
+    type Key;
+
+    /// The trackers under the key, empty when it has no quotas
+    fn load_limits(&self, key: &Self::Key) -> StdResult<Vec<RateLimit>>;
+
+    /// Replaces the trackers under the key, removing them when empty
+    fn save_limits(&mut self, key: &Self::Key, limits: &[RateLimit]) -> StdResult<()>;
+}
+
+/// MapStore keeps the trackers in a map of the storage of the embedding
+/// contract
+#[cfg(any(test, feature = "library"))]
+pub struct MapStore<'s, 'k, K> {
+    pub storage: &'s mut dyn Storage,
+    pub map: Map<'k, K, Vec<RateLimit>>,
+}
+
+#[cfg(any(test, feature = "library"))]
+impl<'s, 'k, K> LimitStore for MapStore<'s, 'k, K>
+where
+    K: PrimaryKey<'k> + Clone,
+{
+    type Key = K;
+
+    fn load_limits(&self, key: &K) -> StdResult<Vec<RateLimit>> {
+        Ok(self
+            .map
+            .may_load(self.storage, key.clone())?
+            .unwrap_or_default())
+    }
+
+    fn save_limits(&mut self, key: &K, limits: &[RateLimit]) -> StdResult<()> {
+        if limits.is_empty() {
+            self.map.remove(self.storage, key.clone());
+            return Ok(());
+        }
+        self.map.save(self.storage, key.clone(), &limits.to_vec())
+    }
+}
+
+#[cfg(any(test, feature = "library"))]
+impl<K: Ord + Clone> LimitStore for BTreeMap<K, Vec<RateLimit>> {
+    type Key = K;
+
+    fn load_limits(&self, key: &K) -> StdResult<Vec<RateLimit>> {
+        Ok(self.get(key).cloned().unwrap_or_default())
+    }
+
+    fn save_limits(&mut self, key: &K, limits: &[RateLimit]) -> StdResult<()> {
+        if limits.is_empty() {
+            self.remove(key);
+        } else {
+            self.insert(key.clone(), limits.to_vec());
+        }
+        Ok(())
+    }
+}
+
+/// Replaces the quotas under the key with fresh trackers. An empty list
+/// removes them
+#[cfg(any(test, feature = "library"))]
+pub fn set_quotas<S: LimitStore>(
+    store: &mut S,
+    key: &S::Key,
+    quotas: &[QuotaMsg],
+    clock: &dyn TimeProvider,
+) -> Result<(), ContractError> {
+    quotas.iter().try_for_each(QuotaMsg::validate)?;
+    store.save_limits(key, &new_trackers(quotas, clock))?;
+    Ok(())
+}
+
+/// Accounts a transfer on the quotas under the key, which must all allow it.
+/// The trackers are saved and returned when it's accepted, and left untouched
+/// otherwise. Keys without quotas accept everything. The path only names the
+/// transfer in the errors
+#[cfg(any(test, feature = "library"))]
+pub fn transfer<S: LimitStore>(
+    store: &mut S,
+    key: &S::Key,
+    path: &Path,
+    direction: &FlowType,
+    funds: Uint128,
+    channel_value: Option<Uint128>,
+    clock: &dyn TimeProvider,
+) -> Result<Vec<RateLimit>, ContractError> {
+    let mut limits = store.load_limits(key)?;
+    if limits.is_empty() {
+        return Ok(limits);
+    }
+    let results = evaluate_quotas(
+        &mut limits,
+        &EvaluationMode::All,
+        path,
+        direction,
+        funds,
+        channel_value,
+        clock,
+    )?;
+    store.save_limits(key, &results)?;
+    Ok(results)
+}
+
+// Helpers
+
+pub(crate) fn new_trackers(quotas: &[QuotaMsg], clock: &dyn TimeProvider) -> Vec<RateLimit> {
+    quotas
+        .iter()
+        .map(|q| {
+            let quota: Quota = q.into();
+            let mut flow = Flow::new(0_u128, 0_u128, clock, quota.duration_on(&FlowType::Out));
+            flow.recv_period_end = quota
+                .split_recv_duration()
+                .map(|recv_duration| clock.now().plus_nanos(recv_duration));
+            RateLimit {
+                quota,
+                flow,
+                last_reset: None,
+            }
+        })
+        .collect()
+}
+
+// Applies the transfer to every quota. With EvaluationMode::All, any exceeded
+// quota rejects the transfer, with EvaluationMode::Any only all of them do.
+// The rejection is a single RateLimitExceded error listing the violations of
+// every exceeded quota, merged by merge_violations
+pub(crate) fn evaluate_quotas(
+    trackers: &mut [RateLimit],
+    mode: &EvaluationMode,
+    path: &Path,
+    direction: &FlowType,
+    funds: Uint128,
+    channel_value: Option<Uint128>,
+    clock: &dyn TimeProvider,
+) -> Result<Vec<RateLimit>, ContractError> {
+    // Every quota is evaluated, so the error lists all the violated ones and
+    // not only the first
+    let outcomes: Vec<_> = trackers
+        .iter_mut()
+        .map(|limit| limit.allow_transfer(path, direction, funds, channel_value, clock))
+        .collect();
+    let accepted = match mode {
+        EvaluationMode::All => outcomes.iter().all(Result::is_ok),
+        // allow_transfer applies the transfer to the flow even when the quota
+        // is exceeded, so the trackers hold the updated flows
+        EvaluationMode::Any => outcomes.is_empty() || outcomes.iter().any(Result::is_ok),
+    };
+    if accepted {
+        return Ok(trackers.to_vec());
+    }
+    let mut errors = outcomes.into_iter().filter_map(Result::err);
+    match errors.next() {
+        Some(first) => Err(merge_violations(first, errors)),
+        None => Ok(trackers.to_vec()),
+    }
+}
+
+// Folds the RateLimitExceded errors of the other quotas into the first one,
+// which then lists the violations of all of them
+fn merge_violations(
+    mut first: ContractError,
+    errors: impl Iterator<Item = ContractError>,
+) -> ContractError {
+    if let ContractError::RateLimitExceded { violations, .. } = &mut first {
+        for err in errors {
+            if let ContractError::RateLimitExceded {
+                violations: others, ..
+            } = err
+            {
+                violations.extend(others);
+            }
+        }
+    }
+    first
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::tests::RESET_TIME_DAILY;
+    use cosmwasm_std::testing::{mock_env, MockStorage};
+    use cosmwasm_std::{Addr, Uint256};
+
+    fn daily(max: u32) -> Vec<QuotaMsg> {
+        vec![QuotaMsg::new(
+            "daily",
+            RESET_TIME_DAILY,
+            max.into(),
+            max.into(),
+        )]
+    }
+
+    // Sends until the quota is used up on any store
+    fn use_up<S: LimitStore>(store: &mut S, key: &S::Key) {
+        let clock = mock_env().block.time;
+        let path = Path::new(&Addr::unchecked("embedder"), "channel", "denom");
+        set_quotas(store, key, &daily(10), &clock).unwrap();
+
+        let send = |store: &mut S, funds: u32| {
+            transfer(
+                store,
+                key,
+                &path,
+                &FlowType::Out,
+                funds.into(),
+                None,
+                &clock,
+            )
+        };
+        let limits = send(store, 10).unwrap();
+        assert_eq!(limits[0].flow.outflow, Uint256::from(10_u32));
+        let err = send(store, 1).unwrap_err();
+        assert!(matches!(err, ContractError::RateLimitExceded { .. }));
+        // The rejected transfer left the trackers as they were
+        let limits = store.load_limits(key).unwrap();
+        assert_eq!(limits[0].flow.outflow, Uint256::from(10_u32));
+
+        set_quotas(store, key, &[], &clock).unwrap();
+        assert!(store.load_limits(key).unwrap().is_empty());
+        assert!(send(store, 100).unwrap().is_empty());
+    }
+
+    #[test] // Tests that the quotas apply the same through any store
+    fn stores() {
+        let mut storage = MockStorage::new();
+        let mut store = MapStore {
+            storage: &mut storage,
+            map: Map::new("embedded_limits"),
+        };
+        use_up(&mut store, &"key".to_string());
+
+        let mut store: BTreeMap<u64, Vec<RateLimit>> = BTreeMap::new();
+        use_up(&mut store, &7);
+    }
+}
@@ -1,24 +1,339 @@
-use cosmwasm_std::{StdError, Timestamp, Uint128};
+use cosmwasm_std::{StdError, Timestamp, Uint128, Uint256};
+use cw_utils::PaymentError;
 use thiserror::Error;
 
+use crate::msg::{QuotaViolation, RateLimitExceededData};
+use crate::state::KeyDimension;
+
 #[derive(Error, Debug, PartialEq)]
 pub enum ContractError {
     #[error("{0}")]
     Std(#[from] StdError),
 
+    #[error("{0}")]
+    Payment(#[from] PaymentError),
+
+    #[error("{0}")]
+    Ownership(#[from] cw_ownable::OwnershipError),
+
     #[error("Unauthorized")]
     Unauthorized {},
 
-    #[error("IBC Rate Limit exceeded for {contract}{channel}/{denom}. Tried to transfer {amount} which exceeds capacity on the '{quota_name}' quota ({used}/{max}). Try again after {reset:?}")]
+    #[error("Packet processing is paused")]
+    Paused {},
+
+    #[error("The pause can't be lifted at {auto_unpause_at}, which has already passed")]
+    PauseExpiryInPast { auto_unpause_at: Timestamp },
+
+    #[error("The contract is in maintenance")]
+    Maintenance {},
+
+    #[error("Path {channel_id}/{denom} hasn't been migrated yet")]
+    PathNotMigrated { channel_id: String, denom: String },
+
+    #[error("Processing of {direction} packets is paused")]
+    DirectionPaused { direction: String },
+
+    #[error("Processing of the packets of {contract} is paused")]
+    ContractPaused { contract: String },
+
+    #[error("Processing of the packets of {contract} on {channel_id} is paused")]
+    ChannelPaused {
+        contract: String,
+        channel_id: String,
+    },
+
+    #[error("Processing of the packets of path {channel_id}/{denom} is paused")]
+    PathPaused { channel_id: String, denom: String },
+
+    #[error("IBC Rate Limit exceeded for {contract}{channel}/{denom}. Tried to transfer {amount} which exceeds capacity on the '{quota_name}' quota ({used}/{max}). Try again after {reset:?}. {}", self.rate_limit_data().map(|data| data.to_error_suffix()).unwrap_or_default())]
     RateLimitExceded {
         contract: String,
         channel: String,
         denom: String,
         amount: Uint128,
         quota_name: String,
-        used: Uint128,
-        max: Uint128,
+        used: Uint256,
+        max: Uint256,
         reset: Timestamp,
+        violations: Vec<QuotaViolation>,
+    },
+
+    #[error("Packet {sequence} on {channel} was already processed. Last processed sequence is {last_sequence}")]
+    DuplicatePacket {
+        channel: String,
+        sequence: u64,
+        last_sequence: u64,
+    },
+
+    #[error("No quotas configured for {contract}{channel}/{denom}")]
+    PathNotConfigured {
+        contract: String,
+        channel: String,
+        denom: String,
+    },
+
+    #[error("Quotas of {contract}{channel}/{denom} are corrupted, packets are rejected until they're replaced")]
+    PathQuarantined {
+        contract: String,
+        channel: String,
+        denom: String,
+    },
+
+    #[error("Transfer denied by policy contract: {reason}")]
+    PolicyDenied { reason: String },
+
+    #[error("Escrowed funds ({received}) don't match the packet amount ({expected})")]
+    EscrowMismatch {
+        expected: Uint128,
+        received: Uint128,
+    },
+
+    #[error("Only {accepted} of the {amount}{denom} moved by the ICA packet can be sent")]
+    IcaPacketNotAccepted {
+        denom: String,
+        amount: Uint128,
+        accepted: Uint128,
+    },
+
+    #[error("Transfer of {amount} would use the {reserved} reserved on the '{quota_name}' quota, which has {remaining} left")]
+    CapacityReserved {
+        quota_name: String,
+        amount: Uint128,
+        reserved: Uint256,
+        remaining: Uint256,
+    },
+
+    #[error("Paths can have at most {max} active reservations")]
+    TooManyReservations { max: usize },
+
+    #[error("Reservations can last at most {max} seconds, got {window}")]
+    ReservationWindowTooLong { window: u64, max: u64 },
+
+    #[error("Path {channel_id}/{denom} has no counterparty path")]
+    NoCounterparty { channel_id: String, denom: String },
+
+    #[error("Packet on {channel_id}/{denom} was sent as {declared} but its endpoints make it {inferred}")]
+    DirectionMismatch {
+        channel_id: String,
+        denom: String,
+        declared: String,
+        inferred: String,
+    },
+
+    #[error("Neither endpoint of the packet on {channel_id}/{denom} is the local channel {port_id}/{local_channel}")]
+    UnknownPacketEndpoints {
+        channel_id: String,
+        denom: String,
+        port_id: String,
+        local_channel: String,
+    },
+
+    #[error("The direction of the packet on {channel_id}/{denom} can't be inferred without its endpoints and the local channel of the path")]
+    CannotInferDirection { channel_id: String, denom: String },
+
+    #[error("Unknown reply id {id}")]
+    UnknownReplyId { id: u64 },
+
+    #[error("An on_reject_contract is only notified of rejections answered with data, set reject_with_data")]
+    RejectHookWithoutData {},
+
+    #[error("Fee of {fee_bps} basis points is above the maximum of {max}")]
+    FeeTooHigh { fee_bps: u16, max: u16 },
+
+    #[error("Sends of {denom} are refused until the {owed}{denom} of fees owed are paid")]
+    FeesOwed { denom: String, owed: Uint128 },
+
+    #[error("Paid {paid}{denom} of fees but only {owed}{denom} are owed")]
+    FeeOverpaid {
+        denom: String,
+        paid: Uint128,
+        owed: Uint128,
+    },
+
+    #[error("Filter {filter_id} not found")]
+    FilterNotFound { filter_id: String },
+
+    #[error("Invalid filter id {filter_id:?}, it must be 1 to {max} characters long")]
+    InvalidFilterId { filter_id: String, max: usize },
+
+    #[error("Invalid tag {tag:?}, it must be 1 to {max} characters long")]
+    InvalidTag { tag: String, max: usize },
+
+    #[error("Invalid chain id {chain_id:?}, it must be 1 to {max} characters long")]
+    InvalidChainId { chain_id: String, max: usize },
+
+    #[error("Counterparty state reported at {reported_at} isn't newer than the last one, reported at {last_reported_at}")]
+    StaleCounterpartyState {
+        reported_at: Timestamp,
+        last_reported_at: Timestamp,
+    },
+
+    #[error("Packet amount is empty")]
+    EmptyAmount {},
+
+    #[error("Packet amount {amount:?} isn't a decimal integer")]
+    MalformedAmount { amount: String },
+
+    #[error("Packet amount {amount} doesn't fit in a {max}")]
+    AmountOverflow { amount: String, max: String },
+
+    #[error("Path {channel_id}/{denom} still holds {balance} in escrow")]
+    EscrowNotEmpty {
+        channel_id: String,
+        denom: String,
+        balance: Uint128,
+    },
+
+    #[error(
+        "Quota {name} has a zero limit. Use a blocked quota to intentionally block all transfers"
+    )]
+    ZeroQuota { name: String },
+
+    #[error("The partitions of quota {name} must cover the UTC day in order, with shares of at most 10000 bps")]
+    InvalidDayPartitions { name: String },
+
+    #[error("Paths can have at most {max} quotas, got {count}")]
+    TooManyQuotas { max: u32, count: usize },
+
+    #[error("The max quota duration must be between one second and {max} seconds")]
+    InvalidMaxQuotaDuration { max: u64 },
+
+    #[error("No pending send on channel {channel_id} with sequence {sequence:?} to undo")]
+    PendingSendNotFound {
+        channel_id: String,
+        sequence: Option<u64>,
+    },
+
+    #[error("The key spec lists the {dimension:?} dimension more than once")]
+    DuplicateKeyDimension { dimension: KeyDimension },
+
+    #[error("Pending send {sequence} is of {expected}, not {denom}")]
+    PendingSendMismatch {
+        sequence: u64,
+        denom: String,
+        expected: String,
+    },
+
+    #[error("Sends on a path in escrow mode must carry their sequence")]
+    EscrowWithoutSequence {},
+
+    #[error("Quota names can't be empty")]
+    EmptyQuotaName {},
+
+    #[error("Quota {name} lasts {duration_nanos} nanoseconds, above the maximum of {max_nanos}")]
+    QuotaDurationTooLong {
+        name: String,
+        duration_nanos: u64,
+        max_nanos: u64,
+    },
+
+    #[error("Contract {contract} already has the maximum of {max} paths")]
+    TooManyPaths { contract: String, max: u32 },
+
+    #[error("Quota '{quota_name}' of {channel_id}/{denom} allows {child}, more than the {parent} of the channel default")]
+    ChildQuotaExceedsParent {
+        channel_id: String,
+        denom: String,
+        quota_name: String,
+        child: Uint256,
+        parent: Uint256,
+    },
+
+    #[error("Quotas '{quota_name}' of the paths of {channel_id} allow {total} together, more than the {parent} of the channel default")]
+    ChildQuotasExceedParent {
+        channel_id: String,
+        quota_name: String,
+        total: Uint256,
+        parent: Uint256,
+    },
+
+    #[error("Denom {denom} is not in the allowed assets of channel {channel_id}")]
+    DenomNotAllowed { channel_id: String, denom: String },
+
+    #[error("Channels can have at most {max} allowed assets, got {count}")]
+    TooManyChannelAssets { max: usize, count: usize },
+
+    #[error("Attribute schema version {version} is not supported, the latest is {latest}")]
+    UnsupportedAttributeSchema { version: u32, latest: u32 },
+
+    #[error("The expiry grace can be at most {max} nanoseconds, got {grace_nanos}")]
+    GraceTooLong { grace_nanos: u64, max: u64 },
+
+    #[error("The capacity bucket must be between 1 and 10000 basis points, got {bps}")]
+    InvalidCapacityBucket { bps: u32 },
+
+    #[error("The heartbeat interval must be between one second and {max} seconds")]
+    InvalidHeartbeatInterval { max: u64 },
+
+    #[error("max_rejections_per_block must accept at least one rejection")]
+    InvalidMaxRejectionsPerBlock {},
+
+    #[error("Probation must last at least one period and leave between 1 and 10000 basis points of the capacity")]
+    InvalidProbation {},
+
+    #[error("Sends are frozen as the last heartbeat, at {last_heartbeat}, is too old")]
+    HeartbeatMissed { last_heartbeat: Timestamp },
+
+    #[error("The channel value of {channel_id}/{denom}, reported at {updated_at}, is too old")]
+    StaleChannelValue {
+        channel_id: String,
+        denom: String,
+        updated_at: Timestamp,
+    },
+
+    #[error("No escrow querier is configured")]
+    NoEscrowQuerier {},
+
+    #[error("Contract {sender} has no paths and is not a registered bridge")]
+    UnregisteredSender { sender: String },
+
+    #[error("Threshold must be between 1 and the number of owners ({owners}), got {threshold}")]
+    InvalidThreshold { threshold: u32, owners: usize },
+
+    #[error("Proposal {id} expired")]
+    ProposalExpired { id: u64 },
+
+    #[error("Proposals can expire in at most {max} seconds, got {expires_in}")]
+    ProposalExpiryTooLong { expires_in: u64, max: u64 },
+
+    #[error("Pre-approvals can last at most {max} seconds, got {window}")]
+    PreApprovalWindowTooLong { window: u64, max: u64 },
+
+    #[error("Proposal {id} was already executed")]
+    ProposalExecuted { id: u64 },
+
+    #[error("Proposal {id} has {confirmations} of the {threshold} confirmations needed")]
+    ThresholdNotMet {
+        id: u64,
+        confirmations: usize,
+        threshold: u32,
+    },
+
+    #[error("Sending {amount} on {channel_id}/{denom} would bring the unsettled value to over {max} ({in_flight} in flight)")]
+    InFlightExceeded {
+        channel_id: String,
+        denom: String,
+        amount: Uint128,
+        in_flight: Uint256,
+        max: Uint256,
+    },
+
+    #[error("Path {channel_id}/{denom} is already configured")]
+    PathAlreadyExists { channel_id: String, denom: String },
+
+    #[error("Quota {quota_id} already exists on {channel_id}/{denom}")]
+    QuotaAlreadyExists {
+        quota_id: String,
+        channel_id: String,
+        denom: String,
+    },
+
+    #[error("Seeded period of quota {quota_id} ends at {period_end}, after the end of a full period at {max}")]
+    SeedPeriodTooLong {
+        quota_id: String,
+        period_end: Timestamp,
+        max: Timestamp,
     },
 
     #[error("Quota {quota_id} not found for channel {channel_id}")]
@@ -28,3 +343,33 @@ pub enum ContractError {
         denom: String,
     },
 }
+
+impl ContractError {
+    /// The machine-readable details of a RateLimitExceded error
+    pub fn rate_limit_data(&self) -> Option<RateLimitExceededData> {
+        match self {
+            ContractError::RateLimitExceded {
+                contract,
+                channel,
+                denom,
+                amount,
+                quota_name,
+                used,
+                max,
+                reset,
+                violations,
+            } => Some(RateLimitExceededData {
+                contract: contract.clone(),
+                channel_id: channel.clone(),
+                denom: denom.clone(),
+                amount: *amount,
+                quota_name: quota_name.clone(),
+                used: *used,
+                max: *max,
+                reset: *reset,
+                violations: violations.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
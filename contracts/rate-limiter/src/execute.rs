@@ -1,125 +1,3706 @@
-use crate::msg::{PathMsg, QuotaMsg};
-use crate::packet::Packet;
-use crate::state::{Flow, FlowType, Path, RateLimit, RATE_LIMIT_TRACKERS};
+use crate::core::{evaluate_quotas, new_trackers};
+use crate::msg::{
+    AlertMsg, EscrowQuerierMsg, ExecuteMsg, PathId, PathMsg, PolicyQueryMsg, PolicyResponse,
+    QuotaMsg, QuotaUsage, RejectHookMsg, ResetScope, SeedFlow, TransferData,
+};
+use crate::packet::{IcaPacket, Ics20Transfer, Packet, PacketEndpoints, ReceiverClass};
+use crate::pagination::{
+    clamp_limit, start_bound, PRUNE_DEFAULT_LIMIT, PRUNE_MAX_LIMIT, TICK_DEFAULT_LIMIT,
+    TICK_MAX_LIMIT,
+};
+use crate::state::{
+    continue_migration, is_migrated, is_paused, load_config, load_key_spec, load_tenant_config,
+    normalize_denom, remove_trackers, save_trackers, update_metrics, ApproverRole, AuditAction,
+    AuditEntry, BlockRejections, Config, CounterpartyState, DailyStats, EscrowValue,
+    EvaluationMode, FeeAccount, FlowType, HierarchyStrictness, HistoryEntry, KeySpec, LastPacket,
+    LocalChannel, Multisig, OnMissedHeartbeat, OnStaleChannelValue, Path, PathConfig, PathFilter,
+    PathKey, PauseScope, PendingPeriod, PendingSend, PreApproval, Probation, Proposal, Quota,
+    RateLimit, Rejection, Reservation, ResetMode, StoredFilter, Streak, TenantConfig,
+    UnconfiguredBehavior, ACTIVE_RESERVATIONS, ATTRIBUTE_SCHEMA_VERSION, BLOCK_REJECTIONS,
+    CHAIN_TRACKERS, CHANNEL_ASSETS, CHANNEL_DEFAULT_QUOTAS, CHANNEL_TRACKERS, CLASS_TRACKERS,
+    CONFIG, COUNTERPARTY_STATES, DAILY_STATS, DEGRADED_TRACKERS, ESCROW_BALANCES, ESCROW_VALUES,
+    FEES, FILTERS, HISTORY, HISTORY_LENGTH, IN_FLIGHT, LAST_HEARTBEAT, MAINTENANCE,
+    MAX_ACTIVE_RESERVATIONS, MAX_CHANNEL_ASSETS, MAX_FEE_BPS, MAX_GRACE_NANOS,
+    MAX_HEARTBEAT_INTERVAL, MAX_PRE_APPROVAL_WINDOW, MAX_PROPOSAL_EXPIRY, MAX_QUOTA_DURATION,
+    MAX_RESERVATION_WINDOW, MIGRATION_BATCH, MULTISIG, NANOS_PER_SECOND, OPEN_PRE_APPROVALS,
+    PACKET_SEQUENCES, PATH_AUDIT_LOG, PATH_CONFIGS, PATH_REDIRECTS, PAUSED, PAUSED_CHANNELS,
+    PAUSED_CONTRACTS, PAUSED_DIRECTIONS, PAUSED_PATHS, PAUSE_EXPIRY, PENDING_ICA_SENDS,
+    PENDING_IN_FLIGHT, PENDING_SENDS, PRE_APPROVALS, PRE_APPROVAL_COUNT, PROPOSALS, PROPOSAL_COUNT,
+    QUARANTINE, RATE_LIMIT_TRACKERS, RECEIVER_CLASSES, REGISTERED_BRIDGES, REJECTIONS,
+    REJECTIONS_LENGTH, RESERVATIONS, RESERVATION_COUNT, SECONDS_PER_DAY, STREAKS, TAG_TRACKERS,
+    TENANT_CONFIG, TICK_CURSOR,
+};
+use crate::time::TimeProvider;
 use crate::ContractError;
-use cosmwasm_std::{Addr, DepsMut, Response, Timestamp, Uint128};
+use cosmwasm_std::{
+    coins, from_json, to_json_binary, Addr, BankMsg, Binary, CosmosMsg, Deps, DepsMut, Empty,
+    Event, MessageInfo, Order, Reply, Response, StdError, StdResult, Storage, SubMsg, SubMsgResult,
+    Timestamp, Uint128, Uint256, WasmMsg,
+};
+use cw_utils::{must_pay, nonpayable};
+use std::collections::BTreeMap;
 
 pub fn add_new_paths(
     deps: DepsMut,
     path_msgs: Vec<PathMsg>,
-    now: Timestamp,
+    actor: &Addr,
+    clock: &dyn TimeProvider,
 ) -> Result<(), ContractError> {
+    let key_spec = load_key_spec(deps.storage)?;
     for path_msg in path_msgs {
-        let path = Path::new(&path_msg.contract_addr, path_msg.channel_id, path_msg.denom);
+        let config = load_tenant_config(deps.storage, &path_msg.contract_addr)?;
+        let path = check_path_msg(deps.storage, &config, &key_spec, &path_msg)?;
 
-        RATE_LIMIT_TRACKERS.save(
+        let current = RATE_LIMIT_TRACKERS.may_load(deps.storage, (&path).into())?;
+        let action = match current {
+            Some(_) => AuditAction::EditQuota,
+            None => {
+                update_metrics(deps.storage, |m| m.paths += 1)?;
+                AuditAction::AddPath
+            }
+        };
+        record_audit(deps.storage, &path, action, actor, clock, None)?;
+
+        // Edits keep the probation of the path, so replacing its quotas can't
+        // graduate it early
+        let probation = match current {
+            Some(trackers) => trackers
+                .into_iter()
+                .find_map(|tracker| tracker.quota.probation),
+            None => start_probation(&config, clock),
+        };
+        let mut trackers = new_trackers(&path_msg.quotas, clock);
+        set_probation(&mut trackers, probation);
+        save_trackers(deps.storage, &path, &trackers)?;
+    }
+    Ok(())
+}
+
+// The probation of a path added now under the probation policy of the config
+fn start_probation(config: &Config, clock: &dyn TimeProvider) -> Option<Probation> {
+    config
+        .probation
+        .as_ref()
+        .map(|policy| Probation::start(policy, clock.now()))
+}
+
+fn set_probation(trackers: &mut [RateLimit], probation: Option<Probation>) {
+    for tracker in trackers {
+        tracker.quota.probation = probation.clone();
+    }
+}
+
+// Runs the checks of AddPath on a path message without applying it, and returns
+// the path it configures
+pub(crate) fn check_path_msg(
+    storage: &dyn Storage,
+    config: &Config,
+    key_spec: &KeySpec,
+    path_msg: &PathMsg,
+) -> Result<Path, ContractError> {
+    path_msg.quotas.iter().try_for_each(QuotaMsg::validate)?;
+    config.check_quotas(&path_msg.quotas)?;
+    let path = key_spec.path(
+        &path_msg.contract_addr,
+        &path_msg.channel_id,
+        &path_msg.denom,
+    );
+    check_quota_hierarchy(
+        storage,
+        &config.quota_hierarchy,
+        &path.contract,
+        &path.channel,
+        None,
+        Some((&path.denom, &path_msg.quotas)),
+    )?;
+    if !RATE_LIMIT_TRACKERS.has(storage, (&path).into()) {
+        check_path_count(storage, config, &path.contract)?;
+    }
+    Ok(path)
+}
+
+// Carries the usage of the current period of quotas over from another limiter
+pub fn seed_flows(
+    deps: DepsMut,
+    seeds: Vec<SeedFlow>,
+    clock: &dyn TimeProvider,
+) -> Result<(), ContractError> {
+    let key_spec = load_key_spec(deps.storage)?;
+    for seed in seeds {
+        let path = key_spec.path(&seed.contract, &seed.channel_id, &seed.denom);
+        let mut trackers = RATE_LIMIT_TRACKERS
+            .may_load(deps.storage, (&path).into())?
+            .unwrap_or_default();
+        let tracker = trackers
+            .iter_mut()
+            .find(|tracker| tracker.quota.name == seed.quota_id)
+            .ok_or_else(|| ContractError::QuotaNotFound {
+                quota_id: seed.quota_id.clone(),
+                channel_id: seed.channel_id.clone(),
+                denom: seed.denom.clone(),
+            })?;
+
+        let max = clock
+            .now()
+            .plus_nanos(tracker.quota.duration_on(&FlowType::Out));
+        if seed.period_end > max {
+            return Err(ContractError::SeedPeriodTooLong {
+                quota_id: seed.quota_id,
+                period_end: seed.period_end,
+                max,
+            });
+        }
+        tracker.flow.inflow = seed.inflow;
+        tracker.flow.outflow = seed.outflow;
+        tracker.flow.period_end = seed.period_end;
+        tracker.flow.recv_period_end = tracker.quota.split_recv_duration().map(|_| seed.period_end);
+        save_trackers(deps.storage, &path, &trackers)?;
+    }
+    Ok(())
+}
+
+// Checks that the contract can register one more path
+fn check_path_count(
+    storage: &dyn Storage,
+    config: &Config,
+    contract: &Addr,
+) -> Result<(), ContractError> {
+    let Some(max) = config.max_paths_per_contract else {
+        return Ok(());
+    };
+
+    let count = RATE_LIMIT_TRACKERS
+        .sub_prefix(contract.clone())
+        .keys_raw(storage, None, None, Order::Ascending)
+        .take(max as usize)
+        .count();
+    if count >= max as usize {
+        return Err(ContractError::TooManyPaths {
+            contract: contract.to_string(),
+            max,
+        });
+    }
+    Ok(())
+}
+
+// Appends an entry to the audit log of the path
+fn record_audit(
+    storage: &mut dyn Storage,
+    path: &Path,
+    action: AuditAction,
+    actor: &Addr,
+    clock: &dyn TimeProvider,
+    memo: Option<String>,
+) -> StdResult<()> {
+    let key: (Addr, String, String) = path.into();
+    let index = PATH_AUDIT_LOG
+        .prefix(key.clone())
+        .keys(storage, None, None, Order::Descending)
+        .next()
+        .transpose()?
+        .map(|last| last + 1)
+        .unwrap_or_default();
+
+    PATH_AUDIT_LOG.save(
+        storage,
+        (key, index),
+        &AuditEntry {
+            index,
+            action,
+            actor: actor.clone(),
+            at: clock.now(),
+            memo,
+        },
+    )
+}
+
+// Moves the state of a path to a new key and leaves a redirect behind. Packet
+// sequences, the cached channel value and the pending sends with their in
+// flight value are not moved, as they are specific to the channel the packets
+// were sent on
+pub fn try_migrate_path(
+    deps: DepsMut,
+    contract: Addr,
+    from: (String, String),
+    to: (String, String),
+    clock: &dyn TimeProvider,
+) -> Result<Response, ContractError> {
+    let key_spec = load_key_spec(deps.storage)?;
+    let old = key_spec.path(&contract, &from.0, &from.1);
+    let new = key_spec.path(&contract, &to.0, &to.1);
+    let old_key: PathKey = (&old).into();
+    let new_key: PathKey = (&new).into();
+
+    let trackers = RATE_LIMIT_TRACKERS
+        .may_load(deps.storage, old_key.clone())?
+        .ok_or_else(|| ContractError::PathNotConfigured {
+            contract: contract.to_string(),
+            channel: from.0.clone(),
+            denom: from.1.clone(),
+        })?;
+    if RATE_LIMIT_TRACKERS.has(deps.storage, new_key.clone()) {
+        return Err(ContractError::PathAlreadyExists {
+            channel_id: to.0,
+            denom: to.1,
+        });
+    }
+
+    move_path(deps.storage, &old, &new, &trackers)?;
+
+    // The new key may have been migrated away from before
+    PATH_REDIRECTS.remove(deps.storage, new_key);
+    PATH_REDIRECTS.save(deps.storage, old_key, &(&new).into())?;
+
+    let memo = format!("{}/{} -> {}/{}", from.0, from.1, to.0, to.1);
+    for path in [&old, &new] {
+        record_audit(
+            deps.storage,
+            path,
+            AuditAction::MigratePath,
+            &contract,
+            clock,
+            Some(memo.clone()),
+        )?;
+    }
+
+    Ok(Response::new()
+        .add_attribute("method", "try_migrate_path")
+        .add_attribute("contract", contract.as_str())
+        .add_attribute("from", format!("{}/{}", from.0, from.1))
+        .add_attribute("to", format!("{}/{}", to.0, to.1)))
+}
+
+// Moves the quotas, flows, history, configuration, pause, escrow, stats,
+// streak, counterparty state, pre-approvals and reservations of a path to
+// another key
+fn move_path(
+    storage: &mut dyn Storage,
+    old: &Path,
+    new: &Path,
+    trackers: &[RateLimit],
+) -> StdResult<()> {
+    let old_key: PathKey = old.into();
+    let new_key: PathKey = new.into();
+    remove_trackers(storage, old)?;
+    save_trackers(storage, new, trackers)?;
+    if let Some(history) = HISTORY.may_load(storage, old_key.clone())? {
+        HISTORY.remove(storage, old_key.clone());
+        HISTORY.save(storage, new_key.clone(), &history)?;
+    }
+    if let Some(rejections) = REJECTIONS.may_load(storage, old_key.clone())? {
+        REJECTIONS.remove(storage, old_key.clone());
+        REJECTIONS.save(storage, new_key.clone(), &rejections)?;
+    }
+    if let Some(config) = PATH_CONFIGS.may_load(storage, old_key.clone())? {
+        PATH_CONFIGS.remove(storage, old_key.clone());
+        PATH_CONFIGS.save(storage, new_key.clone(), &config)?;
+    }
+    for (denom, balance) in path_escrow(storage, old)? {
+        ESCROW_BALANCES.remove(storage, escrow_key(old, &denom));
+        ESCROW_BALANCES.save(storage, escrow_key(new, &denom), &balance)?;
+    }
+    let classes = CLASS_TRACKERS
+        .prefix(old_key.clone())
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for (class, trackers) in classes {
+        CLASS_TRACKERS.remove(storage, (old_key.clone(), class.clone()));
+        CLASS_TRACKERS.save(storage, (new_key.clone(), class), &trackers)?;
+    }
+    if let Some(trackers) = DEGRADED_TRACKERS.may_load(storage, old_key.clone())? {
+        DEGRADED_TRACKERS.remove(storage, old_key.clone());
+        DEGRADED_TRACKERS.save(storage, new_key.clone(), &trackers)?;
+    }
+    let tags = TAG_TRACKERS
+        .prefix(old_key.clone())
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for (tag, trackers) in tags {
+        TAG_TRACKERS.remove(storage, (old_key.clone(), tag.clone()));
+        TAG_TRACKERS.save(storage, (new_key.clone(), tag), &trackers)?;
+    }
+    // A paused or frozen path stays so under its new key
+    if PAUSED_PATHS.has(storage, old_key.clone()) {
+        PAUSED_PATHS.remove(storage, old_key.clone());
+        PAUSED_PATHS.save(storage, new_key.clone(), &Empty {})?;
+    }
+    if let Some(streak) = STREAKS.may_load(storage, old_key.clone())? {
+        STREAKS.remove(storage, old_key.clone());
+        STREAKS.save(storage, new_key.clone(), &streak)?;
+    }
+    if let Some(stats) = DAILY_STATS.may_load(storage, old_key.clone())? {
+        DAILY_STATS.remove(storage, old_key.clone());
+        DAILY_STATS.save(storage, new_key.clone(), &stats)?;
+    }
+    if let Some(state) = COUNTERPARTY_STATES.may_load(storage, old_key.clone())? {
+        COUNTERPARTY_STATES.remove(storage, old_key.clone());
+        COUNTERPARTY_STATES.save(storage, new_key.clone(), &state)?;
+    }
+    let approvals = PRE_APPROVALS
+        .prefix(old_key.clone())
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for (id, approval) in approvals {
+        PRE_APPROVALS.remove(storage, (old_key.clone(), id));
+        PRE_APPROVALS.save(storage, (new_key.clone(), id), &approval)?;
+        let amount = approval.amount.u128();
+        if OPEN_PRE_APPROVALS.has(storage, (old_key.clone(), amount, id)) {
+            OPEN_PRE_APPROVALS.remove(storage, (old_key.clone(), amount, id));
+            OPEN_PRE_APPROVALS.save(storage, (new_key.clone(), amount, id), &Empty {})?;
+        }
+    }
+    let reservations = RESERVATIONS
+        .prefix(old_key.clone())
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for (id, reservation) in reservations {
+        RESERVATIONS.remove(storage, (old_key.clone(), id));
+        RESERVATIONS.save(storage, (new_key.clone(), id), &reservation)?;
+        if ACTIVE_RESERVATIONS.has(storage, (old_key.clone(), id)) {
+            ACTIVE_RESERVATIONS.remove(storage, (old_key.clone(), id));
+            ACTIVE_RESERVATIONS.save(storage, (new_key.clone(), id), &Empty {})?;
+        }
+    }
+    Ok(())
+}
+
+// Sets the paths gauge of the metrics to the number of configured paths.
+// Contracts instantiated before the metrics started it from zero, so it's
+// seeded once a migration has all the trackers in their namespace
+pub(crate) fn seed_path_gauge(storage: &mut dyn Storage) -> StdResult<()> {
+    let paths = RATE_LIMIT_TRACKERS
+        .keys_raw(storage, None, None, Order::Ascending)
+        .count() as u64;
+    update_metrics(storage, |m| m.paths = paths)
+}
+
+// Denoms used to be stored as given. Moves the paths stored under a denom
+// that doesn't normalize to itself, i.e. a lowercase ibc/ hash or a full
+// trace, to their normalized denom. Paths whose normalized key is already
+// taken are left for the owner to merge with MigratePath
+pub(crate) fn normalize_path_denoms(storage: &mut dyn Storage) -> StdResult<(u32, u32)> {
+    let keys = RATE_LIMIT_TRACKERS
+        .keys(storage, None, None, Order::Ascending)
+        .filter(|key| match key {
+            Ok((_, _, denom)) => normalize_denom(denom) != *denom,
+            Err(_) => true,
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    let (mut moved, mut conflicts) = (0, 0);
+    for (contract, channel, denom) in keys {
+        // Path::new would normalize the denom
+        let old = Path {
+            contract: contract.clone(),
+            channel: channel.clone(),
+            denom,
+        };
+        let new = Path::new(&contract, channel, &old.denom);
+        if RATE_LIMIT_TRACKERS.has(storage, (&new).into()) {
+            conflicts += 1;
+            continue;
+        }
+        let trackers = RATE_LIMIT_TRACKERS.load(storage, (&old).into())?;
+        move_path(storage, &old, &new, &trackers)?;
+        moved += 1;
+    }
+    Ok((moved, conflicts))
+}
+
+pub fn try_set_class_quotas(
+    deps: DepsMut,
+    contract: Addr,
+    channel_id: String,
+    denom: String,
+    class: &str,
+    quotas: Vec<QuotaMsg>,
+    clock: &dyn TimeProvider,
+) -> Result<Response, ContractError> {
+    quotas.iter().try_for_each(QuotaMsg::validate)?;
+    load_tenant_config(deps.storage, &contract)?.check_quotas(&quotas)?;
+
+    let path = load_key_spec(deps.storage)?.path(&contract, &channel_id, &denom);
+    let key = (path.into(), class.to_string());
+    if quotas.is_empty() {
+        CLASS_TRACKERS.remove(deps.storage, key);
+    } else {
+        CLASS_TRACKERS.save(deps.storage, key, &new_trackers(&quotas, clock))?;
+    }
+
+    Ok(Response::new()
+        .add_attribute("method", "try_set_class_quotas")
+        .add_attribute("contract", contract.as_str())
+        .add_attribute("channel_id", channel_id)
+        .add_attribute("denom", denom)
+        .add_attribute("class", class)
+        .add_attribute("quotas", quotas.len().to_string()))
+}
+
+// Tags end up in storage keys and attributes, so they are kept short
+const MAX_TAG_LENGTH: usize = 64;
+
+pub fn try_set_tag_quotas(
+    deps: DepsMut,
+    contract: Addr,
+    channel_id: String,
+    denom: String,
+    tag: String,
+    quotas: Vec<QuotaMsg>,
+    clock: &dyn TimeProvider,
+) -> Result<Response, ContractError> {
+    if tag.is_empty() || tag.len() > MAX_TAG_LENGTH {
+        return Err(ContractError::InvalidTag {
+            tag,
+            max: MAX_TAG_LENGTH,
+        });
+    }
+    quotas.iter().try_for_each(QuotaMsg::validate)?;
+    load_tenant_config(deps.storage, &contract)?.check_quotas(&quotas)?;
+
+    let path = load_key_spec(deps.storage)?.path(&contract, &channel_id, &denom);
+    let key = (path.into(), tag.clone());
+    if quotas.is_empty() {
+        TAG_TRACKERS.remove(deps.storage, key);
+    } else {
+        TAG_TRACKERS.save(deps.storage, key, &new_trackers(&quotas, clock))?;
+    }
+
+    Ok(Response::new()
+        .add_attribute("method", "try_set_tag_quotas")
+        .add_attribute("contract", contract.as_str())
+        .add_attribute("channel_id", channel_id)
+        .add_attribute("denom", denom)
+        .add_attribute("tag", tag)
+        .add_attribute("quotas", quotas.len().to_string()))
+}
+
+// Chain ids end up in storage keys and attributes, so they are kept short
+const MAX_CHAIN_ID_LENGTH: usize = 64;
+
+pub fn try_set_chain_quotas(
+    deps: DepsMut,
+    contract: Addr,
+    chain_id: String,
+    denom: String,
+    quotas: Vec<QuotaMsg>,
+    clock: &dyn TimeProvider,
+) -> Result<Response, ContractError> {
+    if chain_id.is_empty() || chain_id.len() > MAX_CHAIN_ID_LENGTH {
+        return Err(ContractError::InvalidChainId {
+            chain_id,
+            max: MAX_CHAIN_ID_LENGTH,
+        });
+    }
+    quotas.iter().try_for_each(QuotaMsg::validate)?;
+    load_tenant_config(deps.storage, &contract)?.check_quotas(&quotas)?;
+
+    let denom = load_key_spec(deps.storage)?.denom(&denom);
+    let key = (contract.clone(), chain_id.clone(), denom.clone());
+    if quotas.is_empty() {
+        CHAIN_TRACKERS.remove(deps.storage, key);
+    } else {
+        CHAIN_TRACKERS.save(deps.storage, key, &new_trackers(&quotas, clock))?;
+    }
+
+    Ok(Response::new()
+        .add_attribute("method", "try_set_chain_quotas")
+        .add_attribute("contract", contract.as_str())
+        .add_attribute("chain_id", chain_id)
+        .add_attribute("denom", denom)
+        .add_attribute("quotas", quotas.len().to_string()))
+}
+
+// Registers the class of a receiver on a counterparty chain. Registering an
+// account removes the entry, accounts being the default
+pub fn try_set_receiver_class(
+    deps: DepsMut,
+    sender: Addr,
+    address: String,
+    class: ReceiverClass,
+) -> Result<Response, ContractError> {
+    cw_ownable::assert_owner(deps.storage, &sender)?;
+    match class {
+        ReceiverClass::Account => RECEIVER_CLASSES.remove(deps.storage, &address),
+        _ => RECEIVER_CLASSES.save(deps.storage, &address, &class)?,
+    }
+
+    Ok(Response::new()
+        .add_attribute("method", "try_set_receiver_class")
+        .add_attribute("address", address)
+        .add_attribute("class", class.as_str()))
+}
+
+// The classes of quotas a packet is accounted on: the class of its memo, then
+// the class of its receiver when the packet names one
+pub(crate) fn packet_classes(
+    storage: &dyn Storage,
+    packet: &Packet,
+) -> StdResult<Vec<&'static str>> {
+    let mut classes = vec![packet.class().as_str()];
+    if let Some(receiver) = &packet.receiver {
+        let class = RECEIVER_CLASSES
+            .may_load(storage, receiver)?
+            .unwrap_or_default();
+        classes.push(class.as_str());
+    }
+    Ok(classes)
+}
+
+// Sets the denoms the contract accepts on a channel. An empty list accepts any
+pub fn try_set_channel_assets(
+    deps: DepsMut,
+    contract: Addr,
+    channel_id: String,
+    denoms: Vec<String>,
+) -> Result<Response, ContractError> {
+    let mut denoms: Vec<String> = denoms.iter().map(|denom| normalize_denom(denom)).collect();
+    denoms.sort();
+    denoms.dedup();
+    if denoms.len() > MAX_CHANNEL_ASSETS {
+        return Err(ContractError::TooManyChannelAssets {
+            max: MAX_CHANNEL_ASSETS,
+            count: denoms.len(),
+        });
+    }
+
+    let key = (&contract, channel_id.as_str());
+    if denoms.is_empty() {
+        CHANNEL_ASSETS.remove(deps.storage, key);
+    } else {
+        CHANNEL_ASSETS.save(deps.storage, key, &denoms)?;
+    }
+
+    Ok(Response::new()
+        .add_attribute("method", "try_set_channel_assets")
+        .add_attribute("contract", contract.as_str())
+        .add_attribute("channel_id", channel_id)
+        .add_attribute("denoms", denoms.join(",")))
+}
+
+// Rejects denoms outside of the allowed assets of the channel, if it has any
+pub(crate) fn check_channel_asset(
+    storage: &dyn Storage,
+    contract: &Addr,
+    channel: &str,
+    denom: &str,
+) -> Result<(), ContractError> {
+    match CHANNEL_ASSETS.may_load(storage, (contract, channel))? {
+        Some(denoms) if !denoms.iter().any(|allowed| allowed == denom) => {
+            Err(ContractError::DenomNotAllowed {
+                channel_id: channel.to_string(),
+                denom: denom.to_string(),
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+// Sets the quotas inherited by the denoms of a channel without their own path.
+// An empty list removes the channel defaults
+pub fn try_set_channel_default_quotas(
+    deps: DepsMut,
+    contract: Addr,
+    channel_id: String,
+    quotas: Vec<QuotaMsg>,
+    clock: &dyn TimeProvider,
+) -> Result<Response, ContractError> {
+    quotas.iter().try_for_each(QuotaMsg::validate)?;
+    let config = load_tenant_config(deps.storage, &contract)?;
+    config.check_quotas(&quotas)?;
+    // Keyed like the paths of the channel, which inherit them
+    let channel = load_key_spec(deps.storage)?.channel(&channel_id);
+    check_quota_hierarchy(
+        deps.storage,
+        &config.quota_hierarchy,
+        &contract,
+        &channel,
+        Some(&quotas),
+        None,
+    )?;
+
+    let key = (contract.clone(), channel);
+    if quotas.is_empty() {
+        CHANNEL_DEFAULT_QUOTAS.remove(deps.storage, key.clone());
+        CHANNEL_TRACKERS.remove(deps.storage, key);
+    } else {
+        CHANNEL_DEFAULT_QUOTAS.save(deps.storage, key.clone(), &quotas)?;
+        // The quotas that keep their name keep their flows, so editing the
+        // defaults doesn't give back the capacity already used
+        if let Some(previous) = CHANNEL_TRACKERS.may_load(deps.storage, key.clone())? {
+            let trackers: Vec<RateLimit> = new_trackers(&quotas, clock)
+                .into_iter()
+                .map(|mut limit| {
+                    if let Some(old) = previous
+                        .iter()
+                        .find(|old| old.quota.name == limit.quota.name)
+                    {
+                        limit.flow = old.flow.clone();
+                    }
+                    limit
+                })
+                .collect();
+            CHANNEL_TRACKERS.save(deps.storage, key, &trackers)?;
+        }
+    }
+
+    Ok(Response::new()
+        .add_attribute("method", "try_set_channel_default_quotas")
+        .add_attribute("contract", contract.as_str())
+        .add_attribute("channel_id", channel_id)
+        .add_attribute("quotas", quotas.len().to_string()))
+}
+
+// Finds the quotas a path without trackers inherits: the channel defaults if
+// set, the global defaults otherwise and, when failing open, the new denom
+// quota. Returns where they come from
+pub(crate) fn inherited_quotas(
+    storage: &dyn Storage,
+    path: &Path,
+) -> StdResult<Option<(&'static str, Vec<QuotaMsg>)>> {
+    let channel_key = (path.contract.clone(), path.channel.clone());
+    if let Some(quotas) = CHANNEL_DEFAULT_QUOTAS.may_load(storage, channel_key)? {
+        return Ok(Some(("channel", quotas)));
+    }
+
+    let config = load_tenant_config(storage, &path.contract)?;
+    if !config.default_quotas.is_empty() {
+        return Ok(Some(("global", config.default_quotas)));
+    }
+
+    match (config.unconfigured_behavior, config.default_new_denom_quota) {
+        (UnconfiguredBehavior::Allow, Some(quota)) => Ok(Some(("new_denom", vec![quota]))),
+        _ => Ok(None),
+    }
+}
+
+pub fn try_add_path(
+    deps: DepsMut,
+    actor: &Addr,
+    contract: Addr,
+    channel_id: String,
+    denom: String,
+    quotas: Vec<QuotaMsg>,
+    clock: &dyn TimeProvider,
+) -> Result<Response, ContractError> {
+    // codenit: should we make a function for checking this authorization?
+
+    add_new_paths(
+        deps,
+        vec![PathMsg::new(&contract, &channel_id, &denom, quotas)],
+        actor,
+        clock,
+    )?;
+
+    Ok(Response::new()
+        .add_attribute("method", "try_add_channel")
+        .add_attribute("contract", contract.as_str())
+        .add_attribute("channel_id", channel_id)
+        .add_attribute("denom", denom))
+}
+
+pub fn try_remove_path(
+    deps: DepsMut,
+    actor: &Addr,
+    contract: Addr,
+    channel_id: String,
+    denom: String,
+    clock: &dyn TimeProvider,
+) -> Result<Response, ContractError> {
+    let path = load_key_spec(deps.storage)?.path(&contract, &channel_id, &denom);
+
+    // Removing the path would make the escrowed funds unrefundable
+    let balance: Uint128 = path_escrow(deps.storage, &path)?
+        .into_iter()
+        .map(|(_, balance)| balance)
+        .sum();
+    if !balance.is_zero() {
+        return Err(ContractError::EscrowNotEmpty {
+            channel_id,
+            denom,
+            balance,
+        });
+    }
+
+    if RATE_LIMIT_TRACKERS.has(deps.storage, (&path).into()) {
+        update_metrics(deps.storage, |m| m.paths = m.paths.saturating_sub(1))?;
+        record_audit(
+            deps.storage,
+            &path,
+            AuditAction::RemovePath,
+            actor,
+            clock,
+            None,
+        )?;
+    }
+    remove_trackers(deps.storage, &path)?;
+    for tag in TAG_TRACKERS
+        .prefix((&path).into())
+        .keys(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?
+    {
+        TAG_TRACKERS.remove(deps.storage, ((&path).into(), tag));
+    }
+    for class in CLASS_TRACKERS
+        .prefix((&path).into())
+        .keys(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?
+    {
+        CLASS_TRACKERS.remove(deps.storage, ((&path).into(), class));
+    }
+    ESCROW_VALUES.remove(deps.storage, (&path).into());
+    // The fee is the owner's, so it outlives the path
+    let fee_bps = PATH_CONFIGS
+        .may_load(deps.storage, (&path).into())?
+        .map(|config| config.fee_bps)
+        .unwrap_or_default();
+    match fee_bps {
+        0 => PATH_CONFIGS.remove(deps.storage, path.into()),
+        fee_bps => PATH_CONFIGS.save(
+            deps.storage,
+            path.into(),
+            &PathConfig {
+                fee_bps,
+                ..PathConfig::default()
+            },
+        )?,
+    }
+    Ok(Response::new()
+        .add_attribute("method", "try_remove_channel")
+        .add_attribute("contract", contract.as_str())
+        .add_attribute("denom", denom)
+        .add_attribute("channel_id", channel_id))
+}
+
+// Reset specified quote_id for the given channel_id. The reset is recorded on the
+// quota together with the optional memo
+#[allow(clippy::too_many_arguments)]
+pub fn try_reset_path_quota(
+    deps: DepsMut,
+    actor: &Addr,
+    contract: Addr,
+    channel_id: String,
+    denom: String,
+    quota_id: String,
+    memo: Option<String>,
+    mode: ResetMode,
+    clock: &dyn TimeProvider,
+) -> Result<Response, ContractError> {
+    let path = load_key_spec(deps.storage)?.path(&contract, &channel_id, &denom);
+    let limits = match RATE_LIMIT_TRACKERS.may_load(deps.storage, (&path).into())? {
+        None => Err(ContractError::QuotaNotFound {
+            quota_id,
+            channel_id: channel_id.clone(),
+            denom: denom.clone(),
+        }),
+        Some(mut limits) => {
+            // A full reset drops the usage of the period, which is archived
+            // first
+            if let ResetMode::Full = mode {
+                let reset: Vec<&RateLimit> = limits
+                    .iter()
+                    .filter(|limit| limit.quota.name == quota_id)
+                    .collect();
+                archive_flows(deps.storage, &path, &reset, clock)?;
+            }
+            // Q: What happens here if quote_id not found? seems like we return ok?
+            limits.iter_mut().for_each(|limit| {
+                if limit.quota.name == quota_id.as_ref() {
+                    limit.reset(actor, clock, memo.clone(), mode.clone());
+                }
+            });
+            Ok(limits)
+        }
+    }?;
+    save_trackers(deps.storage, &path, &limits)?;
+    record_audit(
+        deps.storage,
+        &path,
+        AuditAction::Reset,
+        actor,
+        clock,
+        memo.clone(),
+    )?;
+
+    Ok(Response::new()
+        .add_attribute("method", "try_reset_channel")
+        .add_attribute("contract", contract.as_str())
+        .add_attribute("denom", denom)
+        .add_attribute("channel_id", channel_id)
+        .add_attribute("memo", memo.unwrap_or_default()))
+}
+
+// The archived periods of the quota are renamed too, so its history carries on
+// under the new name, and so are the periods of the pending sends, so they can
+// still be undone
+pub fn try_rename_quota(
+    deps: DepsMut,
+    contract: Addr,
+    channel_id: String,
+    denom: String,
+    from: String,
+    to: String,
+    clock: &dyn TimeProvider,
+) -> Result<Response, ContractError> {
+    if to.is_empty() {
+        return Err(ContractError::EmptyQuotaName {});
+    }
+    let key_spec = load_key_spec(deps.storage)?;
+    let path = key_spec.path(&contract, &channel_id, &denom);
+    let mut limits = RATE_LIMIT_TRACKERS
+        .may_load(deps.storage, (&path).into())?
+        .unwrap_or_default();
+    if limits.iter().any(|limit| limit.quota.name == to) {
+        return Err(ContractError::QuotaAlreadyExists {
+            quota_id: to,
+            channel_id,
+            denom,
+        });
+    }
+    let Some(limit) = limits.iter_mut().find(|limit| limit.quota.name == from) else {
+        return Err(ContractError::QuotaNotFound {
+            quota_id: from,
+            channel_id,
+            denom,
+        });
+    };
+    limit.quota.name = to.clone();
+    save_trackers(deps.storage, &path, &limits)?;
+
+    if let Some(mut history) = HISTORY.may_load(deps.storage, (&path).into())? {
+        history
+            .iter_mut()
+            .filter(|entry| entry.quota == from)
+            .for_each(|entry| entry.quota = to.clone());
+        HISTORY.save(deps.storage, (&path).into(), &history)?;
+    }
+    // Sends are keyed by the channel they were sent on, which the path may not
+    // be keyed by
+    let of_path =
+        |channel: &str, send: &PendingSend| key_spec.path(&contract, channel, &send.denom) == path;
+    let pending = PENDING_SENDS
+        .sub_prefix(contract.clone())
+        .range(deps.storage, None, None, Order::Ascending)
+        .filter(|item| match item {
+            Ok(((channel, _), send)) => {
+                of_path(channel, send)
+                    && send.tag.is_none()
+                    && send.periods.iter().any(|period| period.quota == from)
+            }
+            Err(_) => true,
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    for ((channel, sequence), mut send) in pending {
+        send.periods
+            .iter_mut()
+            .filter(|period| period.quota == from)
+            .for_each(|period| period.quota = to.clone());
+        PENDING_SENDS.save(deps.storage, (contract.clone(), channel, sequence), &send)?;
+    }
+    let pending = PENDING_ICA_SENDS
+        .sub_prefix(contract.clone())
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for ((channel, sequence), mut coins) in pending {
+        coins
+            .iter_mut()
+            .filter(|send| of_path(&channel, send) && send.tag.is_none())
+            .flat_map(|send| send.periods.iter_mut())
+            .filter(|period| period.quota == from)
+            .for_each(|period| period.quota = to.clone());
+        PENDING_ICA_SENDS.save(deps.storage, (contract.clone(), channel, sequence), &coins)?;
+    }
+    record_audit(
+        deps.storage,
+        &path,
+        AuditAction::RenameQuota,
+        &contract,
+        clock,
+        Some(format!("{from} -> {to}")),
+    )?;
+
+    Ok(Response::new()
+        .add_attribute("method", "try_rename_quota")
+        .add_attribute("contract", contract.as_str())
+        .add_attribute("channel_id", channel_id)
+        .add_attribute("denom", denom)
+        .add_attribute("from", from)
+        .add_attribute("to", to))
+}
+
+// Resets every quota of the paths matching the scope. Only the paths registered
+// for the calling contract can be reset. One event is emitted per reset path
+pub fn try_reset_all_quotas(
+    deps: DepsMut,
+    contract: Addr,
+    scope: ResetScope,
+    memo: Option<String>,
+    clock: &dyn TimeProvider,
+) -> Result<Response, ContractError> {
+    // The trackers are keyed by (contract, channel_id, denom), so the prefixes
+    // give us the paths of a contract or of one of its channels
+    let key_spec = load_key_spec(deps.storage)?;
+    let keys: Vec<(String, String)> = match scope {
+        ResetScope::Contract => RATE_LIMIT_TRACKERS
+            .sub_prefix(contract.clone())
+            .keys(deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()?,
+        ResetScope::Channel(channel_id) => RATE_LIMIT_TRACKERS
+            .prefix((contract.clone(), key_spec.channel(&channel_id)))
+            .keys(deps.storage, None, None, Order::Ascending)
+            .map(|denom| denom.map(|denom| (key_spec.channel(&channel_id), denom)))
+            .collect::<StdResult<_>>()?,
+        ResetScope::Path { channel_id, denom } => vec![(channel_id, denom)],
+    };
+
+    let mut response = Response::new()
+        .add_attribute("method", "try_reset_all_quotas")
+        .add_attribute("contract", contract.as_str());
+
+    for (channel_id, denom) in keys {
+        let path = key_spec.path(&contract, &channel_id, &denom);
+        let Some(mut limits) = RATE_LIMIT_TRACKERS.may_load(deps.storage, path.clone().into())?
+        else {
+            continue;
+        };
+        archive_flows(
+            deps.storage,
+            &path,
+            &limits.iter().collect::<Vec<_>>(),
+            clock,
+        )?;
+        limits
+            .iter_mut()
+            .for_each(|limit| limit.reset(&contract, clock, memo.clone(), ResetMode::Full));
+        save_trackers(deps.storage, &path, &limits)?;
+        record_audit(
+            deps.storage,
+            &path,
+            AuditAction::Reset,
+            &contract,
+            clock,
+            memo.clone(),
+        )?;
+
+        response = response.add_event(
+            Event::new("reset_path")
+                .add_attribute("contract", contract.as_str())
+                .add_attribute("channel_id", channel_id)
+                .add_attribute("denom", denom)
+                .add_attribute("quotas", limits.len().to_string()),
+        );
+    }
+
+    Ok(response.add_attribute("memo", memo.unwrap_or_default()))
+}
+
+pub fn try_set_path_config(
+    deps: DepsMut,
+    contract: Addr,
+    channel_id: String,
+    denom: String,
+    config: PathConfig,
+) -> Result<Response, ContractError> {
+    if config.on_reject_contract.is_some() && !config.reject_with_data {
+        return Err(ContractError::RejectHookWithoutData {});
+    }
+
+    let path = load_key_spec(deps.storage)?.path(&contract, &channel_id, &denom);
+    // The fee is the owner's to set
+    let mut config = config;
+    config.fee_bps = PATH_CONFIGS
+        .may_load(deps.storage, (&path).into())?
+        .map(|stored| stored.fee_bps)
+        .unwrap_or_default();
+    PATH_CONFIGS.save(deps.storage, (&path).into(), &config)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "try_set_path_config")
+        .add_attribute("contract", contract.as_str())
+        .add_attribute("channel_id", channel_id)
+        .add_attribute("denom", denom)
+        .add_attribute("escrow", config.escrow.to_string()))
+}
+
+pub fn try_set_path_fee(
+    deps: DepsMut,
+    sender: Addr,
+    path: PathId,
+    fee_bps: u16,
+) -> Result<Response, ContractError> {
+    cw_ownable::assert_owner(deps.storage, &sender)?;
+    if fee_bps > MAX_FEE_BPS {
+        return Err(ContractError::FeeTooHigh {
+            fee_bps,
+            max: MAX_FEE_BPS,
+        });
+    }
+
+    let path = load_key_spec(deps.storage)?.path(&path.contract, &path.channel_id, &path.denom);
+    let mut config = PATH_CONFIGS
+        .may_load(deps.storage, (&path).into())?
+        .unwrap_or_default();
+    config.fee_bps = fee_bps;
+    PATH_CONFIGS.save(deps.storage, (&path).into(), &config)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "try_set_path_fee")
+        .add_attribute("contract", path.contract.as_str())
+        .add_attribute("channel_id", path.channel)
+        .add_attribute("denom", path.denom)
+        .add_attribute("fee_bps", fee_bps.to_string()))
+}
+
+// The funds attached by the bridge pay its owed fees, denom by denom. Paying
+// more than owed is refused rather than kept
+pub fn try_pay_fees(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    for coin in &info.funds {
+        let key = (&info.sender, coin.denom.as_str());
+        let mut account = FEES.may_load(deps.storage, key)?.unwrap_or_default();
+        if coin.amount > account.owed {
+            return Err(ContractError::FeeOverpaid {
+                denom: coin.denom.clone(),
+                paid: coin.amount,
+                owed: account.owed,
+            });
+        }
+        account.owed -= coin.amount;
+        account.collected += coin.amount;
+        FEES.save(deps.storage, key, &account)?;
+    }
+    let paid: Vec<String> = info.funds.iter().map(ToString::to_string).collect();
+
+    Ok(Response::new()
+        .add_attribute("method", "try_pay_fees")
+        .add_attribute("contract", info.sender.as_str())
+        .add_attribute("paid", paid.join(",")))
+}
+
+pub fn try_withdraw_fees(
+    deps: DepsMut,
+    sender: Addr,
+    contract: Addr,
+    denom: String,
+    recipient: Option<String>,
+) -> Result<Response, ContractError> {
+    cw_ownable::assert_owner(deps.storage, &sender)?;
+    let recipient = match recipient {
+        Some(recipient) => deps.api.addr_validate(&recipient)?,
+        None => sender,
+    };
+
+    let key = (&contract, denom.as_str());
+    let mut account = FEES.may_load(deps.storage, key)?.unwrap_or_default();
+    let amount = account.collected;
+    account.collected = Uint128::zero();
+    FEES.save(deps.storage, key, &account)?;
+
+    let response = Response::new()
+        .add_attribute("method", "try_withdraw_fees")
+        .add_attribute("contract", contract.as_str())
+        .add_attribute("recipient", recipient.as_str())
+        .add_attribute("amount", format!("{amount}{denom}"));
+    if amount.is_zero() {
+        return Ok(response);
+    }
+    Ok(response.add_message(BankMsg::Send {
+        to_address: recipient.into_string(),
+        amount: coins(amount.u128(), denom),
+    }))
+}
+
+// The prefix naming cw20 tokens in a sweep
+const CW20_PREFIX: &str = "cw20:";
+
+// Forwards the balance of a denom the contract holds beyond what it owes: the
+// escrow of the paths and the collected fees. Both maps are scanned, they
+// hold an entry per path at most
+pub fn try_sweep(
+    deps: DepsMut,
+    contract_addr: &Addr,
+    sender: Addr,
+    denom: String,
+    to: String,
+) -> Result<Response, ContractError> {
+    cw_ownable::assert_owner(deps.storage, &sender)?;
+    let to = deps.api.addr_validate(&to)?;
+
+    let held = held_funds(deps.storage, &denom)?;
+    let token = denom
+        .strip_prefix(CW20_PREFIX)
+        .map(|token| deps.api.addr_validate(token))
+        .transpose()?;
+    let balance = match &token {
+        Some(token) => {
+            let query = cw20::Cw20QueryMsg::Balance {
+                address: contract_addr.to_string(),
+            };
+            let balance: cw20::BalanceResponse = deps.querier.query_wasm_smart(token, &query)?;
+            balance.balance
+        }
+        None => deps.querier.query_balance(contract_addr, &denom)?.amount,
+    };
+    let swept = balance.saturating_sub(held);
+
+    let response = Response::new()
+        .add_attribute("method", "try_sweep")
+        .add_attribute("denom", &denom)
+        .add_attribute("to", to.as_str())
+        .add_attribute("held", held.to_string())
+        .add_attribute("swept", swept.to_string());
+    if swept.is_zero() {
+        return Ok(response);
+    }
+    let transfer: CosmosMsg = match token {
+        Some(token) => WasmMsg::Execute {
+            contract_addr: token.into_string(),
+            msg: to_json_binary(&cw20::Cw20ExecuteMsg::Transfer {
+                recipient: to.to_string(),
+                amount: swept,
+            })?,
+            funds: vec![],
+        }
+        .into(),
+        None => BankMsg::Send {
+            to_address: to.to_string(),
+            amount: coins(swept.u128(), &denom),
+        }
+        .into(),
+    };
+    Ok(response.add_message(transfer).add_event(
+        Event::new("swept")
+            .add_attribute("denom", denom)
+            .add_attribute("amount", swept.to_string())
+            .add_attribute("to", to),
+    ))
+}
+
+// The funds of a denom the contract holds on behalf of others
+fn held_funds(storage: &dyn Storage, denom: &str) -> StdResult<Uint128> {
+    let mut held = Uint128::zero();
+    for item in ESCROW_BALANCES.range(storage, None, None, Order::Ascending) {
+        let ((_, _, path_denom), balance) = item?;
+        if path_denom == denom {
+            held = held.checked_add(balance)?;
+        }
+    }
+    for item in FEES.range(storage, None, None, Order::Ascending) {
+        let ((_, fee_denom), account) = item?;
+        if fee_denom == denom {
+            held = held.checked_add(account.collected)?;
+        }
+    }
+    Ok(held)
+}
+
+// Charges the fee of the path on an accepted transfer, owed by the bridge in the
+// denom of the packet, as the path may be keyed on any denom
+fn charge_fee(
+    storage: &mut dyn Storage,
+    path: &Path,
+    denom: &str,
+    accepted: Uint128,
+) -> StdResult<Uint128> {
+    let fee_bps = PATH_CONFIGS
+        .may_load(storage, path.into())?
+        .map(|config| config.fee_bps)
+        .unwrap_or_default();
+    let fee = accepted.multiply_ratio(fee_bps, 10_000_u128);
+    if fee.is_zero() {
+        return Ok(fee);
+    }
+    FEES.update(storage, (&path.contract, denom), |account| {
+        let mut account: FeeAccount = account.unwrap_or_default();
+        account.owed = account.owed.checked_add(fee)?;
+        Ok::<_, StdError>(account)
+    })?;
+    Ok(fee)
+}
+
+// Fees are owed until paid, so the bridge can't keep sending without paying
+// them. Receives go through, as refusing them wouldn't get the fees paid
+pub(crate) fn check_fees_paid(
+    storage: &dyn Storage,
+    contract: &Addr,
+    denom: &str,
+    direction: &FlowType,
+) -> Result<(), ContractError> {
+    if let FlowType::In = direction {
+        return Ok(());
+    }
+    let owed = FEES
+        .may_load(storage, (contract, denom))?
+        .unwrap_or_default()
+        .owed;
+    match owed.is_zero() {
+        true => Ok(()),
+        false => Err(ContractError::FeesOwed {
+            denom: denom.to_string(),
+            owed,
+        }),
+    }
+}
+
+pub fn try_sync_counterparty_state(
+    deps: DepsMut,
+    sender: Addr,
+    path: PathId,
+    usage: Vec<QuotaUsage>,
+    reported_at: Timestamp,
+    clock: &dyn TimeProvider,
+) -> Result<Response, ContractError> {
+    let path = load_key_spec(deps.storage)?.path(&path.contract, &path.channel_id, &path.denom);
+    let counterparty = PATH_CONFIGS
+        .may_load(deps.storage, (&path).into())?
+        .and_then(|config| config.counterparty)
+        .ok_or_else(|| ContractError::NoCounterparty {
+            channel_id: path.channel.clone(),
+            denom: path.denom.clone(),
+        })?;
+    if sender != counterparty.relayer {
+        return Err(ContractError::Unauthorized {});
+    }
+    if let Some(last) = COUNTERPARTY_STATES.may_load(deps.storage, (&path).into())? {
+        if reported_at <= last.reported_at {
+            return Err(ContractError::StaleCounterpartyState {
+                reported_at,
+                last_reported_at: last.reported_at,
+            });
+        }
+    }
+
+    let state = CounterpartyState {
+        usage,
+        reported_at,
+        synced_at: clock.now(),
+    };
+    COUNTERPARTY_STATES.save(deps.storage, (&path).into(), &state)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "try_sync_counterparty_state")
+        .add_attribute("contract", path.contract.as_str())
+        .add_attribute("channel_id", path.channel)
+        .add_attribute("denom", path.denom)
+        .add_attribute("counterparty_chain_id", counterparty.chain_id)
+        .add_attribute("reported_at", reported_at.to_string()))
+}
+
+// Filter ids end up in queries and attributes, so they are kept short
+const MAX_FILTER_ID_LENGTH: usize = 64;
+
+pub fn try_save_filter(
+    deps: DepsMut,
+    sender: Addr,
+    filter_id: String,
+    filter: PathFilter,
+) -> Result<Response, ContractError> {
+    if filter_id.is_empty() || filter_id.len() > MAX_FILTER_ID_LENGTH {
+        return Err(ContractError::InvalidFilterId {
+            filter_id,
+            max: MAX_FILTER_ID_LENGTH,
+        });
+    }
+    if let Some(stored) = FILTERS.may_load(deps.storage, &filter_id)? {
+        if stored.owner != sender {
+            return Err(ContractError::Unauthorized {});
+        }
+    }
+    let stored = StoredFilter {
+        owner: sender,
+        filter,
+    };
+    FILTERS.save(deps.storage, &filter_id, &stored)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "try_save_filter")
+        .add_attribute("filter_id", filter_id)
+        .add_attribute("owner", stored.owner))
+}
+
+pub fn try_remove_filter(
+    deps: DepsMut,
+    sender: Addr,
+    filter_id: String,
+) -> Result<Response, ContractError> {
+    let Some(stored) = FILTERS.may_load(deps.storage, &filter_id)? else {
+        return Err(ContractError::FilterNotFound { filter_id });
+    };
+    if stored.owner != sender {
+        cw_ownable::assert_owner(deps.storage, &sender)?;
+    }
+    FILTERS.remove(deps.storage, &filter_id);
+
+    Ok(Response::new()
+        .add_attribute("method", "try_remove_filter")
+        .add_attribute("filter_id", filter_id))
+}
+
+// Checks the settings of a config, or of a tenant once its overrides are
+// applied
+fn check_config(config: &Config) -> Result<(), ContractError> {
+    config
+        .default_quotas
+        .iter()
+        .try_for_each(QuotaMsg::validate)?;
+    config.check_quotas(&config.default_quotas)?;
+    if let Some(quota) = &config.default_new_denom_quota {
+        quota.validate()?;
+        config.check_quota_durations(std::slice::from_ref(quota))?;
+    }
+    if let Some(version) = config.attribute_schema_version {
+        if !(1..=ATTRIBUTE_SCHEMA_VERSION).contains(&version) {
+            return Err(ContractError::UnsupportedAttributeSchema {
+                version,
+                latest: ATTRIBUTE_SCHEMA_VERSION,
+            });
+        }
+    }
+    if config.grace_nanos > MAX_GRACE_NANOS {
+        return Err(ContractError::GraceTooLong {
+            grace_nanos: config.grace_nanos,
+            max: MAX_GRACE_NANOS,
+        });
+    }
+    if let Some(heartbeat) = &config.heartbeat {
+        if !(1..=MAX_HEARTBEAT_INTERVAL).contains(&heartbeat.interval) {
+            return Err(ContractError::InvalidHeartbeatInterval {
+                max: MAX_HEARTBEAT_INTERVAL,
+            });
+        }
+        if let OnMissedHeartbeat::Degrade { quotas } = &heartbeat.on_missed {
+            quotas.iter().try_for_each(QuotaMsg::validate)?;
+            config.check_quotas(quotas)?;
+        }
+    }
+    if let Some(bps) = config.capacity_bucket_bps {
+        if !(1..=10_000).contains(&bps) {
+            return Err(ContractError::InvalidCapacityBucket { bps });
+        }
+    }
+    if let Some(duration) = config.max_quota_duration {
+        if !(1..=MAX_QUOTA_DURATION).contains(&duration) {
+            return Err(ContractError::InvalidMaxQuotaDuration {
+                max: MAX_QUOTA_DURATION,
+            });
+        }
+    }
+    if config.max_rejections_per_block == Some(0) {
+        return Err(ContractError::InvalidMaxRejectionsPerBlock {});
+    }
+    if let Some(probation) = &config.probation {
+        if probation.periods == 0 || !(1..=10_000).contains(&probation.share_bps) {
+            return Err(ContractError::InvalidProbation {});
+        }
+    }
+    Ok(())
+}
+
+pub fn try_update_config(
+    deps: DepsMut,
+    sender: Addr,
+    config: Config,
+    clock: &dyn TimeProvider,
+) -> Result<Response, ContractError> {
+    cw_ownable::assert_owner(deps.storage, &sender)?;
+    check_config(&config)?;
+    // A new heartbeat policy gives the keeper a full interval to start
+    let previous = load_config(deps.storage)?.heartbeat;
+    if config.heartbeat.is_some() && previous.is_none() {
+        LAST_HEARTBEAT.save(deps.storage, &clock.now())?;
+    }
+    // The degraded flows were accounted against the quotas of the previous
+    // policy
+    if config.heartbeat != previous {
+        let degraded = DEGRADED_TRACKERS
+            .keys(deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<_>>>()?;
+        for key in degraded {
+            DEGRADED_TRACKERS.remove(deps.storage, key);
+        }
+    }
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "try_update_config")
+        .add_attribute(
+            "policy_contract",
+            config
+                .policy_contract
+                .map(|addr| addr.to_string())
+                .unwrap_or_else(|| "none".to_string()),
+        ))
+}
+
+pub fn try_heartbeat(
+    deps: DepsMut,
+    sender: Addr,
+    clock: &dyn TimeProvider,
+) -> Result<Response, ContractError> {
+    let keeper = load_config(deps.storage)?
+        .heartbeat
+        .map(|policy| policy.keeper);
+    if keeper != Some(sender) {
+        return Err(ContractError::Unauthorized {});
+    }
+    LAST_HEARTBEAT.save(deps.storage, &clock.now())?;
+
+    Ok(Response::new()
+        .add_attribute("method", "try_heartbeat")
+        .add_attribute("at", clock.now().to_string()))
+}
+
+// The time of the last heartbeat when it's older than the interval of the
+// heartbeat policy. Without a policy nothing is read
+pub(crate) fn missed_heartbeat(
+    storage: &dyn Storage,
+    config: &Config,
+    clock: &dyn TimeProvider,
+) -> StdResult<Option<Timestamp>> {
+    let Some(policy) = &config.heartbeat else {
+        return Ok(None);
+    };
+    let last_heartbeat = LAST_HEARTBEAT.may_load(storage)?.unwrap_or_default();
+    let missed = last_heartbeat.plus_seconds(policy.interval) < clock.now();
+    Ok(Some(last_heartbeat).filter(|_| missed))
+}
+
+// Rejects sends while heartbeats are missed and the policy freezes them.
+// Returns the last heartbeat when the send has to be degraded instead
+pub(crate) fn check_heartbeat(
+    storage: &dyn Storage,
+    config: &Config,
+    direction: &FlowType,
+    clock: &dyn TimeProvider,
+) -> Result<Option<Timestamp>, ContractError> {
+    let FlowType::Out = direction else {
+        return Ok(None);
+    };
+    let Some(last_heartbeat) = missed_heartbeat(storage, config, clock)? else {
+        return Ok(None);
+    };
+    match config.heartbeat.as_ref().map(|policy| &policy.on_missed) {
+        Some(OnMissedHeartbeat::Freeze) => Err(ContractError::HeartbeatMissed { last_heartbeat }),
+        _ => Ok(Some(last_heartbeat)),
+    }
+}
+
+// Accounts a send made while heartbeats are missed on the degraded quotas of
+// the path, returning the event telling it was degraded
+fn degrade_send(
+    storage: &mut dyn Storage,
+    config: &Config,
+    path: &Path,
+    packet: &Packet,
+    last_heartbeat: Timestamp,
+    clock: &dyn TimeProvider,
+) -> Result<Event, ContractError> {
+    let results = degraded_transfer(storage, config, path, packet, clock)?;
+    DEGRADED_TRACKERS.save(storage, path.into(), &results)?;
+    Ok(Event::new("heartbeat_missed")
+        .add_attribute("last_heartbeat", last_heartbeat.to_string())
+        .add_attribute("on_missed", "degrade"))
+}
+
+// Checks a send against the degraded quotas of the path, and returns their
+// flows with it
+pub(crate) fn degraded_transfer(
+    storage: &dyn Storage,
+    config: &Config,
+    path: &Path,
+    packet: &Packet,
+    clock: &dyn TimeProvider,
+) -> Result<Vec<RateLimit>, ContractError> {
+    let quotas = match config.heartbeat.as_ref().map(|policy| &policy.on_missed) {
+        Some(OnMissedHeartbeat::Degrade { quotas }) => quotas.as_slice(),
+        _ => &[],
+    };
+    let mut trackers = match DEGRADED_TRACKERS.may_load(storage, path.into())? {
+        Some(trackers) => trackers,
+        None => new_trackers(quotas, clock),
+    };
+    let results: Vec<RateLimit> = trackers
+        .iter_mut()
+        .map(|limit| {
+            limit.allow_transfer(
+                path,
+                &FlowType::Out,
+                packet.amount,
+                packet.channel_value,
+                clock,
+            )
+        })
+        .collect::<Result<_, ContractError>>()?;
+    Ok(results)
+}
+
+pub fn try_set_tenant_config(
+    deps: DepsMut,
+    sender: Addr,
+    contract: Addr,
+    tenant: Option<TenantConfig>,
+) -> Result<Response, ContractError> {
+    cw_ownable::assert_owner(deps.storage, &sender)?;
+    let response = Response::new()
+        .add_attribute("method", "try_set_tenant_config")
+        .add_attribute("contract", contract.as_str());
+    let Some(tenant) = tenant else {
+        TENANT_CONFIG.remove(deps.storage, &contract);
+        return Ok(response.add_attribute("overrides", "none"));
+    };
+
+    check_config(&load_config(deps.storage)?.with_tenant(tenant.clone()))?;
+    TENANT_CONFIG.save(deps.storage, &contract, &tenant)?;
+    Ok(response)
+}
+
+pub fn try_escrow_balance_callback(
+    deps: DepsMut,
+    sender: Addr,
+    path: Path,
+    balance: Uint128,
+    clock: &dyn TimeProvider,
+) -> Result<Response, ContractError> {
+    if load_config(deps.storage)?.escrow_querier != Some(sender) {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let value = EscrowValue {
+        amount: balance,
+        updated_at: clock.now(),
+    };
+    ESCROW_VALUES.save(deps.storage, (&path).into(), &value)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "try_escrow_balance_callback")
+        .add_attribute("contract", path.contract.as_str())
+        .add_attribute("channel_id", path.channel)
+        .add_attribute("denom", path.denom)
+        .add_attribute("balance", balance.to_string()))
+}
+
+// Asks the escrow querier for the balance of the path when a period of one of
+// its percentage quotas rolls over. The answer is used from the next packet on,
+// so a failing query only keeps the last balance and doesn't fail the packet
+fn escrow_query(
+    config: &Config,
+    path: &Path,
+    trackers: &[RateLimit],
+    clock: &dyn TimeProvider,
+) -> StdResult<Option<SubMsg>> {
+    let Some(querier) = &config.escrow_querier else {
+        return Ok(None);
+    };
+    let rollover = trackers
+        .iter()
+        .any(|limit| limit.quota.percentage.is_some() && limit.flow.is_expired(clock));
+    if !rollover {
+        return Ok(None);
+    }
+
+    let query = escrow_query_msg(querier, path)?;
+    Ok(Some(SubMsg::reply_on_error(query, ESCROW_QUERY_REPLY_ID)))
+}
+
+fn escrow_query_msg(querier: &Addr, path: &Path) -> StdResult<WasmMsg> {
+    Ok(WasmMsg::Execute {
+        contract_addr: querier.to_string(),
+        msg: to_json_binary(&EscrowQuerierMsg::QueryEscrowBalance {
+            contract: path.contract.clone(),
+            channel_id: path.channel.clone(),
+            denom: path.denom.clone(),
+        })?,
+        funds: vec![],
+    })
+}
+
+pub fn try_refresh_channel_value(deps: DepsMut, path: Path) -> Result<Response, ContractError> {
+    let Some(querier) = load_config(deps.storage)?.escrow_querier else {
+        return Err(ContractError::NoEscrowQuerier {});
+    };
+
+    Ok(Response::new()
+        .add_message(escrow_query_msg(&querier, &path)?)
+        .add_attribute("method", "try_refresh_channel_value")
+        .add_attribute("contract", path.contract.as_str())
+        .add_attribute("channel_id", path.channel)
+        .add_attribute("denom", path.denom))
+}
+
+// The channel value of a path: the one reported by the escrow querier while
+// it's fresh enough, the fallback of the packet if none was reported
+pub(crate) fn cached_channel_value(
+    storage: &dyn Storage,
+    config: &Config,
+    path: &Path,
+    fallback: Option<Uint128>,
+    clock: &dyn TimeProvider,
+) -> Result<Option<Uint128>, ContractError> {
+    let Some(value) = ESCROW_VALUES.may_load(storage, path.into())? else {
+        return Ok(fallback);
+    };
+    let age_nanos = clock.now().nanos().saturating_sub(value.updated_at.nanos());
+    let stale = config
+        .channel_value_max_age
+        .is_some_and(|max_age| age_nanos > max_age.saturating_mul(NANOS_PER_SECOND));
+    match (stale, &config.on_stale_channel_value) {
+        (false, _) => Ok(Some(value.amount)),
+        (true, OnStaleChannelValue::Floor) => Ok(Some(Uint128::zero())),
+        (true, OnStaleChannelValue::Reject) => Err(ContractError::StaleChannelValue {
+            channel_id: path.channel.clone(),
+            denom: path.denom.clone(),
+            updated_at: value.updated_at,
+        }),
+    }
+}
+
+pub fn try_register_bridge(
+    deps: DepsMut,
+    sender: Addr,
+    bridge: String,
+    registered: bool,
+) -> Result<Response, ContractError> {
+    cw_ownable::assert_owner(deps.storage, &sender)?;
+    let bridge = deps.api.addr_validate(&bridge)?;
+    if registered {
+        REGISTERED_BRIDGES.save(deps.storage, &bridge, &Empty {})?;
+    } else {
+        REGISTERED_BRIDGES.remove(deps.storage, &bridge);
+    }
+
+    Ok(Response::new()
+        .add_attribute("method", "try_register_bridge")
+        .add_attribute("bridge", bridge.as_str())
+        .add_attribute("registered", registered.to_string()))
+}
+
+// Senders are accepted if they have paths or channel defaults, so packets
+// aren't accounted on paths nobody configured for them
+pub(crate) fn check_sender(
+    storage: &dyn Storage,
+    config: &Config,
+    sender: &Addr,
+) -> Result<(), ContractError> {
+    if !config.require_registered_sender || REGISTERED_BRIDGES.has(storage, sender) {
+        return Ok(());
+    }
+
+    let has_paths = RATE_LIMIT_TRACKERS
+        .sub_prefix(sender.clone())
+        .keys(storage, None, None, Order::Ascending)
+        .next()
+        .is_some();
+    let has_defaults = CHANNEL_DEFAULT_QUOTAS
+        .prefix(sender.clone())
+        .keys(storage, None, None, Order::Ascending)
+        .next()
+        .is_some();
+    match has_paths || has_defaults {
+        true => Ok(()),
+        false => Err(ContractError::UnregisteredSender {
+            sender: sender.to_string(),
+        }),
+    }
+}
+
+// The contract takes over its own ownership, so owner messages can only be
+// executed through proposals
+pub fn try_set_multisig(
+    deps: DepsMut,
+    sender: Addr,
+    contract: &Addr,
+    owners: Vec<String>,
+    threshold: u32,
+) -> Result<Response, ContractError> {
+    cw_ownable::assert_owner(deps.storage, &sender)?;
+
+    let mut owners = owners
+        .iter()
+        .map(|owner| deps.api.addr_validate(owner))
+        .collect::<StdResult<Vec<_>>>()?;
+    owners.sort();
+    owners.dedup();
+    if threshold == 0 || threshold as usize > owners.len() {
+        return Err(ContractError::InvalidThreshold {
+            threshold,
+            owners: owners.len(),
+        });
+    }
+
+    MULTISIG.save(deps.storage, &Multisig { owners, threshold })?;
+    cw_ownable::initialize_owner(deps.storage, deps.api, Some(contract.as_str()))?;
+
+    Ok(Response::new()
+        .add_attribute("method", "try_set_multisig")
+        .add_attribute("threshold", threshold.to_string()))
+}
+
+fn assert_multisig_owner(storage: &dyn Storage, sender: &Addr) -> Result<Multisig, ContractError> {
+    match MULTISIG.may_load(storage)? {
+        Some(multisig) if multisig.owners.contains(sender) => Ok(multisig),
+        _ => Err(ContractError::Unauthorized {}),
+    }
+}
+
+fn load_open_proposal(
+    storage: &dyn Storage,
+    id: u64,
+    clock: &dyn TimeProvider,
+) -> Result<Proposal, ContractError> {
+    let proposal = PROPOSALS.load(storage, id)?;
+    if proposal.executed {
+        return Err(ContractError::ProposalExecuted { id });
+    }
+    if proposal.expires < clock.now() {
+        return Err(ContractError::ProposalExpired { id });
+    }
+    Ok(proposal)
+}
+
+pub fn try_propose(
+    deps: DepsMut,
+    sender: Addr,
+    msg: ExecuteMsg,
+    expires_in: u64,
+    clock: &dyn TimeProvider,
+) -> Result<Response, ContractError> {
+    assert_multisig_owner(deps.storage, &sender)?;
+    if expires_in > MAX_PROPOSAL_EXPIRY {
+        return Err(ContractError::ProposalExpiryTooLong {
+            expires_in,
+            max: MAX_PROPOSAL_EXPIRY,
+        });
+    }
+
+    let id = PROPOSAL_COUNT.may_load(deps.storage)?.unwrap_or_default();
+    PROPOSAL_COUNT.save(deps.storage, &(id + 1))?;
+    let proposal = Proposal {
+        id,
+        msg,
+        proposer: sender.clone(),
+        confirmations: vec![sender],
+        expires: clock.now().plus_seconds(expires_in),
+        executed: false,
+    };
+    PROPOSALS.save(deps.storage, id, &proposal)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "try_propose")
+        .add_attribute("proposal_id", id.to_string()))
+}
+
+// The multisig acts through its proposals, which the contract executes on its
+// own behalf, so a single multisig owner can't act for it
+fn assert_approver(
+    storage: &dyn Storage,
+    sender: &Addr,
+    contract: &Addr,
+    role: &ApproverRole,
+) -> Result<(), ContractError> {
+    match role {
+        ApproverRole::Owner => cw_ownable::assert_owner(storage, sender)?,
+        ApproverRole::Multisig => {
+            if sender != contract || MULTISIG.may_load(storage)?.is_none() {
+                return Err(ContractError::Unauthorized {});
+            }
+        }
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn try_pre_approve_transfer(
+    deps: DepsMut,
+    sender: Addr,
+    contract: &Addr,
+    path: PathId,
+    amount: Uint128,
+    window: u64,
+    approver_role: ApproverRole,
+    clock: &dyn TimeProvider,
+) -> Result<Response, ContractError> {
+    assert_approver(deps.storage, &sender, contract, &approver_role)?;
+    if window > MAX_PRE_APPROVAL_WINDOW {
+        return Err(ContractError::PreApprovalWindowTooLong {
+            window,
+            max: MAX_PRE_APPROVAL_WINDOW,
+        });
+    }
+
+    let path = load_key_spec(deps.storage)?.path(&path.contract, &path.channel_id, &path.denom);
+    let id = PRE_APPROVAL_COUNT
+        .may_load(deps.storage)?
+        .unwrap_or_default();
+    PRE_APPROVAL_COUNT.save(deps.storage, &(id + 1))?;
+    let approval = PreApproval {
+        id,
+        amount,
+        expires: clock.now().plus_seconds(window),
+        approver: sender,
+        approver_role,
+        consumed_at: None,
+    };
+    PRE_APPROVALS.save(deps.storage, ((&path).into(), id), &approval)?;
+    OPEN_PRE_APPROVALS.save(deps.storage, ((&path).into(), amount.u128(), id), &Empty {})?;
+
+    Ok(Response::new()
+        .add_attribute("method", "try_pre_approve_transfer")
+        .add_attribute("pre_approval_id", id.to_string())
+        .add_attribute("contract", path.contract.as_str())
+        .add_attribute("channel_id", path.channel)
+        .add_attribute("denom", path.denom)
+        .add_attribute("amount", amount.to_string())
+        .add_attribute("expires", approval.expires.to_string()))
+}
+
+// Marks the oldest open pre-approval of the path for exactly `amount` as
+// consumed, and returns its id. The packet then skips the quota checks. The
+// expired approvals it comes across leave the index of open ones
+fn consume_pre_approval(
+    storage: &mut dyn Storage,
+    path: &Path,
+    amount: Uint128,
+    clock: &dyn TimeProvider,
+) -> StdResult<Option<u64>> {
+    let now = clock.now();
+    loop {
+        let next = OPEN_PRE_APPROVALS
+            .prefix((path.into(), amount.u128()))
+            .keys(storage, None, None, Order::Ascending)
+            .next()
+            .transpose()?;
+        let Some(id) = next else {
+            return Ok(None);
+        };
+
+        OPEN_PRE_APPROVALS.remove(storage, (path.into(), amount.u128(), id));
+        let mut approval = PRE_APPROVALS.load(storage, (path.into(), id))?;
+        if now <= approval.expires {
+            approval.consumed_at = Some(now);
+            PRE_APPROVALS.save(storage, (path.into(), id), &approval)?;
+            return Ok(Some(id));
+        }
+    }
+}
+
+// Reservations made before ACTIVE_RESERVATIONS was added are indexed on
+// migration. Only approvers make them, so there are few
+pub fn index_active_reservations(
+    storage: &mut dyn Storage,
+    clock: &dyn TimeProvider,
+) -> StdResult<()> {
+    let active: Vec<(PathKey, u64)> = RESERVATIONS
+        .range(storage, None, None, Order::Ascending)
+        .filter_map(|item| match item {
+            Ok((key, reservation)) => reservation.is_active(clock.now()).then_some(Ok(key)),
+            Err(err) => Some(Err(err)),
+        })
+        .collect::<StdResult<_>>()?;
+    active
+        .into_iter()
+        .try_for_each(|key| ACTIVE_RESERVATIONS.save(storage, key, &Empty {}))
+}
+
+// Pre-approvals created before OPEN_PRE_APPROVALS was added are indexed on
+// migration. Only the owner creates them, so there are few
+pub fn index_open_pre_approvals(storage: &mut dyn Storage) -> StdResult<()> {
+    let open: Vec<(PathKey, u128, u64)> = PRE_APPROVALS
+        .range(storage, None, None, Order::Ascending)
+        .filter_map(|item| match item {
+            Ok(((path, id), approval)) => {
+                approval
+                    .consumed_at
+                    .is_none()
+                    .then_some(Ok((path, approval.amount.u128(), id)))
+            }
+            Err(err) => Some(Err(err)),
+        })
+        .collect::<StdResult<_>>()?;
+    open.into_iter()
+        .try_for_each(|key| OPEN_PRE_APPROVALS.save(storage, key, &Empty {}))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn try_reserve_capacity(
+    deps: DepsMut,
+    sender: Addr,
+    contract: &Addr,
+    path: PathId,
+    direction: FlowType,
+    amount: Uint128,
+    window: u64,
+    beneficiary: String,
+    role: ApproverRole,
+    clock: &dyn TimeProvider,
+) -> Result<Response, ContractError> {
+    assert_approver(deps.storage, &sender, contract, &role)?;
+    if window > MAX_RESERVATION_WINDOW {
+        return Err(ContractError::ReservationWindowTooLong {
+            window,
+            max: MAX_RESERVATION_WINDOW,
+        });
+    }
+
+    let path = load_key_spec(deps.storage)?.path(&path.contract, &path.channel_id, &path.denom);
+    if prune_reservations(deps.storage, &path, clock)? >= MAX_ACTIVE_RESERVATIONS {
+        return Err(ContractError::TooManyReservations {
+            max: MAX_ACTIVE_RESERVATIONS,
+        });
+    }
+    let id = RESERVATION_COUNT
+        .may_load(deps.storage)?
+        .unwrap_or_default();
+    RESERVATION_COUNT.save(deps.storage, &(id + 1))?;
+    let reservation = Reservation {
+        id,
+        direction,
+        amount,
+        consumed: Uint128::zero(),
+        beneficiary,
+        expires: clock.now().plus_seconds(window),
+        reserved_by: sender,
+        role,
+    };
+    RESERVATIONS.save(deps.storage, ((&path).into(), id), &reservation)?;
+    ACTIVE_RESERVATIONS.save(deps.storage, ((&path).into(), id), &Empty {})?;
+
+    Ok(Response::new()
+        .add_attribute("method", "try_reserve_capacity")
+        .add_attribute("reservation_id", id.to_string())
+        .add_attribute("contract", path.contract.as_str())
+        .add_attribute("channel_id", path.channel)
+        .add_attribute("denom", path.denom)
+        .add_attribute("direction", reservation.direction.as_str())
+        .add_attribute("amount", amount.to_string())
+        .add_attribute("beneficiary", reservation.beneficiary)
+        .add_attribute("expires", reservation.expires.to_string()))
+}
+
+// Makes sure the packet leaves the open reservations of the path untouched,
+// save for the one of its sender, which it draws from first. That one is
+// returned, to be drawn from once the packet is accepted. Packets that don't
+// fit the quotas anyway are left to fail on them. Expired reservations are
+// dropped from the active ones on the way
+fn check_reservations(
+    storage: &mut dyn Storage,
+    config: &Config,
+    path: &Path,
+    packet: &Packet,
+    direction: &FlowType,
+    clock: &dyn TimeProvider,
+) -> Result<Option<Reservation>, ContractError> {
+    prune_reservations(storage, path, clock)?;
+    check_reserved_capacity(storage, config, path, packet, direction, clock)
+}
+
+// Drops the expired reservations of the path from the active ones, and returns
+// how many are left
+fn prune_reservations(
+    storage: &mut dyn Storage,
+    path: &Path,
+    clock: &dyn TimeProvider,
+) -> StdResult<usize> {
+    let ids = ACTIVE_RESERVATIONS
+        .prefix(path.into())
+        .keys(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    let mut active = 0;
+    for id in ids {
+        let reservation = RESERVATIONS.load(storage, (path.into(), id))?;
+        match reservation.is_active(clock.now()) {
+            true => active += 1,
+            false => ACTIVE_RESERVATIONS.remove(storage, (path.into(), id)),
+        }
+    }
+    Ok(active)
+}
+
+// The checks of check_reservations, without dropping the expired reservations
+pub(crate) fn check_reserved_capacity(
+    storage: &dyn Storage,
+    config: &Config,
+    path: &Path,
+    packet: &Packet,
+    direction: &FlowType,
+    clock: &dyn TimeProvider,
+) -> Result<Option<Reservation>, ContractError> {
+    let now = clock.now();
+    let ids = ACTIVE_RESERVATIONS
+        .prefix(path.into())
+        .keys(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    let mut reservations = vec![];
+    for id in ids {
+        let reservation = RESERVATIONS.load(storage, (path.into(), id))?;
+        if reservation.is_active(now) && reservation.direction == *direction {
+            reservations.push(reservation);
+        }
+    }
+    if reservations.is_empty() {
+        return Ok(None);
+    }
+
+    let own = reservations
+        .iter()
+        .find(|reservation| packet.sender.as_deref() == Some(reservation.beneficiary.as_str()))
+        .cloned();
+    let drawn = own.as_ref().map_or(Uint128::zero(), |reservation| {
+        packet.amount.min(reservation.left())
+    });
+    let reserved = reservations
+        .iter()
+        .fold(Uint256::zero(), |total, reservation| {
+            total + Uint256::from(reservation.left())
+        })
+        - Uint256::from(drawn);
+
+    let trackers = RATE_LIMIT_TRACKERS
+        .may_load(storage, path.into())?
+        .unwrap_or_default();
+    let channel_value = cached_channel_value(storage, config, path, packet.channel_value, clock)?;
+    for tracker in trackers {
+        let remaining = tracker.remaining(direction, channel_value, clock);
+        let amount = Uint256::from(packet.amount);
+        if amount <= remaining && amount + reserved > remaining {
+            return Err(ContractError::CapacityReserved {
+                quota_name: tracker.quota.name,
+                amount: packet.amount,
+                reserved,
+                remaining,
+            });
+        }
+    }
+    Ok(own)
+}
+
+pub fn try_confirm(
+    deps: DepsMut,
+    sender: Addr,
+    id: u64,
+    clock: &dyn TimeProvider,
+) -> Result<Response, ContractError> {
+    assert_multisig_owner(deps.storage, &sender)?;
+
+    let mut proposal = load_open_proposal(deps.storage, id, clock)?;
+    if !proposal.confirmations.contains(&sender) {
+        proposal.confirmations.push(sender.clone());
+        PROPOSALS.save(deps.storage, id, &proposal)?;
+    }
+
+    Ok(Response::new()
+        .add_attribute("method", "try_confirm")
+        .add_attribute("proposal_id", id.to_string())
+        .add_attribute("owner", sender.as_str())
+        .add_attribute("confirmations", proposal.confirmations.len().to_string()))
+}
+
+// The message is sent by the contract to itself, so it passes the owner checks.
+// Confirmations of addresses removed from the multisig since don't count
+pub fn try_execute_proposal(
+    deps: DepsMut,
+    contract: &Addr,
+    id: u64,
+    clock: &dyn TimeProvider,
+) -> Result<Response, ContractError> {
+    let mut proposal = load_open_proposal(deps.storage, id, clock)?;
+    let multisig = MULTISIG.load(deps.storage)?;
+    let confirmations = proposal
+        .confirmations
+        .iter()
+        .filter(|owner| multisig.owners.contains(owner))
+        .count();
+    if confirmations < multisig.threshold as usize {
+        return Err(ContractError::ThresholdNotMet {
+            id,
+            confirmations,
+            threshold: multisig.threshold,
+        });
+    }
+
+    proposal.executed = true;
+    PROPOSALS.save(deps.storage, id, &proposal)?;
+
+    Ok(Response::new()
+        .add_message(WasmMsg::Execute {
+            contract_addr: contract.to_string(),
+            msg: to_json_binary(&proposal.msg)?,
+            funds: vec![],
+        })
+        .add_attribute("method", "try_execute_proposal")
+        .add_attribute("proposal_id", id.to_string()))
+}
+
+// Authorization is done by the caller, as pausing can be done both by the owner
+// and by the chain through sudo
+pub fn try_set_paused(
+    deps: DepsMut,
+    paused: bool,
+    auto_unpause_at: Option<Timestamp>,
+    clock: &dyn TimeProvider,
+) -> Result<Response, ContractError> {
+    let auto_unpause_at = auto_unpause_at.filter(|_| paused);
+    if let Some(expiry) = auto_unpause_at.filter(|expiry| *expiry <= clock.now()) {
+        return Err(ContractError::PauseExpiryInPast {
+            auto_unpause_at: expiry,
+        });
+    }
+    PAUSED.save(deps.storage, &paused)?;
+    match auto_unpause_at {
+        Some(expiry) => PAUSE_EXPIRY.save(deps.storage, &expiry)?,
+        None => PAUSE_EXPIRY.remove(deps.storage),
+    }
+
+    Ok(Response::new()
+        .add_attribute("method", "try_set_paused")
+        .add_attribute("paused", paused.to_string())
+        .add_attribute(
+            "auto_unpause_at",
+            auto_unpause_at.map_or("none".to_string(), |expiry| expiry.to_string()),
+        ))
+}
+
+// Lifts the global pause once its auto_unpause_at has passed, returning the
+// auto_unpaused event. Fails while the pause holds
+fn lift_expired_pause(
+    storage: &mut dyn Storage,
+    clock: &dyn TimeProvider,
+) -> Result<Option<Event>, ContractError> {
+    if !PAUSED.may_load(storage)?.unwrap_or_default() {
+        return Ok(None);
+    }
+    let Some(expiry) = PAUSE_EXPIRY.may_load(storage)? else {
+        return Err(ContractError::Paused {});
+    };
+    if clock.now() < expiry {
+        return Err(ContractError::Paused {});
+    }
+    PAUSED.save(storage, &false)?;
+    PAUSE_EXPIRY.remove(storage);
+    Ok(Some(
+        Event::new("auto_unpaused").add_attribute("auto_unpause_at", expiry.to_string()),
+    ))
+}
+
+pub fn try_set_direction_paused(
+    deps: DepsMut,
+    sender: Addr,
+    direction: FlowType,
+    paused: bool,
+) -> Result<Response, ContractError> {
+    cw_ownable::assert_owner(deps.storage, &sender)?;
+
+    if paused {
+        PAUSED_DIRECTIONS.save(deps.storage, direction.as_str(), &Empty {})?;
+    } else {
+        PAUSED_DIRECTIONS.remove(deps.storage, direction.as_str());
+    }
+
+    Ok(Response::new()
+        .add_attribute("method", "try_set_direction_paused")
+        .add_attribute("direction", direction.as_str())
+        .add_attribute("paused", paused.to_string()))
+}
+
+pub fn try_set_scope_paused(
+    deps: DepsMut,
+    sender: Addr,
+    scope: PauseScope,
+    paused: bool,
+) -> Result<Response, ContractError> {
+    cw_ownable::assert_owner(deps.storage, &sender)?;
+
+    let key_spec = load_key_spec(deps.storage)?;
+    let scope = match scope {
+        PauseScope::Channel {
+            contract,
+            channel_id,
+        } => PauseScope::Channel {
+            channel_id: key_spec.channel(&channel_id),
+            contract,
+        },
+        PauseScope::Path(path) => {
+            PauseScope::Path(key_spec.path(&path.contract, &path.channel, &path.denom))
+        }
+        scope => scope,
+    };
+    let response = Response::new().add_attribute("method", "try_set_scope_paused");
+    let response = match &scope {
+        PauseScope::Contract { contract } => {
+            match paused {
+                true => PAUSED_CONTRACTS.save(deps.storage, contract, &Empty {})?,
+                false => PAUSED_CONTRACTS.remove(deps.storage, contract),
+            }
+            response
+                .add_attribute("scope", "contract")
+                .add_attribute("contract", contract.as_str())
+        }
+        PauseScope::Channel {
+            contract,
+            channel_id,
+        } => {
+            let key = (contract, channel_id.as_str());
+            match paused {
+                true => PAUSED_CHANNELS.save(deps.storage, key, &Empty {})?,
+                false => PAUSED_CHANNELS.remove(deps.storage, key),
+            }
+            response
+                .add_attribute("scope", "channel")
+                .add_attribute("contract", contract.as_str())
+                .add_attribute("channel_id", channel_id)
+        }
+        PauseScope::Path(path) => {
+            match paused {
+                true => PAUSED_PATHS.save(deps.storage, path.into(), &Empty {})?,
+                false => PAUSED_PATHS.remove(deps.storage, path.into()),
+            }
+            response
+                .add_attribute("scope", "path")
+                .add_attribute("contract", path.contract.as_str())
+                .add_attribute("channel_id", &path.channel)
+                .add_attribute("denom", &path.denom)
+        }
+    };
+    Ok(response.add_attribute("paused", paused.to_string()))
+}
+
+// Checks the pauses covering the packet, from the broadest to the narrowest:
+// the global pause, the pause of its direction, then the ones of its bridge
+// contract, of the channel and of the path
+pub(crate) fn check_paused(
+    storage: &dyn Storage,
+    path: &Path,
+    direction: &FlowType,
+    clock: &dyn TimeProvider,
+) -> Result<(), ContractError> {
+    if is_paused(storage, clock.now())? {
+        return Err(ContractError::Paused {});
+    }
+    check_scopes_paused(storage, path, direction)
+}
+
+// The pauses narrower than the global one holding the packets of the path back
+fn check_scopes_paused(
+    storage: &dyn Storage,
+    path: &Path,
+    direction: &FlowType,
+) -> Result<(), ContractError> {
+    if PAUSED_DIRECTIONS.has(storage, direction.as_str()) {
+        return Err(ContractError::DirectionPaused {
+            direction: direction.as_str().to_string(),
+        });
+    }
+    if PAUSED_CONTRACTS.has(storage, &path.contract) {
+        return Err(ContractError::ContractPaused {
+            contract: path.contract.to_string(),
+        });
+    }
+    if PAUSED_CHANNELS.has(storage, (&path.contract, &path.channel)) {
+        return Err(ContractError::ChannelPaused {
+            contract: path.contract.to_string(),
+            channel_id: path.channel.clone(),
+        });
+    }
+    if PAUSED_PATHS.has(storage, path.into()) {
+        return Err(ContractError::PathPaused {
+            channel_id: path.channel.clone(),
+            denom: path.denom.clone(),
+        });
+    }
+    Ok(())
+}
+
+// Enters or leaves maintenance. Only the owner can clear the flag left by a
+// migration continued over several transactions
+pub fn try_set_maintenance(
+    deps: DepsMut,
+    sender: Addr,
+    enabled: bool,
+) -> Result<Response, ContractError> {
+    cw_ownable::assert_owner(deps.storage, &sender)?;
+    MAINTENANCE.save(deps.storage, &enabled)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "try_set_maintenance")
+        .add_attribute("maintenance", enabled.to_string()))
+}
+
+// While in maintenance only the owner, or the multisig owners, can execute
+// messages. Packets are rejected so they aren't accounted on half-migrated state
+pub(crate) fn check_maintenance(storage: &dyn Storage, sender: &Addr) -> Result<(), ContractError> {
+    if !MAINTENANCE.may_load(storage)?.unwrap_or_default()
+        || cw_ownable::is_owner(storage, sender)?
+        || assert_multisig_owner(storage, sender).is_ok()
+    {
+        return Ok(());
+    }
+    Err(ContractError::Maintenance {})
+}
+
+// Packets on paths still waiting in a legacy namespace are rejected until
+// ContinueMigration gets to them, as their trackers can't be loaded
+pub(crate) fn check_migrated(storage: &dyn Storage, path: &Path) -> Result<(), ContractError> {
+    if is_migrated(storage, path) {
+        return Ok(());
+    }
+    Err(ContractError::PathNotMigrated {
+        channel_id: path.channel.clone(),
+        denom: path.denom.clone(),
+    })
+}
+
+// Moves the next batch of a migration that didn't fit in the migrate call.
+// Callable by anyone
+pub fn try_continue_migration(
+    deps: DepsMut,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    let limit = limit.unwrap_or(MIGRATION_BATCH).max(1);
+    let (moved, done) = continue_migration(deps.storage, limit)?;
+    let (normalized, conflicts) = match done {
+        true => normalize_path_denoms(deps.storage)?,
+        false => (0, 0),
+    };
+    if done {
+        seed_path_gauge(deps.storage)?;
+    }
+
+    Ok(Response::new()
+        .add_attribute("method", "try_continue_migration")
+        .add_attribute("moved_entries", moved.to_string())
+        .add_attribute("done", done.to_string())
+        .add_attribute("normalized_paths", normalized.to_string())
+        .add_attribute("denom_conflicts", conflicts.to_string()))
+}
+
+// This function will process a packet and extract the paths information, funds,
+// and channel value from it. This is will have to interact with the chain via grpc queries to properly
+// obtain this information.
+//
+// For backwards compatibility, we're teporarily letting the chain override the
+// denom and channel value, but these should go away in favour of the contract
+// extracting these from the packet
+//
+// All the flow state is written before any external call is made: the policy
+// contract is only queried, and hooks, escrow refunds and escrow queries are
+// returned as messages, which run after this call has returned. Only the
+// notifications of the reject hook and of the alert contract, and the escrow
+// queries, ask for a reply, on error, so any other failing call reverts the
+// whole transaction, flows included. A hook re-entering the contract sees the
+// packet accounted, and a failing one is reverted along with the sends and
+// undos it made
+pub fn process_packet(
+    deps: DepsMut,
+    info: MessageInfo,
+    config: &Config,
+    packet: Packet,
+    direction: FlowType,
+    clock: &dyn TimeProvider,
+) -> Result<Response, ContractError> {
+    transfer_packet(deps, info, config, packet, direction, false, clock)
+}
+
+// The coins of an ICA packet share its sequence, which is checked once for the
+// whole packet, and are recorded together as pending sends
+fn transfer_packet(
+    mut deps: DepsMut,
+    info: MessageInfo,
+    config: &Config,
+    mut packet: Packet,
+    direction: FlowType,
+    ica: bool,
+    clock: &dyn TimeProvider,
+) -> Result<Response, ContractError> {
+    packet.denom = normalize_denom(&packet.denom);
+
+    let contract = info.sender.clone();
+    let path = &load_key_spec(deps.storage)?.path(&contract, &packet.channel, &packet.denom);
+    // The global pause is checked on its own as an expired one is lifted here
+    let auto_unpaused = lift_expired_pause(deps.storage, clock)?;
+    check_scopes_paused(deps.storage, path, &direction)?;
+    let missed_heartbeat = check_heartbeat(deps.storage, config, &direction, clock)?;
+    check_channel_asset(deps.storage, &contract, &packet.channel, &packet.denom)?;
+    check_sender(deps.storage, config, &contract)?;
+    check_fees_paid(deps.storage, &contract, &packet.denom, &direction)?;
+    if let Some(endpoints) = &packet.endpoints {
+        check_direction(deps.storage, path, endpoints, &direction)?;
+    }
+    check_migrated(deps.storage, path)?;
+    let tracked = tracks_denom(deps.storage, config, path)?;
+    if let Some(sequence) = packet.sequence.filter(|_| !ica) {
+        check_packet_sequence(
             deps.storage,
-            path.into(),
-            &path_msg
-                .quotas
+            &contract,
+            &packet.channel,
+            &direction,
+            sequence,
+        )?;
+    }
+
+    let funds = packet.amount;
+
+    check_policy(deps.as_ref(), config, path, &packet, &direction)?;
+
+    let escrowed = match direction {
+        FlowType::Out => escrow_funds(deps.storage, &info, path, &packet)?,
+        FlowType::In => {
+            nonpayable(&info)?;
+            false
+        }
+    };
+
+    let approval = consume_pre_approval(deps.storage, path, funds, clock)?;
+    let reservation = match approval {
+        Some(_) => None,
+        None => check_reservations(deps.storage, config, path, &packet, &direction, clock)?,
+    };
+    let transfer = match approval {
+        Some(id) => Ok((
+            Response::new()
+                .add_attribute("method", "process_packet")
+                .add_attribute("pre_approval", id.to_string()),
+            funds,
+        )),
+        None => try_transfer(
+            deps.branch(),
+            config,
+            path,
+            &packet,
+            direction.clone(),
+            clock,
+        ),
+    };
+    let (mut response, funds) = match (transfer, &direction) {
+        (Err(err @ ContractError::RateLimitExceded { .. }), FlowType::Out) => {
+            // Rejected sends answered with data get their escrow back whole
+            let refund = match escrowed {
+                true => refund_escrow(deps.storage, path, &packet.denom, packet.amount)?,
+                false => None,
+            };
+            return reject_send(deps.storage, config, path, &packet, err, clock)
+                .map(|response| response.add_messages(refund).add_events(auto_unpaused));
+        }
+        (transfer, _) => transfer?,
+    };
+    // The escrowed remainder of a truncated send goes back to the bridge
+    if escrowed {
+        let remainder = packet.amount - funds;
+        response =
+            response.add_messages(refund_escrow(deps.storage, path, &packet.denom, remainder)?);
+    }
+    // Pre-approved transfers don't pay the fee of the path
+    if approval.is_none() {
+        let fee = charge_fee(deps.storage, path, &packet.denom, funds)?;
+        if !fee.is_zero() {
+            response = response.set_data(to_json_binary(&TransferData {
+                accepted: funds,
+                remainder: packet.amount - funds,
+                fee,
+            })?);
+        }
+    }
+    packet.amount = funds;
+    if let Some(mut reservation) = reservation {
+        reservation.consumed += funds.min(reservation.left());
+        RESERVATIONS.save(deps.storage, (path.into(), reservation.id), &reservation)?;
+        if reservation.left().is_zero() {
+            ACTIVE_RESERVATIONS.remove(deps.storage, (path.into(), reservation.id));
+        }
+        response = response.add_attribute("reservation", reservation.id.to_string());
+    }
+    let mut degraded = None;
+    if approval.is_none() {
+        check_class_quotas(deps.storage, path, &packet, &direction, clock)?;
+        if let Some(last_heartbeat) = missed_heartbeat {
+            let event = degrade_send(deps.storage, config, path, &packet, last_heartbeat, clock)?;
+            degraded = Some(event);
+        }
+    }
+
+    if let FlowType::Out = direction {
+        let in_flight = check_in_flight(deps.storage, path, funds)?;
+        if let Some(sequence) = packet.sequence {
+            record_pending_send(
+                deps.storage,
+                config,
+                path,
+                &packet,
+                sequence,
+                in_flight,
+                escrowed,
+                approval.is_some(),
+                degraded.is_some(),
+                ica,
+            )?;
+        }
+    }
+
+    update_metrics(deps.storage, |m| match direction {
+        FlowType::In => m.packets_in += 1,
+        FlowType::Out => m.packets_out += 1,
+    })?;
+    // Denoms past max_denoms_per_channel go through without stats
+    let mut rollup = None;
+    if tracked {
+        rollup = record_daily_stats(deps.storage, path, clock, |stats| {
+            match direction {
+                FlowType::In => {
+                    stats.inflow += Uint256::from(funds);
+                    stats.packets_in += 1;
+                }
+                FlowType::Out => {
+                    stats.outflow += Uint256::from(funds);
+                    stats.packets_out += 1;
+                }
+            }
+            stats.last_packet = Some(LastPacket {
+                direction,
+                amount: funds,
+                timestamp: clock.now(),
+                sequence: packet.sequence,
+            });
+        })?;
+    }
+
+    Ok(response
+        .add_events(auto_unpaused)
+        .add_events(degraded)
+        .add_events(rollup))
+}
+
+// The coins of an ICA packet are sent as one: each is accounted as a transfer
+// on its own path, pauses included, and the packet fails unless all of them
+// are accepted in full. So neither truncation nor the reject hook applies to
+// them. Coins of the same denom are added up first, and the escrow is split by
+// denom. Each denom is recorded as a pending send of the packet sequence,
+// undone or acknowledged on its own
+pub fn process_ica_packet(
+    mut deps: DepsMut,
+    info: MessageInfo,
+    config: &Config,
+    packet: IcaPacket,
+    clock: &dyn TimeProvider,
+) -> Result<Response, ContractError> {
+    check_sender(deps.storage, config, &info.sender)?;
+    if let Some(sequence) = packet.sequence {
+        check_packet_sequence(
+            deps.storage,
+            &info.sender,
+            &packet.channel,
+            &FlowType::Out,
+            sequence,
+        )?;
+    }
+
+    let mut amounts: BTreeMap<String, Uint128> = BTreeMap::new();
+    for coin in packet.msgs_value {
+        let total = amounts.entry(normalize_denom(&coin.denom)).or_default();
+        *total = total
+            .checked_add(coin.amount)
+            .map_err(|_| ContractError::AmountOverflow {
+                amount: (Uint256::from(*total) + Uint256::from(coin.amount)).to_string(),
+                max: "uint128".to_string(),
+            })?;
+    }
+
+    let mut response = Response::new()
+        .add_attribute("method", "process_ica_packet")
+        .add_attribute("channel_id", &packet.channel);
+    for (denom, amount) in amounts {
+        let info = MessageInfo {
+            sender: info.sender.clone(),
+            funds: info
+                .funds
                 .iter()
-                .map(|q| RateLimit {
-                    quota: q.into(),
-                    flow: Flow::new(0_u128, 0_u128, now, q.duration),
-                })
+                .filter(|coin| normalize_denom(&coin.denom) == denom)
+                .cloned()
                 .collect(),
-        )?
+        };
+        let mut coin_packet = Packet::new(&packet.channel, &denom, amount);
+        coin_packet.sender = packet.sender.clone();
+        coin_packet.sequence = packet.sequence;
+        let coin_response = transfer_packet(
+            deps.branch(),
+            info,
+            config,
+            coin_packet,
+            FlowType::Out,
+            true,
+            clock,
+        )?;
+        if let Some(data) = &coin_response.data {
+            let data: TransferData = from_json(data)?;
+            if data.accepted != amount {
+                return Err(ContractError::IcaPacketNotAccepted {
+                    denom,
+                    amount,
+                    accepted: data.accepted,
+                });
+            }
+        }
+        response = response
+            .add_attributes(coin_response.attributes)
+            .add_submessages(coin_response.messages)
+            .add_events(coin_response.events);
+    }
+    Ok(response)
+}
+
+// cw-ics20 contracts call the limiter directly from their transfer hook, so
+// the transfer goes through the same checks as a packet of the bridge
+pub fn process_ics20_transfer(
+    deps: DepsMut,
+    info: MessageInfo,
+    config: &Config,
+    transfer: Ics20Transfer,
+    clock: &dyn TimeProvider,
+) -> Result<Response, ContractError> {
+    let direction = match transfer.is_receive {
+        true => FlowType::In,
+        false => FlowType::Out,
+    };
+    let response = process_packet(deps, info, config, transfer.packet(), direction, clock)?;
+    Ok(response.add_attribute("remote_denom", transfer.remote_denom))
+}
+
+// Sends rejected on paths answering rejections with data don't fail. The
+// escrow goes back to the bridge and the response data tells it nothing was
+// accepted. As the transaction succeeds, the rejection can be recorded and the
+// on_reject_contract notified. The notification can't revert the rejection, a
+// failing one is answered by the reply entry point
+fn reject_send(
+    storage: &mut dyn Storage,
+    config: &Config,
+    path: &Path,
+    packet: &Packet,
+    err: ContractError,
+    clock: &dyn TimeProvider,
+) -> Result<Response, ContractError> {
+    let path_config = PATH_CONFIGS
+        .may_load(storage, path.into())?
+        .unwrap_or_default();
+    let (true, Some(exceeded)) = (path_config.reject_with_data, err.rate_limit_data()) else {
+        return Err(err);
+    };
+    if rejections_exhausted(storage, config, path, clock)? {
+        return drop_send(packet);
+    }
+    if config.max_rejections_per_block.is_some() {
+        count_block_rejection(storage, path, clock)?;
+    }
+
+    let rejection = Rejection {
+        at: clock.now(),
+        amount: packet.amount,
+        direction: FlowType::Out,
+        quota: exceeded.quota_name.clone(),
+        sender: packet.sender.clone(),
+    };
+    REJECTIONS.update(storage, path.into(), |rejections| -> StdResult<_> {
+        let mut rejections = rejections.unwrap_or_default();
+        rejections.push(rejection);
+        if rejections.len() > REJECTIONS_LENGTH {
+            rejections.drain(..rejections.len() - REJECTIONS_LENGTH);
+        }
+        Ok(rejections)
+    })?;
+    let rollup = count_rejection(storage, config, path, clock)?;
+
+    let mut response = record_streak(storage, path, clock)?
+        .add_events(rollup)
+        .add_attribute("method", "reject_send")
+        .set_data(to_json_binary(&TransferData::rejected(packet.amount))?);
+    if let Some(hook) = path_config.on_reject_contract {
+        let notify = WasmMsg::Execute {
+            contract_addr: hook.to_string(),
+            msg: to_json_binary(&RejectHookMsg::RateLimitRejected {
+                packet: packet.clone(),
+                exceeded,
+            })?,
+            funds: vec![],
+        };
+        response = response
+            .add_attribute("on_reject_contract", hook.as_str())
+            .add_submessage(SubMsg::reply_on_error(notify, REJECT_HOOK_REPLY_ID));
+    }
+    Ok(response)
+}
+
+// Counts the rejection in the metrics and in the stats of the current periods
+// and day of the path, returning the daily_rollup event if it started a new
+// day. Flows aren't rolled over by rejections, so the periods that are over
+// don't count the rejections made after them
+fn count_rejection(
+    storage: &mut dyn Storage,
+    config: &Config,
+    path: &Path,
+    clock: &dyn TimeProvider,
+) -> StdResult<Option<Event>> {
+    update_metrics(storage, |m| m.rejected += 1)?;
+    if let Some(mut trackers) = RATE_LIMIT_TRACKERS.may_load(storage, path.into())? {
+        trackers
+            .iter_mut()
+            .filter(|limit| !limit.flow.is_expired(clock))
+            .for_each(|limit| limit.flow.stats.rejections += 1);
+        save_trackers(storage, path, &trackers)?;
+    }
+    if !tracks_denom(storage, config, path)? {
+        return Ok(None);
+    }
+    record_daily_stats(storage, path, clock, |stats| stats.rejections += 1)
+}
+
+// The id of the on_reject_contract notifications, for the reply entry point
+pub const REJECT_HOOK_REPLY_ID: u64 = 1;
+
+// The id of the escalation alerts, for the reply entry point
+pub const ALERT_REPLY_ID: u64 = 2;
+
+// The id of the escrow queries sent on period rollovers, for the reply entry
+// point
+pub const ESCROW_QUERY_REPLY_ID: u64 = 3;
+
+// Notifications are fire-and-forget: the reply entry point gets the failing
+// ones, and reports the error instead of reverting the packet that caused them
+pub fn notification_failed(msg: Reply) -> Result<Response, ContractError> {
+    let notification = match msg.id {
+        REJECT_HOOK_REPLY_ID => "on_reject_contract",
+        ALERT_REPLY_ID => "alert_contract",
+        ESCROW_QUERY_REPLY_ID => "escrow_querier",
+        id => return Err(ContractError::UnknownReplyId { id }),
+    };
+    let error = match msg.result {
+        SubMsgResult::Err(error) => error,
+        SubMsgResult::Ok(_) => String::new(),
+    };
+
+    Ok(Response::new()
+        .add_attribute("method", "notification_failed")
+        .add_attribute("notification", notification)
+        .add_attribute("error", error))
+}
+
+// Whether the path already had all the rejections the config accepts in the
+// current block
+fn rejections_exhausted(
+    storage: &dyn Storage,
+    config: &Config,
+    path: &Path,
+    clock: &dyn TimeProvider,
+) -> StdResult<bool> {
+    let Some(max) = config.max_rejections_per_block else {
+        return Ok(false);
+    };
+    Ok(BLOCK_REJECTIONS
+        .may_load(storage, path.into())?
+        .is_some_and(|rejections| rejections.height == clock.height() && rejections.count >= max))
+}
+
+fn count_block_rejection(
+    storage: &mut dyn Storage,
+    path: &Path,
+    clock: &dyn TimeProvider,
+) -> StdResult<()> {
+    let height = clock.height();
+    let count = match BLOCK_REJECTIONS.may_load(storage, path.into())? {
+        Some(rejections) if rejections.height == height => rejections.count + 1,
+        _ => 1,
+    };
+    BLOCK_REJECTIONS.save(storage, path.into(), &BlockRejections { height, count })
+}
+
+// Answers a send of a path out of rejections for the block like a rejection,
+// without the record or the notification
+fn drop_send(packet: &Packet) -> Result<Response, ContractError> {
+    Ok(Response::new()
+        .add_attribute("method", "drop_send")
+        .add_attribute("rejection_dropped", "max_rejections_per_block")
+        .set_data(to_json_binary(&TransferData::rejected(packet.amount))?))
+}
+
+// Adds the rejection to the streak of the path, starting a new streak once the
+// window of the policy has passed. The response carries the escalation, if the
+// streak reached the threshold of the policy
+fn record_streak(
+    storage: &mut dyn Storage,
+    path: &Path,
+    clock: &dyn TimeProvider,
+) -> Result<Response, ContractError> {
+    let Some(policy) = PATH_CONFIGS
+        .may_load(storage, path.into())?
+        .and_then(|config| config.escalation)
+    else {
+        return Ok(Response::new());
+    };
+
+    let now = clock.now();
+    let mut streak = match STREAKS.may_load(storage, path.into())? {
+        Some(streak) if now <= streak.started_at.plus_seconds(policy.window) => streak,
+        _ => Streak {
+            count: 0,
+            started_at: now,
+            last_at: now,
+            escalated: false,
+        },
+    };
+    streak.count += 1;
+    streak.last_at = now;
+    let escalate = !streak.escalated && streak.count >= policy.threshold;
+    streak.escalated |= escalate;
+    STREAKS.save(storage, path.into(), &streak)?;
+    if !escalate {
+        return Ok(Response::new());
+    }
+
+    if policy.freeze {
+        PAUSED_PATHS.save(storage, path.into(), &Empty {})?;
+    }
+    let event = Event::new("escalation")
+        .add_attribute("contract", path.contract.as_str())
+        .add_attribute("channel_id", &path.channel)
+        .add_attribute("denom", &path.denom)
+        .add_attribute("count", streak.count.to_string())
+        .add_attribute("started_at", streak.started_at.to_string())
+        .add_attribute("frozen", policy.freeze.to_string());
+    let mut response = Response::new().add_event(event);
+    if let Some(alert_contract) = policy.alert_contract {
+        let alert = WasmMsg::Execute {
+            contract_addr: alert_contract.to_string(),
+            msg: to_json_binary(&AlertMsg::Escalation {
+                contract: path.contract.clone(),
+                channel_id: path.channel.clone(),
+                denom: path.denom.clone(),
+                streak,
+                frozen: policy.freeze,
+            })?,
+            funds: vec![],
+        };
+        response = response.add_submessage(SubMsg::reply_on_error(alert, ALERT_REPLY_ID));
+    }
+    Ok(response)
+}
+
+// Starts a new day for the path if its stats belong to a previous one, and
+// returns the daily_rollup event summarizing the finished day
+fn roll_daily_stats(
+    storage: &dyn Storage,
+    path: &Path,
+    clock: &dyn TimeProvider,
+) -> StdResult<(DailyStats, Option<Event>)> {
+    let today = clock.now().seconds() / SECONDS_PER_DAY;
+    let Some(stats) = DAILY_STATS.may_load(storage, path.into())? else {
+        let stats = DailyStats {
+            day: today,
+            ..DailyStats::default()
+        };
+        return Ok((stats, None));
+    };
+    if stats.day >= today {
+        return Ok((stats, None));
+    }
+
+    let rollup = Event::new("daily_rollup")
+        .add_attribute("contract", path.contract.as_str())
+        .add_attribute("channel_id", path.channel.to_string())
+        .add_attribute("denom", path.denom.to_string())
+        .add_attribute("day", stats.day.to_string())
+        .add_attribute("inflow", stats.inflow.to_string())
+        .add_attribute("outflow", stats.outflow.to_string())
+        .add_attribute("packets_in", stats.packets_in.to_string())
+        .add_attribute("packets_out", stats.packets_out.to_string())
+        .add_attribute("packets_undone", stats.packets_undone.to_string())
+        .add_attribute("rejections", stats.rejections.to_string());
+    let stats = DailyStats {
+        day: today,
+        last_packet: stats.last_packet,
+        ..DailyStats::default()
+    };
+    Ok((stats, Some(rollup)))
+}
+
+fn record_daily_stats(
+    storage: &mut dyn Storage,
+    path: &Path,
+    clock: &dyn TimeProvider,
+    f: impl FnOnce(&mut DailyStats),
+) -> StdResult<Option<Event>> {
+    let (mut stats, rollup) = roll_daily_stats(storage, path, clock)?;
+    f(&mut stats);
+    DAILY_STATS.save(storage, path.into(), &stats)?;
+    Ok(rollup)
+}
+
+// Accounts the packet on the quotas of its classes, if the path has any
+fn check_class_quotas(
+    storage: &mut dyn Storage,
+    path: &Path,
+    packet: &Packet,
+    direction: &FlowType,
+    clock: &dyn TimeProvider,
+) -> Result<(), ContractError> {
+    for (class, results) in class_transfer(storage, path, packet, direction, clock)? {
+        CLASS_TRACKERS.save(storage, (path.into(), class.to_string()), &results)?;
+    }
+    Ok(())
+}
+
+// Accounts the packet on the quotas of its classes, and returns them with
+// their class. Classes the path has no quotas for are left out
+pub(crate) fn class_transfer(
+    storage: &dyn Storage,
+    path: &Path,
+    packet: &Packet,
+    direction: &FlowType,
+    clock: &dyn TimeProvider,
+) -> Result<Vec<(&'static str, Vec<RateLimit>)>, ContractError> {
+    let mut classes = vec![];
+    for class in packet_classes(storage, packet)? {
+        let key = (path.into(), class.to_string());
+        let Some(mut trackers) = CLASS_TRACKERS.may_load(storage, key)? else {
+            continue;
+        };
+
+        let results: Vec<RateLimit> = trackers
+            .iter_mut()
+            .map(|limit| {
+                limit.allow_transfer(path, direction, packet.amount, packet.channel_value, clock)
+            })
+            .collect::<Result<_, ContractError>>()?;
+        classes.push((class, results));
+    }
+    Ok(classes)
+}
+
+// The key of the quotas of the tag of the packet, if it has one and the path
+// has quotas for it. They replace the quotas of the path for the packet
+fn tag_quotas_key(
+    storage: &dyn Storage,
+    path: &Path,
+    packet: &Packet,
+) -> StdResult<Option<(PathKey, String)>> {
+    let Some(tag) = &packet.tag else {
+        return Ok(None);
+    };
+    let key = (path.into(), tag.clone());
+    Ok(TAG_TRACKERS.has(storage, key.clone()).then_some(key))
+}
+
+// Checks that the send fits under the max_in_flight of the path. Returns
+// whether the path tracks its in flight value, in which case it must be added
+// once the send is recorded as pending
+pub(crate) fn check_in_flight(
+    storage: &dyn Storage,
+    path: &Path,
+    amount: Uint128,
+) -> Result<bool, ContractError> {
+    let config = PATH_CONFIGS
+        .may_load(storage, path.into())?
+        .unwrap_or_default();
+    let Some(max) = config.max_in_flight else {
+        return Ok(false);
+    };
+
+    let in_flight = IN_FLIGHT
+        .may_load(storage, path.into())?
+        .unwrap_or_default();
+    if in_flight + Uint256::from(amount) > max {
+        return Err(ContractError::InFlightExceeded {
+            channel_id: path.channel.clone(),
+            denom: path.denom.clone(),
+            amount,
+            in_flight,
+            max,
+        });
+    }
+    Ok(true)
+}
+
+// Stores the periods the send was just accounted in and adds it to the in
+// flight value of the path. The send keeps the denom of the packet, which the
+// path may not be keyed by. Pre-approved sends weren't accounted in any period,
+// and only degraded ones were accounted on the degraded quotas
+#[allow(clippy::too_many_arguments)]
+fn record_pending_send(
+    storage: &mut dyn Storage,
+    config: &Config,
+    path: &Path,
+    packet: &Packet,
+    sequence: u64,
+    in_flight: bool,
+    escrowed: bool,
+    pre_approved: bool,
+    degraded: bool,
+    ica: bool,
+) -> StdResult<()> {
+    let amount = packet.amount;
+    let tag_key = tag_quotas_key(storage, path, packet)?;
+    let trackers = match (&tag_key, pre_approved) {
+        (_, true) => vec![],
+        (Some(key), false) => TAG_TRACKERS.load(storage, key.clone())?,
+        (None, false) => RATE_LIMIT_TRACKERS
+            .may_load(storage, path.into())?
+            .unwrap_or_default(),
+    };
+    let mut class_periods = vec![];
+    if !pre_approved {
+        for class in packet_classes(storage, packet)? {
+            let key = (path.into(), class.to_string());
+            if let Some(trackers) = CLASS_TRACKERS.may_load(storage, key)? {
+                class_periods.extend(
+                    pending_periods(&trackers)
+                        .into_iter()
+                        .map(|period| (class.to_string(), period)),
+                );
+            }
+        }
+    }
+    // The flows of the channel are only accounted along the quotas of the path
+    let mut channel_periods = vec![];
+    let hierarchy = !matches!(config.quota_hierarchy, HierarchyStrictness::Off);
+    if hierarchy && !trackers.is_empty() {
+        let key = (path.contract.clone(), path.channel.clone());
+        if let Some(trackers) = CHANNEL_TRACKERS.may_load(storage, key)? {
+            channel_periods = pending_periods(&trackers);
+        }
+    }
+    // So are the flows of the counterparty chain
+    let mut chain_periods = vec![];
+    let counterparty = PATH_CONFIGS
+        .may_load(storage, path.into())?
+        .and_then(|config| config.counterparty);
+    if let Some(counterparty) = counterparty.filter(|_| !trackers.is_empty()) {
+        let key = (
+            path.contract.clone(),
+            counterparty.chain_id,
+            path.denom.clone(),
+        );
+        if let Some(trackers) = CHAIN_TRACKERS.may_load(storage, key)? {
+            chain_periods = pending_periods(&trackers);
+        }
+    }
+    let mut degraded_periods = vec![];
+    if degraded {
+        if let Some(trackers) = DEGRADED_TRACKERS.may_load(storage, path.into())? {
+            degraded_periods = pending_periods(&trackers);
+        }
+    }
+    if in_flight {
+        IN_FLIGHT.update(storage, path.into(), |value| -> StdResult<_> {
+            Ok(value.unwrap_or_default() + Uint256::from(amount))
+        })?;
+    }
+
+    let pending = PendingSend {
+        denom: packet.denom.clone(),
+        amount,
+        periods: pending_periods(&trackers),
+        in_flight,
+        escrowed,
+        tag: tag_key.map(|(_, tag)| tag),
+        pre_approved,
+        class_periods,
+        channel_periods,
+        chain_periods,
+        degraded_periods,
+    };
+    if in_flight {
+        PENDING_IN_FLIGHT.update(storage, path.into(), |value| -> StdResult<_> {
+            Ok(value.unwrap_or_default() + Uint256::from(amount))
+        })?;
+    }
+    // Sequences are only unique within the channel the packet was sent on,
+    // whatever the path is keyed by
+    let key = (path.contract.clone(), packet.channel.clone(), sequence);
+    if !ica {
+        return PENDING_SENDS.save(storage, key, &pending);
+    }
+    PENDING_ICA_SENDS.update(storage, key, |coins| -> StdResult<_> {
+        let mut coins = coins.unwrap_or_default();
+        coins.push(pending);
+        Ok(coins)
+    })?;
+    Ok(())
+}
+
+// The periods a send was just accounted in on the given trackers
+fn pending_periods(trackers: &[RateLimit]) -> Vec<PendingPeriod> {
+    trackers
+        .iter()
+        .map(|limit| PendingPeriod {
+            quota: limit.quota.name.clone(),
+            period_end: limit.flow.period_end,
+        })
+        .collect()
+}
+
+// Undoes a send on the trackers still in the period it was accounted in, and
+// returns the names of the others
+fn undo_in_period(
+    trackers: &mut [RateLimit],
+    periods: &[PendingPeriod],
+    funds: Uint128,
+    clock: &dyn TimeProvider,
+) -> Vec<String> {
+    let mut skipped = vec![];
+    for limit in trackers.iter_mut() {
+        let same_period = !limit.flow.is_expired(clock)
+            && periods.iter().any(|period| {
+                period.quota == limit.quota.name && period.period_end == limit.flow.period_end
+            });
+        match same_period {
+            true => limit.flow.undo_flow(FlowType::Out, funds),
+            false => skipped.push(limit.quota.name.clone()),
+        }
+    }
+    skipped
+}
+
+// Takes the pending send of a sequence out of the store, unless it was sent in
+// another denom. The coins of an ICA packet are taken one by one
+fn take_pending_send(
+    storage: &mut dyn Storage,
+    key: (Addr, String, u64),
+    denom: &str,
+) -> StdResult<Option<PendingSend>> {
+    if let Some(pending) = PENDING_SENDS.may_load(storage, key.clone())? {
+        if pending.denom == denom {
+            PENDING_SENDS.remove(storage, key.clone());
+            untrack_pending_in_flight(storage, &key, &pending)?;
+        }
+        return Ok(Some(pending));
+    }
+
+    let mut coins = PENDING_ICA_SENDS
+        .may_load(storage, key.clone())?
+        .unwrap_or_default();
+    let Some(index) = coins.iter().position(|pending| pending.denom == denom) else {
+        return Ok(None);
+    };
+    let pending = coins.remove(index);
+    match coins.is_empty() {
+        true => PENDING_ICA_SENDS.remove(storage, key.clone()),
+        false => PENDING_ICA_SENDS.save(storage, key.clone(), &coins)?,
+    }
+    untrack_pending_in_flight(storage, &key, &pending)?;
+    Ok(Some(pending))
+}
+
+// Takes a send out of the value of the pending sends of its path
+fn untrack_pending_in_flight(
+    storage: &mut dyn Storage,
+    (contract, channel, _): &(Addr, String, u64),
+    pending: &PendingSend,
+) -> StdResult<()> {
+    if !pending.in_flight {
+        return Ok(());
+    }
+    let path = load_key_spec(storage)?.path(contract, channel, &pending.denom);
+    let value = PENDING_IN_FLIGHT
+        .may_load(storage, (&path).into())?
+        .unwrap_or_default()
+        .saturating_sub(Uint256::from(pending.amount));
+    match value.is_zero() {
+        true => PENDING_IN_FLIGHT.remove(storage, (&path).into()),
+        false => PENDING_IN_FLIGHT.save(storage, (&path).into(), &value)?,
+    }
+    Ok(())
+}
+
+// In escrow mode the funds of a send must be attached to the message. They are
+// held by the contract until the send is undone or acknowledged
+fn escrow_funds(
+    storage: &mut dyn Storage,
+    info: &MessageInfo,
+    path: &Path,
+    packet: &Packet,
+) -> Result<bool, ContractError> {
+    let config = PATH_CONFIGS
+        .may_load(storage, path.into())?
+        .unwrap_or_default();
+    if !config.escrow {
+        nonpayable(info)?;
+        return Ok(false);
+    }
+    // The escrow is released by the ack or the undo of the send, which find it
+    // by its sequence
+    if packet.sequence.is_none() {
+        return Err(ContractError::EscrowWithoutSequence {});
+    }
+
+    let amount = packet.amount;
+    let received = must_pay(info, &packet.denom)?;
+    if received != amount {
+        return Err(ContractError::EscrowMismatch {
+            expected: amount,
+            received,
+        });
+    }
+
+    let key = escrow_key(path, &packet.denom);
+    ESCROW_BALANCES.update(storage, key, |balance| -> StdResult<_> {
+        Ok(balance.unwrap_or_default().checked_add(amount)?)
+    })?;
+    Ok(true)
+}
+
+// Escrowed funds are held per denom, whatever the path is keyed by
+fn escrow_key(path: &Path, denom: &str) -> PathKey {
+    (
+        path.contract.clone(),
+        path.channel.clone(),
+        denom.to_string(),
+    )
+}
+
+// The funds held in escrow for a path, by denom
+fn path_escrow(storage: &dyn Storage, path: &Path) -> StdResult<Vec<(String, Uint128)>> {
+    let key_spec = load_key_spec(storage)?;
+    ESCROW_BALANCES
+        .prefix((path.contract.clone(), path.channel.clone()))
+        .range(storage, None, None, Order::Ascending)
+        .filter(|item| match item {
+            Ok((denom, _)) => key_spec.path(&path.contract, &path.channel, denom) == *path,
+            Err(_) => true,
+        })
+        .collect()
+}
+
+// Releases the escrowed funds of a send back to the bridge, once it's settled
+// or for the part of it that was truncated. We never refund more than what is
+// held for the path
+fn refund_escrow(
+    storage: &mut dyn Storage,
+    path: &Path,
+    denom: &str,
+    amount: Uint128,
+) -> StdResult<Option<BankMsg>> {
+    let key = escrow_key(path, denom);
+    let balance = ESCROW_BALANCES
+        .may_load(storage, key.clone())?
+        .unwrap_or_default();
+    let refund = amount.min(balance);
+    if refund.is_zero() {
+        return Ok(None);
+    }
+
+    ESCROW_BALANCES.save(storage, key, &(balance - refund))?;
+    Ok(Some(BankMsg::Send {
+        to_address: path.contract.to_string(),
+        amount: coins(refund.u128(), denom),
+    }))
+}
+
+// The direction of a packet given its endpoints: sends leave from the local
+// channel, receives arrive on it
+fn endpoints_direction(endpoints: &PacketEndpoints, local: &LocalChannel) -> Option<FlowType> {
+    let is_local = |port: &str, channel: &str| port == local.port_id && channel == local.channel_id;
+    if is_local(&endpoints.source_port, &endpoints.source_channel) {
+        Some(FlowType::Out)
+    } else if is_local(&endpoints.destination_port, &endpoints.destination_channel) {
+        Some(FlowType::In)
+    } else {
+        None
+    }
+}
+
+// The direction of a packet, from the local channel of its path. Both the
+// endpoints of the packet and the local channel are required
+pub fn infer_direction(
+    deps: Deps,
+    contract: &Addr,
+    packet: &Packet,
+) -> Result<FlowType, ContractError> {
+    let denom = normalize_denom(&packet.denom);
+    let path = load_key_spec(deps.storage)?.path(contract, &packet.channel, &denom);
+    let local = PATH_CONFIGS
+        .may_load(deps.storage, (&path).into())?
+        .and_then(|config| config.local_channel);
+    let (Some(endpoints), Some(local)) = (&packet.endpoints, local) else {
+        return Err(ContractError::CannotInferDirection {
+            channel_id: path.channel,
+            denom: path.denom,
+        });
+    };
+    endpoints_direction(endpoints, &local).ok_or(ContractError::UnknownPacketEndpoints {
+        channel_id: path.channel,
+        denom: path.denom,
+        port_id: local.port_id,
+        local_channel: local.channel_id,
+    })
+}
+
+// Checks that the endpoints of a packet agree with the direction it was sent
+// in. Only packets carrying their endpoints are checked, and paths without a
+// local channel can't tell, so their packets are trusted
+fn check_direction(
+    storage: &dyn Storage,
+    path: &Path,
+    endpoints: &PacketEndpoints,
+    direction: &FlowType,
+) -> Result<(), ContractError> {
+    let Some(local) = PATH_CONFIGS
+        .may_load(storage, path.into())?
+        .and_then(|config| config.local_channel)
+    else {
+        return Ok(());
+    };
+    match endpoints_direction(endpoints, &local) {
+        Some(inferred) if &inferred == direction => Ok(()),
+        Some(inferred) => Err(ContractError::DirectionMismatch {
+            channel_id: path.channel.clone(),
+            denom: path.denom.clone(),
+            declared: direction.as_str().to_string(),
+            inferred: inferred.as_str().to_string(),
+        }),
+        None => Err(ContractError::UnknownPacketEndpoints {
+            channel_id: path.channel.clone(),
+            denom: path.denom.clone(),
+            port_id: local.port_id,
+            local_channel: local.channel_id,
+        }),
+    }
+}
+
+// If a policy contract is configured, it gets to veto the packet before any
+// accounting happens. Queries can't modify state, so this is safe to do before
+// the flows are updated
+pub(crate) fn check_policy(
+    deps: Deps,
+    config: &Config,
+    path: &Path,
+    packet: &Packet,
+    direction: &FlowType,
+) -> Result<(), ContractError> {
+    let Some(policy_contract) = config.policy_contract.clone() else {
+        return Ok(());
+    };
+
+    let current_flows = RATE_LIMIT_TRACKERS
+        .may_load(deps.storage, path.into())?
+        .unwrap_or_default();
+    let response: PolicyResponse = deps.querier.query_wasm_smart(
+        policy_contract,
+        &PolicyQueryMsg::CheckPolicy {
+            packet: packet.clone(),
+            direction: direction.clone(),
+            current_flows,
+        },
+    )?;
+
+    match response.allowed {
+        true => Ok(()),
+        false => Err(ContractError::PolicyDenied {
+            reason: response.reason.unwrap_or_default(),
+        }),
+    }
+}
+
+// Packet sequences are strictly increasing per channel and direction, so any
+// sequence not above the last processed one is a duplicate
+fn check_packet_sequence(
+    storage: &mut dyn Storage,
+    contract: &Addr,
+    channel: &str,
+    direction: &FlowType,
+    sequence: u64,
+) -> Result<(), ContractError> {
+    let key = (
+        contract.to_owned(),
+        channel.to_string(),
+        direction.as_str().to_string(),
+    );
+    if let Some(last_sequence) = PACKET_SEQUENCES.may_load(storage, key.clone())? {
+        if sequence <= last_sequence {
+            return Err(ContractError::DuplicatePacket {
+                channel: channel.to_string(),
+                sequence,
+                last_sequence,
+            });
+        }
+    }
+    PACKET_SEQUENCES.save(storage, key, &sequence)?;
+    Ok(())
+}
+
+// Appends the flows of the expired trackers to the history of the path. Has to
+// be called before the flows are rolled over
+fn archive_expired_flows(
+    storage: &mut dyn Storage,
+    path: &Path,
+    trackers: &[RateLimit],
+    clock: &dyn TimeProvider,
+) -> StdResult<usize> {
+    let expired: Vec<&RateLimit> = trackers
+        .iter()
+        .filter(|limit| limit.flow.is_expired(clock))
+        .collect();
+    archive_flows(storage, path, &expired, clock)?;
+    Ok(expired.len())
+}
+
+// Appends the current periods of the trackers to the history of the path. A
+// period cut short by a reset is archived as ending at the reset
+fn archive_flows(
+    storage: &mut dyn Storage,
+    path: &Path,
+    trackers: &[&RateLimit],
+    clock: &dyn TimeProvider,
+) -> StdResult<()> {
+    if trackers.is_empty() {
+        return Ok(());
+    }
+
+    let mut history = HISTORY.may_load(storage, path.into())?.unwrap_or_default();
+    let next_index = history
+        .last()
+        .map(|entry| entry.index + 1)
+        .unwrap_or_default();
+    history.extend(
+        (next_index..)
+            .zip(trackers)
+            .map(|(index, limit)| HistoryEntry {
+                index,
+                quota: limit.quota.name.clone(),
+                inflow: limit.flow.inflow,
+                outflow: limit.flow.outflow,
+                period_end: limit.flow.period_end.min(clock.now()),
+                stats: limit.flow.stats.clone(),
+            }),
+    );
+    if history.len() > HISTORY_LENGTH {
+        history.drain(..history.len() - HISTORY_LENGTH);
+    }
+    HISTORY.save(storage, path.into(), &history)
+}
+
+// Finalizes the expired periods of idle paths so their history is recorded and
+// their period_end is up to date. Without explicit paths, it continues from the
+// path the previous tick stopped at and wraps around once all were visited
+pub fn try_tick(
+    deps: DepsMut,
+    paths: Option<Vec<PathId>>,
+    limit: Option<u32>,
+    clock: &dyn TimeProvider,
+) -> Result<Response, ContractError> {
+    let limit = clamp_limit(limit, TICK_DEFAULT_LIMIT, TICK_MAX_LIMIT);
+
+    let key_spec = load_key_spec(deps.storage)?;
+    let paths: Vec<Path> = match paths {
+        Some(paths) => paths
+            .into_iter()
+            .take(limit)
+            .map(|p| key_spec.path(&p.contract, &p.channel_id, &p.denom))
+            .collect(),
+        None => {
+            let start = start_bound(TICK_CURSOR.may_load(deps.storage)?);
+            let keys = RATE_LIMIT_TRACKERS
+                .keys(deps.storage, start, None, Order::Ascending)
+                .take(limit)
+                .collect::<StdResult<Vec<_>>>()?;
+            match keys.last() {
+                Some(last) if keys.len() == limit => TICK_CURSOR.save(deps.storage, last)?,
+                _ => TICK_CURSOR.remove(deps.storage),
+            }
+            keys.into_iter()
+                .map(|(contract, channel, denom)| Path::new(&contract, channel, denom))
+                .collect()
+        }
+    };
+
+    let config = load_config(deps.storage)?;
+    let mut events = vec![];
+    let mut expired = 0;
+    for path in &paths {
+        let (mut trackers, quarantine) = load_trackers(deps.storage, path)?;
+        events.extend(quarantine);
+        let clamped = clamp_durations(&mut trackers, path, &config, clock);
+
+        // Only paths that saw packets have stats to roll up
+        if DAILY_STATS.has(deps.storage, path.into()) {
+            let (stats, rollup) = roll_daily_stats(deps.storage, path, clock)?;
+            if let Some(rollup) = rollup {
+                DAILY_STATS.save(deps.storage, path.into(), &stats)?;
+                events.push(rollup);
+            }
+        }
+
+        let archived = archive_expired_flows(deps.storage, path, &trackers, clock)?;
+        if archived == 0 {
+            if let Some(clamped) = clamped {
+                save_trackers(deps.storage, path, &trackers)?;
+                events.push(clamped);
+            }
+            continue;
+        }
+        events.extend(clamped);
+        trackers.iter_mut().for_each(|limit| {
+            limit.flow.roll_over(clock, &limit.quota);
+        });
+        save_trackers(deps.storage, path, &trackers)?;
+        expired += archived;
     }
-    Ok(())
+
+    Ok(Response::new()
+        .add_events(events)
+        .add_attribute("method", "try_tick")
+        .add_attribute("paths", paths.len().to_string())
+        .add_attribute("expired", expired.to_string()))
 }
 
-pub fn try_add_path(
+// Loads the trackers of a path. An entry that can't be deserialized would block
+// the path forever, so its raw bytes are moved to QUARANTINE and the path is
+// treated as unconfigured. The returned event reports the quarantine.
+fn load_trackers(
+    storage: &mut dyn Storage,
+    path: &Path,
+) -> StdResult<(Vec<RateLimit>, Option<Event>)> {
+    let (trackers, corrupted) = read_trackers(storage, path)?;
+    let quarantine = quarantine_trackers(storage, path, corrupted)?;
+    Ok((trackers, quarantine))
+}
+
+// The raw bytes of trackers that can't be deserialized, and the error
+type CorruptedTrackers = (Binary, String);
+
+// Reads the trackers of a path without writing anything. An entry that can't
+// be deserialized is returned for quarantine_trackers, and the path has no
+// trackers
+fn read_trackers(
+    storage: &dyn Storage,
+    path: &Path,
+) -> StdResult<(Vec<RateLimit>, Option<CorruptedTrackers>)> {
+    let key = RATE_LIMIT_TRACKERS.key(path.into());
+    let Some(raw) = storage.get(&key) else {
+        return Ok((vec![], None));
+    };
+
+    let raw = Binary::from(raw);
+    match from_json::<Vec<RateLimit>>(&raw) {
+        Ok(trackers) => Ok((trackers, None)),
+        Err(err) => Ok((vec![], Some((raw, err.to_string())))),
+    }
+}
+
+fn quarantine_trackers(
+    storage: &mut dyn Storage,
+    path: &Path,
+    corrupted: Option<CorruptedTrackers>,
+) -> StdResult<Option<Event>> {
+    let Some((raw, err)) = corrupted else {
+        return Ok(None);
+    };
+
+    QUARANTINE.save(storage, path.into(), &raw)?;
+    remove_trackers(storage, path)?;
+    update_metrics(storage, |m| {
+        m.paths = m.paths.saturating_sub(1);
+        m.quarantined_paths += 1;
+    })?;
+    Ok(Some(
+        Event::new("quarantine")
+            .add_attribute("contract", path.contract.as_str())
+            .add_attribute("channel_id", path.channel.to_string())
+            .add_attribute("denom", path.denom.to_string())
+            .add_attribute("error", err),
+    ))
+}
+
+#[cfg(feature = "mock-time")]
+pub fn try_set_mock_time(
     deps: DepsMut,
-    contract: Addr,
-    channel_id: String,
-    denom: String,
-    quotas: Vec<QuotaMsg>,
-    now: Timestamp,
+    sender: Addr,
+    time: Option<cosmwasm_std::Timestamp>,
 ) -> Result<Response, ContractError> {
-    // codenit: should we make a function for checking this authorization?
-
-    add_new_paths(
-        deps,
-        vec![PathMsg::new(&contract, &channel_id, &denom, quotas)],
-        now,
-    )?;
+    cw_ownable::assert_owner(deps.storage, &sender)?;
+    match time {
+        Some(time) => crate::time::MOCK_TIME.save(deps.storage, &time)?,
+        None => crate::time::MOCK_TIME.remove(deps.storage),
+    }
 
     Ok(Response::new()
-        .add_attribute("method", "try_add_channel")
-        .add_attribute("contract", contract.as_str())
-        .add_attribute("channel_id", channel_id)
-        .add_attribute("denom", denom))
+        .add_attribute("method", "try_set_mock_time")
+        .add_attribute("time", time.map(|t| t.to_string()).unwrap_or_default()))
 }
 
-pub fn try_remove_path(
+pub fn try_purge_quarantined(
     deps: DepsMut,
+    sender: Addr,
     contract: Addr,
     channel_id: String,
     denom: String,
 ) -> Result<Response, ContractError> {
-    let path = Path::new(&contract, &channel_id, &denom);
-    RATE_LIMIT_TRACKERS.remove(deps.storage, path.into());
+    cw_ownable::assert_owner(deps.storage, &sender)?;
+
+    let path = load_key_spec(deps.storage)?.path(&contract, &channel_id, &denom);
+    if QUARANTINE.has(deps.storage, (&path).into()) {
+        update_metrics(deps.storage, |m| {
+            m.quarantined_paths = m.quarantined_paths.saturating_sub(1)
+        })?;
+    }
+    QUARANTINE.remove(deps.storage, path.into());
+
     Ok(Response::new()
-        .add_attribute("method", "try_remove_channel")
+        .add_attribute("method", "try_purge_quarantined")
         .add_attribute("contract", contract.as_str())
-        .add_attribute("denom", denom)
-        .add_attribute("channel_id", channel_id))
+        .add_attribute("channel_id", channel_id)
+        .add_attribute("denom", denom))
 }
 
-// Reset specified quote_id for the given channel_id
-pub fn try_reset_path_quota(
+// Drops the daily stats of the denoms of a channel without trackers. Denoms
+// with trackers are kept, they are removed with RemovePath
+pub fn try_prune_channel_denoms(
     deps: DepsMut,
+    sender: Addr,
     contract: Addr,
     channel_id: String,
-    denom: String,
-    quota_id: String,
-    now: Timestamp,
-) -> Result<Response, ContractError> {
-    let path = Path::new(&contract, &channel_id, &denom);
-    RATE_LIMIT_TRACKERS.update(deps.storage, path.into(), |maybe_rate_limit| {
-        match maybe_rate_limit {
-            None => Err(ContractError::QuotaNotFound {
-                quota_id,
-                channel_id: channel_id.clone(),
-                denom: denom.clone(),
-            }),
-            Some(mut limits) => {
-                // Q: What happens here if quote_id not found? seems like we return ok?
-                limits.iter_mut().for_each(|limit| {
-                    if limit.quota.name == quota_id.as_ref() {
-                        limit.flow.expire(now, limit.quota.duration)
-                    }
-                });
-                Ok(limits)
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    cw_ownable::assert_owner(deps.storage, &sender)?;
+
+    let limit = clamp_limit(limit, PRUNE_DEFAULT_LIMIT, PRUNE_MAX_LIMIT);
+    let denoms: Vec<String> = DAILY_STATS
+        .prefix((contract.clone(), channel_id.clone()))
+        .keys(deps.storage, None, None, Order::Ascending)
+        .filter(|denom| match denom {
+            Ok(denom) => {
+                let key = (contract.clone(), channel_id.clone(), denom.clone());
+                !RATE_LIMIT_TRACKERS.has(deps.storage, key)
             }
-        }
-    })?;
+            Err(_) => true,
+        })
+        .take(limit)
+        .collect::<StdResult<Vec<_>>>()?;
+    for denom in &denoms {
+        DAILY_STATS.remove(
+            deps.storage,
+            (contract.clone(), channel_id.clone(), denom.clone()),
+        );
+    }
 
     Ok(Response::new()
-        .add_attribute("method", "try_reset_channel")
+        .add_attribute("method", "try_prune_channel_denoms")
         .add_attribute("contract", contract.as_str())
-        .add_attribute("denom", denom)
-        .add_attribute("channel_id", channel_id))
+        .add_attribute("channel_id", channel_id)
+        .add_attribute("pruned", denoms.len().to_string()))
 }
 
-// This function will process a packet and extract the paths information, funds,
-// and channel value from it. This is will have to interact with the chain via grpc queries to properly
-// obtain this information.
-//
-// For backwards compatibility, we're teporarily letting the chain override the
-// denom and channel value, but these should go away in favour of the contract
-// extracting these from the packet
-pub fn process_packet(
-    deps: DepsMut,
-    contract: Addr,
-    packet: Packet,
-    direction: FlowType,
-    now: Timestamp,
-) -> Result<Response, ContractError> {
-    let path = &Path::new(&contract, &packet.channel, &packet.denom);
-    let funds = packet.amount;
+// Whether the packets of the denom get daily stats. Denoms with quotas, their
+// own or inherited, always do. The others only while the channel tracks fewer
+// than max_denoms_per_channel denoms, and go through untracked past that.
+// Every denom a packet went through has daily stats, so they are counted
+fn tracks_denom(storage: &dyn Storage, config: &Config, path: &Path) -> StdResult<bool> {
+    let Some(max) = config.max_denoms_per_channel else {
+        return Ok(true);
+    };
+    if DAILY_STATS.has(storage, path.into())
+        || RATE_LIMIT_TRACKERS.has(storage, path.into())
+        || inherited_quotas(storage, path)?.is_some()
+    {
+        return Ok(true);
+    }
 
-    try_transfer(deps, path, funds, direction, now)
+    let count = DAILY_STATS
+        .prefix((path.contract.clone(), path.channel.clone()))
+        .keys_raw(storage, None, None, Order::Ascending)
+        .take(max as usize)
+        .count();
+    Ok(count < max as usize)
 }
 
 /// This function checks the rate limit and, if successful, stores the updated data about the value
@@ -128,40 +3709,199 @@ pub fn process_packet(
 ///
 /// The channel_value is the current value of the denom for the the channel as
 /// calculated by the caller. This should be the total supply of a denom
+///
+/// Returns the amount that was accepted, which is less than the funds when the
+/// path truncates the transfers exceeding its quotas
 pub fn try_transfer(
     deps: DepsMut,
+    contract_config: &Config,
     path: &Path,
-    funds: Uint128,
+    packet: &Packet,
     direction: FlowType,
-    now: Timestamp,
-) -> Result<Response, ContractError> {
-    // Fetch trackers for the requested path
-    let mut trackers = RATE_LIMIT_TRACKERS
-        .may_load(deps.storage, path.into())?
-        .unwrap_or_default();
+    clock: &dyn TimeProvider,
+) -> Result<(Response, Uint128), ContractError> {
+    let funds = packet.amount;
+    let channel_value = packet.channel_value;
+    // Fetch trackers for the requested path, or for the tag of the packet if
+    // the path has quotas for it. Sends rejected with data don't revert the
+    // transaction, so nothing is written until the transfer is accepted
+    let tag_key = tag_quotas_key(deps.storage, path, packet)?;
+    let (mut trackers, corrupted) = match &tag_key {
+        Some(key) => (TAG_TRACKERS.load(deps.storage, key.clone())?, None),
+        None => read_trackers(deps.storage, path)?,
+    };
 
+    // Resolution order: exact path -> channel defaults -> global defaults ->
+    // unconfigured behavior. Inherited quotas become the trackers of the path,
+    // which starts on probation like an added one
+    let mut inherited = None;
+    if trackers.is_empty() {
+        if let Some((source, quotas)) = inherited_quotas(deps.storage, path)? {
+            trackers = new_trackers(&quotas, clock);
+            set_probation(&mut trackers, start_probation(contract_config, clock));
+            inherited = Some(source);
+        }
+    }
+
+    let clamped = clamp_durations(&mut trackers, path, contract_config, clock);
     let not_configured = trackers.is_empty();
 
     if not_configured {
+        // Rejecting reverts the quarantine, so the corrupted quotas stay in
+        // place and keep rejecting the packets of the path
+        if let (UnconfiguredBehavior::Reject, Some(_)) =
+            (&contract_config.unconfigured_behavior, &corrupted)
+        {
+            return Err(ContractError::PathQuarantined {
+                contract: path.contract.to_string(),
+                channel: path.channel.to_string(),
+                denom: path.denom.to_string(),
+            });
+        }
+        if let UnconfiguredBehavior::Reject = contract_config.unconfigured_behavior {
+            return Err(ContractError::PathNotConfigured {
+                contract: path.contract.to_string(),
+                channel: path.channel.to_string(),
+                denom: path.denom.to_string(),
+            });
+        }
+
         // No Quota configured for the current path. Allowing all messages.
-        return Ok(Response::new()
+        let quarantine = quarantine_trackers(deps.storage, path, corrupted)?;
+        let response = Response::new()
+            .add_events(quarantine)
             .add_attribute("method", "try_transfer")
             .add_attribute("contract", path.contract.as_str())
             .add_attribute("channel_id", path.channel.to_string())
             .add_attribute("denom", path.denom.to_string())
-            .add_attribute("quota", "none"));
+            .add_attribute("quota", "none");
+        return Ok((response, funds));
     }
 
-    // If any of the RateLimits fails, allow_transfer() will return
-    // ContractError::RateLimitExceded, which we'll propagate out
-    let results: Vec<RateLimit> = trackers
-        .iter_mut()
-        .map(|limit| limit.allow_transfer(path, &direction, funds, now))
-        .collect::<Result<_, ContractError>>()?;
+    let escrow_query = escrow_query(contract_config, path, &trackers, clock)?;
+    let channel_value =
+        cached_channel_value(deps.storage, contract_config, path, channel_value, clock)?;
 
-    RATE_LIMIT_TRACKERS.save(deps.storage, path.into(), &results)?;
+    let config = PATH_CONFIGS
+        .may_load(deps.storage, path.into())?
+        .unwrap_or_default();
+    let original = trackers.clone();
+    let (results, accepted) = match evaluate_quotas(
+        &mut trackers,
+        &config.evaluation_mode,
+        path,
+        &direction,
+        funds,
+        channel_value,
+        clock,
+    ) {
+        Ok(results) => (results, funds),
+        Err(err @ ContractError::RateLimitExceded { .. }) if config.truncates(&direction) => {
+            let accepted = fitting_amount(
+                &original,
+                &config.evaluation_mode,
+                &direction,
+                funds,
+                channel_value,
+                clock,
+            );
+            if accepted.is_zero() {
+                return Err(err);
+            }
+            trackers = original.clone();
+            let results = evaluate_quotas(
+                &mut trackers,
+                &config.evaluation_mode,
+                path,
+                &direction,
+                accepted,
+                channel_value,
+                clock,
+            )?;
+            (results, accepted)
+        }
+        Err(err) => return Err(err),
+    };
+    let channel_results = channel_transfer(
+        deps.storage,
+        contract_config,
+        path,
+        &direction,
+        accepted,
+        channel_value,
+        clock,
+    )?;
+    let chain_results = chain_transfer(
+        deps.storage,
+        &config,
+        path,
+        &direction,
+        accepted,
+        channel_value,
+        clock,
+    )?;
 
-    let response = Response::new()
+    // The transfer is accepted
+    let quarantine = quarantine_trackers(deps.storage, path, corrupted)?;
+    let inherited = match inherited {
+        Some(source) => {
+            update_metrics(deps.storage, |m| m.paths += 1)?;
+            record_audit(
+                deps.storage,
+                path,
+                AuditAction::AddPath,
+                &path.contract,
+                clock,
+                Some(format!("inherited from {source} defaults")),
+            )?;
+            Some(
+                Event::new("inherit_quotas")
+                    .add_attribute("contract", path.contract.as_str())
+                    .add_attribute("channel_id", path.channel.to_string())
+                    .add_attribute("denom", path.denom.to_string())
+                    .add_attribute("source", source),
+            )
+        }
+        None => None,
+    };
+    // Packets that leave the trackers untouched, like zero amounts, skip the
+    // write. Inherited trackers are new and always saved
+    match tag_key {
+        Some(key) => TAG_TRACKERS.save(deps.storage, key, &results)?,
+        None => {
+            archive_expired_flows(deps.storage, path, &original, clock)?;
+            if inherited.is_some() || clamped.is_some() || results != original {
+                save_trackers(deps.storage, path, &results)?;
+            }
+        }
+    }
+    if let Some(channel_results) = channel_results {
+        let key = (path.contract.clone(), path.channel.clone());
+        CHANNEL_TRACKERS.save(deps.storage, key, &channel_results)?;
+    }
+    if let Some((chain_id, chain_results)) = chain_results {
+        let key = (path.contract.clone(), chain_id, path.denom.clone());
+        CHAIN_TRACKERS.save(deps.storage, key, &chain_results)?;
+    }
+    // An accepted transfer ends the streak of rejected sends
+    if config.escalation.is_some() {
+        STREAKS.remove(deps.storage, path.into());
+    }
+
+    // Quotas only have their base capacity to draw from for now. The event
+    // names the pool so bridges don't depend on that
+    let consumed = results.iter().map(|result| {
+        Event::new("quota_consumed")
+            .add_attribute("quota", result.quota.name.to_string())
+            .add_attribute("consumed_from", "base")
+            .add_attribute("amount", accepted.to_string())
+    });
+    let mut response = Response::new()
+        .add_submessages(escrow_query)
+        .add_events(quarantine)
+        .add_events(inherited)
+        .add_events(clamped)
+        .add_events(consumed)
         .add_attribute("method", "try_transfer")
         .add_attribute("channel_id", path.channel.to_string())
         .add_attribute("denom", path.denom.to_string());
@@ -172,15 +3912,229 @@ pub fn try_transfer(
     //     results.iter().fold(Ok(response), |acc, result| {
     //         Ok(add_rate_limit_attributes(acc?, result))
     //     });
-    results.iter().fold(Ok(response), |acc, result| {
-        Ok(add_rate_limit_attributes(acc?, result))
+    if config.truncates(&direction) {
+        response = response.set_data(to_json_binary(&TransferData {
+            accepted,
+            remainder: funds - accepted,
+            fee: Uint128::zero(),
+        })?);
+    }
+    let response = results.iter().fold(response, |acc, result| {
+        add_rate_limit_attributes(acc, result)
+    });
+    #[cfg(feature = "debug_math")]
+    let response = original
+        .iter()
+        .zip(&results)
+        .fold(response, |acc, (before, after)| {
+            add_debug_math_attributes(acc, before, after, &direction, clock)
+        });
+    Ok((response, accepted))
+}
+
+// Clamps the quotas longer than the max duration of the config, which were
+// stored before it was lowered. Returns the event naming the clamped quotas
+fn clamp_durations(
+    trackers: &mut [RateLimit],
+    path: &Path,
+    config: &Config,
+    clock: &dyn TimeProvider,
+) -> Option<Event> {
+    let max_nanos = config.max_quota_duration_nanos();
+    let clamped: Vec<&str> = trackers
+        .iter_mut()
+        .filter_map(|limit| {
+            limit
+                .clamp_duration(max_nanos, clock)
+                .then_some(limit.quota.name.as_str())
+        })
+        .collect();
+    (!clamped.is_empty()).then(|| {
+        Event::new("clamp_quota_duration")
+            .add_attribute("contract", path.contract.as_str())
+            .add_attribute("channel_id", path.channel.to_string())
+            .add_attribute("denom", path.denom.to_string())
+            .add_attribute("quotas", clamped.join(","))
+            .add_attribute("max_nanos", max_nanos.to_string())
     })
 }
 
+// Accounts a transfer on the flows of its whole channel when the quota
+// hierarchy is enforced, and returns them. The channel defaults are the quotas
+// of those flows, so channels without defaults have none
+pub(crate) fn channel_transfer(
+    storage: &dyn Storage,
+    config: &Config,
+    path: &Path,
+    direction: &FlowType,
+    funds: Uint128,
+    channel_value: Option<Uint128>,
+    clock: &dyn TimeProvider,
+) -> Result<Option<Vec<RateLimit>>, ContractError> {
+    if let HierarchyStrictness::Off = config.quota_hierarchy {
+        return Ok(None);
+    }
+    let key = (path.contract.clone(), path.channel.clone());
+    let mut trackers = match CHANNEL_TRACKERS.may_load(storage, key.clone())? {
+        Some(trackers) => trackers,
+        None => match CHANNEL_DEFAULT_QUOTAS.may_load(storage, key)? {
+            Some(quotas) => new_trackers(&quotas, clock),
+            None => return Ok(None),
+        },
+    };
+    let results = evaluate_quotas(
+        &mut trackers,
+        &EvaluationMode::All,
+        path,
+        direction,
+        funds,
+        channel_value,
+        clock,
+    )?;
+    Ok(Some(results))
+}
+
+// Accounts a transfer on the flows of the denom on the counterparty chain of
+// the path, and returns them with the chain id. Paths without a counterparty,
+// or on a chain without quotas for the denom, have none
+pub(crate) fn chain_transfer(
+    storage: &dyn Storage,
+    config: &PathConfig,
+    path: &Path,
+    direction: &FlowType,
+    funds: Uint128,
+    channel_value: Option<Uint128>,
+    clock: &dyn TimeProvider,
+) -> Result<Option<(String, Vec<RateLimit>)>, ContractError> {
+    let Some(counterparty) = &config.counterparty else {
+        return Ok(None);
+    };
+    let key = (
+        path.contract.clone(),
+        counterparty.chain_id.clone(),
+        path.denom.clone(),
+    );
+    let Some(mut trackers) = CHAIN_TRACKERS.may_load(storage, key)? else {
+        return Ok(None);
+    };
+    let results = evaluate_quotas(
+        &mut trackers,
+        &EvaluationMode::All,
+        path,
+        direction,
+        funds,
+        channel_value,
+        clock,
+    )?;
+    Ok(Some((counterparty.chain_id.clone(), results)))
+}
+
+// Checks the quotas of the paths of a channel against the channel defaults,
+// which are the parents of the quotas of the same name. `parents` replaces the
+// stored defaults and `changed` the stored quotas of its denom, so the check
+// runs before either is saved
+fn check_quota_hierarchy(
+    storage: &dyn Storage,
+    strictness: &HierarchyStrictness,
+    contract: &Addr,
+    channel_id: &str,
+    parents: Option<&[QuotaMsg]>,
+    changed: Option<(&str, &[QuotaMsg])>,
+) -> Result<(), ContractError> {
+    if let HierarchyStrictness::Off = strictness {
+        return Ok(());
+    }
+    let channel_key = (contract.clone(), channel_id.to_string());
+    let parents = match parents {
+        Some(parents) => parents.to_vec(),
+        None => CHANNEL_DEFAULT_QUOTAS
+            .may_load(storage, channel_key.clone())?
+            .unwrap_or_default(),
+    };
+    if parents.is_empty() {
+        return Ok(());
+    }
+
+    let mut children = RATE_LIMIT_TRACKERS
+        .prefix(channel_key)
+        .range(storage, None, None, Order::Ascending)
+        .map(|item| {
+            let (denom, trackers) = item?;
+            Ok((denom, trackers.into_iter().map(|t| t.quota).collect()))
+        })
+        .collect::<StdResult<Vec<(String, Vec<Quota>)>>>()?;
+    if let Some((denom, quotas)) = changed {
+        children.retain(|(child, _)| child != denom);
+        children.push((denom.to_string(), quotas.iter().map(Quota::from).collect()));
+    }
+
+    let fixed = |quota: &Quota| quota.percentage.is_none();
+    for parent in parents.iter().map(Quota::from).filter(fixed) {
+        let (parent_in, parent_out) = parent.capacity(None);
+        let (mut total_in, mut total_out) = (Uint256::zero(), Uint256::zero());
+        for (denom, quotas) in &children {
+            let same_name = |quota: &&Quota| quota.name == parent.name && fixed(quota);
+            for child in quotas.iter().filter(same_name) {
+                let (child_in, child_out) = child.capacity(None);
+                if let HierarchyStrictness::Max = strictness {
+                    for (capacity, cap) in [(child_in, parent_in), (child_out, parent_out)] {
+                        if capacity > cap {
+                            return Err(ContractError::ChildQuotaExceedsParent {
+                                channel_id: channel_id.to_string(),
+                                denom: denom.clone(),
+                                quota_name: parent.name.clone(),
+                                child: capacity,
+                                parent: cap,
+                            });
+                        }
+                    }
+                }
+                total_in = total_in.saturating_add(child_in);
+                total_out = total_out.saturating_add(child_out);
+            }
+        }
+        if let HierarchyStrictness::Sum = strictness {
+            for (total, cap) in [(total_in, parent_in), (total_out, parent_out)] {
+                if total > cap {
+                    return Err(ContractError::ChildQuotasExceedParent {
+                        channel_id: channel_id.to_string(),
+                        quota_name: parent.name.clone(),
+                        total,
+                        parent: cap,
+                    });
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// The largest part of the funds the quotas allow. With EvaluationMode::All it
+// has to fit in every quota, with EvaluationMode::Any in at least one
+pub(crate) fn fitting_amount(
+    trackers: &[RateLimit],
+    mode: &EvaluationMode,
+    direction: &FlowType,
+    funds: Uint128,
+    channel_value: Option<Uint128>,
+    clock: &dyn TimeProvider,
+) -> Uint128 {
+    let remaining = trackers
+        .iter()
+        .map(|limit| limit.remaining(direction, channel_value, clock));
+    let remaining = match mode {
+        EvaluationMode::All => remaining.min(),
+        EvaluationMode::Any => remaining.max(),
+    };
+    remaining
+        .and_then(|remaining| Uint128::try_from(remaining).ok())
+        .map_or(funds, |remaining| remaining.min(funds))
+}
+
 // #[cfg(any(feature = "verbose_responses", test))]
 fn add_rate_limit_attributes(response: Response, result: &RateLimit) -> Response {
     let (used_in, used_out) = result.flow.balance();
-    let (max_in, max_out) = result.quota.capacity();
+    let (max_in, max_out) = result.quota.capacity(result.flow.period_channel_value);
     // These attributes are only added during testing. That way we avoid
     // calculating these again on prod.
     response
@@ -203,21 +4157,173 @@ fn add_rate_limit_attributes(response: Response, result: &RateLimit) -> Response
         )
 }
 
+// Shows how the quota got from its stored state to the one after the transfer:
+// the flows before and after, whether the period had expired and the channel
+// value the capacity was computed from
+#[cfg(feature = "debug_math")]
+fn add_debug_math_attributes(
+    response: Response,
+    before: &RateLimit,
+    after: &RateLimit,
+    direction: &FlowType,
+    clock: &dyn TimeProvider,
+) -> Response {
+    let name = &after.quota.name;
+    let channel_value = after
+        .flow
+        .period_channel_value
+        .map_or("none".to_string(), |value| value.to_string());
+    let capacity = after
+        .quota
+        .capacity_on(direction, after.flow.period_channel_value);
+    response
+        .add_attribute(
+            format!("{name}_debug_pre_in"),
+            before.flow.inflow.to_string(),
+        )
+        .add_attribute(
+            format!("{name}_debug_pre_out"),
+            before.flow.outflow.to_string(),
+        )
+        .add_attribute(
+            format!("{name}_debug_expired"),
+            before.flow.is_expired(clock).to_string(),
+        )
+        .add_attribute(
+            format!("{name}_debug_post_in"),
+            after.flow.inflow.to_string(),
+        )
+        .add_attribute(
+            format!("{name}_debug_post_out"),
+            after.flow.outflow.to_string(),
+        )
+        .add_attribute(format!("{name}_debug_channel_value"), channel_value)
+        .add_attribute(format!("{name}_debug_capacity"), capacity.to_string())
+}
+
 // This function manually injects an inflow. This is used when reverting a
 // packet that failed ack or timed-out.
-pub fn undo_send(deps: DepsMut, contract: Addr, packet: Packet) -> Result<Response, ContractError> {
-    let path = &Path::new(&contract, packet.channel, packet.denom);
-    let funds = packet.amount;
+//
+// The send must have been recorded with its sequence. Only the quotas still in
+// the period the send was accounted in are reverted. The others are skipped and
+// reported in an "undo_skipped" event
+pub fn undo_send(
+    deps: DepsMut,
+    contract: Addr,
+    packet: Packet,
+    clock: &dyn TimeProvider,
+) -> Result<Response, ContractError> {
+    let path = &load_key_spec(deps.storage)?.path(&contract, &packet.channel, &packet.denom);
+    check_migrated(deps.storage, path)?;
+    // Only recorded sends can be undone, and only once
+    let denom = normalize_denom(&packet.denom);
+    let pending = packet
+        .sequence
+        .map(|sequence| {
+            let key = (contract.clone(), packet.channel.clone(), sequence);
+            take_pending_send(deps.storage, key, &denom)
+        })
+        .transpose()?
+        .flatten()
+        .ok_or_else(|| ContractError::PendingSendNotFound {
+            channel_id: packet.channel.clone(),
+            sequence: packet.sequence,
+        })?;
+    if pending.denom != denom {
+        return Err(ContractError::PendingSendMismatch {
+            sequence: packet.sequence.unwrap_or_default(),
+            denom,
+            expected: pending.denom,
+        });
+    }
+
+    settle_in_flight(deps.storage, &contract, &packet.channel, Some(&pending))?;
+
+    let classes = packet_classes(deps.storage, &packet)?;
+    // What was accepted, which is less than the packet amount when truncated
+    let funds = pending.amount;
+    let refund = match pending.escrowed {
+        true => refund_escrow(deps.storage, path, &pending.denom, funds)?,
+        false => None,
+    };
+    update_metrics(deps.storage, |m| m.packets_undone += 1)?;
+    // Untracked denoms have no stats to count the undo in
+    let mut rollup = None;
+    if DAILY_STATS.has(deps.storage, path.into()) {
+        rollup = record_daily_stats(deps.storage, path, clock, |stats| stats.packets_undone += 1)?;
+    }
+
+    // Pre-approved sends went through without being accounted on any quota
+    if pending.pre_approved {
+        return Ok(Response::new()
+            .add_messages(refund)
+            .add_events(rollup)
+            .add_attribute("method", "undo_send")
+            .add_attribute("contract", contract.as_str())
+            .add_attribute("channel_id", path.channel.to_string())
+            .add_attribute("denom", path.denom.to_string())
+            .add_attribute("pre_approved", "true"));
+    }
 
-    let mut trackers = RATE_LIMIT_TRACKERS
+    // The class quotas of the send are reverted in the periods it was
+    // accounted in, like the quotas of the path
+    for class in classes {
+        let class_key = (path.into(), class.to_string());
+        if let Some(mut trackers) = CLASS_TRACKERS.may_load(deps.storage, class_key.clone())? {
+            let periods: Vec<PendingPeriod> = pending
+                .class_periods
+                .iter()
+                .filter(|(name, _)| name == class)
+                .map(|(_, period)| period.clone())
+                .collect();
+            undo_in_period(&mut trackers, &periods, funds, clock);
+            CLASS_TRACKERS.save(deps.storage, class_key, &trackers)?;
+        }
+    }
+    if let Some(mut trackers) = DEGRADED_TRACKERS.may_load(deps.storage, path.into())? {
+        undo_in_period(&mut trackers, &pending.degraded_periods, funds, clock);
+        DEGRADED_TRACKERS.save(deps.storage, path.into(), &trackers)?;
+    }
+    let counterparty = PATH_CONFIGS
         .may_load(deps.storage, path.into())?
-        .unwrap_or_default();
+        .and_then(|config| config.counterparty);
+    if let Some(counterparty) = counterparty {
+        let chain_key = (
+            path.contract.clone(),
+            counterparty.chain_id,
+            path.denom.clone(),
+        );
+        if let Some(mut trackers) = CHAIN_TRACKERS.may_load(deps.storage, chain_key.clone())? {
+            undo_in_period(&mut trackers, &pending.chain_periods, funds, clock);
+            CHAIN_TRACKERS.save(deps.storage, chain_key, &trackers)?;
+        }
+    }
+    let channel_key = (path.contract.clone(), path.channel.clone());
+    if let Some(mut trackers) = CHANNEL_TRACKERS.may_load(deps.storage, channel_key.clone())? {
+        undo_in_period(&mut trackers, &pending.channel_periods, funds, clock);
+        CHANNEL_TRACKERS.save(deps.storage, channel_key, &trackers)?;
+    }
+
+    // Tagged sends were accounted on the quotas of their tag instead
+    let tag_key = pending.tag.clone().map(|tag| (path.into(), tag));
+    let (mut trackers, quarantine) = match &tag_key {
+        Some(key) => (
+            TAG_TRACKERS
+                .may_load(deps.storage, key.clone())?
+                .unwrap_or_default(),
+            None,
+        ),
+        None => load_trackers(deps.storage, path)?,
+    };
 
     let not_configured = trackers.is_empty();
 
     if not_configured {
         // No Quota configured for the current path. Allowing all messages.
         return Ok(Response::new()
+            .add_messages(refund)
+            .add_events(rollup)
+            .add_events(quarantine)
             .add_attribute("method", "try_transfer")
             .add_attribute("contract", contract.as_str())
             .add_attribute("channel_id", path.channel.to_string())
@@ -226,32 +4332,224 @@ pub fn undo_send(deps: DepsMut, contract: Addr, packet: Packet) -> Result<Respon
     }
 
     // We force update the flow to remove a failed send
-    let results: Vec<RateLimit> = trackers
-        .iter_mut()
-        .map(|limit| {
-            limit.flow.undo_flow(FlowType::Out, funds);
-            limit.to_owned()
-        })
-        .collect();
+    let skipped = undo_in_period(&mut trackers, &pending.periods, funds, clock);
+
+    match tag_key {
+        Some(key) => TAG_TRACKERS.save(deps.storage, key, &trackers)?,
+        None => save_trackers(deps.storage, path, &trackers)?,
+    }
 
-    RATE_LIMIT_TRACKERS.save(deps.storage, path.into(), &results)?;
+    let skipped_event = (!skipped.is_empty()).then(|| {
+        Event::new("undo_skipped")
+            .add_attribute("contract", contract.as_str())
+            .add_attribute("channel_id", path.channel.to_string())
+            .add_attribute("denom", path.denom.to_string())
+            .add_attribute("quotas", skipped.join(","))
+    });
 
     Ok(Response::new()
+        .add_messages(refund)
+        .add_events(rollup)
+        .add_events(skipped_event)
         .add_attribute("method", "undo_send")
         .add_attribute("contract", contract.as_str())
         .add_attribute("channel_id", path.channel.to_string())
         .add_attribute("denom", path.denom.to_string()))
 }
 
+// Removes a pending send from the in flight value of its path, whether it was
+// acknowledged or undone
+fn settle_in_flight(
+    storage: &mut dyn Storage,
+    contract: &Addr,
+    channel: &str,
+    pending: Option<&PendingSend>,
+) -> StdResult<()> {
+    let Some(pending) = pending.filter(|pending| pending.in_flight) else {
+        return Ok(());
+    };
+
+    let path = load_key_spec(storage)?.path(contract, channel, &pending.denom);
+    let in_flight = IN_FLIGHT
+        .may_load(storage, (&path).into())?
+        .unwrap_or_default()
+        .saturating_sub(Uint256::from(pending.amount));
+    IN_FLIGHT.save(storage, (&path).into(), &in_flight)
+}
+
+// The value of the pending sends added to the in flight value of the path, which
+// IN_FLIGHT must match
+pub(crate) fn pending_in_flight(storage: &dyn Storage, path: &Path) -> StdResult<Uint256> {
+    Ok(PENDING_IN_FLIGHT
+        .may_load(storage, path.into())?
+        .unwrap_or_default())
+}
+
+// Pending sends recorded before PENDING_IN_FLIGHT was added are indexed on
+// migration. Sends are keyed by the channel they were sent on, so the index is
+// rebuilt from all of them
+pub fn index_pending_in_flight(storage: &mut dyn Storage) -> StdResult<()> {
+    let key_spec = load_key_spec(storage)?;
+    let mut totals: BTreeMap<PathKey, Uint256> = BTreeMap::new();
+    let mut add = |contract: &Addr, channel: &str, pending: &PendingSend| {
+        if pending.in_flight {
+            let path = key_spec.path(contract, channel, &pending.denom);
+            *totals.entry((&path).into()).or_default() += Uint256::from(pending.amount);
+        }
+    };
+    for item in PENDING_SENDS.range(storage, None, None, Order::Ascending) {
+        let ((contract, channel, _), pending) = item?;
+        add(&contract, &channel, &pending);
+    }
+    for item in PENDING_ICA_SENDS.range(storage, None, None, Order::Ascending) {
+        let ((contract, channel, _), coins) = item?;
+        coins
+            .iter()
+            .for_each(|pending| add(&contract, &channel, pending));
+    }
+
+    PENDING_IN_FLIGHT.clear(storage);
+    totals
+        .into_iter()
+        .try_for_each(|(key, total)| PENDING_IN_FLIGHT.save(storage, key, &total))
+}
+
+// Rebuilds the in flight value of a page of paths from their pending sends
+pub fn try_repair_invariants(
+    deps: DepsMut,
+    sender: Addr,
+    start_after: Option<(Addr, String, String)>,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    cw_ownable::assert_owner(deps.storage, &sender)?;
+    let limit = clamp_limit(limit, TICK_DEFAULT_LIMIT, TICK_MAX_LIMIT);
+
+    let keys = RATE_LIMIT_TRACKERS
+        .keys(
+            deps.storage,
+            start_bound(start_after),
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .collect::<StdResult<Vec<_>>>()?;
+    let mut repaired = 0;
+    for key in &keys {
+        let path = Path::new(&key.0, &key.1, &key.2);
+        let stored = IN_FLIGHT.may_load(deps.storage, key.clone())?;
+        let pending = pending_in_flight(deps.storage, &path)?;
+        if stored.unwrap_or_default() == pending {
+            continue;
+        }
+        match pending.is_zero() {
+            true => IN_FLIGHT.remove(deps.storage, key.clone()),
+            false => IN_FLIGHT.save(deps.storage, key.clone(), &pending)?,
+        }
+        repaired += 1;
+    }
+
+    let next = keys.last().filter(|_| keys.len() == limit);
+    Ok(Response::new()
+        .add_attribute("method", "try_repair_invariants")
+        .add_attribute("repaired", repaired.to_string())
+        .set_data(to_json_binary(&next)?))
+}
+
+// Rejections are the only per-sender data kept, so purging an address drops its
+// rejections from the paths. Paths left without rejections are removed
+pub fn try_purge_sender_data(
+    deps: DepsMut,
+    sender: Addr,
+    address: String,
+    start_after: Option<(Addr, String, String)>,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    cw_ownable::assert_owner(deps.storage, &sender)?;
+    let limit = clamp_limit(limit, PRUNE_DEFAULT_LIMIT, PRUNE_MAX_LIMIT);
+
+    let entries = REJECTIONS
+        .range(
+            deps.storage,
+            start_bound(start_after),
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .collect::<StdResult<Vec<_>>>()?;
+    let mut response = Response::new()
+        .add_attribute("method", "try_purge_sender_data")
+        .add_attribute("address", &address);
+    for (key, mut rejections) in entries.iter().cloned() {
+        let count = rejections.len();
+        rejections.retain(|rejection| rejection.sender.as_deref() != Some(address.as_str()));
+        if rejections.len() == count {
+            continue;
+        }
+        match rejections.is_empty() {
+            true => REJECTIONS.remove(deps.storage, key.clone()),
+            false => REJECTIONS.save(deps.storage, key.clone(), &rejections)?,
+        }
+        response = response.add_event(
+            Event::new("sender_data_purged")
+                .add_attribute("address", &address)
+                .add_attribute("contract", key.0.as_str())
+                .add_attribute("channel_id", key.1)
+                .add_attribute("denom", key.2)
+                .add_attribute("rejections", (count - rejections.len()).to_string()),
+        );
+    }
+
+    let next = entries
+        .last()
+        .filter(|_| entries.len() == limit)
+        .map(|(key, _)| key);
+    Ok(response.set_data(to_json_binary(&next)?))
+}
+
+// Acknowledges a send. Failed acks are reverted like UndoSend, while successful
+// ones settle the pending send and release its escrow, if any, to the bridge
+pub fn ack_packet(
+    deps: DepsMut,
+    contract: Addr,
+    packet: Packet,
+    success: bool,
+    clock: &dyn TimeProvider,
+) -> Result<Response, ContractError> {
+    if !success {
+        return undo_send(deps, contract, packet, clock);
+    }
+
+    let mut release = None;
+    if let Some(sequence) = packet.sequence {
+        let key = (contract.clone(), packet.channel.clone(), sequence);
+        let path = load_key_spec(deps.storage)?.path(&contract, &packet.channel, &packet.denom);
+        // A send acknowledged in another denom stays pending
+        let denom = normalize_denom(&packet.denom);
+        let pending =
+            take_pending_send(deps.storage, key, &denom)?.filter(|pending| pending.denom == denom);
+        settle_in_flight(deps.storage, &contract, &packet.channel, pending.as_ref())?;
+        if let Some(pending) = pending.filter(|pending| pending.escrowed) {
+            release = refund_escrow(deps.storage, &path, &pending.denom, pending.amount)?;
+        }
+    }
+
+    Ok(Response::new()
+        .add_messages(release)
+        .add_attribute("method", "ack_packet")
+        .add_attribute("contract", contract.as_str())
+        .add_attribute("channel_id", packet.channel)
+        .add_attribute("denom", packet.denom))
+}
+
 #[cfg(test)]
 mod tests {
     use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
-    use cosmwasm_std::{from_json, Addr, StdError, Uint128};
+    use cosmwasm_std::{from_json, Addr, StdError, Uint256};
 
     use crate::contract::{execute, query};
     use crate::helpers::tests::verify_query_response;
-    use crate::msg::{ExecuteMsg, QueryMsg, QuotaMsg};
-    use crate::state::RateLimit;
+    use crate::msg::{ExecuteMsg, QueryMsg, QuotaMsg, RateLimitResponse, Versioned};
+    use crate::state::QuotaMode;
 
     const BRIDGE_CONTRACT: &str = "bridge_contract";
 
@@ -265,9 +4563,17 @@ mod tests {
             quotas: vec![QuotaMsg {
                 name: "daily".to_string(),
                 duration: 1600,
-                max_send: Uint128::new(1000000),
-                max_receive: Uint128::new(1000000),
+                duration_nanos: None,
+                max_send: Uint256::from(1000000_u128),
+                max_receive: Uint256::from(1000000_u128),
+                blocked: false,
+                mode: QuotaMode::Netted,
+                percentage: None,
+                send_duration: None,
+                recv_duration: None,
+                partitions: vec![],
             }],
+            idempotency_key: None,
         };
         let info = mock_info(BRIDGE_CONTRACT, &vec![]);
 
@@ -283,12 +4589,12 @@ mod tests {
 
         let res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
 
-        let value: Vec<RateLimit> = from_json(&res).unwrap();
+        let value: Vec<RateLimitResponse> = from_json::<Versioned<_>>(&res).unwrap().data;
         verify_query_response(
             &value[0],
             "daily",
-            Uint128::new(1000000),
-            Uint128::new(1000000),
+            Uint256::from(1000000_u128),
+            Uint256::from(1000000_u128),
             1600,
             0_u32.into(),
             0_u32.into(),
@@ -304,9 +4610,17 @@ mod tests {
             quotas: vec![QuotaMsg {
                 name: "daily".to_string(),
                 duration: 1600,
-                max_send: Uint128::new(1000000),
-                max_receive: Uint128::new(1000000),
+                duration_nanos: None,
+                max_send: Uint256::from(1000000_u128),
+                max_receive: Uint256::from(1000000_u128),
+                blocked: false,
+                mode: QuotaMode::Netted,
+                percentage: None,
+                send_duration: None,
+                recv_duration: None,
+                partitions: vec![],
             }],
+            idempotency_key: None,
         };
         let info = mock_info(BRIDGE_CONTRACT, &vec![]);
 
@@ -317,6 +4631,7 @@ mod tests {
         let msg = ExecuteMsg::RemovePath {
             channel_id: format!("channel"),
             denom: format!("denom"),
+            idempotency_key: None,
         };
 
         let info = mock_info(BRIDGE_CONTRACT, &vec![]);
@@ -334,13 +4649,13 @@ mod tests {
             denom: format!("denom"),
         };
         let res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
-        let value: Vec<RateLimit> = from_json(&res).unwrap();
+        let value: Vec<RateLimitResponse> = from_json::<Versioned<_>>(&res).unwrap().data;
         assert_eq!(value.len(), 1);
         verify_query_response(
             &value[0],
             "daily",
-            Uint128::new(1000000),
-            Uint128::new(1000000),
+            Uint256::from(1000000_u128),
+            Uint256::from(1000000_u128),
             1600,
             0_u32.into(),
             0_u32.into(),
@@ -354,9 +4669,17 @@ mod tests {
             quotas: vec![QuotaMsg {
                 name: "different".to_string(),
                 duration: 5000,
-                max_send: Uint128::new(10000000),
-                max_receive: Uint128::new(10000000),
+                duration_nanos: None,
+                max_send: Uint256::from(10000000_u128),
+                max_receive: Uint256::from(10000000_u128),
+                blocked: false,
+                mode: QuotaMode::Netted,
+                percentage: None,
+                send_duration: None,
+                recv_duration: None,
+                partitions: vec![],
             }],
+            idempotency_key: None,
         };
         let info = mock_info(BRIDGE_CONTRACT, &vec![]);
 
@@ -369,14 +4692,14 @@ mod tests {
             denom: format!("denom"),
         };
         let res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
-        let value: Vec<RateLimit> = from_json(&res).unwrap();
+        let value: Vec<RateLimitResponse> = from_json::<Versioned<_>>(&res).unwrap().data;
         assert_eq!(value.len(), 1);
 
         verify_query_response(
             &value[0],
             "different",
-            Uint128::new(10000000),
-            Uint128::new(10000000),
+            Uint256::from(10000000_u128),
+            Uint256::from(10000000_u128),
             5000,
             0_u32.into(),
             0_u32.into(),
@@ -27,24 +27,25 @@ impl RateLimitingContract {
 }
 
 pub mod tests {
-    use cosmwasm_std::{Timestamp, Uint128};
+    use cosmwasm_std::{Timestamp, Uint256};
 
-    use crate::state::RateLimit;
+    use crate::msg::RateLimitResponse;
+    use crate::state::NANOS_PER_SECOND;
 
     pub fn verify_query_response(
-        value: &RateLimit,
+        value: &RateLimitResponse,
         quota_name: &str,
-        send: Uint128,
-        receive: Uint128,
+        send: Uint256,
+        receive: Uint256,
         duration: u64,
-        inflow: Uint128,
-        outflow: Uint128,
+        inflow: Uint256,
+        outflow: Uint256,
         period_end: Timestamp,
     ) {
         assert_eq!(value.quota.name, quota_name);
         assert_eq!(value.quota.max_send, send);
         assert_eq!(value.quota.max_recv, receive);
-        assert_eq!(value.quota.duration, duration);
+        assert_eq!(value.quota.duration_nanos, duration * NANOS_PER_SECOND);
         assert_eq!(value.flow.inflow, inflow);
         assert_eq!(value.flow.outflow, outflow);
         assert_eq!(value.flow.period_end, period_end);
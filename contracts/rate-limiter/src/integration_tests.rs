@@ -1,25 +1,27 @@
 #![cfg(test)]
-use crate::{helpers::RateLimitingContract, test_msg_send, ContractError};
-use cosmwasm_std::{Addr, Coin, Empty, Timestamp, Uint128};
-use cosmwasm_testing_util::{App, AppBuilder, Contract, ContractWrapper, Executor};
+use crate::{
+    assert_rate_limited, helpers::RateLimitingContract, test_msg_send, testing, ContractError,
+};
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{
+    to_json_binary, Addr, Binary, Coin, Deps, DepsMut, Empty, Env, MessageInfo, Response, StdError,
+    StdResult, Timestamp, Uint128, Uint256, WasmMsg,
+};
+use cosmwasm_testing_util::{App, AppBuilder, ContractWrapper, Executor};
+use cw_storage_plus::{Item, Map};
 
 use crate::{
-    msg::{InstantiateMsg, PathMsg, QuotaMsg},
+    msg::{
+        ExecuteMsg, InstantiateMsg, PathId, PathMsg, QuotaMsg, QuotaViolation,
+        RateLimitExceededData,
+    },
+    packet::{Ics20Transfer, Packet},
     state::tests::{RESET_TIME_DAILY, RESET_TIME_MONTHLY, RESET_TIME_WEEKLY},
+    state::PathConfig,
 };
 
-pub fn contract_template() -> Box<dyn Contract<Empty>> {
-    let contract = ContractWrapper::new(
-        crate::contract::execute,
-        crate::contract::instantiate,
-        crate::contract::query,
-    );
-
-    Box::new(contract)
-}
-
 const USER: &str = "USER";
-const OWNER: &str = "OWNER";
+const OWNER: &str = "owner";
 const BRIDGE_CONTRACT: &str = "BRIDGE_CONTRACT";
 const NATIVE_DENOM: &str = "orai";
 
@@ -42,9 +44,13 @@ fn mock_app() -> App {
 // Instantiate the contract
 fn proper_instantiate(paths: Vec<PathMsg>) -> (App, RateLimitingContract) {
     let mut app = mock_app();
-    let cw_code_id = app.store_code(contract_template());
+    let cw_code_id = app.store_code(testing::contract());
 
-    let msg = InstantiateMsg { paths };
+    let msg = InstantiateMsg {
+        paths,
+        key_spec: None,
+        seed_flows: vec![],
+    };
 
     let cw_rate_limit_contract_addr = app
         .instantiate_contract(cw_code_id, Addr::unchecked(OWNER), &msg, &[], "test", None)
@@ -57,13 +63,25 @@ fn proper_instantiate(paths: Vec<PathMsg>) -> (App, RateLimitingContract) {
 
 use cosmwasm_std::Attribute;
 
+// Moves the contract time without producing new blocks
+#[cfg(feature = "mock-time")]
+fn set_contract_time(app: &mut App, contract: &RateLimitingContract, time: Timestamp) {
+    app.execute_contract(
+        Addr::unchecked(OWNER),
+        contract.addr(),
+        &crate::msg::ExecuteMsg::SetMockTime { time: Some(time) },
+        &[],
+    )
+    .unwrap();
+}
+
 #[test] // Checks that the RateLimit flows are expired properly when time passes
 fn expiration() {
     let quota = QuotaMsg::new(
         "weekly",
         RESET_TIME_WEEKLY,
-        Uint128::new(1000),
-        Uint128::new(1000),
+        Uint256::from(1000_u128),
+        Uint256::from(1000_u128),
     );
 
     let (mut app, cw_rate_limit_contract) = proper_instantiate(vec![PathMsg {
@@ -116,9 +134,15 @@ fn expiration() {
             denom: "denom".to_string(),
             amount: Uint128::new(800),
             quota_name: "weekly".to_string(),
-            used: Uint128::new(300),
-            max: Uint128::new(1000),
+            used: Uint256::from(300_u128),
+            max: Uint256::from(1000_u128),
             reset: Timestamp::from_nanos(1572402219879305533),
+            violations: vec![QuotaViolation {
+                quota_name: "weekly".to_string(),
+                used: Uint256::from(300_u128),
+                max: Uint256::from(1000_u128),
+                reset: Timestamp::from_nanos(1572402219879305533),
+            }],
         }
     );
 
@@ -160,20 +184,20 @@ fn multiple_quotas() {
         QuotaMsg::new(
             "daily",
             RESET_TIME_DAILY,
-            Uint128::new(1000),
-            Uint128::new(1000),
+            Uint256::from(1000_u128),
+            Uint256::from(1000_u128),
         ),
         QuotaMsg::new(
             "weekly",
             RESET_TIME_WEEKLY,
-            Uint128::new(5000),
-            Uint128::new(5000),
+            Uint256::from(5000_u128),
+            Uint256::from(5000_u128),
         ),
         QuotaMsg::new(
             "monthly",
             RESET_TIME_MONTHLY,
-            Uint128::new(5000),
-            Uint128::new(5000),
+            Uint256::from(5000_u128),
+            Uint256::from(5000_u128),
         ),
     ];
 
@@ -304,3 +328,375 @@ fn multiple_quotas() {
     app.execute(Addr::unchecked(BRIDGE_CONTRACT), cosmos_msg)
         .unwrap();
 }
+
+#[cfg(feature = "mock-time")]
+#[test] // Checks that flows expire based on the contract time and not the block time
+fn expiration_with_mock_time() {
+    let quota = QuotaMsg::new(
+        "weekly",
+        RESET_TIME_WEEKLY,
+        Uint256::from(1000_u128),
+        Uint256::from(1000_u128),
+    );
+
+    let (mut app, cw_rate_limit_contract) = proper_instantiate(vec![PathMsg {
+        contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        quotas: vec![quota],
+    }]);
+
+    let msg = test_msg_send!(
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        funds: 800_u32.into()
+    );
+    let cosmos_msg = cw_rate_limit_contract.call(msg.clone()).unwrap();
+    app.execute(Addr::unchecked(BRIDGE_CONTRACT), cosmos_msg)
+        .unwrap();
+
+    let cosmos_msg = cw_rate_limit_contract.call(msg.clone()).unwrap();
+    app.execute(Addr::unchecked(BRIDGE_CONTRACT), cosmos_msg)
+        .unwrap_err();
+
+    // ... A week passes for the contract, while the chain stays at the same block
+    let week_later = app.block_info().time.plus_seconds(RESET_TIME_WEEKLY + 1);
+    set_contract_time(&mut app, &cw_rate_limit_contract, week_later);
+
+    let cosmos_msg = cw_rate_limit_contract.call(msg).unwrap();
+    app.execute(Addr::unchecked(BRIDGE_CONTRACT), cosmos_msg)
+        .unwrap();
+}
+
+#[test] // Checks the multitest helpers exposed to integrators
+fn testing_helpers() {
+    let quotas = vec![
+        QuotaMsg::new(
+            "daily",
+            RESET_TIME_DAILY,
+            Uint256::from(500_u128),
+            Uint256::from(500_u128),
+        ),
+        QuotaMsg::new(
+            "weekly",
+            RESET_TIME_WEEKLY,
+            Uint256::from(1000_u128),
+            Uint256::from(1000_u128),
+        ),
+    ];
+    let (mut app, cw_rate_limit_contract) = proper_instantiate(vec![PathMsg {
+        contract_addr: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        quotas,
+    }]);
+    let path = PathId {
+        contract: Addr::unchecked(BRIDGE_CONTRACT),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+    };
+
+    let send = |app: &mut App, funds: u32| {
+        let msg = test_msg_send!(
+            channel_id: format!("channel"),
+            denom: format!("denom"),
+            funds: funds.into()
+        );
+        let cosmos_msg = cw_rate_limit_contract.call(msg).unwrap();
+        app.execute(Addr::unchecked(BRIDGE_CONTRACT), cosmos_msg)
+    };
+
+    send(&mut app, 500).unwrap();
+    assert_rate_limited!(send(&mut app, 1), "daily");
+
+    // The daily quota expires, but the weekly one is still used
+    app.update_block(|b| b.time = b.time.plus_seconds(RESET_TIME_DAILY + 1));
+    send(&mut app, 500).unwrap();
+    assert_rate_limited!(send(&mut app, 1));
+
+    testing::advance_period(&mut app, &cw_rate_limit_contract.addr(), &path, 1);
+    send(&mut app, 500).unwrap();
+
+    let quotas = testing::path_quotas(&app, &cw_rate_limit_contract.addr(), &path);
+    assert_eq!(quotas[1].flow.outflow, Uint256::from(500_u128));
+}
+
+// A bridge that is its own on_reject_contract. When one of its sends is
+// rejected, it undoes the send before it to make room and sends the packet
+// again, as the next sequence, on the same path. Packets it sent again aren't
+// retried
+const BRIDGE_LIMITER: Item<Addr> = Item::new("rate_limiter");
+const BRIDGE_SENT: Map<u64, Packet> = Map::new("sent");
+
+#[cw_serde]
+struct MockBridgeInstantiateMsg {
+    rate_limiter: Addr,
+}
+
+#[cw_serde]
+enum MockBridgeMsg {
+    Send {
+        packet: Packet,
+    },
+    // The RejectHookMsg of the rate limiter
+    RateLimitRejected {
+        packet: Packet,
+        exceeded: RateLimitExceededData,
+    },
+}
+
+fn mock_bridge_instantiate(
+    deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    msg: MockBridgeInstantiateMsg,
+) -> StdResult<Response> {
+    BRIDGE_LIMITER.save(deps.storage, &msg.rate_limiter)?;
+    Ok(Response::new())
+}
+
+fn mock_bridge_execute(
+    deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    msg: MockBridgeMsg,
+) -> StdResult<Response> {
+    let mut msgs = vec![];
+    match msg {
+        MockBridgeMsg::Send { packet } => {
+            BRIDGE_SENT.save(deps.storage, packet.sequence.unwrap(), &packet)?;
+            msgs.push(ExecuteMsg::SendPacket { packet });
+        }
+        MockBridgeMsg::RateLimitRejected { mut packet, .. } => {
+            let sequence = packet.sequence.unwrap();
+            if !BRIDGE_SENT.has(deps.storage, sequence) {
+                return Ok(Response::new());
+            }
+            let previous = BRIDGE_SENT.load(deps.storage, sequence - 1)?;
+            msgs.push(ExecuteMsg::UndoSend { packet: previous });
+            packet.sequence = Some(sequence + 1);
+            msgs.push(ExecuteMsg::SendPacket { packet });
+        }
+    }
+
+    let rate_limiter = BRIDGE_LIMITER.load(deps.storage)?;
+    let msgs = msgs
+        .iter()
+        .map(|msg| {
+            Ok(WasmMsg::Execute {
+                contract_addr: rate_limiter.to_string(),
+                msg: to_json_binary(msg)?,
+                funds: vec![],
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(Response::new().add_messages(msgs))
+}
+
+fn hook_query(_deps: Deps, _env: Env, _msg: Empty) -> StdResult<Binary> {
+    to_json_binary(&Empty {})
+}
+
+#[test] // Checks that hooks re-entering the contract can't change the accounting of the path
+fn reentrant_hooks() {
+    let (mut app, cw_rate_limit_contract) = proper_instantiate(vec![]);
+    let code_id = app.store_code(Box::new(ContractWrapper::new(
+        mock_bridge_execute,
+        mock_bridge_instantiate,
+        hook_query,
+    )));
+    let bridge = app
+        .instantiate_contract(
+            code_id,
+            Addr::unchecked(OWNER),
+            &MockBridgeInstantiateMsg {
+                rate_limiter: cw_rate_limit_contract.addr(),
+            },
+            &[],
+            "bridge",
+            None,
+        )
+        .unwrap();
+
+    let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 10_u32.into(), 10_u32.into());
+    let msg = ExecuteMsg::AddPath {
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        quotas: vec![quota],
+        idempotency_key: None,
+    };
+    app.execute_contract(bridge.clone(), cw_rate_limit_contract.addr(), &msg, &[])
+        .unwrap();
+    let msg = ExecuteMsg::SetPathConfig {
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+        config: PathConfig {
+            reject_with_data: true,
+            on_reject_contract: Some(bridge.clone()),
+            ..PathConfig::default()
+        },
+        idempotency_key: None,
+    };
+    app.execute_contract(bridge.clone(), cw_rate_limit_contract.addr(), &msg, &[])
+        .unwrap();
+    let path = PathId {
+        contract: bridge.clone(),
+        channel_id: format!("channel"),
+        denom: format!("denom"),
+    };
+
+    let send = |app: &mut App, funds: u32, sequence: u64| {
+        let packet = Packet::new("channel", "denom", funds.into()).with_sequence(sequence);
+        let msg = MockBridgeMsg::Send { packet };
+        app.execute_contract(Addr::unchecked(USER), bridge.clone(), &msg, &[])
+    };
+    let outflow = |app: &App| {
+        testing::path_quotas(app, &cw_rate_limit_contract.addr(), &path)[0]
+            .flow
+            .outflow
+    };
+
+    send(&mut app, 6, 1).unwrap();
+    assert_eq!(outflow(&app), Uint256::from(6_u32));
+
+    // The hook sees the rejection accounted: it undoes the first send and the
+    // packet sent again fits
+    send(&mut app, 5, 2).unwrap();
+    assert_eq!(outflow(&app), Uint256::from(5_u32));
+
+    // The send before this one was rejected, so there's nothing to undo. The
+    // failing hook is reverted, undo and new send included, but not the
+    // rejection
+    let res = send(&mut app, 6, 4).unwrap();
+    assert!(res.events.iter().any(|event| event
+        .attributes
+        .contains(&Attribute::new("notification", "on_reject_contract"))));
+    assert_eq!(outflow(&app), Uint256::from(5_u32));
+
+    // What's left of the quota is still available
+    send(&mut app, 5, 5).unwrap();
+    assert_eq!(outflow(&app), Uint256::from(10_u32));
+}
+
+// A cw-ics20 contract reporting its transfers to the rate limiter from its
+// transfer hook
+const ICS20_LIMITER: Item<Addr> = Item::new("rate_limiter");
+
+#[cw_serde]
+struct MockIcs20InstantiateMsg {
+    rate_limiter: Addr,
+}
+
+#[cw_serde]
+enum MockIcs20Msg {
+    Transfer {
+        channel: String,
+        remote_denom: String,
+        local_denom: String,
+        amount: Uint128,
+        is_receive: bool,
+    },
+}
+
+fn mock_ics20_instantiate(
+    deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    msg: MockIcs20InstantiateMsg,
+) -> StdResult<Response> {
+    ICS20_LIMITER.save(deps.storage, &msg.rate_limiter)?;
+    Ok(Response::new())
+}
+
+fn mock_ics20_execute(
+    deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    msg: MockIcs20Msg,
+) -> StdResult<Response> {
+    let MockIcs20Msg::Transfer {
+        channel,
+        remote_denom,
+        local_denom,
+        amount,
+        is_receive,
+    } = msg;
+    let hook = ExecuteMsg::Ics20TransferHook(Ics20Transfer {
+        channel,
+        remote_denom,
+        local_denom,
+        amount,
+        is_receive,
+    });
+    Ok(Response::new().add_message(WasmMsg::Execute {
+        contract_addr: ICS20_LIMITER.load(deps.storage)?.to_string(),
+        msg: to_json_binary(&hook)?,
+        funds: vec![],
+    }))
+}
+
+#[test] // Checks that a cw-ics20 contract can call the limiter from its transfer hook
+fn cw_ics20_transfers() {
+    let (mut app, cw_rate_limit_contract) = proper_instantiate(vec![]);
+    let code_id = app.store_code(Box::new(ContractWrapper::new(
+        mock_ics20_execute,
+        mock_ics20_instantiate,
+        hook_query,
+    )));
+    let ics20 = app
+        .instantiate_contract(
+            code_id,
+            Addr::unchecked(OWNER),
+            &MockIcs20InstantiateMsg {
+                rate_limiter: cw_rate_limit_contract.addr(),
+            },
+            &[],
+            "cw-ics20",
+            None,
+        )
+        .unwrap();
+
+    // The paths of the cw-ics20 contract are named by their local denom
+    let local_denom = format!("cw20:orai1token");
+    let quota = QuotaMsg::new("daily", RESET_TIME_DAILY, 10_u32.into(), 10_u32.into());
+    let msg = ExecuteMsg::AddPath {
+        channel_id: format!("channel-1"),
+        denom: local_denom.clone(),
+        quotas: vec![quota],
+        idempotency_key: None,
+    };
+    app.execute_contract(ics20.clone(), cw_rate_limit_contract.addr(), &msg, &[])
+        .unwrap();
+    let path = PathId {
+        contract: ics20.clone(),
+        channel_id: format!("channel-1"),
+        denom: local_denom.clone(),
+    };
+
+    let transfer = |app: &mut App, amount: u32, is_receive: bool| {
+        let msg = MockIcs20Msg::Transfer {
+            channel: format!("channel-1"),
+            remote_denom: format!("uatom"),
+            local_denom: local_denom.clone(),
+            amount: amount.into(),
+            is_receive,
+        };
+        app.execute_contract(Addr::unchecked(USER), ics20.clone(), &msg, &[])
+    };
+    let flow = |app: &App| {
+        let quotas = testing::path_quotas(app, &cw_rate_limit_contract.addr(), &path);
+        (quotas[0].flow.inflow, quotas[0].flow.outflow)
+    };
+
+    transfer(&mut app, 10, false).unwrap();
+    assert_eq!(flow(&app), (Uint256::zero(), Uint256::from(10_u32)));
+
+    // The limiter rejecting the transfer fails the whole cw-ics20 transfer
+    transfer(&mut app, 1, false).unwrap_err();
+    assert_eq!(flow(&app), (Uint256::zero(), Uint256::from(10_u32)));
+
+    // Receives are netted against the sends
+    transfer(&mut app, 4, true).unwrap();
+    transfer(&mut app, 4, false).unwrap();
+    assert_eq!(flow(&app), (Uint256::from(4_u32), Uint256::from(14_u32)));
+}
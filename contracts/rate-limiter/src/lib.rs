@@ -7,12 +7,26 @@ pub mod msg;
 pub mod state;
 
 pub mod packet;
+pub mod time;
+
+// The contract evaluates its quotas through the core module, which is only
+// public for the contracts embedding it
+#[cfg(any(test, feature = "library"))]
+pub mod core;
+#[cfg(not(any(test, feature = "library")))]
+mod core;
+
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
 // Functions
 mod execute;
+mod pagination;
 mod query;
 
 // Tests
+#[cfg(feature = "bench")]
+mod bench;
 mod contract_tests;
 mod helpers;
 mod integration_tests;
@@ -1,9 +1,18 @@
 use cosmwasm_schema::{cw_serde, QueryResponses};
-use cosmwasm_std::Addr;
+use cosmwasm_std::{from_json, to_json_vec, Addr, Binary};
 
-use cosmwasm_std::Uint128;
+use cosmwasm_std::{Timestamp, Uint128, Uint256};
+use cw_ownable::{cw_ownable_execute, cw_ownable_query};
 
-use crate::packet::Packet;
+use crate::packet::{
+    IcaPacket, Ics20PacketData, Ics20Transfer, Packet, PacketClass, ReceiverClass,
+};
+use crate::state::{
+    ApproverRole, Config, CounterpartyPath, CounterpartyState, DayPartition, Flow, FlowType,
+    KeySpec, LastPacket, PathConfig, PathFilter, PercentageLimit, Probation, Quota, QuotaMode,
+    RateLimit, ResetInfo, ResetMode, Streak, TenantConfig, NANOS_PER_SECOND, SECONDS_PER_DAY,
+};
+use crate::ContractError;
 
 // PathMsg contains a channel_id and denom to represent a unique identifier within ibc-go, and a list of rate limit quotas
 #[cw_serde]
@@ -30,24 +39,179 @@ impl PathMsg {
     }
 }
 
-// QuotaMsg represents a rate limiting Quota when sent as a wasm msg
+// QuotaMsg represents a rate limiting Quota when sent as a wasm msg. A blocked
+// quota rejects every transfer and its max values are ignored. Zero max values
+// are only accepted on blocked quotas. Combined quotas only use the max_total of
+// their mode, and percentage quotas their send and recv fractions. The send and
+// recv durations override the shared duration for their direction. Durations are
+// in seconds, duration_nanos replaces the shared one for sub-second periods.
+// Partitions must cover the whole UTC day, in order and without overlaps
 #[cw_serde]
 pub struct QuotaMsg {
     pub name: String,
     pub duration: u64,
-    pub max_send: Uint128,
-    pub max_receive: Uint128,
+    #[serde(default)]
+    pub duration_nanos: Option<u64>,
+    pub max_send: Uint256,
+    pub max_receive: Uint256,
+    #[serde(default)]
+    pub blocked: bool,
+    #[serde(default)]
+    pub mode: QuotaMode,
+    #[serde(default)]
+    pub percentage: Option<PercentageLimit>,
+    #[serde(default)]
+    pub send_duration: Option<u64>,
+    #[serde(default)]
+    pub recv_duration: Option<u64>,
+    #[serde(default)]
+    pub partitions: Vec<DayPartition>,
 }
 
 impl QuotaMsg {
-    pub fn new(name: &str, seconds: u64, send: Uint128, recv: Uint128) -> Self {
+    pub fn new(name: &str, seconds: u64, send: Uint256, recv: Uint256) -> Self {
         QuotaMsg {
             name: name.to_string(),
             duration: seconds,
+            duration_nanos: None,
             max_send: send,
             max_receive: recv,
+            blocked: false,
+            mode: QuotaMode::Netted,
+            percentage: None,
+            send_duration: None,
+            recv_duration: None,
+            partitions: vec![],
         }
     }
+
+    pub fn combined(name: &str, seconds: u64, max_total: Uint256) -> Self {
+        QuotaMsg {
+            name: name.to_string(),
+            duration: seconds,
+            duration_nanos: None,
+            max_send: Uint256::zero(),
+            max_receive: Uint256::zero(),
+            blocked: false,
+            mode: QuotaMode::Combined { max_total },
+            percentage: None,
+            send_duration: None,
+            recv_duration: None,
+            partitions: vec![],
+        }
+    }
+
+    pub fn percentage(name: &str, seconds: u64, percentage: PercentageLimit) -> Self {
+        QuotaMsg {
+            name: name.to_string(),
+            duration: seconds,
+            duration_nanos: None,
+            max_send: Uint256::zero(),
+            max_receive: Uint256::zero(),
+            blocked: false,
+            mode: QuotaMode::Netted,
+            percentage: Some(percentage),
+            send_duration: None,
+            recv_duration: None,
+            partitions: vec![],
+        }
+    }
+
+    pub fn blocked(name: &str, seconds: u64) -> Self {
+        QuotaMsg {
+            name: name.to_string(),
+            duration: seconds,
+            duration_nanos: None,
+            max_send: Uint256::zero(),
+            max_receive: Uint256::zero(),
+            blocked: true,
+            mode: QuotaMode::Netted,
+            percentage: None,
+            send_duration: None,
+            recv_duration: None,
+            partitions: vec![],
+        }
+    }
+
+    pub fn with_durations(mut self, send: u64, recv: u64) -> Self {
+        self.send_duration = Some(send);
+        self.recv_duration = Some(recv);
+        self
+    }
+
+    pub fn with_duration_nanos(mut self, nanos: u64) -> Self {
+        self.duration_nanos = Some(nanos);
+        self
+    }
+
+    pub fn with_partitions(mut self, partitions: Vec<DayPartition>) -> Self {
+        self.partitions = partitions;
+        self
+    }
+
+    /// The shared duration in nanoseconds
+    pub fn duration_nanos(&self) -> u64 {
+        self.duration_nanos
+            .unwrap_or(self.duration.saturating_mul(NANOS_PER_SECOND))
+    }
+
+    pub fn validate(&self) -> Result<(), ContractError> {
+        let zero = match (&self.mode, &self.percentage) {
+            (QuotaMode::Combined { max_total }, _) => max_total.is_zero(),
+            (QuotaMode::Netted, Some(percentage)) => {
+                percentage.send.is_zero() || percentage.recv.is_zero()
+            }
+            (QuotaMode::Netted, None) => self.max_send.is_zero() || self.max_receive.is_zero(),
+        };
+        if !self.blocked && zero {
+            return Err(ContractError::ZeroQuota {
+                name: self.name.clone(),
+            });
+        }
+        self.validate_partitions()
+    }
+
+    // Each partition starts where the previous one ended, from midnight to
+    // midnight
+    fn validate_partitions(&self) -> Result<(), ContractError> {
+        if self.partitions.is_empty() {
+            return Ok(());
+        }
+        let mut covered = 0;
+        for partition in &self.partitions {
+            if partition.start != covered
+                || partition.end <= partition.start
+                || partition.share_bps > 10_000
+            {
+                return Err(ContractError::InvalidDayPartitions {
+                    name: self.name.clone(),
+                });
+            }
+            covered = partition.end;
+        }
+        if u64::from(covered) != SECONDS_PER_DAY {
+            return Err(ContractError::InvalidDayPartitions {
+                name: self.name.clone(),
+            });
+        }
+        Ok(())
+    }
+}
+
+// PathId identifies a path of any contract
+#[cw_serde]
+pub struct PathId {
+    pub contract: Addr,
+    pub channel_id: String,
+    pub denom: String,
+}
+
+// ResetScope selects the paths of the calling contract affected by a bulk reset
+#[cw_serde]
+pub enum ResetScope {
+    Contract,
+    Channel(String),
+    Path { channel_id: String, denom: String },
 }
 
 /// Initialize the contract with the address of the IBC module and any existing channels.
@@ -55,47 +219,1335 @@ impl QuotaMsg {
 #[cw_serde]
 pub struct InstantiateMsg {
     pub paths: Vec<PathMsg>,
+    /// The dimensions paths are keyed by. It can't be changed later, as the
+    /// existing keys would no longer match. Defaults to channel and denom
+    #[serde(default)]
+    pub key_spec: Option<KeySpec>,
+    /// Current-period usage carried over from another limiter, applied to the
+    /// quotas of `paths` once they are created
+    #[serde(default)]
+    pub seed_flows: Vec<SeedFlow>,
+}
+
+/// SeedFlow sets the usage of a quota of a path, and the end of its current
+/// period, which can't be further than the duration of the quota
+#[cw_serde]
+pub struct SeedFlow {
+    pub contract: Addr,
+    pub channel_id: String,
+    pub denom: String,
+    pub quota_id: String,
+    pub inflow: Uint256,
+    pub outflow: Uint256,
+    pub period_end: Timestamp,
 }
 
 /// The caller (IBC module) is responsible for correctly calculating the funds
 /// being sent through the channel
+///
+/// Management messages take an optional idempotency_key. A key already used by
+/// the same sender turns the message into a no-op, so retried transactions
+/// aren't applied twice
+#[allow(clippy::large_enum_variant)]
+#[cw_ownable_execute]
 #[cw_serde]
 pub enum ExecuteMsg {
     AddPath {
         channel_id: String,
         denom: String,
         quotas: Vec<QuotaMsg>,
+        #[serde(default)]
+        idempotency_key: Option<String>,
     },
     RemovePath {
         channel_id: String,
         denom: String,
+        #[serde(default)]
+        idempotency_key: Option<String>,
     },
     ResetPathQuota {
         channel_id: String,
         denom: String,
         quota_id: String,
+        memo: Option<String>,
+        #[serde(default)]
+        mode: ResetMode,
+        #[serde(default)]
+        idempotency_key: Option<String>,
+    },
+    /// Renames a quota of a path of the caller, keeping its flow and history
+    RenameQuota {
+        channel_id: String,
+        denom: String,
+        from: String,
+        to: String,
+        #[serde(default)]
+        idempotency_key: Option<String>,
+    },
+    ResetAllQuotas {
+        scope: ResetScope,
+        memo: Option<String>,
+        #[serde(default)]
+        idempotency_key: Option<String>,
     },
     SendPacket {
         packet: Packet,
     },
+    /// Accounts a packet as a send or a receive depending on which of its
+    /// endpoints is the local channel of its path
+    ProcessPacket {
+        packet: Packet,
+    },
+    SendIcaPacket {
+        packet: IcaPacket,
+    },
+    /// Accounts a transfer of a cw-ics20 contract, in the shape of its
+    /// transfer hook, on the path of its local denom. Receives are accounted
+    /// as RecvPacket and the rest as SendPacket
+    Ics20TransferHook(Ics20Transfer),
     RecvPacket {
         packet: Packet,
     },
+    /// Accounts an ICS-20 packet as relayed, with its data still undecoded, as
+    /// SendPacket. Amounts that don't fit in bank coins are refused
+    SendIcs20Packet {
+        channel_id: String,
+        sequence: Option<u64>,
+        data: Ics20PacketData,
+    },
+    /// Accounts an ICS-20 packet as relayed as RecvPacket
+    RecvIcs20Packet {
+        channel_id: String,
+        sequence: Option<u64>,
+        data: Ics20PacketData,
+    },
     UndoSend {
         packet: Packet,
     },
+    AckPacket {
+        packet: Packet,
+        success: bool,
+    },
+    SetPathConfig {
+        channel_id: String,
+        denom: String,
+        config: PathConfig,
+        #[serde(default)]
+        idempotency_key: Option<String>,
+    },
+    /// Moves the quotas, flows, history, configuration and pause of a path to
+    /// a new (channel, denom), i.e. after a channel upgrade, along with its
+    /// stats, pre-approvals and reservations. The sends still pending stay
+    /// with the old channel. Queries against the old path are redirected to
+    /// the new one
+    MigratePath {
+        from: (String, String),
+        to: (String, String),
+        #[serde(default)]
+        idempotency_key: Option<String>,
+    },
+    /// Sets the quotas applied on top of the path quotas to a class of
+    /// packets. An empty list removes them
+    SetClassQuotas {
+        channel_id: String,
+        denom: String,
+        class: PacketClass,
+        quotas: Vec<QuotaMsg>,
+        #[serde(default)]
+        idempotency_key: Option<String>,
+    },
+    /// Sets the quotas applied to the transfers carrying a tag instead of the
+    /// path quotas. An empty list removes them
+    SetTagQuotas {
+        channel_id: String,
+        denom: String,
+        tag: String,
+        quotas: Vec<QuotaMsg>,
+        #[serde(default)]
+        idempotency_key: Option<String>,
+    },
+    /// Sets the quotas capping the flow of a denom over all the paths of the
+    /// sender linked to a counterparty chain together. An empty list removes
+    /// them
+    SetChainQuotas {
+        chain_id: String,
+        denom: String,
+        quotas: Vec<QuotaMsg>,
+        #[serde(default)]
+        idempotency_key: Option<String>,
+    },
+    /// Sets the quotas applied on top of the path quotas to the packets sent
+    /// to a class of receivers. An empty list removes them
+    SetReceiverClassQuotas {
+        channel_id: String,
+        denom: String,
+        class: ReceiverClass,
+        quotas: Vec<QuotaMsg>,
+        #[serde(default)]
+        idempotency_key: Option<String>,
+    },
+    /// Registers the class of a receiver address on the counterparty chains.
+    /// Only the owner can register receivers
+    SetReceiverClass {
+        address: String,
+        class: ReceiverClass,
+    },
+    /// Sets the quotas used by the denoms of a channel that don't have their
+    /// own path. An empty list removes them
+    SetChannelDefaultQuotas {
+        channel_id: String,
+        quotas: Vec<QuotaMsg>,
+        #[serde(default)]
+        idempotency_key: Option<String>,
+    },
+    /// Restricts the packets of the caller on a channel to a list of denoms.
+    /// An empty list lifts the restriction
+    SetChannelAssets {
+        channel_id: String,
+        denoms: Vec<String>,
+    },
+    /// Rolls over the expired flows of the given paths, archiving them in the
+    /// history. Without paths, walks through the next `limit` paths.
+    /// Callable by anyone
+    Tick {
+        paths: Option<Vec<PathId>>,
+        limit: Option<u32>,
+    },
+    /// Moves the next `limit` entries of a migration that didn't fit in the
+    /// migrate call. Packets on paths not moved yet are rejected until then.
+    /// Callable by anyone
+    ContinueMigration {
+        limit: Option<u32>,
+    },
+    /// Replaces the contract config. Only callable by the owner
+    UpdateConfig {
+        config: Config,
+        #[serde(default)]
+        idempotency_key: Option<String>,
+    },
+    /// Replaces the settings a bridge contract overrides, or removes them when
+    /// unset. Only callable by the owner
+    SetTenantConfig {
+        contract: Addr,
+        config: Option<TenantConfig>,
+    },
+    /// Drops a quarantined tracker entry. Only callable by the owner
+    PurgeQuarantined {
+        contract: Addr,
+        channel_id: String,
+        denom: String,
+    },
+    /// Drops the stats of the denoms of a channel that have no quotas of their
+    /// own, making room under max_denoms_per_channel. At most 300 denoms are
+    /// dropped per call. Only callable by the owner
+    PruneChannelDenoms {
+        contract: Addr,
+        channel_id: String,
+        limit: Option<u32>,
+    },
+    /// Reports the escrow balance of a path. Only callable by the escrow
+    /// querier of the config
+    EscrowBalanceCallback {
+        contract: Addr,
+        channel_id: String,
+        denom: String,
+        balance: Uint128,
+    },
+    /// Asks the escrow querier for the balance of a path now, rather than at
+    /// the next period rollover, to replace a stale channel value. Callable by
+    /// anyone
+    RefreshChannelValue {
+        contract: Addr,
+        channel_id: String,
+        denom: String,
+    },
+    /// Proves the monitoring is up, see `HeartbeatPolicy`. Only callable by the
+    /// keeper of the heartbeat policy
+    Heartbeat {},
+    /// Allows or disallows a bridge to send packets before having paths of its
+    /// own. Only callable by the owner
+    RegisterBridge {
+        bridge: String,
+        registered: bool,
+    },
+    /// Halts or resumes the processing of packets. Only callable by the owner.
+    /// A pause with `auto_unpause_at` is lifted by the first packet processed
+    /// from then on
+    SetPaused {
+        paused: bool,
+        #[serde(default)]
+        auto_unpause_at: Option<Timestamp>,
+    },
+    /// Halts or resumes the processing of packets in a single direction. Only
+    /// callable by the owner
+    SetDirectionPaused {
+        direction: FlowType,
+        paused: bool,
+    },
+    /// Halts or resumes the processing of the packets of a bridge contract.
+    /// Only callable by the owner
+    SetContractPaused {
+        contract: Addr,
+        paused: bool,
+    },
+    /// Halts or resumes the processing of the packets of a bridge contract on
+    /// a channel. Only callable by the owner
+    SetChannelPaused {
+        contract: Addr,
+        channel_id: String,
+        paused: bool,
+    },
+    /// Halts or resumes the processing of the packets of a path. Only callable
+    /// by the owner
+    SetPathPaused {
+        path: PathId,
+        paused: bool,
+    },
+    /// Sets the fee of a path, in basis points of the accepted transfers. Only
+    /// callable by the owner
+    SetPathFee {
+        path: PathId,
+        fee_bps: u16,
+    },
+    /// Pays the fees owed by the calling bridge with the attached funds
+    PayFees {},
+    /// Sends the fees of `denom` collected from a bridge to the recipient, or
+    /// to the owner. Only callable by the owner
+    WithdrawFees {
+        contract: Addr,
+        denom: String,
+        recipient: Option<String>,
+    },
+    /// Sends what the contract holds of `denom` to `to`, except the funds
+    /// escrowed for paths and the fees collected, recovering tokens sent to
+    /// the contract by mistake. Cw20 tokens are named `cw20:<contract>`. Only
+    /// callable by the owner
+    Sweep {
+        denom: String,
+        to: String,
+    },
+    /// Enters or leaves maintenance, during which only the owner can execute
+    /// messages. Only callable by the owner
+    SetMaintenance {
+        enabled: bool,
+    },
+    /// Hands the ownership to a set of owners of which `threshold` must confirm
+    /// every management message. The contract becomes its own owner, so owner
+    /// messages only go through proposals from then on. Only callable by the
+    /// owner
+    SetMultisig {
+        owners: Vec<String>,
+        threshold: u32,
+    },
+    /// Proposes a message for the contract to execute as its own owner. The
+    /// proposal counts as confirmed by the proposer. Only callable by the
+    /// multisig owners
+    Propose {
+        msg: Box<ExecuteMsg>,
+        expires_in: u64,
+    },
+    /// Only callable by the multisig owners
+    Confirm {
+        id: u64,
+    },
+    /// Executes a proposal with enough confirmations
+    ExecuteProposal {
+        id: u64,
+    },
+    /// Lets one packet of exactly `amount` on the path skip its quotas during
+    /// the next `window` seconds. Only callable by the owner, or by the
+    /// multisig owners with the Multisig role
+    PreApproveTransfer {
+        path: PathId,
+        amount: Uint128,
+        window: u64,
+        approver_role: ApproverRole,
+    },
+    /// Earmarks `amount` of the capacity of the path in the direction for the
+    /// next `window` seconds. Other packets can't use it, and the packets sent
+    /// by the beneficiary draw from it. Only callable by the owner, or by the
+    /// multisig owners with the Multisig role
+    ReserveCapacity {
+        path: PathId,
+        direction: FlowType,
+        amount: Uint128,
+        window: u64,
+        beneficiary: String,
+        role: ApproverRole,
+    },
+    /// Records the usage of the counterparty path, as read on the counterparty
+    /// chain at `reported_at`. Only callable by the relayer of the counterparty
+    /// link of the path, and older reports than the last one are refused
+    SyncCounterpartyState {
+        path: PathId,
+        usage: Vec<QuotaUsage>,
+        reported_at: Timestamp,
+    },
+    /// Saves a filter for GetFilteredState under `filter_id`. Callable by
+    /// anyone, but only the caller who first saved a filter can replace it
+    SaveFilter {
+        filter_id: String,
+        filter: PathFilter,
+    },
+    /// Removes a filter. Only callable by the caller who saved it or the owner
+    RemoveFilter {
+        filter_id: String,
+    },
+    /// Rebuilds the in flight value of the next `limit` paths from their
+    /// pending sends. The data of the response is the path to continue after,
+    /// if any. Only callable by the owner
+    RepairInvariants {
+        start_after: Option<(Addr, String, String)>,
+        limit: Option<u32>,
+    },
+    /// Removes what is stored about `address` as the sender of transfers,
+    /// which is the rejections recorded for it, from the next `limit` paths
+    /// having rejections. Each path it was removed from gets an event. The
+    /// data of the response is the path to continue after, if any. Only
+    /// callable by the owner
+    PurgeSenderData {
+        address: String,
+        #[serde(default)]
+        start_after: Option<(Addr, String, String)>,
+        #[serde(default)]
+        limit: Option<u32>,
+    },
+    /// Overrides the contract time, or goes back to the block time if unset.
+    /// Only callable by the owner and only available for testing builds
+    #[cfg(feature = "mock-time")]
+    SetMockTime {
+        time: Option<Timestamp>,
+    },
+}
+
+impl ExecuteMsg {
+    pub fn idempotency_key(&self) -> Option<&str> {
+        match self {
+            ExecuteMsg::AddPath {
+                idempotency_key, ..
+            }
+            | ExecuteMsg::RemovePath {
+                idempotency_key, ..
+            }
+            | ExecuteMsg::ResetPathQuota {
+                idempotency_key, ..
+            }
+            | ExecuteMsg::RenameQuota {
+                idempotency_key, ..
+            }
+            | ExecuteMsg::ResetAllQuotas {
+                idempotency_key, ..
+            }
+            | ExecuteMsg::SetPathConfig {
+                idempotency_key, ..
+            }
+            | ExecuteMsg::MigratePath {
+                idempotency_key, ..
+            }
+            | ExecuteMsg::SetClassQuotas {
+                idempotency_key, ..
+            }
+            | ExecuteMsg::SetReceiverClassQuotas {
+                idempotency_key, ..
+            }
+            | ExecuteMsg::SetTagQuotas {
+                idempotency_key, ..
+            }
+            | ExecuteMsg::SetChainQuotas {
+                idempotency_key, ..
+            }
+            | ExecuteMsg::SetChannelDefaultQuotas {
+                idempotency_key, ..
+            }
+            | ExecuteMsg::UpdateConfig {
+                idempotency_key, ..
+            } => idempotency_key.as_deref(),
+            _ => None,
+        }
+    }
 }
 
+/// SudoMsg is used by the chain (i.e. governance) to manage paths of any
+/// contract. These mirror the execute messages, but the contract owning the
+/// path has to be passed explicitly as there's no sender
+#[cw_serde]
+pub enum SudoMsg {
+    AddPath {
+        contract: Addr,
+        channel_id: String,
+        denom: String,
+        quotas: Vec<QuotaMsg>,
+    },
+    RemovePath {
+        contract: Addr,
+        channel_id: String,
+        denom: String,
+    },
+    ResetPathQuota {
+        contract: Addr,
+        channel_id: String,
+        denom: String,
+        quota_id: String,
+        memo: Option<String>,
+        #[serde(default)]
+        mode: ResetMode,
+    },
+    SetPaused {
+        paused: bool,
+        #[serde(default)]
+        auto_unpause_at: Option<Timestamp>,
+    },
+}
+
+#[cw_ownable_query]
 #[cw_serde]
 #[derive(QueryResponses)]
 pub enum QueryMsg {
-    #[returns(Vec<crate::state::RateLimit>)]
+    #[returns(Versioned<Vec<RateLimitResponse>>)]
     GetQuotas {
         contract: Addr,
         channel_id: String,
         denom: String,
     },
+    /// The quotas of many paths, in the order of `paths`. Paths without
+    /// quotas are None. At most QUOTAS_BATCH_MAX_PATHS paths per call
+    #[returns(Versioned<Vec<Option<Vec<RateLimitResponse>>>>)]
+    GetQuotasBatch { paths: Vec<PathId> },
+    /// Aggregated current-period usage across all the paths of a contract.
+    /// Totals cover the first FILTER_SCAN_LIMIT paths, the per-path breakdown
+    /// is paginated.
+    #[returns(Versioned<ContractUsageResponse>)]
+    GetContractUsage {
+        contract: Addr,
+        start_after: Option<(String, String)>,
+        limit: Option<u32>,
+    },
+    #[returns(Versioned<Config>)]
+    GetConfig {},
+    /// The last heartbeat and whether sends are handled as missing it
+    #[returns(Versioned<HeartbeatResponse>)]
+    GetHeartbeat {},
+    /// The overrides of a bridge contract and the config they result in
+    #[returns(Versioned<TenantConfigResponse>)]
+    GetTenantConfig { contract: Addr },
+    #[returns(Versioned<crate::state::KeySpec>)]
+    GetKeySpec {},
+    #[returns(Versioned<Vec<RateLimitResponse>>)]
+    GetClassQuotas {
+        contract: Addr,
+        channel_id: String,
+        denom: String,
+        class: PacketClass,
+    },
+    #[returns(Versioned<Vec<RateLimitResponse>>)]
+    GetTagQuotas {
+        contract: Addr,
+        channel_id: String,
+        denom: String,
+        tag: String,
+    },
+    #[returns(Versioned<Vec<RateLimitResponse>>)]
+    GetChainQuotas {
+        contract: Addr,
+        chain_id: String,
+        denom: String,
+    },
+    #[returns(Versioned<Vec<RateLimitResponse>>)]
+    GetReceiverClassQuotas {
+        contract: Addr,
+        channel_id: String,
+        denom: String,
+        class: ReceiverClass,
+    },
+    /// The registered class of a receiver, accounts if it isn't registered
+    #[returns(Versioned<ReceiverClass>)]
+    GetReceiverClass { address: String },
+    /// The changes made to a path, oldest first
+    #[returns(Versioned<Vec<crate::state::AuditEntry>>)]
+    GetAuditLog {
+        contract: Addr,
+        channel_id: String,
+        denom: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// The archived periods of a path, oldest first
+    #[returns(Versioned<Vec<crate::state::HistoryEntry>>)]
+    GetHistory {
+        contract: Addr,
+        channel_id: String,
+        denom: String,
+    },
+    /// An archived period of a path with the packet stats of the period. Only
+    /// the last periods are kept, see GetHistory
+    #[returns(Versioned<PeriodReport>)]
+    GetPeriodReport {
+        contract: Addr,
+        channel_id: String,
+        denom: String,
+        period_index: u64,
+    },
+    /// The rate limits stored under a raw RATE_LIMIT_TRACKERS key, namespace
+    /// included, as found in state dumps
+    #[returns(Versioned<RawQuotasResponse>)]
+    GetQuotasRaw { key_base64: Binary },
+    /// What each quota of a path still allows in each direction. Rounded down
+    /// to the capacity buckets of the config
+    #[returns(Versioned<Vec<RemainingCapacity>>)]
+    GetRemainingCapacity {
+        contract: Addr,
+        channel_id: String,
+        denom: String,
+    },
+    /// The last rejections of the path, newest first
+    #[returns(Versioned<Vec<crate::state::Rejection>>)]
+    GetRecentRejections { path: PathId, limit: Option<u32> },
+    /// The current streak of rejected sends of the path, if any
+    #[returns(Versioned<Option<crate::state::Streak>>)]
+    GetStreak { path: PathId },
+    /// The config and live state of a path in a single response
+    #[returns(Versioned<PathOverviewResponse>)]
+    GetPathOverview {
+        contract: Addr,
+        channel_id: String,
+        denom: String,
+    },
+    /// The usage of a path next to the last synced usage of its counterparty
+    #[returns(Versioned<ChannelPairResponse>)]
+    GetChannelPair {
+        contract: Addr,
+        channel_id: String,
+        denom: String,
+    },
+    /// The usage of the quota named `quota_name` on every path that has one.
+    /// Pages scan a bounded number of paths, like GetFilteredState
+    #[returns(Versioned<QuotaAcrossPathsResponse>)]
+    GetQuotaAcrossPaths {
+        quota_name: String,
+        start_after: Option<(Addr, String, String)>,
+        limit: Option<u32>,
+    },
+    /// What an AddPath of `path_msg` would change, without applying it. Trackers
+    /// are replaced as a whole, so the current flows of the path are reset
+    #[returns(Versioned<PathChangePreview>)]
+    PreviewPathChange { path_msg: PathMsg },
+    /// Replays `transfers`, in chronological order, against new trackers of
+    /// `proposed_quotas` using the settings of the path, and reports which
+    /// would be rejected. Nothing is stored
+    #[returns(Versioned<SimulationResponse>)]
+    SimulateSequence {
+        path: PathId,
+        transfers: Vec<SimulatedTransfer>,
+        proposed_quotas: Vec<QuotaMsg>,
+    },
+    #[returns(Versioned<crate::state::FeeAccount>)]
+    GetFees { contract: Addr, denom: String },
+    /// The entries of the next page that break an invariant of the state.
+    /// Paths are checked first, then the pending sends
+    #[returns(Versioned<InvariantsResponse>)]
+    CheckInvariants {
+        start_after: Option<InvariantCursor>,
+        limit: Option<u32>,
+    },
+    #[returns(Versioned<crate::state::StoredFilter>)]
+    GetFilter { filter_id: String },
+    /// The usage of the paths matching a saved filter. A page scans a bounded
+    /// number of paths, so it can hold fewer than `limit` paths while
+    /// `next_start_after` is still set
+    #[returns(Versioned<FilteredStateResponse>)]
+    GetFilteredState {
+        filter_id: String,
+        start_after: Option<(Addr, String, String)>,
+        limit: Option<u32>,
+    },
+    /// The average flow per period of each quota of a path over its last
+    /// `lookback_periods` archived periods, at most the length of the history.
+    /// The idle time between periods counts as periods without flow
+    #[returns(Versioned<Vec<AverageFlow>>)]
+    GetAverageFlow {
+        path: PathId,
+        lookback_periods: Option<u32>,
+    },
+    /// The value of the sends of a path that were not acknowledged or undone
+    /// yet. Only tracked for paths with a max_in_flight configured
+    #[returns(Versioned<Uint256>)]
+    GetInFlight {
+        contract: Addr,
+        channel_id: String,
+        denom: String,
+    },
+    #[returns(Versioned<Vec<QuotaMsg>>)]
+    GetChannelDefaultQuotas { contract: Addr, channel_id: String },
+    /// The denoms allowed on a channel of a contract. Empty when any is
+    #[returns(Versioned<Vec<String>>)]
+    GetChannelAssets { contract: Addr, channel_id: String },
+    /// Lists the tracker entries that were quarantined because they could not
+    /// be deserialized
+    #[returns(Versioned<Vec<QuarantinedEntry>>)]
+    GetQuarantined {
+        start_after: Option<(Addr, String, String)>,
+        limit: Option<u32>,
+    },
+    /// Checks whether a send would currently be accepted, without accounting
+    /// it. Meant for contracts to query before composing a bridge message
+    #[returns(Versioned<CanSendResponse>)]
+    CanSend {
+        contract: Addr,
+        channel_id: String,
+        denom: String,
+        amount: Uint128,
+    },
+    /// The paths whose trackers changed after the given change sequence, with
+    /// their current trackers. Meant for indexers syncing incrementally
+    #[returns(Versioned<ChangesResponse>)]
+    ChangesSince { seq: u64, limit: Option<u32> },
+    #[returns(Versioned<Option<crate::state::Multisig>>)]
+    GetMultisig {},
+    #[returns(Versioned<crate::state::Proposal>)]
+    GetProposal { id: u64 },
+    #[returns(Versioned<Vec<crate::state::Proposal>>)]
+    ListProposals {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// The transfers pre-approved on the path, consumed or not
+    #[returns(Versioned<Vec<crate::state::PreApproval>>)]
+    ListPreApprovals {
+        path: PathId,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// The capacity reservations of the path, expired and used up ones included
+    #[returns(Versioned<Vec<crate::state::Reservation>>)]
+    ListReservations {
+        path: PathId,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Flat list of counters and gauges meant to be scraped by exporters
+    #[returns(Versioned<Vec<Metric>>)]
+    Metrics {},
+    /// Describes the namespaces and key encodings of the contract storage, for
+    /// tools reading the raw state
+    #[returns(Versioned<StorageLayoutResponse>)]
+    StorageLayout {},
+    /// Reports the misconfigurations of a path and what keeps its packets
+    /// from being processed, to sanity-check the configuration after changes
+    #[returns(Versioned<DiagnoseResponse>)]
+    Diagnose {
+        contract: Addr,
+        channel_id: String,
+        denom: String,
+    },
+}
+
+/// Version of the shape of the query responses wrapped in `Versioned`. Bump it
+/// on any breaking change to them
+pub const API_VERSION: u32 = 3;
+
+/// Versioned is the envelope of the query responses, letting off-chain
+/// consumers detect breaking changes to the shape of the data after migrations
+#[cw_serde]
+pub struct Versioned<T> {
+    pub api_version: u32,
+    pub data: T,
+}
+
+#[cw_serde]
+#[derive(Default)]
+pub struct MigrateMsg {
+    /// Leaves the contract in maintenance once migrated, for migrations
+    /// continued over several transactions. The owner clears it with
+    /// SetMaintenance
+    #[serde(default)]
+    pub keep_maintenance: bool,
+}
+
+// QuotaUsage is the current-period usage and capacity of a quota. Expired flows
+// are reported as zero usage, as they will be reset on the next transfer. The
+// capacity is the one at the time of the query, partitions and probation
+// applied
+#[cw_serde]
+pub struct QuotaUsage {
+    pub name: String,
+    pub inflow: Uint256,
+    pub outflow: Uint256,
+    pub max_in: Uint256,
+    pub max_out: Uint256,
+    pub period_end: Option<Timestamp>,
+    /// Only set on quotas with a receive window of their own
+    #[serde(default)]
+    pub recv_period_end: Option<Timestamp>,
+}
+
+// PathChange is a change to the trackers of a path. The trackers are the current
+// ones, not the ones written at seq, and are empty once the path is removed
+#[cw_serde]
+pub struct PathChange {
+    pub seq: u64,
+    pub contract: Addr,
+    pub channel_id: String,
+    pub denom: String,
+    pub trackers: Vec<RateLimitResponse>,
+}
+
+// ChangesResponse lists the changes after the requested sequence. Changes older
+// than oldest_seq were dropped from the log, so indexers behind it must resync
+#[cw_serde]
+pub struct ChangesResponse {
+    pub last_seq: u64,
+    pub oldest_seq: u64,
+    pub changes: Vec<PathChange>,
+}
+
+#[cw_serde]
+pub struct QuarantinedEntry {
+    pub contract: Addr,
+    pub channel_id: String,
+    pub denom: String,
+    pub raw: Binary,
+}
+
+#[cw_serde]
+pub enum MetricKind {
+    Counter,
+    Gauge,
+}
+
+#[cw_serde]
+pub struct Metric {
+    pub name: String,
+    pub kind: MetricKind,
+    pub value: u64,
+}
+
+#[cw_serde]
+pub enum StorageKind {
+    Item,
+    Map,
+}
+
+/// StorageEntry describes a single Item or Map. key and value are the Rust
+/// types, key parts being listed in order for maps
+#[cw_serde]
+pub struct StorageEntry {
+    pub name: String,
+    pub namespace: String,
+    pub kind: StorageKind,
+    pub key: Option<String>,
+    pub value: String,
+}
+
+#[cw_serde]
+pub struct StorageLayoutResponse {
+    pub key_encoding: String,
+    pub entries: Vec<StorageEntry>,
+    /// (old, new) namespaces whose entries were moved on migration
+    pub legacy_namespaces: Vec<(String, String)>,
+}
+
+#[cw_serde]
+pub struct PathUsage {
+    pub channel_id: String,
+    pub denom: String,
+    pub quotas: Vec<QuotaUsage>,
+}
+
+// ContractUsageResponse sums the usage of the paths of a contract grouped by
+// quota name. The period_end of the totals is not meaningful and left empty.
+// Contracts with more paths than a single call can scan get partial totals,
+// with totals_complete unset
+#[cw_serde]
+pub struct ContractUsageResponse {
+    pub contract: Addr,
+    pub totals: Vec<QuotaUsage>,
+    pub totals_complete: bool,
+    pub paths: Vec<PathUsage>,
+}
+
+/// PolicyQueryMsg is the query sent to the configured policy contract for
+/// every packet. current_flows are the rate limits of the path before the
+/// packet is applied
+#[cw_serde]
+pub enum PolicyQueryMsg {
+    CheckPolicy {
+        packet: Packet,
+        direction: FlowType,
+        current_flows: Vec<RateLimit>,
+    },
+}
+
+/// RejectHookMsg is sent to the on_reject_contract of a path when one of its
+/// sends is rejected by a quota. It's fire-and-forget: a failing hook doesn't
+/// revert the rejection. It runs once the rejection has been recorded, so a
+/// hook sending the packet again is checked against the same flows
+#[cw_serde]
+pub enum RejectHookMsg {
+    RateLimitRejected {
+        packet: Packet,
+        exceeded: RateLimitExceededData,
+    },
+}
+
+/// AlertMsg is sent to the alert contract of the escalation policy of a path
+/// when its streak of rejected sends escalates
+#[cw_serde]
+pub enum AlertMsg {
+    Escalation {
+        contract: Addr,
+        channel_id: String,
+        denom: String,
+        streak: Streak,
+        frozen: bool,
+    },
+}
+
+/// EscrowQuerierMsg is sent to the configured escrow querier when a period of
+/// a percentage quota rolls over. The querier is expected to answer with
+/// `ExecuteMsg::EscrowBalanceCallback`
+#[cw_serde]
+pub enum EscrowQuerierMsg {
+    QueryEscrowBalance {
+        contract: Addr,
+        channel_id: String,
+        denom: String,
+    },
+}
+
+/// HeartbeatResponse is the last heartbeat of the keeper, unset without a
+/// heartbeat policy, and whether it's too old
+#[cw_serde]
+pub struct HeartbeatResponse {
+    pub last_heartbeat: Option<Timestamp>,
+    pub missed: bool,
+}
+
+/// RemainingCapacity is the largest transfer a quota currently allows in each
+/// direction, considering expired flows rolled over
+#[cw_serde]
+pub struct RemainingCapacity {
+    pub quota: String,
+    pub send: Uint256,
+    pub recv: Uint256,
+}
+
+/// PathOverviewResponse gathers what front-ends show about a path: its quotas
+/// with their live usage, the pauses and freezes holding its packets back, its
+/// config and the last packet it accepted
+#[cw_serde]
+pub struct PathOverviewResponse {
+    pub contract: Addr,
+    pub channel_id: String,
+    pub denom: String,
+    pub quotas: Vec<QuotaOverview>,
+    pub paused: bool,
+    pub paused_directions: Vec<FlowType>,
+    pub maintenance: bool,
+    pub quarantined: bool,
+    pub config: PathConfig,
+    pub streak: Option<Streak>,
+    #[serde(default)]
+    pub last_packet: Option<LastPacket>,
+}
+
+/// ChannelPairResponse puts the usage of a path next to the one of its
+/// counterparty path, if linked and synced
+#[cw_serde]
+pub struct ChannelPairResponse {
+    pub contract: Addr,
+    pub channel_id: String,
+    pub denom: String,
+    pub usage: Vec<QuotaUsage>,
+    pub counterparty: Option<CounterpartyPath>,
+    pub counterparty_state: Option<CounterpartyState>,
+}
+
+/// FilteredPath is the usage of a path matching a filter
+#[cw_serde]
+pub struct FilteredPath {
+    pub contract: Addr,
+    pub channel_id: String,
+    pub denom: String,
+    pub tags: Vec<String>,
+    pub usage: Vec<QuotaUsage>,
+}
+
+/// FilteredStateResponse is a page of the paths matching a filter. Paging is
+/// over when next_start_after is None
+#[cw_serde]
+pub struct FilteredStateResponse {
+    pub paths: Vec<FilteredPath>,
+    pub next_start_after: Option<(Addr, String, String)>,
+}
+
+/// PathChangePreview is the diff between the quotas of a path and the ones of a
+/// path message. Quotas are matched by name: replaced ones are those whose
+/// definition changes. reset_flows is the usage the change would drop, and
+/// error the reason AddPath would currently fail, if any
+#[cw_serde]
+pub struct PathChangePreview {
+    pub contract: Addr,
+    pub channel_id: String,
+    pub denom: String,
+    pub new_path: bool,
+    pub added: Vec<QuotaResponse>,
+    pub replaced: Vec<QuotaChange>,
+    pub removed: Vec<QuotaResponse>,
+    pub reset_flows: Vec<QuotaUsage>,
+    pub error: Option<String>,
+}
+
+/// SimulatedTransfer is a hypothetical transfer replayed by SimulateSequence.
+/// The channel value is only needed by percentage quotas
+#[cw_serde]
+pub struct SimulatedTransfer {
+    pub direction: FlowType,
+    pub amount: Uint128,
+    pub at: Timestamp,
+    #[serde(default)]
+    pub channel_value: Option<Uint128>,
+}
+
+/// SimulatedOutcome is what the quotas did with a simulated transfer. accepted
+/// is below the amount when the path truncates transfers, and zero when the
+/// transfer is rejected. violations are the quotas the full amount exceeded
+#[cw_serde]
+pub struct SimulatedOutcome {
+    pub accepted: Uint128,
+    pub violations: Vec<QuotaViolation>,
+}
+
+/// SimulationResponse has an outcome for each simulated transfer, in order, and
+/// the usage of the quotas after the last one. error is why the proposed
+/// quotas can't be set, in which case nothing is simulated
+#[cw_serde]
+pub struct SimulationResponse {
+    pub outcomes: Vec<SimulatedOutcome>,
+    pub rejected: u32,
+    pub usage: Vec<QuotaUsage>,
+    pub error: Option<String>,
+}
+
+/// TenantConfigResponse is the overrides of a tenant, if any, and the config
+/// its paths use
+#[cw_serde]
+pub struct TenantConfigResponse {
+    pub overrides: Option<TenantConfig>,
+    pub config: Config,
+}
+
+/// InvariantCursor is the last entry checked by a page of CheckInvariants
+#[cw_serde]
+pub enum InvariantCursor {
+    Path((Addr, String, String)),
+    PendingSend((Addr, String, u64)),
+}
+
+/// InvariantViolation is an entry breaking an invariant. entry is the key of
+/// the entry, as contract/channel_id/denom for paths and
+/// contract/channel_id/sequence for pending sends
+#[cw_serde]
+pub struct InvariantViolation {
+    pub invariant: String,
+    pub entry: String,
+    pub detail: String,
+}
+
+/// InvariantsResponse is a page of the invariant check. The check is over when
+/// next is None
+#[cw_serde]
+pub struct InvariantsResponse {
+    pub violations: Vec<InvariantViolation>,
+    pub next: Option<InvariantCursor>,
+}
+
+/// QuotaChange is a quota replaced by another of the same name
+#[cw_serde]
+pub struct QuotaChange {
+    pub before: QuotaResponse,
+    pub after: QuotaResponse,
+}
+
+/// QuotaResponse is a quota as returned by the queries. Its fields are part of
+/// the API and don't follow the storage layout. Durations are in nanoseconds,
+/// the send and receive ones are always set
+#[cw_serde]
+pub struct QuotaResponse {
+    pub name: String,
+    pub max_send: Uint256,
+    pub max_recv: Uint256,
+    pub duration_nanos: u64,
+    pub send_duration_nanos: u64,
+    pub recv_duration_nanos: u64,
+    pub blocked: bool,
+    pub mode: QuotaMode,
+    pub percentage: Option<PercentageLimit>,
+    pub partitions: Vec<DayPartition>,
+    pub probation: Option<Probation>,
+}
+
+impl From<&Quota> for QuotaResponse {
+    fn from(quota: &Quota) -> Self {
+        QuotaResponse {
+            name: quota.name.clone(),
+            max_send: quota.max_send,
+            max_recv: quota.max_recv,
+            duration_nanos: quota.duration_nanos,
+            send_duration_nanos: quota.duration_on(&FlowType::Out),
+            recv_duration_nanos: quota.duration_on(&FlowType::In),
+            blocked: quota.blocked,
+            mode: quota.mode.clone(),
+            percentage: quota.percentage.clone(),
+            partitions: quota.partitions.clone(),
+            probation: quota.probation.clone(),
+        }
+    }
+}
+
+/// FlowResponse is the usage of a quota in its current period. Both directions
+/// share period_end unless the quota has a receive window of its own
+#[cw_serde]
+pub struct FlowResponse {
+    pub inflow: Uint256,
+    pub outflow: Uint256,
+    pub period_end: Timestamp,
+    pub recv_period_end: Timestamp,
+    pub period_channel_value: Option<Uint128>,
+    pub packets_in: u64,
+    pub packets_out: u64,
+    pub packets_undone: u64,
+    pub largest_transfer: Uint128,
+}
+
+impl From<&Flow> for FlowResponse {
+    fn from(flow: &Flow) -> Self {
+        FlowResponse {
+            inflow: flow.inflow,
+            outflow: flow.outflow,
+            period_end: flow.period_end,
+            recv_period_end: flow.period_end_on(&FlowType::In),
+            period_channel_value: flow.period_channel_value,
+            packets_in: flow.stats.packets_in,
+            packets_out: flow.stats.packets_out,
+            packets_undone: flow.stats.packets_undone,
+            largest_transfer: flow.stats.largest_transfer,
+        }
+    }
+}
+
+/// RateLimitResponse is a quota along with its current flow, as returned by
+/// the queries
+#[cw_serde]
+pub struct RateLimitResponse {
+    pub quota: QuotaResponse,
+    pub flow: FlowResponse,
+    pub last_reset: Option<ResetInfo>,
+}
+
+impl From<&RateLimit> for RateLimitResponse {
+    fn from(limit: &RateLimit) -> Self {
+        RateLimitResponse {
+            quota: (&limit.quota).into(),
+            flow: (&limit.flow).into(),
+            last_reset: limit.last_reset.clone(),
+        }
+    }
+}
+
+/// PathQuotaUsage is the usage of a quota of a path
+#[cw_serde]
+pub struct PathQuotaUsage {
+    pub contract: Addr,
+    pub channel_id: String,
+    pub denom: String,
+    pub usage: QuotaUsage,
+}
+
+/// QuotaAcrossPathsResponse is a page of the paths having a quota of a name.
+/// Paging is over when next_start_after is None
+#[cw_serde]
+pub struct QuotaAcrossPathsResponse {
+    pub paths: Vec<PathQuotaUsage>,
+    pub next_start_after: Option<(Addr, String, String)>,
+}
+
+/// QuotaOverview is a tracker of a path along with its usage in the current
+/// period. Utilization is in basis points of the capacity, and can exceed
+/// 10000 when the capacity was lowered below the usage. probation_end is when
+/// the quota graduates, unset once it has
+#[cw_serde]
+pub struct QuotaOverview {
+    pub rate_limit: RateLimitResponse,
+    pub usage: QuotaUsage,
+    pub send_utilization_bps: u32,
+    pub recv_utilization_bps: u32,
+    pub probation_end: Option<Timestamp>,
+}
+
+/// AverageFlow is the time-weighted average flow of a quota per period, over
+/// the number of archived periods used
+#[cw_serde]
+pub struct AverageFlow {
+    pub quota: String,
+    pub periods: u64,
+    pub inflow: Uint256,
+    pub outflow: Uint256,
 }
 
+/// RawQuotasResponse is the path decoded from a raw key and its rate limits
 #[cw_serde]
-pub enum MigrateMsg {}
+pub struct RawQuotasResponse {
+    pub contract: Addr,
+    pub channel_id: String,
+    pub denom: String,
+    pub rate_limits: Vec<RateLimitResponse>,
+}
+
+/// PeriodReport is a finished period of a path, meant for automated reports
+#[cw_serde]
+pub struct PeriodReport {
+    pub contract: Addr,
+    pub channel_id: String,
+    pub denom: String,
+    pub period: crate::state::HistoryEntry,
+}
+
+/// Severity of a diagnostic. Errors keep packets from being processed, warnings
+/// point at quotas that don't behave as they seem to
+#[cw_serde]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// Diagnostic is a finding of the Diagnose query. The code is stable, meant
+/// for alerts, the message is meant for operators
+#[cw_serde]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: String,
+    pub quota: Option<String>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: &str, message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity,
+            code: code.to_string(),
+            quota: None,
+            message: message.into(),
+        }
+    }
+
+    pub fn on_quota(mut self, quota: &str) -> Self {
+        self.quota = Some(quota.to_string());
+        self
+    }
+}
+
+#[cw_serde]
+pub struct DiagnoseResponse {
+    pub contract: Addr,
+    pub channel_id: String,
+    pub denom: String,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// TransferData is the response data of the packets on paths truncating the
+/// transfers that exceed their quotas or charging a fee. The bridge deducts the
+/// fee from the accepted amount
+#[cw_serde]
+pub struct TransferData {
+    pub accepted: Uint128,
+    pub remainder: Uint128,
+    #[serde(default)]
+    pub fee: Uint128,
+}
+
+impl TransferData {
+    /// The data of a send none of which was accepted
+    pub fn rejected(amount: Uint128) -> Self {
+        TransferData {
+            accepted: Uint128::zero(),
+            remainder: amount,
+            fee: Uint128::zero(),
+        }
+    }
+}
+
+/// CanSendResponse explains why a send would be rejected when allowed is false.
+/// Sends rejected by a quota also get the details of the exceeded quota
+#[cw_serde]
+pub struct CanSendResponse {
+    pub allowed: bool,
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub exceeded: Option<RateLimitExceededData>,
+}
+
+/// RATE_LIMIT_DATA_PREFIX marks the JSON details at the end of the message of a
+/// rate limit error
+pub const RATE_LIMIT_DATA_PREFIX: &str = "rate_limit_data:";
+
+/// RateLimitExceededData is the machine-readable version of the rate limit
+/// error. Callers that only get the error message (i.e. the Go middleware) can
+/// recover it with `from_error_message`. The field names are stable
+#[cw_serde]
+pub struct RateLimitExceededData {
+    pub contract: String,
+    pub channel_id: String,
+    pub denom: String,
+    pub amount: Uint128,
+    pub quota_name: String,
+    pub used: Uint256,
+    pub max: Uint256,
+    pub reset: Timestamp,
+    /// Every quota the transfer exceeded, the first one included
+    #[serde(default)]
+    pub violations: Vec<QuotaViolation>,
+}
+
+/// QuotaViolation is a quota a transfer exceeded, with the capacity it used and
+/// when its period resets
+#[cw_serde]
+pub struct QuotaViolation {
+    pub quota_name: String,
+    pub used: Uint256,
+    pub max: Uint256,
+    pub reset: Timestamp,
+}
+
+impl RateLimitExceededData {
+    /// The earliest time a retry of the same transfer could succeed: when the
+    /// last of the violated quotas resets
+    pub fn retry_after(&self) -> Timestamp {
+        self.violations
+            .iter()
+            .map(|violation| violation.reset)
+            .fold(self.reset, Timestamp::max)
+    }
+
+    pub fn from_error_message(message: &str) -> Option<Self> {
+        let (_, json) = message.rsplit_once(RATE_LIMIT_DATA_PREFIX)?;
+        from_json(&Binary::from(json.trim().as_bytes())).ok()
+    }
+
+    pub fn to_error_suffix(&self) -> String {
+        let json = to_json_vec(self).unwrap_or_default();
+        format!(
+            "{}{}",
+            RATE_LIMIT_DATA_PREFIX,
+            String::from_utf8_lossy(&json)
+        )
+    }
+}
+
+/// PolicyResponse is the answer expected from the policy contract. Packets are
+/// rejected when allowed is false
+#[cw_serde]
+pub struct PolicyResponse {
+    pub allowed: bool,
+    pub reason: Option<String>,
+}
@@ -1,27 +1,258 @@
 use cosmwasm_schema::cw_serde;
-use cosmwasm_std::Uint128;
+use cosmwasm_schema::serde::{de::IgnoredAny, Deserialize};
+use cosmwasm_std::{from_json, Binary, Coin, Uint128, Uint256};
+use std::str::FromStr;
 
-// An IBC packet
+use crate::ContractError;
+
+// An IBC packet. The sequence is optional: when provided, packets are checked
+// against the last processed sequence to prevent double counting. The channel
+// value of the denom, as calculated by the caller, is needed by percentage quotas
+//
+// The memo is the ICS-20 memo of the transfer. It's used to classify the packet,
+// as is the receiver, looked up in the receiver class registry. The sender is
+// only informative, it's recorded along with rejections
+//
+// The endpoints, when given, are checked against the local channel declared for
+// the path, so a send can't be accounted as a receive or the other way round
+//
+// The tag is set by the bridge to tell transfers of different origins apart
+// (e.g. "user", "protocol"). Tagged transfers are accounted on the tag quotas
+// of the path instead of its own quotas, if the tag has any
 #[cw_serde]
 pub struct Packet {
     pub channel: String,
     pub denom: String,
     pub amount: Uint128,
+    pub sequence: Option<u64>,
+    #[serde(default)]
+    pub channel_value: Option<Uint128>,
+    #[serde(default)]
+    pub memo: Option<String>,
+    #[serde(default)]
+    pub receiver: Option<String>,
+    #[serde(default)]
+    pub sender: Option<String>,
+    #[serde(default)]
+    pub endpoints: Option<PacketEndpoints>,
+    #[serde(default)]
+    pub tag: Option<String>,
+}
+
+// The source and destination ends of the IBC packet
+#[cw_serde]
+pub struct PacketEndpoints {
+    pub source_port: String,
+    pub source_channel: String,
+    pub destination_port: String,
+    pub destination_channel: String,
+}
+
+// An interchain accounts packet. The coins moved by its messages (i.e. the
+// amounts of the bank sends it carries), as extracted by the caller, are each
+// accounted on the path of their denom over the channel, like transfers. The
+// sequence is checked once for the whole packet
+#[cw_serde]
+pub struct IcaPacket {
+    pub channel: String,
+    pub msgs_value: Vec<Coin>,
+    pub sequence: Option<u64>,
+    #[serde(default)]
+    pub sender: Option<String>,
+}
+
+// The data of an ICS-20 packet, as relayed. Amounts are decimal strings of up
+// to 256 bits, so tokens with 18 decimals can go beyond u128
+#[cw_serde]
+pub struct Ics20PacketData {
+    pub denom: String,
+    pub amount: String,
+    pub sender: String,
+    pub receiver: String,
+    #[serde(default)]
+    pub memo: Option<String>,
+}
+
+// A transfer as reported by the transfer hook of a cw-ics20 contract, which
+// sends it as the Ics20TransferHook message. The local denom (i.e.
+// "cw20:orai1...") names the path, the remote one is the denom of the token on
+// the counterparty chain and is only informative
+#[cw_serde]
+pub struct Ics20Transfer {
+    pub channel: String,
+    pub remote_denom: String,
+    pub local_denom: String,
+    pub amount: Uint128,
+    pub is_receive: bool,
+}
+
+impl Ics20Transfer {
+    pub fn packet(&self) -> Packet {
+        Packet::new(&self.channel, &self.local_denom, self.amount)
+    }
+}
+
+// Parses an ICS-20 amount. Only plain decimal digits are accepted: signs,
+// whitespace, decimal points and exponents are malformed
+pub fn parse_amount(amount: &str) -> Result<Uint256, ContractError> {
+    if amount.is_empty() {
+        return Err(ContractError::EmptyAmount {});
+    }
+    if !amount.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err(ContractError::MalformedAmount {
+            amount: amount.to_string(),
+        });
+    }
+    Uint256::from_str(amount).map_err(|_| ContractError::AmountOverflow {
+        amount: amount.to_string(),
+        max: "uint256".to_string(),
+    })
+}
+
+// PacketClass tells plain transfers apart from the ones triggering a contract
+// call on arrival through an ibc-hooks memo
+#[cw_serde]
+pub enum PacketClass {
+    Plain,
+    Hooked,
+}
+
+impl PacketClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PacketClass::Plain => "plain",
+            PacketClass::Hooked => "hooked",
+        }
+    }
+}
+
+// ReceiverClass tells contract receivers apart from externally owned accounts.
+// Receivers missing from the registry are accounts
+#[cw_serde]
+#[derive(Default)]
+pub enum ReceiverClass {
+    Contract,
+    #[default]
+    Account,
+}
+
+impl ReceiverClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReceiverClass::Contract => "contract",
+            ReceiverClass::Account => "account",
+        }
+    }
+}
+
+// The part of an ibc-hooks memo that matters for the classification. Other
+// keys (i.e. packet forwarding) are ignored
+#[derive(Deserialize)]
+#[serde(crate = "cosmwasm_schema::serde")]
+struct HooksMemo {
+    wasm: Option<IgnoredAny>,
 }
 
 // Helpers
 
 impl Packet {
-    pub fn mock(channel: String, denom: String, amount: Uint128) -> Self {
+    // A packet of the amount of the denom over the channel, without any of the
+    // optional details, which the with_ methods fill in
+    pub fn new(channel: impl Into<String>, denom: impl Into<String>, amount: Uint128) -> Self {
         Packet {
-            channel,
-            denom,
+            channel: channel.into(),
+            denom: denom.into(),
+            amount,
+            sequence: None,
+            channel_value: None,
+            memo: None,
+            receiver: None,
+            sender: None,
+            endpoints: None,
+            tag: None,
+        }
+    }
+
+    pub fn mock(channel: String, denom: String, amount: Uint128) -> Self {
+        Packet::new(channel, denom, amount)
+    }
+
+    // The packet of an ICS-20 transfer on the channel. Packets are accounted in
+    // the 128 bits of bank coins, so larger amounts are refused
+    pub fn from_ics20(
+        channel: impl Into<String>,
+        sequence: Option<u64>,
+        data: Ics20PacketData,
+    ) -> Result<Self, ContractError> {
+        let amount = parse_amount(&data.amount)?;
+        let amount = Uint128::try_from(amount).map_err(|_| ContractError::AmountOverflow {
+            amount: data.amount,
+            max: "uint128".to_string(),
+        })?;
+        Ok(Packet {
+            channel: channel.into(),
+            denom: data.denom,
             amount,
+            sequence,
+            channel_value: None,
+            memo: data.memo,
+            receiver: Some(data.receiver),
+            sender: Some(data.sender),
+            endpoints: None,
+            tag: None,
+        })
+    }
+
+    pub fn with_memo(mut self, memo: impl Into<String>) -> Self {
+        self.memo = Some(memo.into());
+        self
+    }
+
+    // Packets are hooked when their memo is a JSON object with a wasm key
+    pub fn class(&self) -> PacketClass {
+        let memo = self
+            .memo
+            .as_ref()
+            .and_then(|memo| from_json::<HooksMemo>(&Binary::from(memo.as_bytes())).ok());
+        match memo {
+            Some(HooksMemo { wasm: Some(_) }) => PacketClass::Hooked,
+            _ => PacketClass::Plain,
         }
     }
+
+    pub fn with_receiver(mut self, receiver: impl Into<String>) -> Self {
+        self.receiver = Some(receiver.into());
+        self
+    }
+
+    pub fn with_sender(mut self, sender: impl Into<String>) -> Self {
+        self.sender = Some(sender.into());
+        self
+    }
+
+    pub fn with_channel_value(mut self, channel_value: Uint128) -> Self {
+        self.channel_value = Some(channel_value);
+        self
+    }
+
+    pub fn with_sequence(mut self, sequence: u64) -> Self {
+        self.sequence = Some(sequence);
+        self
+    }
+
+    pub fn with_endpoints(mut self, endpoints: PacketEndpoints) -> Self {
+        self.endpoints = Some(endpoints);
+        self
+    }
+
+    pub fn with_tag(mut self, tag: impl Into<String>) -> Self {
+        self.tag = Some(tag.into());
+        self
+    }
 }
 
-// Create a new packet for testing
+// Create a new packet for testing. The channel value the bridge would report
+// can be given to override the cached one
 #[cfg(test)]
 #[macro_export]
 macro_rules! test_msg_send {
@@ -30,6 +261,18 @@ macro_rules! test_msg_send {
             packet: $crate::packet::Packet::mock($channel_id, $denom, $funds),
         }
     };
+    (channel_id: $channel_id:expr, denom: $denom:expr, funds: $funds:expr, channel_value: $channel_value:expr) => {
+        $crate::msg::ExecuteMsg::SendPacket {
+            packet: $crate::packet::Packet::mock($channel_id, $denom, $funds)
+                .with_channel_value($channel_value),
+        }
+    };
+    (channel_id: $channel_id:expr, denom: $denom:expr, funds: $funds:expr, sequence: $sequence:expr) => {
+        $crate::msg::ExecuteMsg::SendPacket {
+            packet: $crate::packet::Packet::mock($channel_id, $denom, $funds)
+                .with_sequence($sequence),
+        }
+    };
 }
 
 #[cfg(test)]
@@ -40,4 +283,98 @@ macro_rules! test_msg_recv {
             packet: $crate::packet::Packet::mock($channel_id, $denom, $funds),
         }
     };
+    (channel_id: $channel_id:expr, denom: $denom:expr, funds: $funds:expr, channel_value: $channel_value:expr) => {
+        $crate::msg::ExecuteMsg::RecvPacket {
+            packet: $crate::packet::Packet::mock($channel_id, $denom, $funds)
+                .with_channel_value($channel_value),
+        }
+    };
+}
+
+#[cfg(test)]
+#[macro_export]
+macro_rules! test_msg_undo {
+    (channel_id: $channel_id:expr, denom: $denom:expr, funds: $funds:expr, sequence: $sequence:expr) => {
+        $crate::msg::ExecuteMsg::UndoSend {
+            packet: $crate::packet::Packet::mock($channel_id, $denom, $funds)
+                .with_sequence($sequence),
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Packet data in the shape relayed on mainnet: fields in the order ibc-go
+    // sorts them, empty memos, and 18 decimals amounts from axelar
+    const OSMOSIS_TRANSFER: &str = r#"{"amount":"1500000","denom":"uosmo","receiver":"orai1hvr9d72r5um9lvt0rpkd4r75vrsqtw6yujhqs2","sender":"osmo1hvr9d72r5um9lvt0rpkd4r75vrsqtw6y5a4kg9"}"#;
+    const AXELAR_WETH: &str = r#"{"amount":"2500000000000000000","denom":"weth-wei","memo":"","receiver":"orai1hvr9d72r5um9lvt0rpkd4r75vrsqtw6yujhqs2","sender":"axelar1hvr9d72r5um9lvt0rpkd4r75vrsqtw6yd5e8u8"}"#;
+    const HOOKED_TRANSFER: &str = r#"{"amount":"42","denom":"transfer/channel-13/uatom","memo":"{\"wasm\":{\"contract\":\"orai1contract\",\"msg\":{}}}","receiver":"orai1contract","sender":"cosmos1hvr9d72r5um9lvt0rpkd4r75vrsqtw6ytnnvpf"}"#;
+    // 2^128, just beyond what bank coins hold
+    const BEYOND_U128: &str = r#"{"amount":"340282366920938463463374607431768211456","denom":"weth-wei","receiver":"orai1receiver","sender":"axelar1sender"}"#;
+
+    fn packet(json: &str) -> Result<Packet, ContractError> {
+        let data: Ics20PacketData = from_json(&Binary::from(json.as_bytes()))?;
+        Packet::from_ics20("channel-0", Some(7), data)
+    }
+
+    #[test] // Tests that relayed packet data is decoded
+    fn ics20_fixtures() {
+        let osmosis = packet(OSMOSIS_TRANSFER).unwrap();
+        assert_eq!(osmosis.amount, Uint128::new(1_500_000));
+        assert_eq!(osmosis.denom, "uosmo");
+        assert_eq!(osmosis.memo, None);
+        assert_eq!(osmosis.sequence, Some(7));
+
+        let weth = packet(AXELAR_WETH).unwrap();
+        assert_eq!(weth.amount, Uint128::new(2_500_000_000_000_000_000));
+        assert_eq!(weth.memo, Some(String::new()));
+        assert_eq!(weth.class(), PacketClass::Plain);
+
+        let hooked = packet(HOOKED_TRANSFER).unwrap();
+        assert_eq!(hooked.class(), PacketClass::Hooked);
+        assert_eq!(hooked.receiver.as_deref(), Some("orai1contract"));
+
+        let data: Ics20PacketData = from_json(&Binary::from(BEYOND_U128.as_bytes())).unwrap();
+        assert_eq!(
+            parse_amount(&data.amount).unwrap(),
+            Uint256::from(u128::MAX) + Uint256::from(1_u32)
+        );
+        assert_eq!(
+            packet(BEYOND_U128).unwrap_err(),
+            ContractError::AmountOverflow {
+                amount: data.amount,
+                max: "uint128".to_string(),
+            }
+        );
+
+        // Amounts are strings, never JSON numbers
+        let numeric = r#"{"amount":1500000,"denom":"uosmo","receiver":"r","sender":"s"}"#;
+        assert!(from_json::<Ics20PacketData>(&Binary::from(numeric.as_bytes())).is_err());
+    }
+
+    #[test] // Tests that malformed amounts are told apart from overflowing ones
+    fn amount_errors() {
+        assert_eq!(parse_amount(""), Err(ContractError::EmptyAmount {}));
+        for amount in ["-1", "+1", " 1", "1 ", "1.5", "1e18", "0x10", "1_000"] {
+            assert_eq!(
+                parse_amount(amount),
+                Err(ContractError::MalformedAmount {
+                    amount: amount.to_string()
+                })
+            );
+        }
+        let max = Uint256::MAX.to_string();
+        assert_eq!(parse_amount(&max), Ok(Uint256::MAX));
+        let beyond = format!("{max}0");
+        assert_eq!(
+            parse_amount(&beyond),
+            Err(ContractError::AmountOverflow {
+                amount: beyond.clone(),
+                max: "uint256".to_string(),
+            })
+        );
+        assert_eq!(parse_amount("007"), Ok(Uint256::from(7_u32)));
+    }
 }
@@ -0,0 +1,173 @@
+use cw_storage_plus::{Bound, PrimaryKey};
+
+// The list queries, prunes and ticks go through these, so a single call can't
+// iterate more entries than the hard cap of its kind allows. The scans that
+// have to see a whole map to be right are left out: the funds held in a denom,
+// read by Sweep, and the indexes rebuilt on migration
+
+pub const QUERY_DEFAULT_LIMIT: u32 = 10;
+pub const QUERY_MAX_LIMIT: u32 = 30;
+
+pub const TICK_DEFAULT_LIMIT: u32 = 10;
+pub const TICK_MAX_LIMIT: u32 = 30;
+
+pub const PRUNE_DEFAULT_LIMIT: u32 = 100;
+pub const PRUNE_MAX_LIMIT: u32 = 300;
+
+// The paths a page of a filtered query looks at, matching or not. Applies to
+// GetFilteredState and GetQuotaAcrossPaths, and to the totals of
+// GetContractUsage
+pub const FILTER_SCAN_LIMIT: u32 = 300;
+
+// The transfers a single SimulateSequence can replay
+pub const SIMULATION_MAX_TRANSFERS: usize = 500;
+
+// The paths a single GetQuotasBatch can look up
+pub const QUOTAS_BATCH_MAX_PATHS: usize = 100;
+
+// The page size of a call, never above max whatever was requested
+pub fn clamp_limit(limit: Option<u32>, default: u32, max: u32) -> usize {
+    limit.unwrap_or(default).min(max) as usize
+}
+
+// The page size of a list query
+pub fn query_limit(limit: Option<u32>) -> usize {
+    clamp_limit(limit, QUERY_DEFAULT_LIMIT, QUERY_MAX_LIMIT)
+}
+
+// The bound a page starts from. Cursors are always the full key of the last
+// entry of the previous page, within the prefix being iterated: the id for
+// (PathKey, u64) maps ranged by path, (channel_id, denom) for the paths of a
+// contract, (contract, channel_id, denom) for all the paths
+pub fn start_bound<'a, K: PrimaryKey<'a>>(cursor: Option<K>) -> Option<Bound<'a, K>> {
+    cursor.map(Bound::exclusive)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::PathKey;
+    use cosmwasm_std::testing::MockStorage;
+    use cosmwasm_std::{Addr, Order, StdResult, Storage};
+    use cw_storage_plus::Map;
+    use std::collections::BTreeSet;
+
+    const PATHS: Map<PathKey, u32> = Map::new("paths");
+    const ENTRIES: Map<(PathKey, u64), u32> = Map::new("entries");
+
+    // A deterministic pseudo-random sequence, so failures can be reproduced
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self, bound: u64) -> u64 {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1);
+            (self.0 >> 33) % bound
+        }
+    }
+
+    fn random_path(rng: &mut Lcg) -> PathKey {
+        (
+            Addr::unchecked(format!("contract{}", rng.next(4))),
+            format!("channel-{}", rng.next(20)),
+            format!("denom{}", rng.next(5)),
+        )
+    }
+
+    // Pages through the paths with the query helpers, as a client would
+    fn page_paths(storage: &dyn Storage, limit: Option<u32>) -> StdResult<Vec<PathKey>> {
+        let mut keys = vec![];
+        let mut cursor = None;
+        loop {
+            let page = PATHS
+                .keys(storage, start_bound(cursor), None, Order::Ascending)
+                .take(query_limit(limit))
+                .collect::<StdResult<Vec<_>>>()?;
+            assert!(page.len() <= QUERY_MAX_LIMIT as usize);
+            match page.last() {
+                Some(last) => cursor = Some(last.clone()),
+                None => return Ok(keys),
+            }
+            keys.extend(page);
+        }
+    }
+
+    // Pages through the entries of a path, whose cursor is the id alone
+    fn page_entries(storage: &dyn Storage, path: &PathKey, limit: Option<u32>) -> Vec<u64> {
+        let mut ids = vec![];
+        let mut cursor = None;
+        loop {
+            let page = ENTRIES
+                .prefix(path.clone())
+                .keys(storage, start_bound(cursor), None, Order::Ascending)
+                .take(query_limit(limit))
+                .collect::<StdResult<Vec<_>>>()
+                .unwrap();
+            match page.last() {
+                Some(last) => cursor = Some(*last),
+                None => return ids,
+            }
+            ids.extend(page);
+        }
+    }
+
+    #[test] // Tests that the page size never goes above the hard cap
+    fn limits_are_capped() {
+        assert_eq!(query_limit(None), QUERY_DEFAULT_LIMIT as usize);
+        assert_eq!(query_limit(Some(5)), 5);
+        assert_eq!(query_limit(Some(u32::MAX)), QUERY_MAX_LIMIT as usize);
+        assert_eq!(
+            clamp_limit(Some(u32::MAX), PRUNE_DEFAULT_LIMIT, PRUNE_MAX_LIMIT),
+            PRUNE_MAX_LIMIT as usize
+        );
+    }
+
+    #[test] // Tests that paging yields every entry exactly once, for any page size
+    fn pages_cover_all_entries() {
+        let mut rng = Lcg(42);
+        for round in 0..20 {
+            let mut storage = MockStorage::new();
+            let mut expected = BTreeSet::new();
+            for _ in 0..rng.next(120) {
+                let path = random_path(&mut rng);
+                PATHS.save(&mut storage, path.clone(), &round).unwrap();
+                expected.insert(path);
+            }
+
+            for limit in [None, Some(1), Some(3), Some(7), Some(1000)] {
+                let keys = page_paths(&storage, limit).unwrap();
+                let unique: BTreeSet<_> = keys.iter().cloned().collect();
+                assert_eq!(unique.len(), keys.len(), "duplicates with {limit:?}");
+                assert_eq!(unique, expected, "gaps with {limit:?}");
+            }
+        }
+    }
+
+    #[test] // Tests that paging within a path prefix neither leaks nor skips entries
+    fn prefixed_pages_cover_all_entries() {
+        let mut rng = Lcg(7);
+        let mut storage = MockStorage::new();
+        let paths: Vec<PathKey> = (0..5)
+            .map(|i| {
+                let contract = Addr::unchecked(format!("contract{}", i % 2));
+                (contract, format!("channel-{i}"), format!("denom"))
+            })
+            .collect();
+        let mut expected: Vec<BTreeSet<u64>> = vec![BTreeSet::new(); paths.len()];
+        for id in 0..200 {
+            let index = rng.next(paths.len() as u64) as usize;
+            ENTRIES
+                .save(&mut storage, (paths[index].clone(), id), &0)
+                .unwrap();
+            expected[index].insert(id);
+        }
+
+        for (path, expected) in paths.iter().zip(&expected) {
+            for limit in [None, Some(1), Some(4), Some(30), Some(31)] {
+                let ids = page_entries(&storage, path, limit);
+                let unique: BTreeSet<_> = ids.iter().copied().collect();
+                assert_eq!(unique.len(), ids.len());
+                assert_eq!(&unique, expected);
+            }
+        }
+    }
+}
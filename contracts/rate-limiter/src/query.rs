@@ -1,13 +1,1833 @@
-use cosmwasm_std::{to_json_binary, Addr, Binary, Deps, StdResult};
+use cosmwasm_schema::serde::Serialize;
+use cosmwasm_std::{
+    to_json_binary, Addr, Binary, Deps, Order, StdError, StdResult, Uint128, Uint256,
+};
+use cw_storage_plus::Bound;
 
-use crate::state::{Path, RATE_LIMIT_TRACKERS};
+use crate::core::{evaluate_quotas, new_trackers};
+use crate::execute::{
+    cached_channel_value, chain_transfer, channel_transfer, check_channel_asset, check_fees_paid,
+    check_heartbeat, check_in_flight, check_migrated, check_path_msg, check_paused, check_policy,
+    check_reserved_capacity, check_sender, class_transfer, degraded_transfer, fitting_amount,
+    inherited_quotas, missed_heartbeat, pending_in_flight,
+};
+use crate::msg::{
+    AverageFlow, CanSendResponse, ChangesResponse, ChannelPairResponse, ContractUsageResponse,
+    DiagnoseResponse, Diagnostic, FilteredPath, FilteredStateResponse, HeartbeatResponse,
+    InvariantCursor, InvariantViolation, InvariantsResponse, Metric, MetricKind, PathChange,
+    PathChangePreview, PathId, PathMsg, PathOverviewResponse, PathQuotaUsage, PathUsage,
+    PeriodReport, QuarantinedEntry, QuotaAcrossPathsResponse, QuotaChange, QuotaMsg, QuotaOverview,
+    QuotaResponse, QuotaUsage, RateLimitResponse, RawQuotasResponse, RemainingCapacity, Severity,
+    SimulatedOutcome, SimulatedTransfer, SimulationResponse, StorageEntry, StorageKind,
+    StorageLayoutResponse, TenantConfigResponse, Versioned, API_VERSION,
+};
+use crate::packet::{Packet, ReceiverClass};
+use crate::pagination::{
+    query_limit, start_bound, FILTER_SCAN_LIMIT, QUOTAS_BATCH_MAX_PATHS, SIMULATION_MAX_TRANSFERS,
+};
+use crate::state::{
+    decode_tracker_key, is_migrated, is_paused, load_config, load_key_spec, load_tenant_config,
+    normalize_denom, resolve_path, FlowType, HistoryEntry, Path, Quota, QuotaMode, RateLimit,
+    Rejection, UnconfiguredBehavior, CHAIN_TRACKERS, CHANGE_LOG, CHANGE_SEQ, CHANNEL_ASSETS,
+    CHANNEL_DEFAULT_QUOTAS, CLASS_TRACKERS, COUNTERPARTY_STATES, DAILY_STATS, FEES, FILTERS,
+    HISTORY, HISTORY_LENGTH, IN_FLIGHT, LAST_HEARTBEAT, LEGACY_NAMESPACES, MAINTENANCE, METRICS,
+    MULTISIG, NANOS_PER_SECOND, PACKET_SEQUENCES, PATH_AUDIT_LOG, PATH_CONFIGS, PAUSED,
+    PAUSED_CHANNELS, PAUSED_CONTRACTS, PAUSED_DIRECTIONS, PAUSED_PATHS, PENDING_SENDS,
+    PRE_APPROVALS, PROPOSALS, QUARANTINE, RATE_LIMIT_TRACKERS, RECEIVER_CLASSES, REJECTIONS,
+    REJECTIONS_LENGTH, RESERVATIONS, SECONDS_PER_DAY, STREAKS, TAG_TRACKERS, TENANT_CONFIG,
+};
+use crate::time::{ContractClock, TimeProvider};
+use crate::ContractError;
+
+// Wraps a response in the versioned envelope
+fn to_versioned_binary<T: Serialize>(data: &T) -> StdResult<Binary> {
+    to_json_binary(&Versioned {
+        api_version: API_VERSION,
+        data,
+    })
+}
+
+// The trackers as returned by the queries
+fn rate_limit_responses(limits: &[RateLimit]) -> Vec<RateLimitResponse> {
+    limits.iter().map(RateLimitResponse::from).collect()
+}
+
+// Rounds the flows up to the capacity buckets of the config, so the queries
+// returning them don't give away the exact remaining capacity either
+fn round_flows(
+    mut limits: Vec<RateLimit>,
+    bucket_bps: Option<u32>,
+    clock: &dyn TimeProvider,
+) -> Vec<RateLimit> {
+    let Some(bps) = bucket_bps else {
+        return limits;
+    };
+    for limit in &mut limits {
+        let (max_in, max_out) = limit
+            .quota
+            .capacity_at(limit.flow.period_channel_value, clock.now());
+        limit.flow.inflow = round_up_to_bucket(limit.flow.inflow, max_in, bps);
+        limit.flow.outflow = round_up_to_bucket(limit.flow.outflow, max_out, bps);
+    }
+    limits
+}
 
 pub fn get_quotas(
     deps: Deps,
+    clock: &dyn TimeProvider,
     contract: Addr,
     channel_id: impl Into<String>,
     denom: impl Into<String>,
 ) -> StdResult<Binary> {
-    let path = Path::new(&contract, channel_id, denom);
-    to_json_binary(&RATE_LIMIT_TRACKERS.load(deps.storage, path.into())?)
+    let bucket_bps = load_config(deps.storage)?.capacity_bucket_bps;
+    let path = resolve_path(deps.storage, Path::new(&contract, channel_id, denom))?;
+    let trackers = RATE_LIMIT_TRACKERS.load(deps.storage, path.into())?;
+    let trackers = round_flows(trackers, bucket_bps, clock);
+    to_versioned_binary(&rate_limit_responses(&trackers))
+}
+
+pub fn get_quotas_batch(
+    deps: Deps,
+    clock: &dyn TimeProvider,
+    paths: Vec<PathId>,
+) -> StdResult<Binary> {
+    if paths.len() > QUOTAS_BATCH_MAX_PATHS {
+        return Err(StdError::generic_err(format!(
+            "At most {QUOTAS_BATCH_MAX_PATHS} paths can be queried at once"
+        )));
+    }
+    let bucket_bps = load_config(deps.storage)?.capacity_bucket_bps;
+    let quotas = paths
+        .into_iter()
+        .map(|path| {
+            let path =
+                load_key_spec(deps.storage)?.path(&path.contract, &path.channel_id, &path.denom);
+            let path = resolve_path(deps.storage, path)?;
+            let trackers = RATE_LIMIT_TRACKERS.may_load(deps.storage, path.into())?;
+            Ok(trackers
+                .map(|trackers| rate_limit_responses(&round_flows(trackers, bucket_bps, clock))))
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    to_versioned_binary(&quotas)
+}
+
+// The path is decoded from the key rather than loaded with the raw key, so keys
+// of other namespaces are rejected instead of misread
+pub fn get_quotas_raw(deps: Deps, key: Binary) -> StdResult<Binary> {
+    let path = decode_tracker_key(&key)?;
+    let rate_limits = RATE_LIMIT_TRACKERS.load(deps.storage, (&path).into())?;
+    to_versioned_binary(&RawQuotasResponse {
+        contract: path.contract,
+        channel_id: path.channel,
+        denom: path.denom,
+        rate_limits: rate_limit_responses(&rate_limits),
+    })
+}
+
+pub fn get_channel_default_quotas(
+    deps: Deps,
+    contract: Addr,
+    channel_id: String,
+) -> StdResult<Binary> {
+    let channel = load_key_spec(deps.storage)?.channel(&channel_id);
+    let quotas = CHANNEL_DEFAULT_QUOTAS
+        .may_load(deps.storage, (contract, channel))?
+        .unwrap_or_default();
+    to_versioned_binary(&quotas)
+}
+
+pub fn get_channel_assets(deps: Deps, contract: Addr, channel_id: String) -> StdResult<Binary> {
+    let denoms = CHANNEL_ASSETS
+        .may_load(deps.storage, (&contract, &channel_id))?
+        .unwrap_or_default();
+    to_versioned_binary(&denoms)
+}
+
+pub fn get_audit_log(
+    deps: Deps,
+    contract: Addr,
+    channel_id: String,
+    denom: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<Binary> {
+    let limit = query_limit(limit);
+    let start = start_bound(start_after);
+    let path = load_key_spec(deps.storage)?.path(&contract, &channel_id, &denom);
+
+    let entries = PATH_AUDIT_LOG
+        .prefix(path.into())
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, entry)| entry))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    to_versioned_binary(&entries)
+}
+
+pub fn get_history(
+    deps: Deps,
+    contract: Addr,
+    channel_id: String,
+    denom: String,
+) -> StdResult<Binary> {
+    let path = resolve_path(deps.storage, Path::new(&contract, channel_id, denom))?;
+    to_versioned_binary(
+        &HISTORY
+            .may_load(deps.storage, path.into())?
+            .unwrap_or_default(),
+    )
+}
+
+pub fn get_period_report(
+    deps: Deps,
+    contract: Addr,
+    channel_id: String,
+    denom: String,
+    period_index: u64,
+) -> StdResult<Binary> {
+    let path = resolve_path(deps.storage, Path::new(&contract, channel_id, denom))?;
+    let period = HISTORY
+        .may_load(deps.storage, (&path).into())?
+        .unwrap_or_default()
+        .into_iter()
+        .find(|entry| entry.index == period_index)
+        .ok_or_else(|| StdError::not_found(format!("period {period_index}")))?;
+
+    to_versioned_binary(&PeriodReport {
+        contract: path.contract,
+        channel_id: path.channel,
+        denom: path.denom,
+        period,
+    })
+}
+
+pub fn diagnose(
+    deps: Deps,
+    contract: Addr,
+    channel_id: String,
+    denom: String,
+) -> StdResult<Binary> {
+    let path = resolve_path(deps.storage, Path::new(&contract, channel_id, denom))?;
+    let mut diagnostics = vec![];
+
+    // Anything holding the packets of the path back
+    if PAUSED.may_load(deps.storage)?.unwrap_or_default() {
+        diagnostics.push(Diagnostic::new(
+            Severity::Error,
+            "paused",
+            "Packet processing is paused",
+        ));
+    }
+    for direction in [FlowType::In, FlowType::Out] {
+        if PAUSED_DIRECTIONS.has(deps.storage, direction.as_str()) {
+            diagnostics.push(Diagnostic::new(
+                Severity::Error,
+                "direction_paused",
+                format!("Processing of {} packets is paused", direction.as_str()),
+            ));
+        }
+    }
+    if PAUSED_CONTRACTS.has(deps.storage, &path.contract) {
+        diagnostics.push(Diagnostic::new(
+            Severity::Error,
+            "contract_paused",
+            format!("Processing of the packets of {} is paused", path.contract),
+        ));
+    }
+    if PAUSED_CHANNELS.has(deps.storage, (&path.contract, &path.channel)) {
+        diagnostics.push(Diagnostic::new(
+            Severity::Error,
+            "channel_paused",
+            format!("Processing of the packets on {} is paused", path.channel),
+        ));
+    }
+    if PAUSED_PATHS.has(deps.storage, (&path).into()) {
+        diagnostics.push(Diagnostic::new(
+            Severity::Error,
+            "path_paused",
+            "Processing of the packets of the path is paused",
+        ));
+    }
+    if MAINTENANCE.may_load(deps.storage)?.unwrap_or_default() {
+        diagnostics.push(Diagnostic::new(
+            Severity::Error,
+            "maintenance",
+            "The contract is in maintenance",
+        ));
+    }
+    if !is_migrated(deps.storage, &path) {
+        diagnostics.push(Diagnostic::new(
+            Severity::Error,
+            "not_migrated",
+            "The path waits for ContinueMigration",
+        ));
+    }
+    if QUARANTINE.has(deps.storage, (&path).into()) {
+        diagnostics.push(Diagnostic::new(
+            Severity::Error,
+            "quarantined",
+            "The trackers of the path were quarantined, see GetQuarantined",
+        ));
+    }
+
+    let quotas: Vec<Quota> = match RATE_LIMIT_TRACKERS.may_load(deps.storage, (&path).into()) {
+        Ok(Some(trackers)) => trackers.into_iter().map(|limit| limit.quota).collect(),
+        Ok(None) => match inherited_quotas(deps.storage, &path)? {
+            Some((source, quotas)) => {
+                diagnostics.push(Diagnostic::new(
+                    Severity::Info,
+                    "inherited",
+                    format!("The path has no quotas, it inherits the {source} defaults"),
+                ));
+                quotas.iter().map(Quota::from).collect()
+            }
+            None => {
+                let seen = DAILY_STATS.has(deps.storage, (&path).into())
+                    || [FlowType::In, FlowType::Out].iter().any(|direction| {
+                        let key = (
+                            path.contract.clone(),
+                            path.channel.clone(),
+                            direction.as_str().to_string(),
+                        );
+                        PACKET_SEQUENCES.has(deps.storage, key)
+                    });
+                diagnostics.push(match seen {
+                    true => Diagnostic::new(
+                        Severity::Warning,
+                        "missing_path",
+                        "Packets went through the path but it has no quotas",
+                    ),
+                    false => Diagnostic::new(Severity::Info, "no_quotas", "The path has no quotas"),
+                });
+                vec![]
+            }
+        },
+        Err(_) => {
+            diagnostics.push(Diagnostic::new(
+                Severity::Error,
+                "corrupt_trackers",
+                "The trackers of the path can't be loaded, the next packet quarantines them",
+            ));
+            vec![]
+        }
+    };
+
+    let unlimited = Uint256::from(Uint128::MAX);
+    for quota in &quotas {
+        let durations = [
+            quota.duration_nanos,
+            quota.duration_on(&FlowType::In),
+            quota.duration_on(&FlowType::Out),
+        ];
+        if durations.contains(&0) {
+            diagnostics.push(
+                Diagnostic::new(
+                    Severity::Error,
+                    "zero_duration",
+                    "The quota has a zero duration, its period never ends",
+                )
+                .on_quota(&quota.name),
+            );
+        }
+        if quota.blocked {
+            diagnostics.push(
+                Diagnostic::new(
+                    Severity::Warning,
+                    "blocked",
+                    "The quota blocks every transfer",
+                )
+                .on_quota(&quota.name),
+            );
+        }
+        if quota.percentage.is_none()
+            && (quota.max_send >= unlimited || quota.max_recv >= unlimited)
+        {
+            diagnostics.push(
+                Diagnostic::new(
+                    Severity::Warning,
+                    "unlimited",
+                    "The quota is capped at the max u128, it never limits anything",
+                )
+                .on_quota(&quota.name),
+            );
+        }
+    }
+
+    // A quota is redundant when another one spans a multiple of its periods
+    // with caps no higher, as that one always runs out first
+    let comparable = |quota: &Quota| {
+        quota.percentage.is_none()
+            && !quota.blocked
+            && quota.mode == QuotaMode::Netted
+            && quota.duration_on(&FlowType::In) > 0
+            && quota.duration_on(&FlowType::Out) > 0
+    };
+    for (i, quota) in quotas.iter().enumerate() {
+        let covering = quotas.iter().enumerate().find(|(j, other)| {
+            *j != i
+                && comparable(quota)
+                && comparable(other)
+                && [FlowType::In, FlowType::Out].iter().all(|direction| {
+                    other.duration_on(direction) % quota.duration_on(direction) == 0
+                })
+                && other.max_send <= quota.max_send
+                && other.max_recv <= quota.max_recv
+                // Of two identical quotas, only the second is redundant
+                && (*j < i
+                    || other.max_send < quota.max_send
+                    || other.max_recv < quota.max_recv
+                    || other.duration_on(&FlowType::In) > quota.duration_on(&FlowType::In)
+                    || other.duration_on(&FlowType::Out) > quota.duration_on(&FlowType::Out))
+        });
+        if let Some((_, other)) = covering {
+            diagnostics.push(
+                Diagnostic::new(
+                    Severity::Warning,
+                    "redundant",
+                    format!("The quota is never reached before '{}'", other.name),
+                )
+                .on_quota(&quota.name),
+            );
+        }
+    }
+
+    to_versioned_binary(&DiagnoseResponse {
+        contract: path.contract,
+        channel_id: path.channel,
+        denom: path.denom,
+        diagnostics,
+    })
+}
+
+pub fn get_remaining_capacity(
+    deps: Deps,
+    clock: &dyn TimeProvider,
+    path: Path,
+) -> StdResult<Binary> {
+    let path = resolve_path(deps.storage, path)?;
+    let config = load_config(deps.storage)?;
+    let bucket_bps = config.capacity_bucket_bps;
+    let channel_value = cached_channel_value(deps.storage, &config, &path, None, clock)
+        .map_err(|err| StdError::generic_err(err.to_string()))?;
+    let remaining: Vec<RemainingCapacity> = RATE_LIMIT_TRACKERS
+        .may_load(deps.storage, path.into())?
+        .unwrap_or_default()
+        .iter()
+        .map(|limit| {
+            let remaining = |direction: &FlowType| {
+                let remaining = limit.remaining(direction, channel_value, clock);
+                match bucket_bps {
+                    Some(bps) => {
+                        let capacity =
+                            limit
+                                .quota
+                                .capacity_on_at(direction, channel_value, clock.now());
+                        round_to_bucket(remaining, capacity, bps)
+                    }
+                    None => remaining,
+                }
+            };
+            RemainingCapacity {
+                quota: limit.quota.name.clone(),
+                send: remaining(&FlowType::Out),
+                recv: remaining(&FlowType::In),
+            }
+        })
+        .collect();
+    to_versioned_binary(&remaining)
+}
+
+// Rounds an amount down to a multiple of bps basis points of the capacity.
+// Buckets too small to hold a unit leave the amount as is
+fn round_to_bucket(amount: Uint256, capacity: Uint256, bps: u32) -> Uint256 {
+    let bucket = capacity.multiply_ratio(bps, 10_000_u32);
+    if bucket.is_zero() {
+        return amount;
+    }
+    amount - amount % bucket
+}
+
+// Rounds an amount up to a multiple of bps basis points of the capacity, like
+// round_to_bucket
+fn round_up_to_bucket(amount: Uint256, capacity: Uint256, bps: u32) -> Uint256 {
+    let bucket = capacity.multiply_ratio(bps, 10_000_u32);
+    if bucket.is_zero() || (amount % bucket).is_zero() {
+        return amount;
+    }
+    amount.saturating_add(bucket - amount % bucket)
+}
+
+pub fn get_recent_rejections(deps: Deps, path: PathId, limit: Option<u32>) -> StdResult<Binary> {
+    let limit = limit.map_or(REJECTIONS_LENGTH, |limit| limit as usize);
+    let path = resolve_path(
+        deps.storage,
+        Path::new(&path.contract, path.channel_id, path.denom),
+    )?;
+    let rejections: Vec<Rejection> = REJECTIONS
+        .may_load(deps.storage, path.into())?
+        .unwrap_or_default()
+        .into_iter()
+        .rev()
+        .take(limit)
+        .collect();
+    to_versioned_binary(&rejections)
+}
+
+pub fn get_streak(deps: Deps, path: PathId) -> StdResult<Binary> {
+    let path = load_key_spec(deps.storage)?.path(&path.contract, &path.channel_id, &path.denom);
+    to_versioned_binary(&STREAKS.may_load(deps.storage, (&path).into())?)
+}
+
+pub fn get_channel_pair(
+    deps: Deps,
+    clock: &dyn TimeProvider,
+    contract: Addr,
+    channel_id: String,
+    denom: String,
+) -> StdResult<Binary> {
+    let path = resolve_path(deps.storage, Path::new(&contract, channel_id, denom))?;
+    let bucket_bps = load_config(deps.storage)?.capacity_bucket_bps;
+    let trackers = RATE_LIMIT_TRACKERS
+        .may_load(deps.storage, (&path).into())?
+        .unwrap_or_default();
+    let usage = round_flows(trackers, bucket_bps, clock)
+        .iter()
+        .map(|rate_limit| quota_usage(rate_limit, clock))
+        .collect();
+    let counterparty = PATH_CONFIGS
+        .may_load(deps.storage, (&path).into())?
+        .and_then(|config| config.counterparty);
+
+    to_versioned_binary(&ChannelPairResponse {
+        usage,
+        counterparty,
+        counterparty_state: COUNTERPARTY_STATES.may_load(deps.storage, (&path).into())?,
+        contract: path.contract,
+        channel_id: path.channel,
+        denom: path.denom,
+    })
+}
+
+pub fn get_path_overview(
+    deps: Deps,
+    clock: &dyn TimeProvider,
+    contract: Addr,
+    channel_id: String,
+    denom: String,
+) -> StdResult<Binary> {
+    let path = resolve_path(deps.storage, Path::new(&contract, channel_id, denom))?;
+    let bucket_bps = load_config(deps.storage)?.capacity_bucket_bps;
+    let trackers = RATE_LIMIT_TRACKERS
+        .may_load(deps.storage, (&path).into())?
+        .unwrap_or_default();
+    let quotas = round_flows(trackers, bucket_bps, clock)
+        .into_iter()
+        .map(|rate_limit| {
+            let usage = quota_usage(&rate_limit, clock);
+            QuotaOverview {
+                send_utilization_bps: utilization_bps(usage.outflow, usage.max_out),
+                recv_utilization_bps: utilization_bps(usage.inflow, usage.max_in),
+                probation_end: rate_limit.quota.probation_end(clock.now()),
+                rate_limit: (&rate_limit).into(),
+                usage,
+            }
+        })
+        .collect();
+    let paused_directions = [FlowType::In, FlowType::Out]
+        .into_iter()
+        .filter(|direction| PAUSED_DIRECTIONS.has(deps.storage, direction.as_str()))
+        .collect();
+
+    to_versioned_binary(&PathOverviewResponse {
+        quotas,
+        paused: is_paused(deps.storage, clock.now())?,
+        paused_directions,
+        maintenance: MAINTENANCE.may_load(deps.storage)?.unwrap_or_default(),
+        quarantined: QUARANTINE.has(deps.storage, (&path).into()),
+        config: PATH_CONFIGS
+            .may_load(deps.storage, (&path).into())?
+            .unwrap_or_default(),
+        streak: STREAKS.may_load(deps.storage, (&path).into())?,
+        last_packet: DAILY_STATS
+            .may_load(deps.storage, (&path).into())?
+            .and_then(|stats| stats.last_packet),
+        contract: path.contract,
+        channel_id: path.channel,
+        denom: path.denom,
+    })
+}
+
+// Usage in basis points of the capacity. Any usage of a zero capacity counts
+// as fully used
+fn utilization_bps(used: Uint256, max: Uint256) -> u32 {
+    if max.is_zero() {
+        return if used.is_zero() { 0 } else { 10_000 };
+    }
+    let bps = used.multiply_ratio(10_000_u32, max);
+    Uint128::try_from(bps).map_or(u32::MAX, |bps| bps.u128().min(u32::MAX as u128) as u32)
+}
+
+pub fn get_average_flow(
+    deps: Deps,
+    path: PathId,
+    lookback_periods: Option<u32>,
+) -> StdResult<Binary> {
+    let path = resolve_path(
+        deps.storage,
+        Path::new(&path.contract, path.channel_id, path.denom),
+    )?;
+    let lookback = lookback_periods
+        .map_or(HISTORY_LENGTH, |periods| periods as usize)
+        .clamp(1, HISTORY_LENGTH);
+    let history = HISTORY
+        .may_load(deps.storage, (&path).into())?
+        .unwrap_or_default();
+
+    let averages: Vec<AverageFlow> = RATE_LIMIT_TRACKERS
+        .may_load(deps.storage, path.into())?
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|limit| {
+            let duration = limit.quota.duration_on(&FlowType::Out);
+            let periods: Vec<&HistoryEntry> = history
+                .iter()
+                .rev()
+                .filter(|entry| entry.quota == limit.quota.name)
+                .take(lookback)
+                .collect();
+            let (last, first) = (periods.first()?, periods.last()?);
+            if duration == 0 {
+                return None;
+            }
+
+            // Periods only start with traffic, so they are spread over more
+            // time than their count times the duration
+            let start = first.period_end.nanos().saturating_sub(duration);
+            let span = last.period_end.nanos().saturating_sub(start).max(duration);
+            let average = |flow: fn(&HistoryEntry) -> Uint256| {
+                periods
+                    .iter()
+                    .fold(Uint256::zero(), |total, entry| {
+                        total.saturating_add(flow(entry))
+                    })
+                    .multiply_ratio(duration, span)
+            };
+            Some(AverageFlow {
+                quota: limit.quota.name.clone(),
+                periods: periods.len() as u64,
+                inflow: average(|entry| entry.inflow),
+                outflow: average(|entry| entry.outflow),
+            })
+        })
+        .collect();
+    to_versioned_binary(&averages)
+}
+
+pub fn get_in_flight(
+    deps: Deps,
+    contract: Addr,
+    channel_id: String,
+    denom: String,
+) -> StdResult<Binary> {
+    let path = load_key_spec(deps.storage)?.path(&contract, &channel_id, &denom);
+    to_versioned_binary(
+        &IN_FLIGHT
+            .may_load(deps.storage, path.into())?
+            .unwrap_or_default(),
+    )
+}
+
+pub fn get_class_quotas(
+    deps: Deps,
+    clock: &dyn TimeProvider,
+    contract: Addr,
+    channel_id: String,
+    denom: String,
+    class: &str,
+) -> StdResult<Binary> {
+    let path = resolve_path(deps.storage, Path::new(&contract, channel_id, denom))?;
+    let trackers = CLASS_TRACKERS
+        .may_load(deps.storage, (path.into(), class.to_string()))?
+        .unwrap_or_default();
+    let bucket_bps = load_config(deps.storage)?.capacity_bucket_bps;
+    to_versioned_binary(&rate_limit_responses(&round_flows(
+        trackers, bucket_bps, clock,
+    )))
+}
+
+pub fn get_tag_quotas(
+    deps: Deps,
+    clock: &dyn TimeProvider,
+    contract: Addr,
+    channel_id: String,
+    denom: String,
+    tag: String,
+) -> StdResult<Binary> {
+    let path = resolve_path(deps.storage, Path::new(&contract, channel_id, denom))?;
+    let trackers = TAG_TRACKERS
+        .may_load(deps.storage, (path.into(), tag))?
+        .unwrap_or_default();
+    let bucket_bps = load_config(deps.storage)?.capacity_bucket_bps;
+    to_versioned_binary(&rate_limit_responses(&round_flows(
+        trackers, bucket_bps, clock,
+    )))
+}
+
+pub fn get_chain_quotas(
+    deps: Deps,
+    clock: &dyn TimeProvider,
+    contract: Addr,
+    chain_id: String,
+    denom: String,
+) -> StdResult<Binary> {
+    let denom = load_key_spec(deps.storage)?.denom(&denom);
+    let trackers = CHAIN_TRACKERS
+        .may_load(deps.storage, (contract, chain_id, denom))?
+        .unwrap_or_default();
+    let bucket_bps = load_config(deps.storage)?.capacity_bucket_bps;
+    to_versioned_binary(&rate_limit_responses(&round_flows(
+        trackers, bucket_bps, clock,
+    )))
+}
+
+pub fn get_receiver_class(deps: Deps, address: String) -> StdResult<Binary> {
+    let class: ReceiverClass = RECEIVER_CLASSES
+        .may_load(deps.storage, &address)?
+        .unwrap_or_default();
+    to_versioned_binary(&class)
+}
+
+pub fn get_config(deps: Deps) -> StdResult<Binary> {
+    to_versioned_binary(&load_config(deps.storage)?)
+}
+
+pub fn get_key_spec(deps: Deps) -> StdResult<Binary> {
+    to_versioned_binary(&load_key_spec(deps.storage)?)
+}
+
+pub fn get_heartbeat(deps: Deps, clock: &dyn TimeProvider) -> StdResult<Binary> {
+    let config = load_config(deps.storage)?;
+    let last_heartbeat = match config.heartbeat {
+        Some(_) => LAST_HEARTBEAT.may_load(deps.storage)?,
+        None => None,
+    };
+    to_versioned_binary(&HeartbeatResponse {
+        missed: missed_heartbeat(deps.storage, &config, clock)?.is_some(),
+        last_heartbeat,
+    })
+}
+
+pub fn get_tenant_config(deps: Deps, contract: Addr) -> StdResult<Binary> {
+    to_versioned_binary(&TenantConfigResponse {
+        overrides: TENANT_CONFIG.may_load(deps.storage, &contract)?,
+        config: load_tenant_config(deps.storage, &contract)?,
+    })
+}
+
+pub fn can_send(
+    deps: Deps,
+    clock: &dyn TimeProvider,
+    contract: Addr,
+    channel_id: String,
+    denom: String,
+    amount: Uint128,
+) -> StdResult<Binary> {
+    let denom = normalize_denom(&denom);
+    let path = load_key_spec(deps.storage)?.path(&contract, &channel_id, &denom);
+    let checked = check_channel_asset(deps.storage, &contract, &channel_id, &denom)
+        .and_then(|_| check_send(deps, clock, &path, &denom, amount));
+    let response = match checked {
+        Ok(()) => CanSendResponse {
+            allowed: true,
+            reason: None,
+            exceeded: None,
+        },
+        Err(err) => CanSendResponse {
+            allowed: false,
+            reason: Some(err.to_string()),
+            exceeded: err.rate_limit_data(),
+        },
+    };
+    to_versioned_binary(&response)
+}
+
+// Goes through the checks of SendPacket on a copy of the trackers, for a plain
+// packet without a memo, receiver, sender or tag. Left out are the escrow and
+// the packet sequence, which depend on the message itself, the pre-approvals,
+// which the send would consume, and the tag quotas. The packet is checked
+// against the reservations of others and the plain class quotas only
+fn check_send(
+    deps: Deps,
+    clock: &dyn TimeProvider,
+    path: &Path,
+    denom: &str,
+    amount: Uint128,
+) -> Result<(), ContractError> {
+    check_paused(deps.storage, path, &FlowType::Out, clock)?;
+    let config = load_tenant_config(deps.storage, &path.contract)?;
+    let missed_heartbeat = check_heartbeat(deps.storage, &config, &FlowType::Out, clock)?;
+    check_sender(deps.storage, &config, &path.contract)?;
+    check_fees_paid(deps.storage, &path.contract, denom, &FlowType::Out)?;
+    check_migrated(deps.storage, path)?;
+
+    let packet = Packet::new(&path.channel, &path.denom, amount);
+    check_policy(deps, &config, path, &packet, &FlowType::Out)?;
+    check_in_flight(deps.storage, path, amount)?;
+    check_reserved_capacity(deps.storage, &config, path, &packet, &FlowType::Out, clock)?;
+    if missed_heartbeat.is_some() {
+        degraded_transfer(deps.storage, &config, path, &packet, clock)?;
+    }
+
+    // Corrupt entries are quarantined by the next packet, so they are treated
+    // as missing
+    let mut trackers = match RATE_LIMIT_TRACKERS.may_load(deps.storage, path.into()) {
+        Ok(Some(trackers)) => trackers,
+        Ok(None) | Err(_) => inherited_quotas(deps.storage, path)?
+            .map(|(_, quotas)| new_trackers(&quotas, clock))
+            .unwrap_or_default(),
+    };
+
+    if trackers.is_empty() {
+        if let UnconfiguredBehavior::Reject = config.unconfigured_behavior {
+            return Err(ContractError::PathNotConfigured {
+                contract: path.contract.to_string(),
+                channel: path.channel.to_string(),
+                denom: path.denom.to_string(),
+            });
+        }
+        return Ok(());
+    }
+
+    let channel_value = cached_channel_value(deps.storage, &config, path, None, clock)?;
+    let path_config = PATH_CONFIGS
+        .may_load(deps.storage, path.into())?
+        .unwrap_or_default();
+    evaluate_quotas(
+        &mut trackers,
+        &path_config.evaluation_mode,
+        path,
+        &FlowType::Out,
+        amount,
+        channel_value,
+        clock,
+    )?;
+    channel_transfer(
+        deps.storage,
+        &config,
+        path,
+        &FlowType::Out,
+        amount,
+        channel_value,
+        clock,
+    )?;
+    chain_transfer(
+        deps.storage,
+        &path_config,
+        path,
+        &FlowType::Out,
+        amount,
+        channel_value,
+        clock,
+    )?;
+    class_transfer(deps.storage, path, &packet, &FlowType::Out, clock)?;
+    Ok(())
+}
+
+pub fn changes_since(
+    deps: Deps,
+    clock: &dyn TimeProvider,
+    seq: u64,
+    limit: Option<u32>,
+) -> StdResult<Binary> {
+    let limit = query_limit(limit);
+    let bucket_bps = load_config(deps.storage)?.capacity_bucket_bps;
+
+    let changes = CHANGE_LOG
+        .range(
+            deps.storage,
+            Some(Bound::exclusive(seq)),
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .map(|item| {
+            let (seq, (contract, channel_id, denom)) = item?;
+            let trackers = RATE_LIMIT_TRACKERS
+                .may_load(
+                    deps.storage,
+                    (contract.clone(), channel_id.clone(), denom.clone()),
+                )?
+                .unwrap_or_default();
+            Ok(PathChange {
+                seq,
+                contract,
+                channel_id,
+                denom,
+                trackers: rate_limit_responses(&round_flows(trackers, bucket_bps, clock)),
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let oldest_seq = CHANGE_LOG
+        .keys(deps.storage, None, None, Order::Ascending)
+        .next()
+        .transpose()?
+        .unwrap_or_default();
+
+    to_versioned_binary(&ChangesResponse {
+        last_seq: CHANGE_SEQ.may_load(deps.storage)?.unwrap_or_default(),
+        oldest_seq,
+        changes,
+    })
+}
+
+pub fn get_multisig(deps: Deps) -> StdResult<Binary> {
+    to_versioned_binary(&MULTISIG.may_load(deps.storage)?)
+}
+
+pub fn get_proposal(deps: Deps, id: u64) -> StdResult<Binary> {
+    to_versioned_binary(&PROPOSALS.load(deps.storage, id)?)
+}
+
+pub fn list_proposals(
+    deps: Deps,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<Binary> {
+    let limit = query_limit(limit);
+    let start = start_bound(start_after);
+
+    let proposals = PROPOSALS
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, proposal)| proposal))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    to_versioned_binary(&proposals)
+}
+
+pub fn list_pre_approvals(
+    deps: Deps,
+    path: PathId,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<Binary> {
+    let limit = query_limit(limit);
+    let start = start_bound(start_after);
+    let path = load_key_spec(deps.storage)?.path(&path.contract, &path.channel_id, &path.denom);
+
+    let approvals = PRE_APPROVALS
+        .prefix((&path).into())
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, approval)| approval))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    to_versioned_binary(&approvals)
+}
+
+pub fn list_reservations(
+    deps: Deps,
+    path: PathId,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<Binary> {
+    let limit = query_limit(limit);
+    let start = start_bound(start_after);
+    let path = load_key_spec(deps.storage)?.path(&path.contract, &path.channel_id, &path.denom);
+
+    let reservations = RESERVATIONS
+        .prefix((&path).into())
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, reservation)| reservation))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    to_versioned_binary(&reservations)
+}
+
+pub fn get_metrics(deps: Deps) -> StdResult<Binary> {
+    let metrics = METRICS.may_load(deps.storage)?.unwrap_or_default();
+    let metric = |name: &str, kind: MetricKind, value: u64| Metric {
+        name: name.to_string(),
+        kind,
+        value,
+    };
+
+    to_versioned_binary(&vec![
+        metric(
+            "packets_processed",
+            MetricKind::Counter,
+            metrics.packets_in + metrics.packets_out,
+        ),
+        metric("packets_in", MetricKind::Counter, metrics.packets_in),
+        metric("packets_out", MetricKind::Counter, metrics.packets_out),
+        metric(
+            "packets_undone",
+            MetricKind::Counter,
+            metrics.packets_undone,
+        ),
+        metric("rejected", MetricKind::Counter, metrics.rejected),
+        metric("paths", MetricKind::Gauge, metrics.paths),
+        metric(
+            "quarantined_paths",
+            MetricKind::Gauge,
+            metrics.quarantined_paths,
+        ),
+    ])
+}
+
+pub fn get_quarantined(
+    deps: Deps,
+    start_after: Option<(Addr, String, String)>,
+    limit: Option<u32>,
+) -> StdResult<Binary> {
+    let limit = query_limit(limit);
+    let start = start_bound(start_after);
+
+    let entries = QUARANTINE
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            let ((contract, channel_id, denom), raw) = item?;
+            Ok(QuarantinedEntry {
+                contract,
+                channel_id,
+                denom,
+                raw,
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    to_versioned_binary(&entries)
+}
+
+pub fn preview_path_change(
+    deps: Deps,
+    clock: &dyn TimeProvider,
+    path_msg: PathMsg,
+) -> StdResult<Binary> {
+    let config = load_tenant_config(deps.storage, &path_msg.contract_addr)?;
+    let key_spec = load_key_spec(deps.storage)?;
+    let error = check_path_msg(deps.storage, &config, &key_spec, &path_msg)
+        .err()
+        .map(|err| err.to_string());
+    let path = key_spec.path(
+        &path_msg.contract_addr,
+        &path_msg.channel_id,
+        &path_msg.denom,
+    );
+
+    let current = RATE_LIMIT_TRACKERS.may_load(deps.storage, (&path).into())?;
+    let new_path = current.is_none();
+    let current = current.unwrap_or_default();
+    let proposed: Vec<Quota> = path_msg.quotas.iter().map(Quota::from).collect();
+    let find = |quotas: &[Quota], name: &str| quotas.iter().find(|q| q.name == name).cloned();
+    let current_quotas: Vec<Quota> = current.iter().map(|l| l.quota.clone()).collect();
+
+    let mut added = vec![];
+    let mut replaced = vec![];
+    for after in &proposed {
+        match find(&current_quotas, &after.name) {
+            None => added.push(after.into()),
+            Some(before) if &before != after => replaced.push(QuotaChange {
+                before: (&before).into(),
+                after: after.into(),
+            }),
+            Some(_) => {}
+        }
+    }
+    let removed = current_quotas
+        .iter()
+        .filter(|quota| find(&proposed, &quota.name).is_none())
+        .map(QuotaResponse::from)
+        .collect();
+    let bucket_bps = load_config(deps.storage)?.capacity_bucket_bps;
+    let reset_flows = round_flows(current, bucket_bps, clock)
+        .iter()
+        .map(|limit| quota_usage(limit, clock))
+        .filter(|usage| !usage.inflow.is_zero() || !usage.outflow.is_zero())
+        .collect();
+
+    to_versioned_binary(&PathChangePreview {
+        contract: path.contract,
+        channel_id: path.channel,
+        denom: path.denom,
+        new_path,
+        added,
+        replaced,
+        removed,
+        reset_flows,
+        error,
+    })
+}
+
+// Each transfer runs at its own time, with the expiry grace of the config.
+// Rejected transfers leave the trackers as they were, like the reverted
+// transactions they would be
+pub fn simulate_sequence(
+    deps: Deps,
+    path: PathId,
+    transfers: Vec<SimulatedTransfer>,
+    proposed_quotas: Vec<QuotaMsg>,
+) -> StdResult<Binary> {
+    if transfers.len() > SIMULATION_MAX_TRANSFERS {
+        return Err(StdError::generic_err(format!(
+            "At most {SIMULATION_MAX_TRANSFERS} transfers can be simulated"
+        )));
+    }
+    if transfers.windows(2).any(|pair| pair[1].at < pair[0].at) {
+        return Err(StdError::generic_err(
+            "Simulated transfers must be in chronological order",
+        ));
+    }
+
+    let config = load_config(deps.storage)?;
+    let clock_at = |at| ContractClock {
+        now: at,
+        grace_nanos: config.grace_nanos,
+        height: 0,
+    };
+    let error = proposed_quotas
+        .iter()
+        .try_for_each(QuotaMsg::validate)
+        .and_then(|_| config.check_quotas(&proposed_quotas))
+        .err()
+        .map(|err| err.to_string());
+    let (Some(first), None) = (transfers.first(), &error) else {
+        return to_versioned_binary(&SimulationResponse {
+            outcomes: vec![],
+            rejected: 0,
+            usage: vec![],
+            error,
+        });
+    };
+
+    let path = resolve_path(
+        deps.storage,
+        Path::new(&path.contract, path.channel_id, path.denom),
+    )?;
+    let path_config = PATH_CONFIGS
+        .may_load(deps.storage, (&path).into())?
+        .unwrap_or_default();
+    let mut trackers = new_trackers(&proposed_quotas, &clock_at(first.at));
+    let mut outcomes = vec![];
+    for transfer in &transfers {
+        let clock = clock_at(transfer.at);
+        let original = trackers.clone();
+        let evaluate = |trackers: &mut Vec<RateLimit>, funds| {
+            evaluate_quotas(
+                trackers,
+                &path_config.evaluation_mode,
+                &path,
+                &transfer.direction,
+                funds,
+                transfer.channel_value,
+                &clock,
+            )
+        };
+        let outcome = match evaluate(&mut trackers, transfer.amount) {
+            Ok(results) => {
+                trackers = results;
+                SimulatedOutcome {
+                    accepted: transfer.amount,
+                    violations: vec![],
+                }
+            }
+            Err(ContractError::RateLimitExceded { violations, .. }) => {
+                trackers = original;
+                let accepted = match path_config.truncates(&transfer.direction) {
+                    true => fitting_amount(
+                        &trackers,
+                        &path_config.evaluation_mode,
+                        &transfer.direction,
+                        transfer.amount,
+                        transfer.channel_value,
+                        &clock,
+                    ),
+                    false => Uint128::zero(),
+                };
+                if !accepted.is_zero() {
+                    trackers = evaluate(&mut trackers, accepted)
+                        .map_err(|err| StdError::generic_err(err.to_string()))?;
+                }
+                SimulatedOutcome {
+                    accepted,
+                    violations,
+                }
+            }
+            Err(err) => return Err(StdError::generic_err(err.to_string())),
+        };
+        outcomes.push(outcome);
+    }
+
+    let last = clock_at(transfers[transfers.len() - 1].at);
+    to_versioned_binary(&SimulationResponse {
+        rejected: outcomes
+            .iter()
+            .filter(|o| o.accepted.is_zero() && !o.violations.is_empty())
+            .count() as u32,
+        outcomes,
+        usage: trackers.iter().map(|l| quota_usage(l, &last)).collect(),
+        error,
+    })
+}
+
+// The cursor of the next page of a scan over the paths that stopped after
+// `last`, unless no path is left
+fn next_start_after(
+    deps: Deps,
+    last: Option<(Addr, String, String)>,
+) -> Option<(Addr, String, String)> {
+    let last = last?;
+    RATE_LIMIT_TRACKERS
+        .keys(
+            deps.storage,
+            start_bound(Some(last.clone())),
+            None,
+            Order::Ascending,
+        )
+        .next()
+        .map(|_| last)
+}
+
+// Paths are scanned like in get_filtered_state. Quota names are unique within a
+// path, so each path shows up at most once
+pub fn get_quota_across_paths(
+    deps: Deps,
+    clock: &dyn TimeProvider,
+    quota_name: String,
+    start_after: Option<(Addr, String, String)>,
+    limit: Option<u32>,
+) -> StdResult<Binary> {
+    let limit = query_limit(limit);
+    let start = start_bound(start_after);
+    let bucket_bps = load_config(deps.storage)?.capacity_bucket_bps;
+
+    let mut paths = vec![];
+    let mut last = None;
+    let scanned = RATE_LIMIT_TRACKERS
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(FILTER_SCAN_LIMIT as usize);
+    for item in scanned {
+        if paths.len() == limit {
+            break;
+        }
+        let (key, limits) = item?;
+        let limits = round_flows(limits, bucket_bps, clock);
+        if let Some(limit) = limits.iter().find(|l| l.quota.name == quota_name) {
+            let (contract, channel_id, denom) = key.clone();
+            paths.push(PathQuotaUsage {
+                contract,
+                channel_id,
+                denom,
+                usage: quota_usage(limit, clock),
+            });
+        }
+        last = Some(key);
+    }
+
+    to_versioned_binary(&QuotaAcrossPathsResponse {
+        paths,
+        next_start_after: next_start_after(deps, last),
+    })
+}
+
+pub fn get_fees(deps: Deps, contract: Addr, denom: String) -> StdResult<Binary> {
+    let account = FEES
+        .may_load(deps.storage, (&contract, &denom))?
+        .unwrap_or_default();
+    to_versioned_binary(&account)
+}
+
+// Checks a page of paths, then of pending sends once the paths are done. A path
+// breaks period_end when a flow has none, stats_cover_flows when a period
+// started today holds more than the daily stats of the path, and
+// in_flight_index when its in flight value isn't the sum of its pending sends
+pub fn check_invariants(
+    deps: Deps,
+    clock: &dyn TimeProvider,
+    start_after: Option<InvariantCursor>,
+    limit: Option<u32>,
+) -> StdResult<Binary> {
+    let limit = query_limit(limit);
+    let mut violations = vec![];
+
+    let (path_start, send_start) = match start_after {
+        None => (None, None),
+        Some(InvariantCursor::Path(key)) => (Some(key), None),
+        Some(InvariantCursor::PendingSend(key)) => (None, Some(key)),
+    };
+    let mut checked = 0;
+    if send_start.is_none() {
+        let keys = RATE_LIMIT_TRACKERS
+            .keys(
+                deps.storage,
+                start_bound(path_start),
+                None,
+                Order::Ascending,
+            )
+            .take(limit)
+            .collect::<StdResult<Vec<_>>>()?;
+        for key in &keys {
+            check_path_invariants(deps, clock, key, &mut violations)?;
+        }
+        checked = keys.len();
+        if let Some(last) = keys.last().filter(|_| checked == limit) {
+            return to_versioned_binary(&InvariantsResponse {
+                violations,
+                next: Some(InvariantCursor::Path(last.clone())),
+            });
+        }
+    }
+
+    let key_spec = load_key_spec(deps.storage)?;
+    let sends = PENDING_SENDS
+        .range(
+            deps.storage,
+            start_bound(send_start),
+            None,
+            Order::Ascending,
+        )
+        .take(limit - checked)
+        .collect::<StdResult<Vec<_>>>()?;
+    for ((contract, channel, sequence), pending) in &sends {
+        let entry = format!("{contract}/{channel}/{sequence}");
+        let path = key_spec.path(contract, channel, &pending.denom);
+        if !RATE_LIMIT_TRACKERS.has(deps.storage, (&path).into())
+            && !PATH_CONFIGS.has(deps.storage, (&path).into())
+        {
+            violations.push(InvariantViolation {
+                invariant: "pending_send_path".to_string(),
+                entry: entry.clone(),
+                detail: format!("no path {}/{}", path.channel, path.denom),
+            });
+        }
+        for period in &pending.periods {
+            if period.period_end.nanos() == 0 {
+                violations.push(InvariantViolation {
+                    invariant: "period_end".to_string(),
+                    entry: entry.clone(),
+                    detail: format!("quota {} has no period_end", period.quota),
+                });
+            }
+        }
+    }
+
+    let next = match sends.last() {
+        Some((key, _)) if checked + sends.len() == limit => {
+            Some(InvariantCursor::PendingSend(key.clone()))
+        }
+        _ => None,
+    };
+    to_versioned_binary(&InvariantsResponse { violations, next })
+}
+
+fn check_path_invariants(
+    deps: Deps,
+    clock: &dyn TimeProvider,
+    key: &(Addr, String, String),
+    violations: &mut Vec<InvariantViolation>,
+) -> StdResult<()> {
+    let (contract, channel, denom) = key;
+    let entry = format!("{contract}/{channel}/{denom}");
+    let path = Path::new(contract, channel, denom);
+    let mut violation = |invariant: &str, detail: String| {
+        violations.push(InvariantViolation {
+            invariant: invariant.to_string(),
+            entry: entry.clone(),
+            detail,
+        })
+    };
+
+    let trackers = RATE_LIMIT_TRACKERS.load(deps.storage, key.clone())?;
+    let now = clock.now();
+    let day_start = now.seconds() / SECONDS_PER_DAY * SECONDS_PER_DAY;
+    let stats = DAILY_STATS
+        .may_load(deps.storage, key.clone())?
+        .filter(|stats| stats.day == now.seconds() / SECONDS_PER_DAY);
+    for limit in &trackers {
+        let flow = &limit.flow;
+        if flow.period_end.nanos() == 0 {
+            violation(
+                "period_end",
+                format!("quota {} has no period_end", limit.quota.name),
+            );
+            continue;
+        }
+        let Some(stats) = &stats else {
+            continue;
+        };
+        for (direction, value, total) in [
+            (FlowType::In, flow.inflow, stats.inflow),
+            (FlowType::Out, flow.outflow, stats.outflow),
+        ] {
+            // Only periods that started today are covered by the daily stats
+            let period_end = flow.period_end_on(&direction);
+            let started = period_end
+                .nanos()
+                .saturating_sub(limit.quota.duration_on(&direction));
+            if period_end > now && started >= day_start * NANOS_PER_SECOND && value > total {
+                violation(
+                    "stats_cover_flows",
+                    format!(
+                        "quota {} has a {direction:?} flow of {value} above the {total} of the day",
+                        limit.quota.name
+                    ),
+                );
+            }
+        }
+    }
+
+    let stored = IN_FLIGHT
+        .may_load(deps.storage, key.clone())?
+        .unwrap_or_default();
+    let pending = pending_in_flight(deps.storage, &path)?;
+    if stored != pending {
+        violation(
+            "in_flight_index",
+            format!("in flight is {stored} while the pending sends add up to {pending}"),
+        );
+    }
+    Ok(())
+}
+
+pub fn get_filter(deps: Deps, filter_id: String) -> StdResult<Binary> {
+    to_versioned_binary(&FILTERS.load(deps.storage, &filter_id)?)
+}
+
+// Filters are evaluated against every path, so a page stops after scanning
+// FILTER_SCAN_LIMIT paths even when fewer than `limit` matched
+pub fn get_filtered_state(
+    deps: Deps,
+    clock: &dyn TimeProvider,
+    filter_id: String,
+    start_after: Option<(Addr, String, String)>,
+    limit: Option<u32>,
+) -> StdResult<Binary> {
+    let filter = FILTERS.load(deps.storage, &filter_id)?.filter;
+    let limit = query_limit(limit);
+    let start = start_bound(start_after);
+    let bucket_bps = load_config(deps.storage)?.capacity_bucket_bps;
+
+    let mut paths = vec![];
+    let mut last = None;
+    let scanned = RATE_LIMIT_TRACKERS
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(FILTER_SCAN_LIMIT as usize);
+    for item in scanned {
+        if paths.len() == limit {
+            break;
+        }
+        let (key, limits) = item?;
+        let config = PATH_CONFIGS.may_load(deps.storage, key.clone())?;
+        if filter.matches(&key, config.as_ref()) {
+            let (contract, channel_id, denom) = key.clone();
+            paths.push(FilteredPath {
+                contract,
+                channel_id,
+                denom,
+                tags: config.map(|config| config.tags).unwrap_or_default(),
+                usage: round_flows(limits, bucket_bps, clock)
+                    .iter()
+                    .map(|l| quota_usage(l, clock))
+                    .collect(),
+            });
+        }
+        last = Some(key);
+    }
+    to_versioned_binary(&FilteredStateResponse {
+        paths,
+        next_start_after: next_start_after(deps, last),
+    })
+}
+
+// The trackers are keyed by (contract, channel_id, denom), so all the paths of a
+// contract can be iterated through the contract prefix.
+pub fn get_contract_usage(
+    deps: Deps,
+    clock: &dyn TimeProvider,
+    contract: Addr,
+    start_after: Option<(String, String)>,
+    limit: Option<u32>,
+) -> StdResult<Binary> {
+    let limit = query_limit(limit);
+    let start = start_bound(start_after);
+    let bucket_bps = load_config(deps.storage)?.capacity_bucket_bps;
+
+    let paths = RATE_LIMIT_TRACKERS
+        .sub_prefix(contract.clone())
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            let ((channel_id, denom), limits) = item?;
+            Ok(PathUsage {
+                channel_id,
+                denom,
+                quotas: round_flows(limits, bucket_bps, clock)
+                    .iter()
+                    .map(|l| quota_usage(l, clock))
+                    .collect(),
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let mut totals: Vec<QuotaUsage> = vec![];
+    let mut totaled = 0;
+    for item in RATE_LIMIT_TRACKERS.sub_prefix(contract.clone()).range(
+        deps.storage,
+        None,
+        None,
+        Order::Ascending,
+    ) {
+        totaled += 1;
+        if totaled > FILTER_SCAN_LIMIT {
+            break;
+        }
+        let (_, limits) = item?;
+        let limits = round_flows(limits, bucket_bps, clock);
+        for usage in limits.iter().map(|l| quota_usage(l, clock)) {
+            match totals.iter_mut().find(|t| t.name == usage.name) {
+                Some(total) => {
+                    total.inflow = total.inflow.saturating_add(usage.inflow);
+                    total.outflow = total.outflow.saturating_add(usage.outflow);
+                    total.max_in = total.max_in.saturating_add(usage.max_in);
+                    total.max_out = total.max_out.saturating_add(usage.max_out);
+                }
+                None => totals.push(QuotaUsage {
+                    period_end: None,
+                    recv_period_end: None,
+                    ..usage
+                }),
+            }
+        }
+    }
+
+    to_versioned_binary(&ContractUsageResponse {
+        contract,
+        totals,
+        totals_complete: totaled <= FILTER_SCAN_LIMIT,
+        paths,
+    })
+}
+
+fn quota_usage(limit: &RateLimit, clock: &dyn TimeProvider) -> QuotaUsage {
+    let (max_in, max_out) = limit
+        .quota
+        .capacity_at(limit.flow.period_channel_value, clock.now());
+    let mut flow = limit.flow.clone();
+    flow.roll_over(clock, &limit.quota);
+    let (inflow, outflow) = (flow.inflow, flow.outflow);
+    QuotaUsage {
+        name: limit.quota.name.clone(),
+        inflow,
+        outflow,
+        max_in,
+        max_out,
+        period_end: Some(limit.flow.period_end),
+        recv_period_end: limit.flow.recv_period_end,
+    }
+}
+
+// Keep in sync with the Item and Map namespaces of state.rs and time.rs. Entries
+// without a key are Items
+const STORAGE_LAYOUT: &[(&str, &str, Option<&str>, &str)] = &[
+    ("CONFIG", "config", None, "Config"),
+    (
+        "TENANT_CONFIG",
+        "tenant_config",
+        Some("contract: Addr"),
+        "TenantConfig",
+    ),
+    ("KEY_SPEC", "key_spec", None, "KeySpec"),
+    (
+        "RATE_LIMIT_TRACKERS",
+        "flow_v3",
+        Some("(contract: Addr, channel_id: String, denom: String)"),
+        "Vec<RateLimit>",
+    ),
+    ("CHANGE_SEQ", "change_seq", None, "u64"),
+    (
+        "CHANGE_LOG",
+        "change_log",
+        Some("seq: u64"),
+        "(Addr, String, String)",
+    ),
+    (
+        "CHANNEL_ASSETS",
+        "channel_assets",
+        Some("(contract: Addr, channel_id: String)"),
+        "Vec<String>",
+    ),
+    (
+        "CHANNEL_DEFAULT_QUOTAS",
+        "channel_default_quotas",
+        Some("(contract: Addr, channel_id: String)"),
+        "Vec<QuotaMsg>",
+    ),
+    (
+        "CHANNEL_TRACKERS",
+        "channel_trackers",
+        Some("(contract: Addr, channel_id: String)"),
+        "Vec<RateLimit>",
+    ),
+    (
+        "HISTORY",
+        "history",
+        Some("(contract: Addr, channel_id: String, denom: String)"),
+        "Vec<HistoryEntry>",
+    ),
+    ("TICK_CURSOR", "tick_cursor", None, "(Addr, String, String)"),
+    (
+        "REJECTIONS",
+        "rejections",
+        Some("(contract: Addr, channel_id: String, denom: String)"),
+        "Vec<Rejection>",
+    ),
+    (
+        "BLOCK_REJECTIONS",
+        "block_rejections",
+        Some("(contract: Addr, channel_id: String, denom: String)"),
+        "BlockRejections",
+    ),
+    (
+        "PATH_AUDIT_LOG",
+        "path_audit_log",
+        Some("((contract: Addr, channel_id: String, denom: String), seq: u64)"),
+        "AuditEntry",
+    ),
+    (
+        "CLASS_TRACKERS",
+        "class_flow_v2",
+        Some("((contract: Addr, channel_id: String, denom: String), class: String)"),
+        "Vec<RateLimit>",
+    ),
+    (
+        "TAG_TRACKERS",
+        "tag_flow",
+        Some("((contract: Addr, channel_id: String, denom: String), tag: String)"),
+        "Vec<RateLimit>",
+    ),
+    (
+        "CHAIN_TRACKERS",
+        "chain_flow",
+        Some("(contract: Addr, chain_id: String, denom: String)"),
+        "Vec<RateLimit>",
+    ),
+    (
+        "PATH_REDIRECTS",
+        "path_redirects",
+        Some("(contract: Addr, channel_id: String, denom: String)"),
+        "(Addr, String, String)",
+    ),
+    (
+        "QUARANTINE",
+        "quarantine",
+        Some("(contract: Addr, channel_id: String, denom: String)"),
+        "Binary",
+    ),
+    (
+        "PATH_CONFIGS",
+        "path_configs",
+        Some("(contract: Addr, channel_id: String, denom: String)"),
+        "PathConfig",
+    ),
+    (
+        "ESCROW_BALANCES",
+        "escrow_balances",
+        Some("(contract: Addr, channel_id: String, denom: String)"),
+        "Uint128",
+    ),
+    ("METRICS", "metrics", None, "Metrics"),
+    (
+        "PENDING_SENDS",
+        "pending_sends",
+        Some("(contract: Addr, channel_id: String, sequence: u64)"),
+        "PendingSend",
+    ),
+    (
+        "PENDING_ICA_SENDS",
+        "pending_ica_sends",
+        Some("(contract: Addr, channel_id: String, sequence: u64)"),
+        "Vec<PendingSend>",
+    ),
+    (
+        "IN_FLIGHT",
+        "in_flight",
+        Some("(contract: Addr, channel_id: String, denom: String)"),
+        "Uint256",
+    ),
+    (
+        "PENDING_IN_FLIGHT",
+        "pending_in_flight",
+        Some("(contract: Addr, channel_id: String, denom: String)"),
+        "Uint256",
+    ),
+    (
+        "DAILY_STATS",
+        "daily_stats",
+        Some("(contract: Addr, channel_id: String, denom: String)"),
+        "DailyStats",
+    ),
+    (
+        "ESCROW_VALUES",
+        "escrow_values",
+        Some("(contract: Addr, channel_id: String, denom: String)"),
+        "EscrowValue",
+    ),
+    (
+        "REGISTERED_BRIDGES",
+        "registered_bridges",
+        Some("bridge: Addr"),
+        "Empty",
+    ),
+    (
+        "RECEIVER_CLASSES",
+        "receiver_classes",
+        Some("address: String"),
+        "ReceiverClass",
+    ),
+    ("IDEMPOTENCY_SEQ", "idempotency_seq", None, "u64"),
+    (
+        "IDEMPOTENCY_KEYS",
+        "idempotency_keys",
+        Some("(sender: Addr, key: String)"),
+        "u64",
+    ),
+    (
+        "IDEMPOTENCY_LOG",
+        "idempotency_log",
+        Some("seq: u64"),
+        "(Addr, String)",
+    ),
+    ("MULTISIG", "multisig", None, "Multisig"),
+    ("PROPOSALS", "proposals", Some("id: u64"), "Proposal"),
+    ("PROPOSAL_COUNT", "proposal_count", None, "u64"),
+    (
+        "PRE_APPROVALS",
+        "pre_approvals",
+        Some("((contract: Addr, channel_id: String, denom: String), id: u64)"),
+        "PreApproval",
+    ),
+    (
+        "OPEN_PRE_APPROVALS",
+        "open_pre_approvals",
+        Some("((contract: Addr, channel_id: String, denom: String), amount: u128, id: u64)"),
+        "Empty",
+    ),
+    ("PRE_APPROVAL_COUNT", "pre_approval_count", None, "u64"),
+    (
+        "STREAKS",
+        "streaks",
+        Some("(contract: Addr, channel_id: String, denom: String)"),
+        "Streak",
+    ),
+    (
+        "COUNTERPARTY_STATES",
+        "counterparty_states",
+        Some("(contract: Addr, channel_id: String, denom: String)"),
+        "CounterpartyState",
+    ),
+    (
+        "RESERVATIONS",
+        "reservations",
+        Some("((contract: Addr, channel_id: String, denom: String), id: u64)"),
+        "Reservation",
+    ),
+    (
+        "ACTIVE_RESERVATIONS",
+        "active_reservations",
+        Some("((contract: Addr, channel_id: String, denom: String), id: u64)"),
+        "Empty",
+    ),
+    ("RESERVATION_COUNT", "reservation_count", None, "u64"),
+    (
+        "FILTERS",
+        "filters",
+        Some("filter_id: String"),
+        "StoredFilter",
+    ),
+    (
+        "FEES",
+        "fees",
+        Some("(contract: Addr, denom: String)"),
+        "FeeAccount",
+    ),
+    ("PAUSED", "paused", None, "bool"),
+    ("LAST_HEARTBEAT", "last_heartbeat", None, "Timestamp"),
+    (
+        "DEGRADED_TRACKERS",
+        "degraded_flow",
+        Some("(contract: Addr, channel_id: String, denom: String)"),
+        "Vec<RateLimit>",
+    ),
+    ("PAUSE_EXPIRY", "pause_expiry", None, "Timestamp"),
+    ("MAINTENANCE", "maintenance", None, "bool"),
+    (
+        "MIGRATION_PROGRESS",
+        "migration_progress",
+        None,
+        "MigrationProgress",
+    ),
+    (
+        "PAUSED_DIRECTIONS",
+        "paused_directions",
+        Some("direction: String"),
+        "Empty",
+    ),
+    (
+        "PAUSED_CONTRACTS",
+        "paused_contracts",
+        Some("contract: Addr"),
+        "Empty",
+    ),
+    (
+        "PAUSED_CHANNELS",
+        "paused_channels",
+        Some("(contract: Addr, channel_id: String)"),
+        "Empty",
+    ),
+    (
+        "PAUSED_PATHS",
+        "paused_paths",
+        Some("(contract: Addr, channel_id: String, denom: String)"),
+        "Empty",
+    ),
+    (
+        "PACKET_SEQUENCES",
+        "packet_sequences",
+        Some("(contract: Addr, channel_id: String, direction: String)"),
+        "u64",
+    ),
+    ("MOCK_TIME", "mock_time", None, "Timestamp"),
+];
+
+pub fn get_storage_layout() -> StdResult<Binary> {
+    let entries = STORAGE_LAYOUT
+        .iter()
+        .map(|(name, namespace, key, value)| StorageEntry {
+            name: name.to_string(),
+            namespace: namespace.to_string(),
+            kind: match key {
+                Some(_) => StorageKind::Map,
+                None => StorageKind::Item,
+            },
+            key: key.map(str::to_string),
+            value: value.to_string(),
+        })
+        .collect();
+
+    to_versioned_binary(&StorageLayoutResponse {
+        key_encoding: "Items are stored under their namespace. Map keys are the namespace and \
+            every key part but the last, each prefixed by its length as a big-endian u16, \
+            followed by the last key part. Addr and String parts are UTF-8, u64 parts are \
+            big-endian. Values are JSON"
+            .to_string(),
+        entries,
+        legacy_namespaces: LEGACY_NAMESPACES
+            .iter()
+            .map(|(old, new, _)| (old.to_string(), new.to_string()))
+            .collect(),
+    })
 }
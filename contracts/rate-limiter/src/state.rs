@@ -1,9 +1,300 @@
 use cosmwasm_schema::cw_serde;
-use cosmwasm_std::{Addr, Timestamp, Uint128};
+use cosmwasm_std::{
+    from_json, to_json_vec, Addr, Binary, Decimal, Empty, Order, StdError, StdResult, Storage,
+    Timestamp, Uint128, Uint256,
+};
 
-use cw_storage_plus::Map;
+use cw_storage_plus::{Item, Map};
+use sha2::{Digest, Sha256};
 
-use crate::{msg::QuotaMsg, ContractError};
+use crate::{
+    msg::{ExecuteMsg, QuotaMsg, QuotaUsage, QuotaViolation},
+    packet::ReceiverClass,
+    time::TimeProvider,
+    ContractError,
+};
+
+/// UnconfiguredBehavior decides what happens to packets on paths without quotas
+#[cw_serde]
+#[derive(Default)]
+pub enum UnconfiguredBehavior {
+    #[default]
+    Allow,
+    Reject,
+}
+
+/// Config holds the contract-wide settings, managed by the owner
+#[cw_serde]
+#[derive(Default)]
+pub struct Config {
+    /// Checks every packet against this contract (see `PolicyQueryMsg`) before
+    /// it's accounted
+    #[serde(default)]
+    pub policy_contract: Option<Addr>,
+    #[serde(default)]
+    pub unconfigured_behavior: UnconfiguredBehavior,
+    /// Apply to the paths of any contract that have neither their own quotas
+    /// nor channel defaults
+    #[serde(default)]
+    pub default_quotas: Vec<QuotaMsg>,
+    /// The last fallback: with the Allow unconfigured behavior, paths without
+    /// any other quotas get it instead of unlimited flow
+    #[serde(default)]
+    pub default_new_denom_quota: Option<QuotaMsg>,
+    /// Caps the quotas of a path, checked when quotas are added. Paths created
+    /// by inheriting default quotas aren't capped, as that would block their
+    /// packets
+    #[serde(default)]
+    pub max_quotas_per_path: Option<u32>,
+    /// Caps the paths of a contract, checked when paths are added, same as
+    /// max_quotas_per_path
+    #[serde(default)]
+    pub max_paths_per_contract: Option<u32>,
+    /// Caps the distinct denoms tracked on a channel of a contract, so packets
+    /// with garbage denoms can't grow the stats forever. Packets of the denoms
+    /// past the cap without quotas go through untracked
+    #[serde(default)]
+    pub max_denoms_per_channel: Option<u32>,
+    /// Only accepts packets from contracts with paths or channel defaults of
+    /// their own, or registered by the owner as bridges
+    #[serde(default)]
+    pub require_registered_sender: bool,
+    /// Asked on period rollovers of percentage quotas for the escrow balance of
+    /// the channel on the counterparty (see `EscrowQuerierMsg`), which is
+    /// reported back through `ExecuteMsg::EscrowBalanceCallback`
+    #[serde(default)]
+    pub escrow_querier: Option<Addr>,
+    /// Packets arriving within it before the end of a period are accounted in
+    /// the next one, to tolerate block time jitter
+    #[serde(default)]
+    pub grace_nanos: u64,
+    /// Pins the layout of the response attributes, whose names and relative
+    /// order are kept within a version. Unset follows the latest version
+    #[serde(default)]
+    pub attribute_schema_version: Option<u32>,
+    /// Makes the channel default quotas the parent cap of the path quotas of
+    /// the same name, see `HierarchyStrictness`
+    #[serde(default)]
+    pub quota_hierarchy: HierarchyStrictness,
+    /// Caps the duration of the quotas, in seconds. Quotas stored before it was
+    /// lowered are clamped the next time their path is used. Defaults to
+    /// `DEFAULT_MAX_QUOTA_DURATION`
+    #[serde(default)]
+    pub max_quota_duration: Option<u64>,
+    /// Rounds the remaining capacity down, and the flows up, to multiples of
+    /// this share of the capacity, in basis points, so front-ends and bots
+    /// can't use the exact headroom to time drains. Unset returns exact values
+    #[serde(default)]
+    pub capacity_bucket_bps: Option<u32>,
+    /// Expects a keeper to prove the monitoring is up, see `HeartbeatPolicy`
+    #[serde(default)]
+    pub heartbeat: Option<HeartbeatPolicy>,
+    /// How long, in seconds, a channel value reported by the escrow querier is
+    /// used for before on_stale_channel_value applies. Unset uses it however
+    /// old
+    #[serde(default)]
+    pub channel_value_max_age: Option<u64>,
+    #[serde(default)]
+    pub on_stale_channel_value: OnStaleChannelValue,
+    /// Runs the quotas of the paths added from now on at a share of their
+    /// capacity for their first periods, see `ProbationPolicy`
+    #[serde(default)]
+    pub probation: Option<ProbationPolicy>,
+    /// Bounds the rejections of a path answered with data in a block. Past it,
+    /// rejected sends are dropped without being recorded for the rest of the
+    /// block. Zero isn't accepted
+    #[serde(default)]
+    pub max_rejections_per_block: Option<u32>,
+}
+
+/// ProbationPolicy limits new paths to share_bps of the capacity of their
+/// quotas for their first `periods` periods, after which they graduate
+#[cw_serde]
+pub struct ProbationPolicy {
+    pub periods: u32,
+    pub share_bps: u32,
+}
+
+/// OnStaleChannelValue is what a stale channel value is replaced with. Floor
+/// takes a zero value, leaving percentage quotas their min_capacity. Reject
+/// refuses the packets of the path
+#[cw_serde]
+#[derive(Default)]
+pub enum OnStaleChannelValue {
+    #[default]
+    Floor,
+    Reject,
+}
+
+/// HeartbeatPolicy expects a Heartbeat from the keeper at least every
+/// `interval` seconds, up to a year. Once one is missed, sends are handled as
+/// `on_missed` says until the next heartbeat, so an outage of the monitoring
+/// can't hide a drain. Receives are not affected
+#[cw_serde]
+pub struct HeartbeatPolicy {
+    pub keeper: Addr,
+    pub interval: u64,
+    #[serde(default)]
+    pub on_missed: OnMissedHeartbeat,
+}
+
+/// OnMissedHeartbeat is what happens to sends while heartbeats are missed.
+/// Freeze rejects them. Degrade checks them against `quotas` on top of the
+/// quotas of the path, the degraded flows of each path being kept in
+/// `DEGRADED_TRACKERS`
+#[cw_serde]
+#[derive(Default)]
+pub enum OnMissedHeartbeat {
+    #[default]
+    Freeze,
+    Degrade {
+        quotas: Vec<QuotaMsg>,
+    },
+}
+
+/// HierarchyStrictness decides how the channel default quotas bound the quotas
+/// of the same name of the paths of the channel. It's checked when path quotas
+/// or channel defaults are set: with Max each path quota must fit in its
+/// parent, with Sum the path quotas of the channel must fit in it together.
+/// Percentage quotas have no fixed capacity and aren't compared.
+///
+/// Unless Off, transfers are also accounted on the flows of the whole channel
+/// (see `CHANNEL_TRACKERS`), which have to fit in the channel defaults on top
+/// of the quotas of the path.
+#[cw_serde]
+#[derive(Default)]
+pub enum HierarchyStrictness {
+    #[default]
+    Off,
+    Max,
+    Sum,
+}
+
+/// ATTRIBUTE_SCHEMA_VERSION is the latest layout of the response attributes.
+/// Bump it, keeping the previous layouts available, when attributes are renamed,
+/// removed or reordered
+pub const ATTRIBUTE_SCHEMA_VERSION: u32 = 1;
+
+pub const CONFIG: Item<Config> = Item::new("config");
+
+/// Loads the config, falling back to the defaults if it was never stored
+pub fn load_config(storage: &dyn Storage) -> StdResult<Config> {
+    Ok(CONFIG.may_load(storage)?.unwrap_or_default())
+}
+
+/// TenantConfig overrides settings of the config for the paths of one bridge
+/// contract, when several bridges share the limiter. Unset fields fall back to
+/// the config. Tenants are paused with `PauseScope::Contract`, the global
+/// pause applies to all of them
+#[cw_serde]
+#[derive(Default)]
+pub struct TenantConfig {
+    #[serde(default)]
+    pub unconfigured_behavior: Option<UnconfiguredBehavior>,
+    #[serde(default)]
+    pub default_quotas: Option<Vec<QuotaMsg>>,
+    #[serde(default)]
+    pub default_new_denom_quota: Option<QuotaMsg>,
+    #[serde(default)]
+    pub max_quotas_per_path: Option<u32>,
+    #[serde(default)]
+    pub max_paths_per_contract: Option<u32>,
+    #[serde(default)]
+    pub max_denoms_per_channel: Option<u32>,
+    #[serde(default)]
+    pub attribute_schema_version: Option<u32>,
+}
+
+/// TENANT_CONFIG maps a bridge contract to the settings it overrides
+pub const TENANT_CONFIG: Map<&Addr, TenantConfig> = Map::new("tenant_config");
+
+/// Loads the config as it applies to the paths of a bridge contract
+pub fn load_tenant_config(storage: &dyn Storage, contract: &Addr) -> StdResult<Config> {
+    let config = load_config(storage)?;
+    Ok(match TENANT_CONFIG.may_load(storage, contract)? {
+        Some(tenant) => config.with_tenant(tenant),
+        None => config,
+    })
+}
+
+impl Config {
+    /// The config with the overrides of a tenant applied
+    pub fn with_tenant(self, tenant: TenantConfig) -> Config {
+        Config {
+            unconfigured_behavior: tenant
+                .unconfigured_behavior
+                .unwrap_or(self.unconfigured_behavior),
+            default_quotas: tenant.default_quotas.unwrap_or(self.default_quotas),
+            default_new_denom_quota: tenant
+                .default_new_denom_quota
+                .or(self.default_new_denom_quota),
+            max_quotas_per_path: tenant.max_quotas_per_path.or(self.max_quotas_per_path),
+            max_paths_per_contract: tenant
+                .max_paths_per_contract
+                .or(self.max_paths_per_contract),
+            max_denoms_per_channel: tenant
+                .max_denoms_per_channel
+                .or(self.max_denoms_per_channel),
+            attribute_schema_version: tenant
+                .attribute_schema_version
+                .or(self.attribute_schema_version),
+            ..self
+        }
+    }
+
+    pub fn attribute_schema_version(&self) -> u32 {
+        self.attribute_schema_version
+            .unwrap_or(ATTRIBUTE_SCHEMA_VERSION)
+    }
+
+    pub fn check_quota_count(&self, count: usize) -> Result<(), ContractError> {
+        match self.max_quotas_per_path {
+            Some(max) if count > max as usize => Err(ContractError::TooManyQuotas { max, count }),
+            _ => Ok(()),
+        }
+    }
+
+    /// The longest a quota can last, in nanoseconds
+    pub fn max_quota_duration_nanos(&self) -> u64 {
+        self.max_quota_duration
+            .unwrap_or(DEFAULT_MAX_QUOTA_DURATION)
+            .saturating_mul(NANOS_PER_SECOND)
+    }
+
+    /// Checks that none of the durations of the quotas is above the maximum
+    pub fn check_quota_durations(&self, quotas: &[QuotaMsg]) -> Result<(), ContractError> {
+        let max_nanos = self.max_quota_duration_nanos();
+        for msg in quotas {
+            let quota = Quota::from(msg);
+            let duration_nanos = quota
+                .duration_on(&FlowType::In)
+                .max(quota.duration_on(&FlowType::Out))
+                .max(quota.duration_nanos);
+            if duration_nanos > max_nanos {
+                return Err(ContractError::QuotaDurationTooLong {
+                    name: quota.name,
+                    duration_nanos,
+                    max_nanos,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// The checks the config applies to the quotas of a path
+    pub fn check_quotas(&self, quotas: &[QuotaMsg]) -> Result<(), ContractError> {
+        self.check_quota_count(quotas.len())?;
+        self.check_quota_durations(quotas)
+    }
+}
+
+/// DEFAULT_MAX_QUOTA_DURATION is the maximum duration of the quotas, in
+/// seconds, when the config doesn't set one: ten years
+pub const DEFAULT_MAX_QUOTA_DURATION: u64 = 10 * 365 * 86_400;
+
+/// MAX_QUOTA_DURATION bounds the max_quota_duration of the config, in seconds,
+/// so the ends of the periods always fit in a timestamp: a hundred years
+pub const MAX_QUOTA_DURATION: u64 = 100 * 365 * 86_400;
 
 #[cw_serde]
 pub struct Path {
@@ -13,15 +304,121 @@ pub struct Path {
 }
 
 impl Path {
+    /// The denom is normalized, so paths match whatever form of an IBC denom
+    /// they are given
     pub fn new(contract: &Addr, channel: impl Into<String>, denom: impl Into<String>) -> Self {
         Path {
             contract: contract.to_owned(),
             channel: channel.into(),
-            denom: denom.into(),
+            denom: normalize_denom(&denom.into()),
+        }
+    }
+}
+
+/// KeyDimension is a part of a packet that paths are keyed by, besides the
+/// contract reporting it
+#[cw_serde]
+pub enum KeyDimension {
+    Channel,
+    Denom,
+}
+
+/// WILDCARD stands for the dimensions a deployment doesn't key its paths by
+pub const WILDCARD: &str = "*";
+
+/// KeySpec is the set of dimensions paths are keyed by, chosen at instantiate.
+/// Dimensions left out are stored as WILDCARD, so all the packets differing
+/// only on them share their limits, while the keys keep the same layout
+/// whatever the spec. Messages naming a path are keyed the same way, so the
+/// missing dimensions can be given any value. Escrowed funds, fees and pending
+/// sends keep the denom of the packet
+#[cw_serde]
+pub struct KeySpec {
+    pub dimensions: Vec<KeyDimension>,
+}
+
+impl Default for KeySpec {
+    fn default() -> Self {
+        KeySpec {
+            dimensions: vec![KeyDimension::Channel, KeyDimension::Denom],
         }
     }
 }
 
+impl KeySpec {
+    /// The path the packets of a contract on the channel for the denom are
+    /// accounted on
+    pub fn path(&self, contract: &Addr, channel: &str, denom: &str) -> Path {
+        Path::new(
+            contract,
+            self.key(KeyDimension::Channel, channel),
+            self.key(KeyDimension::Denom, denom),
+        )
+    }
+
+    /// Each dimension can only be listed once
+    pub fn validate(&self) -> Result<(), ContractError> {
+        let mut dimensions = self.dimensions.iter();
+        while let Some(dimension) = dimensions.next() {
+            if dimensions.as_slice().contains(dimension) {
+                return Err(ContractError::DuplicateKeyDimension {
+                    dimension: dimension.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// The channel the paths of a channel are keyed by
+    pub fn channel(&self, channel: &str) -> String {
+        self.key(KeyDimension::Channel, channel)
+    }
+
+    /// The denom the paths of a denom are keyed by
+    pub fn denom(&self, denom: &str) -> String {
+        self.key(KeyDimension::Denom, &normalize_denom(denom))
+    }
+
+    fn key(&self, dimension: KeyDimension, value: &str) -> String {
+        match self.dimensions.contains(&dimension) {
+            true => value.to_string(),
+            false => WILDCARD.to_string(),
+        }
+    }
+}
+
+pub const KEY_SPEC: Item<KeySpec> = Item::new("key_spec");
+
+/// Loads the key spec, falling back to every dimension for contracts
+/// instantiated before it existed
+pub fn load_key_spec(storage: &dyn Storage) -> StdResult<KeySpec> {
+    Ok(KEY_SPEC.may_load(storage)?.unwrap_or_default())
+}
+
+/// Returns the canonical form of a denom. IBC hashes are uppercased, as the
+/// chain prints them, and full traces (`transfer/channel-0/uatom`) are replaced
+/// by their `ibc/` hash. Other denoms are case sensitive and kept as they are.
+pub fn normalize_denom(denom: &str) -> String {
+    let denom = denom.trim();
+    if let (Some(prefix), Some(hash)) = (denom.get(..4), denom.get(4..)) {
+        if prefix.eq_ignore_ascii_case("ibc/") {
+            return format!("ibc/{}", hash.to_uppercase());
+        }
+    }
+
+    // A trace is a list of port/channel hops followed by the base denom
+    let segments: Vec<&str> = denom.split('/').collect();
+    let is_trace = segments.len() >= 3
+        && segments[1].starts_with("channel-")
+        && !segments[0].is_empty()
+        && !segments[segments.len() - 1].is_empty();
+    if is_trace {
+        return format!("ibc/{:X}", Sha256::digest(denom.as_bytes()));
+    }
+
+    denom.to_string()
+}
+
 impl From<Path> for (Addr, String, String) {
     fn from(path: Path) -> (Addr, String, String) {
         (path.contract, path.channel, path.denom)
@@ -38,12 +435,21 @@ impl From<&Path> for (Addr, String, String) {
     }
 }
 
-#[derive(Debug, Clone)]
+#[cw_serde]
 pub enum FlowType {
     In,
     Out,
 }
 
+impl FlowType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FlowType::In => "in",
+            FlowType::Out => "out",
+        }
+    }
+}
+
 /// A Flow represents the transfer of value for a denom through an contract bridge
 /// during a time window.
 ///
@@ -60,24 +466,68 @@ pub enum FlowType {
 /// specified duration for the quota.
 ///
 /// This is a design decision to avoid the period calculations and thus reduce gas consumption
+///
+/// period_channel_value is the value of the denom in the channel reported by
+/// the first packet of the period. It is frozen for the rest of the period so
+/// the capacity of percentage quotas doesn't move with supply changes. Periods
+/// rolled over without a packet (resets, ticks) keep the previous value.
+///
+/// stats count the packets of the period. They are archived along with the
+/// flows when the period rolls over.
+///
+/// Quotas with different send and receive durations roll each direction over
+/// on its own. period_end is then the end of the send window and
+/// recv_period_end the end of the receive one. Without it, both directions
+/// share period_end. Archiving and ticks follow the send window.
 #[cw_serde]
 pub struct Flow {
-    pub inflow: Uint128,
-    pub outflow: Uint128,
+    pub inflow: Uint256,
+    pub outflow: Uint256,
     pub period_end: Timestamp,
+    #[serde(default)]
+    pub period_channel_value: Option<Uint128>,
+    #[serde(default)]
+    pub stats: FlowStats,
+    #[serde(default)]
+    pub recv_period_end: Option<Timestamp>,
+}
+
+/// FlowStats are the packet counters of a period. packets_undone counts the
+/// sends that were reverted after being accepted, and rejections the sends
+/// rejected with data during the period.
+#[cw_serde]
+#[derive(Default)]
+pub struct FlowStats {
+    pub packets_in: u64,
+    pub packets_out: u64,
+    pub packets_undone: u64,
+    pub largest_transfer: Uint128,
+    #[serde(default)]
+    pub rejections: u64,
 }
 
 impl Flow {
     pub fn new(
-        inflow: impl Into<Uint128>,
-        outflow: impl Into<Uint128>,
-        now: Timestamp,
-        duration: u64,
+        inflow: impl Into<Uint256>,
+        outflow: impl Into<Uint256>,
+        clock: &dyn TimeProvider,
+        duration_nanos: u64,
     ) -> Self {
         Self {
             inflow: inflow.into(),
             outflow: outflow.into(),
-            period_end: now.plus_seconds(duration),
+            period_end: clock.now().plus_nanos(duration_nanos),
+            period_channel_value: None,
+            stats: FlowStats::default(),
+            recv_period_end: None,
+        }
+    }
+
+    /// The end of the current period of a direction
+    pub fn period_end_on(&self, direction: &FlowType) -> Timestamp {
+        match (direction, self.recv_period_end) {
+            (FlowType::In, Some(recv_period_end)) => recv_period_end,
+            _ => self.period_end,
         }
     }
 
@@ -86,7 +536,7 @@ impl Flow {
     /// (balance_in, balance_out) where balance_in in is how much has been
     /// transferred into the flow, and balance_out is how much value transferred
     /// out.
-    pub fn balance(&self) -> (Uint128, Uint128) {
+    pub fn balance(&self) -> (Uint256, Uint256) {
         (
             self.inflow.saturating_sub(self.outflow),
             self.outflow.saturating_sub(self.inflow),
@@ -94,7 +544,7 @@ impl Flow {
     }
 
     /// checks if the flow, in the current state, has exceeded a max allowance
-    pub fn exceeds(&self, direction: &FlowType, max_inflow: Uint128, max_outflow: Uint128) -> bool {
+    pub fn exceeds(&self, direction: &FlowType, max_inflow: Uint256, max_outflow: Uint256) -> bool {
         let (balance_in, balance_out) = self.balance();
         match direction {
             FlowType::In => balance_in > max_inflow,
@@ -102,8 +552,13 @@ impl Flow {
         }
     }
 
+    /// The sum of inflows and outflows, without netting them
+    pub fn total(&self) -> Uint256 {
+        self.inflow.saturating_add(self.outflow)
+    }
+
     /// returns the balance in a direction. This is used for displaying cleaner errors
-    pub fn balance_on(&self, direction: &FlowType) -> Uint128 {
+    pub fn balance_on(&self, direction: &FlowType) -> Uint256 {
         let (balance_in, balance_out) = self.balance();
         match direction {
             FlowType::In => balance_in,
@@ -112,30 +567,94 @@ impl Flow {
     }
 
     /// If now is greater than the period_end, the Flow is considered expired.
-    pub fn is_expired(&self, now: Timestamp) -> bool {
-        self.period_end < now
+    /// Within the grace of the clock before period_end, it already is
+    pub fn is_expired(&self, clock: &dyn TimeProvider) -> bool {
+        self.period_end < clock.now().plus_nanos(clock.grace_nanos())
     }
 
     // Mutating methods
 
     /// Expire resets the Flow to start tracking the value transfer from the
     /// moment this method is called.
-    pub fn expire(&mut self, now: Timestamp, duration: u64) {
-        self.inflow = Uint128::from(0_u32);
-        self.outflow = Uint128::from(0_u32);
-        self.period_end = now.plus_seconds(duration);
+    pub fn expire(&mut self, clock: &dyn TimeProvider, duration_nanos: u64) {
+        self.inflow = Uint256::zero();
+        self.outflow = Uint256::zero();
+        self.period_end = clock.now().plus_nanos(duration_nanos);
+        self.stats = FlowStats::default();
+        self.recv_period_end = None;
+    }
+
+    /// Starts new periods for the windows of the quota that are over. Returns
+    /// whether any did
+    pub fn roll_over(&mut self, clock: &dyn TimeProvider, quota: &Quota) -> bool {
+        let Some(recv_duration) = quota.split_recv_duration() else {
+            if !self.is_expired(clock) {
+                return false;
+            }
+            self.expire(clock, quota.duration_on(&FlowType::Out));
+            return true;
+        };
+
+        // Flows started before the durations were split share the send window
+        let recv_period_end = *self.recv_period_end.get_or_insert(self.period_end);
+        let mut expired = false;
+        if self.is_expired(clock) {
+            self.outflow = Uint256::zero();
+            self.period_end = clock.now().plus_nanos(quota.duration_on(&FlowType::Out));
+            self.stats = FlowStats::default();
+            expired = true;
+        }
+        if recv_period_end < clock.now().plus_nanos(clock.grace_nanos()) {
+            self.inflow = Uint256::zero();
+            self.recv_period_end = Some(clock.now().plus_nanos(recv_duration));
+            expired = true;
+        }
+        expired
+    }
+
+    /// Starts a new period from now, keeping the current usage.
+    pub fn extend(&mut self, clock: &dyn TimeProvider, duration_nanos: u64) {
+        self.period_end = clock.now().plus_nanos(duration_nanos);
+    }
+
+    /// Starts a new period from now, keeping the share of the current usage
+    /// matching the time that was left in the period.
+    pub fn prorate(&mut self, clock: &dyn TimeProvider, duration_nanos: u64) {
+        let remaining = self.period_end.nanos().saturating_sub(clock.now().nanos());
+        let period = duration_nanos;
+        let scale = |value: Uint256| match period {
+            0 => Uint256::zero(),
+            _ => value.multiply_ratio(remaining.min(period), period),
+        };
+        self.inflow = scale(self.inflow);
+        self.outflow = scale(self.outflow);
+        self.period_end = clock.now().plus_nanos(duration_nanos);
     }
 
-    /// Updates the current flow incrementing it by a transfer of value.
+    /// Updates the current flow incrementing it by a transfer of value. Zero
+    /// transfers are ignored, so they don't change the flow at all
     pub fn add_flow(&mut self, direction: FlowType, value: Uint128) {
+        if value.is_zero() {
+            return;
+        }
+        self.stats.largest_transfer = self.stats.largest_transfer.max(value);
+        let value = Uint256::from(value);
         match direction {
-            FlowType::In => self.inflow = self.inflow.saturating_add(value),
-            FlowType::Out => self.outflow = self.outflow.saturating_add(value),
+            FlowType::In => {
+                self.inflow = self.inflow.saturating_add(value);
+                self.stats.packets_in += 1;
+            }
+            FlowType::Out => {
+                self.outflow = self.outflow.saturating_add(value);
+                self.stats.packets_out += 1;
+            }
         }
     }
 
     /// Updates the current flow reducing it by a transfer of value.
     pub fn undo_flow(&mut self, direction: FlowType, value: Uint128) {
+        self.stats.packets_undone += 1;
+        let value = Uint256::from(value);
         match direction {
             FlowType::In => self.inflow = self.inflow.saturating_sub(value),
             FlowType::Out => self.outflow = self.outflow.saturating_sub(value),
@@ -143,24 +662,89 @@ impl Flow {
     }
 
     /// Applies a transfer. If the Flow is expired (now > period_end), it will
-    /// reset it before applying the transfer.
+    /// reset it before applying the transfer. The channel value is only taken
+    /// when a new period starts or if none is known yet.
     fn apply_transfer(
         &mut self,
         direction: &FlowType,
         funds: Uint128,
-        now: Timestamp,
+        channel_value: Option<Uint128>,
+        clock: &dyn TimeProvider,
         quota: &Quota,
     ) -> bool {
-        let mut expired = false;
-        if self.is_expired(now) {
-            self.expire(now, quota.duration);
-            expired = true;
+        let expired = self.roll_over(clock, quota);
+        if channel_value.is_some() && (expired || self.period_channel_value.is_none()) {
+            self.period_channel_value = channel_value;
         }
         self.add_flow(direction.clone(), funds);
         expired
     }
 }
 
+/// QuotaMode decides how the flows of a quota are checked against its limits.
+///
+/// Netted quotas compare the net balance in each direction against max_send
+/// and max_recv. Combined quotas add up inflows and outflows, without netting,
+/// and compare the sum against max_total.
+#[cw_serde]
+#[derive(Default)]
+pub enum QuotaMode {
+    #[default]
+    Netted,
+    Combined {
+        max_total: Uint256,
+    },
+}
+
+/// Rounding applied when a percentage of the channel value doesn't result in a
+/// whole amount
+#[cw_serde]
+#[derive(Default)]
+pub enum Rounding {
+    #[default]
+    Floor,
+    Ceil,
+}
+
+/// PercentageLimit expresses the capacity of a quota as a fraction of the
+/// channel value (i.e. 0.05 for 5%). Small channel values can round down to a
+/// zero capacity, so min_capacity sets a floor for the resulting allowance.
+#[cw_serde]
+pub struct PercentageLimit {
+    pub send: Decimal,
+    pub recv: Decimal,
+    #[serde(default)]
+    pub rounding: Rounding,
+    #[serde(default)]
+    pub min_capacity: Uint256,
+}
+
+impl PercentageLimit {
+    /// The capacity for a channel value in the directions (FlowType::In,
+    /// FlowType::Out)
+    pub fn capacity(&self, channel_value: Uint128) -> (Uint256, Uint256) {
+        (
+            self.fraction_of(self.recv, channel_value),
+            self.fraction_of(self.send, channel_value),
+        )
+    }
+
+    fn fraction_of(&self, ratio: Decimal, channel_value: Uint128) -> Uint256 {
+        // The product is computed on 256 bits so it can't overflow before the
+        // division
+        let numerator = Uint256::from(channel_value) * Uint256::from(ratio.atomics());
+        let denominator = Uint256::from(Decimal::one().atomics());
+        let mut amount = numerator / denominator;
+        if let Rounding::Ceil = self.rounding {
+            if !(numerator % denominator).is_zero() {
+                amount += Uint256::from(1_u8);
+            }
+        }
+
+        amount.max(self.min_capacity)
+    }
+}
+
 /// A Quota is the percentage of the denom's total value that can be transferred
 /// through the channel in a given period of time (duration)
 ///
@@ -168,26 +752,185 @@ impl Flow {
 ///
 /// The name of the quota is expected to be a human-readable representation of
 /// the duration (i.e.: "weekly", "daily", "every-six-months", ...)
+///
+/// A blocked quota has no capacity in either direction. Percentage quotas
+/// derive their capacity from the channel value instead of max_send/max_recv
+///
+/// send_duration_nanos and recv_duration_nanos override the duration of a
+/// single direction. Durations are in nanoseconds, so periods can be shorter
+/// than a second
+///
+/// partitions, when set, split the UTC day and scale the capacity down to the
+/// share of the partition the block time falls in
+///
+/// probation is set on the quotas of paths added under a probation policy.
+/// They get its share of their capacity until they graduate
 #[cw_serde]
 pub struct Quota {
     pub name: String,
-    pub max_send: Uint128,
-    pub max_recv: Uint128,
-    pub duration: u64,
+    pub max_send: Uint256,
+    pub max_recv: Uint256,
+    pub duration_nanos: u64,
+    #[serde(default)]
+    pub blocked: bool,
+    #[serde(default)]
+    pub mode: QuotaMode,
+    #[serde(default)]
+    pub percentage: Option<PercentageLimit>,
+    #[serde(default)]
+    pub send_duration_nanos: Option<u64>,
+    #[serde(default)]
+    pub recv_duration_nanos: Option<u64>,
+    #[serde(default)]
+    pub partitions: Vec<DayPartition>,
+    #[serde(default)]
+    pub probation: Option<Probation>,
 }
 
+/// Probation is the start of the probation of a quota, along with the number
+/// of periods it lasts and the share of the capacity it leaves
+#[cw_serde]
+pub struct Probation {
+    pub started_at: Timestamp,
+    pub periods: u32,
+    pub share_bps: u32,
+}
+
+impl Probation {
+    pub fn start(policy: &ProbationPolicy, now: Timestamp) -> Self {
+        Probation {
+            started_at: now,
+            periods: policy.periods,
+            share_bps: policy.share_bps,
+        }
+    }
+
+    /// The graduation time of a quota whose periods last duration_nanos. A
+    /// probation ending past the last representable time never graduates
+    pub fn ends_at(&self, duration_nanos: u64) -> Timestamp {
+        let probation_nanos = duration_nanos.saturating_mul(u64::from(self.periods));
+        Timestamp::from_nanos(self.started_at.nanos().saturating_add(probation_nanos))
+    }
+}
+
+pub const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+pub const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// DayPartition is a slice of the UTC day, from start to end in seconds since
+/// midnight, during which a quota only gets share_bps of its capacity (i.e.
+/// 3000 for 30% between 00:00 and 08:00)
+#[cw_serde]
+pub struct DayPartition {
+    pub start: u32,
+    pub end: u32,
+    pub share_bps: u32,
+}
+
+impl DayPartition {
+    pub fn contains(&self, second_of_day: u32) -> bool {
+        self.start <= second_of_day && second_of_day < self.end
+    }
+}
+
+/// MAX_GRACE_NANOS bounds `Config::grace_nanos`. Block time jitter is a few
+/// seconds at most, a longer grace would cut periods short for nothing
+pub const MAX_GRACE_NANOS: u64 = 60 * NANOS_PER_SECOND;
+
+/// MAX_HEARTBEAT_INTERVAL bounds `HeartbeatPolicy::interval` to a year
+pub const MAX_HEARTBEAT_INTERVAL: u64 = 365 * SECONDS_PER_DAY;
+
 impl Quota {
+    /// The duration of the periods of a direction, in nanoseconds
+    pub fn duration_on(&self, direction: &FlowType) -> u64 {
+        match direction {
+            FlowType::In => self.recv_duration_nanos.unwrap_or(self.duration_nanos),
+            FlowType::Out => self.send_duration_nanos.unwrap_or(self.duration_nanos),
+        }
+    }
+
+    /// The receive duration, if it differs from the send one
+    pub fn split_recv_duration(&self) -> Option<u64> {
+        let recv_duration = self.duration_on(&FlowType::In);
+        (recv_duration != self.duration_on(&FlowType::Out)).then_some(recv_duration)
+    }
+
     /// Calculates the max capacity (absolute value in the same unit as
     /// total_value) in each direction based on the total value of the denom in
     /// the channel. The result tuple represents the max capacity when the
     /// transfer is in directions: (FlowType::In, FlowType::Out)
-    pub fn capacity(&self) -> (Uint128, Uint128) {
-        (self.max_recv, self.max_send)
+    ///
+    /// Percentage quotas only get their min_capacity while the channel value
+    /// is unknown
+    pub fn capacity(&self, channel_value: Option<Uint128>) -> (Uint256, Uint256) {
+        if self.blocked {
+            return (Uint256::zero(), Uint256::zero());
+        }
+        match (&self.mode, &self.percentage) {
+            (QuotaMode::Combined { max_total }, _) => (*max_total, *max_total),
+            (QuotaMode::Netted, Some(percentage)) => match channel_value {
+                Some(channel_value) => percentage.capacity(channel_value),
+                None => (percentage.min_capacity, percentage.min_capacity),
+            },
+            (QuotaMode::Netted, None) => (self.max_recv, self.max_send),
+        }
     }
 
     /// returns the capacity in a direction. This is used for displaying cleaner errors
-    pub fn capacity_on(&self, direction: &FlowType) -> Uint128 {
-        let (max_in, max_out) = self.capacity();
+    pub fn capacity_on(&self, direction: &FlowType, channel_value: Option<Uint128>) -> Uint256 {
+        let (max_in, max_out) = self.capacity(channel_value);
+        match direction {
+            FlowType::In => max_in,
+            FlowType::Out => max_out,
+        }
+    }
+
+    /// The partition of the day a time falls in, if the quota is partitioned
+    pub fn partition_at(&self, now: Timestamp) -> Option<&DayPartition> {
+        let second_of_day = (now.seconds() % SECONDS_PER_DAY) as u32;
+        self.partitions
+            .iter()
+            .find(|partition| partition.contains(second_of_day))
+    }
+
+    /// The graduation time of the quota, while it's on probation
+    pub fn probation_end(&self, now: Timestamp) -> Option<Timestamp> {
+        let end = self.probation.as_ref()?.ends_at(self.duration_nanos);
+        (now < end).then_some(end)
+    }
+
+    /// The capacity in each direction at a time, scaled down to the share of
+    /// its partition of the day and to the share of its probation. Shares are
+    /// rounded down
+    pub fn capacity_at(
+        &self,
+        channel_value: Option<Uint128>,
+        now: Timestamp,
+    ) -> (Uint256, Uint256) {
+        let probation_share = self
+            .probation_end(now)
+            .and(self.probation.as_ref())
+            .map(|probation| probation.share_bps);
+        let shares = [
+            self.partition_at(now).map(|partition| partition.share_bps),
+            probation_share,
+        ];
+        let (mut max_in, mut max_out) = self.capacity(channel_value);
+        for bps in shares.into_iter().flatten() {
+            max_in = max_in.multiply_ratio(bps, 10_000_u32);
+            max_out = max_out.multiply_ratio(bps, 10_000_u32);
+        }
+        (max_in, max_out)
+    }
+
+    /// The capacity in a direction at a time
+    pub fn capacity_on_at(
+        &self,
+        direction: &FlowType,
+        channel_value: Option<Uint128>,
+        now: Timestamp,
+    ) -> Uint256 {
+        let (max_in, max_out) = self.capacity_at(channel_value, now);
         match direction {
             FlowType::In => max_in,
             FlowType::Out => max_out,
@@ -201,21 +944,140 @@ impl From<&QuotaMsg> for Quota {
             name: msg.name.clone(),
             max_recv: msg.max_receive,
             max_send: msg.max_send,
-            duration: msg.duration,
+            duration_nanos: msg.duration_nanos(),
+            blocked: msg.blocked,
+            mode: msg.mode.clone(),
+            percentage: msg.percentage.clone(),
+            send_duration_nanos: msg
+                .send_duration
+                .map(|s| s.saturating_mul(NANOS_PER_SECOND)),
+            recv_duration_nanos: msg
+                .recv_duration
+                .map(|s| s.saturating_mul(NANOS_PER_SECOND)),
+            partitions: msg.partitions.clone(),
+            probation: None,
         }
     }
 }
 
+/// ResetMode decides what a manual reset does to the usage of a quota. All of
+/// them start a new period from the time of the reset.
+///
+/// Full zeroes the usage, ExtendOnly keeps it and Prorate keeps the share of it
+/// matching the time that was left in the period.
+#[cw_serde]
+#[derive(Default)]
+pub enum ResetMode {
+    #[default]
+    Full,
+    ExtendOnly,
+    Prorate,
+}
+
+/// ResetInfo records who manually reset the flow of a quota, when it happened
+/// and, optionally, why.
+#[cw_serde]
+pub struct ResetInfo {
+    pub by: Addr,
+    pub at: Timestamp,
+    pub memo: Option<String>,
+    #[serde(default)]
+    pub mode: ResetMode,
+}
+
 /// RateLimit is the main structure tracked for each contract/channel/denom pair. Its quota
 /// represents rate limit configuration, and the flow its
 /// current state (i.e.: how much value has been transfered in the current period)
+///
+/// last_reset is only set once the quota has been manually reset
 #[cw_serde]
 pub struct RateLimit {
     pub quota: Quota,
     pub flow: Flow,
+    pub last_reset: Option<ResetInfo>,
 }
 
 impl RateLimit {
+    /// Shortens the durations of the quota above max_nanos down to it, ending
+    /// the current periods no later than max_nanos from now. Returns whether
+    /// any was
+    pub fn clamp_duration(&mut self, max_nanos: u64, clock: &dyn TimeProvider) -> bool {
+        let quota = &mut self.quota;
+        let too_long = |duration: &u64| *duration > max_nanos;
+        if !too_long(&quota.duration_nanos)
+            && !quota.send_duration_nanos.as_ref().is_some_and(too_long)
+            && !quota.recv_duration_nanos.as_ref().is_some_and(too_long)
+        {
+            return false;
+        }
+        quota.duration_nanos = quota.duration_nanos.min(max_nanos);
+        quota.send_duration_nanos = quota.send_duration_nanos.map(|d| d.min(max_nanos));
+        quota.recv_duration_nanos = quota.recv_duration_nanos.map(|d| d.min(max_nanos));
+
+        let latest = Timestamp::from_nanos(clock.now().nanos().saturating_add(max_nanos));
+        self.flow.period_end = self.flow.period_end.min(latest);
+        self.flow.recv_period_end = self.flow.recv_period_end.map(|end| end.min(latest));
+        true
+    }
+
+    /// Manually starts a new period from now, handling the current usage as
+    /// requested by the mode, and records who requested the reset.
+    pub fn reset(
+        &mut self,
+        by: &Addr,
+        clock: &dyn TimeProvider,
+        memo: Option<String>,
+        mode: ResetMode,
+    ) {
+        let duration = self.quota.duration_on(&FlowType::Out);
+        match mode {
+            ResetMode::Full => self.flow.expire(clock, duration),
+            ResetMode::ExtendOnly => self.flow.extend(clock, duration),
+            ResetMode::Prorate => self.flow.prorate(clock, duration),
+        }
+        self.flow.recv_period_end = self
+            .quota
+            .split_recv_duration()
+            .map(|recv_duration| clock.now().plus_nanos(recv_duration));
+        self.last_reset = Some(ResetInfo {
+            by: by.to_owned(),
+            at: clock.now(),
+            memo,
+            mode,
+        });
+    }
+
+    /// The largest transfer the quota currently allows in a direction. Expired
+    /// flows are considered rolled over
+    pub fn remaining(
+        &self,
+        direction: &FlowType,
+        channel_value: Option<Uint128>,
+        clock: &dyn TimeProvider,
+    ) -> Uint256 {
+        let mut flow = self.flow.clone();
+        flow.apply_transfer(
+            direction,
+            Uint128::zero(),
+            channel_value,
+            clock,
+            &self.quota,
+        );
+
+        let max = self
+            .quota
+            .capacity_on_at(direction, flow.period_channel_value, clock.now());
+        match (&self.quota.mode, direction) {
+            (QuotaMode::Netted, FlowType::In) => {
+                max.saturating_add(flow.outflow).saturating_sub(flow.inflow)
+            }
+            (QuotaMode::Netted, FlowType::Out) => {
+                max.saturating_add(flow.inflow).saturating_sub(flow.outflow)
+            }
+            (QuotaMode::Combined { .. }, _) => max.saturating_sub(flow.total()),
+        }
+    }
+
     /// Checks if a transfer is allowed and updates the data structures
     /// accordingly.
     ///
@@ -227,33 +1089,58 @@ impl RateLimit {
         path: &Path,
         direction: &FlowType,
         funds: Uint128,
-        now: Timestamp,
+        channel_value: Option<Uint128>,
+        clock: &dyn TimeProvider,
     ) -> Result<Self, ContractError> {
         // Flow used before this transaction is applied.
         // This is used to make error messages more informative
-        let initial_flow = self.flow.balance_on(direction);
+        let initial_flow = match self.quota.mode {
+            QuotaMode::Netted => self.flow.balance_on(direction),
+            QuotaMode::Combined { .. } => self.flow.total(),
+        };
 
         // Apply the transfer. From here on, we will updated the flow with the new transfer
         // and check if  it exceeds the quota at the current time
 
-        let _expired = self.flow.apply_transfer(direction, funds, now, &self.quota);
+        let _expired =
+            self.flow
+                .apply_transfer(direction, funds, channel_value, clock, &self.quota);
 
-        let (max_in, max_out) = self.quota.capacity();
+        let channel_value = self.flow.period_channel_value;
+        let (max_in, max_out) = self.quota.capacity_at(channel_value, clock.now());
+        let max = match direction {
+            FlowType::In => max_in,
+            FlowType::Out => max_out,
+        };
+        let exceeded = match self.quota.mode {
+            QuotaMode::Netted => self.flow.exceeds(direction, max_in, max_out),
+            QuotaMode::Combined { .. } => self.flow.total() > max,
+        };
         // Return the effects of applying the transfer or an error.
-        match self.flow.exceeds(direction, max_in, max_out) {
-            true => Err(ContractError::RateLimitExceded {
-                contract: path.contract.to_string(),
-                channel: path.channel.to_string(),
-                denom: path.denom.to_string(),
-                amount: funds,
-                quota_name: self.quota.name.to_string(),
-                used: initial_flow,
-                max: self.quota.capacity_on(direction),
-                reset: self.flow.period_end,
-            }),
+        match exceeded {
+            true => {
+                let violation = QuotaViolation {
+                    quota_name: self.quota.name.to_string(),
+                    used: initial_flow,
+                    max,
+                    reset: self.flow.period_end_on(direction),
+                };
+                Err(ContractError::RateLimitExceded {
+                    contract: path.contract.to_string(),
+                    channel: path.channel.to_string(),
+                    denom: path.denom.to_string(),
+                    amount: funds,
+                    quota_name: violation.quota_name.clone(),
+                    used: violation.used,
+                    max: violation.max,
+                    reset: violation.reset,
+                    violations: vec![violation],
+                })
+            }
             false => Ok(RateLimit {
                 quota: self.quota.clone(), // Cloning here because self.quota.name (String) does not allow us to implement Copy
                 flow: self.flow.clone(), // We can Copy flow, so this is slightly more efficient than cloning the whole RateLimit
+                last_reset: self.last_reset.clone(),
             }),
         }
     }
@@ -270,25 +1157,1058 @@ impl RateLimit {
 /// The map key (Addr,String, String) represents (contract, channel_id, denom). We use
 /// composite keys instead of a struct to avoid having to implement the
 /// PrimaryKey trait
-pub const RATE_LIMIT_TRACKERS: Map<(Addr, String, String), Vec<RateLimit>> = Map::new("flow");
+///
+/// The namespace carries a version suffix, as previous deployments stored
+/// different layouts under "flow" and "flow_v2". See LEGACY_NAMESPACES
+pub const RATE_LIMIT_TRACKERS: Map<(Addr, String, String), Vec<RateLimit>> =
+    Map::new(TRACKERS_NAMESPACE);
+
+const TRACKERS_NAMESPACE: &str = "flow_v3";
+
+const CLASS_TRACKERS_NAMESPACE: &str = "class_flow_v2";
+
+/// Converts the raw value of an entry moved out of a legacy namespace
+pub type ConvertFn = fn(&[u8]) -> StdResult<Vec<u8>>;
+
+/// LEGACY_NAMESPACES maps the namespaces of older versions to the ones that
+/// replaced them, along with the conversion of their values. Their entries are
+/// moved over on migration. The class trackers go first, as they can't be
+/// told apart by path
+pub const LEGACY_NAMESPACES: &[(&str, &str, Option<ConvertFn>)] = &[
+    (
+        "class_flow",
+        CLASS_TRACKERS_NAMESPACE,
+        Some(durations_to_nanos),
+    ),
+    ("flow", TRACKERS_NAMESPACE, Some(durations_to_nanos)),
+    ("flow_v2", TRACKERS_NAMESPACE, Some(durations_to_nanos)),
+];
+
+// Quotas stored their durations in seconds until they moved to nanoseconds
+#[cw_serde]
+struct SecondsQuota {
+    name: String,
+    max_send: Uint256,
+    max_recv: Uint256,
+    duration: u64,
+    #[serde(default)]
+    blocked: bool,
+    #[serde(default)]
+    mode: QuotaMode,
+    #[serde(default)]
+    percentage: Option<PercentageLimit>,
+    #[serde(default)]
+    send_duration: Option<u64>,
+    #[serde(default)]
+    recv_duration: Option<u64>,
+}
+
+#[cw_serde]
+struct SecondsRateLimit {
+    quota: SecondsQuota,
+    flow: Flow,
+    #[serde(default)]
+    last_reset: Option<ResetInfo>,
+}
+
+fn durations_to_nanos(raw: &[u8]) -> StdResult<Vec<u8>> {
+    let nanos = |seconds: u64| seconds.saturating_mul(NANOS_PER_SECOND);
+    let limits: Vec<SecondsRateLimit> = from_json(&Binary::from(raw))?;
+    let limits: Vec<RateLimit> = limits
+        .into_iter()
+        .map(|limit| RateLimit {
+            quota: Quota {
+                name: limit.quota.name,
+                max_send: limit.quota.max_send,
+                max_recv: limit.quota.max_recv,
+                duration_nanos: nanos(limit.quota.duration),
+                blocked: limit.quota.blocked,
+                mode: limit.quota.mode,
+                percentage: limit.quota.percentage,
+                send_duration_nanos: limit.quota.send_duration.map(nanos),
+                recv_duration_nanos: limit.quota.recv_duration.map(nanos),
+                partitions: vec![],
+                probation: None,
+            },
+            flow: limit.flow,
+            last_reset: limit.last_reset,
+        })
+        .collect();
+    to_json_vec(&limits)
+}
+
+// Maps prefix every key with the length of their namespace on two bytes
+fn map_prefix(namespace: &str) -> Vec<u8> {
+    let mut prefix = (namespace.len() as u16).to_be_bytes().to_vec();
+    prefix.extend_from_slice(namespace.as_bytes());
+    prefix
+}
+
+/// Decodes a raw storage key of RATE_LIMIT_TRACKERS, namespace included, into
+/// the path it belongs to
+pub fn decode_tracker_key(raw: &[u8]) -> StdResult<Path> {
+    let invalid = || StdError::generic_err("Invalid rate limit trackers key");
+    let rest = raw
+        .strip_prefix(map_prefix(TRACKERS_NAMESPACE).as_slice())
+        .ok_or_else(invalid)?;
+
+    // Every key part but the last is prefixed by its length
+    let split_part = |bytes: &[u8]| -> StdResult<(String, usize)> {
+        let len = bytes.get(..2).ok_or_else(invalid)?;
+        let len = u16::from_be_bytes([len[0], len[1]]) as usize;
+        let part = bytes.get(2..2 + len).ok_or_else(invalid)?;
+        let part = String::from_utf8(part.to_vec()).map_err(|_| invalid())?;
+        Ok((part, 2 + len))
+    };
+    let (contract, read) = split_part(rest)?;
+    let rest = &rest[read..];
+    let (channel, read) = split_part(rest)?;
+    let denom = String::from_utf8(rest[read..].to_vec()).map_err(|_| invalid())?;
+
+    Ok(Path {
+        contract: Addr::unchecked(contract),
+        channel,
+        denom,
+    })
+}
+
+/// Moves up to `limit` raw entries of a map to a new namespace, keeping the
+/// rest of their keys and converting their values. Values that fail to convert
+/// are moved as they are, to be quarantined by the next packet. Entries
+/// already present in the new namespace are overwritten. Returns the number of
+/// entries moved
+pub fn rename_namespace(
+    storage: &mut dyn Storage,
+    old: &str,
+    new: &str,
+    convert: Option<ConvertFn>,
+    limit: usize,
+) -> StdResult<u64> {
+    let old_prefix = map_prefix(old);
+    let new_prefix = map_prefix(new);
+
+    // The range ends at the prefix with its last byte incremented. Namespaces
+    // are ASCII, so that byte can't overflow
+    let mut end = old_prefix.clone();
+    if let Some(last) = end.last_mut() {
+        *last += 1;
+    }
+    let entries: Vec<(Vec<u8>, Vec<u8>)> = storage
+        .range(Some(&old_prefix), Some(&end), Order::Ascending)
+        .take(limit)
+        .collect();
+
+    for (key, value) in &entries {
+        storage.remove(key);
+        let mut new_key = new_prefix.clone();
+        new_key.extend_from_slice(&key[old_prefix.len()..]);
+        match convert.map(|convert| convert(value)) {
+            Some(Ok(converted)) => storage.set(&new_key, &converted),
+            _ => storage.set(&new_key, value),
+        }
+    }
+    Ok(entries.len() as u64)
+}
+
+/// MIGRATION_BATCH is the number of entries moved by migrate and by each
+/// ContinueMigration without a limit
+pub const MIGRATION_BATCH: u32 = 100;
+
+/// MigrationProgress tracks a migration of the legacy namespaces that didn't
+/// fit in the migrate call
+#[cw_serde]
+pub struct MigrationProgress {
+    /// The index in LEGACY_NAMESPACES of the namespace being moved. Moved
+    /// entries are removed from it, so it resumes at its first key
+    pub namespace: u32,
+    pub moved: u64,
+}
+
+/// MIGRATION_PROGRESS is only set while a migration is unfinished
+pub const MIGRATION_PROGRESS: Item<MigrationProgress> = Item::new("migration_progress");
+
+/// Moves up to `limit` entries of the legacy namespaces, resuming where the
+/// last batch stopped. Returns the number of entries moved and whether the
+/// migration is done
+pub fn continue_migration(storage: &mut dyn Storage, limit: u32) -> StdResult<(u64, bool)> {
+    let Some(mut progress) = MIGRATION_PROGRESS.may_load(storage)? else {
+        return Ok((0, true));
+    };
+
+    let mut moved = 0;
+    while let Some((old, new, convert)) = LEGACY_NAMESPACES.get(progress.namespace as usize) {
+        let batch = limit as u64 - moved;
+        let count = rename_namespace(storage, old, new, *convert, batch as usize)?;
+        moved += count;
+        if count == batch {
+            break;
+        }
+        progress.namespace += 1;
+    }
+    progress.moved += moved;
+
+    let done = progress.namespace as usize >= LEGACY_NAMESPACES.len();
+    if done {
+        MIGRATION_PROGRESS.remove(storage);
+    } else {
+        MIGRATION_PROGRESS.save(storage, &progress)?;
+    }
+    Ok((moved, done))
+}
+
+/// Whether the trackers of a path were moved out of the legacy namespaces. No
+/// path is migrated while the class trackers are still moving
+pub fn is_migrated(storage: &dyn Storage, path: &Path) -> bool {
+    let Some(progress) = MIGRATION_PROGRESS.may_load(storage).ok().flatten() else {
+        return true;
+    };
+
+    let key = RATE_LIMIT_TRACKERS.key(path.into());
+    let suffix = &key[map_prefix(TRACKERS_NAMESPACE).len()..];
+    LEGACY_NAMESPACES
+        .iter()
+        .skip(progress.namespace as usize)
+        .all(|(old, new, _)| {
+            let mut legacy_key = map_prefix(old);
+            legacy_key.extend_from_slice(suffix);
+            *new == TRACKERS_NAMESPACE && storage.get(&legacy_key).is_none()
+        })
+}
+
+/// CHANGE_SEQ is the sequence of the last write to RATE_LIMIT_TRACKERS. It is
+/// bumped on every write, so indexers can sync incrementally
+pub const CHANGE_SEQ: Item<u64> = Item::new("change_seq");
+
+/// CHANGE_LOG_LENGTH is the number of changes kept in the change log
+pub const CHANGE_LOG_LENGTH: u64 = 1000;
+
+/// CHANGE_LOG maps each change sequence to the path that changed. Only the last
+/// CHANGE_LOG_LENGTH changes are kept
+pub const CHANGE_LOG: Map<u64, PathKey> = Map::new("change_log");
+
+/// Records a change to the trackers of a path and returns its sequence
+pub fn record_change(storage: &mut dyn Storage, key: PathKey) -> StdResult<u64> {
+    let seq = CHANGE_SEQ.may_load(storage)?.unwrap_or_default() + 1;
+    CHANGE_SEQ.save(storage, &seq)?;
+    CHANGE_LOG.save(storage, seq, &key)?;
+    if seq > CHANGE_LOG_LENGTH {
+        CHANGE_LOG.remove(storage, seq - CHANGE_LOG_LENGTH);
+    }
+    Ok(seq)
+}
+
+/// IDEMPOTENCY_KEYS_LENGTH is the number of idempotency keys remembered
+pub const IDEMPOTENCY_KEYS_LENGTH: u64 = 1000;
+
+/// IDEMPOTENCY_SEQ is the number of idempotency keys ever recorded
+pub const IDEMPOTENCY_SEQ: Item<u64> = Item::new("idempotency_seq");
+
+/// IDEMPOTENCY_KEYS maps the (sender, key) of the management messages applied
+/// to their sequence. Only the last IDEMPOTENCY_KEYS_LENGTH keys are kept
+pub const IDEMPOTENCY_KEYS: Map<(&Addr, &str), u64> = Map::new("idempotency_keys");
+
+/// IDEMPOTENCY_LOG maps each sequence back to its key, to prune the oldest
+pub const IDEMPOTENCY_LOG: Map<u64, (Addr, String)> = Map::new("idempotency_log");
+
+/// Records the idempotency key of a management message. Returns false if the
+/// sender already used it
+pub fn record_idempotency_key(
+    storage: &mut dyn Storage,
+    sender: &Addr,
+    key: &str,
+) -> StdResult<bool> {
+    if IDEMPOTENCY_KEYS.has(storage, (sender, key)) {
+        return Ok(false);
+    }
+
+    let seq = IDEMPOTENCY_SEQ.may_load(storage)?.unwrap_or_default() + 1;
+    IDEMPOTENCY_SEQ.save(storage, &seq)?;
+    IDEMPOTENCY_KEYS.save(storage, (sender, key), &seq)?;
+    IDEMPOTENCY_LOG.save(storage, seq, &(sender.clone(), key.to_string()))?;
+    if seq > IDEMPOTENCY_KEYS_LENGTH {
+        let oldest = seq - IDEMPOTENCY_KEYS_LENGTH;
+        if let Some((sender, key)) = IDEMPOTENCY_LOG.may_load(storage, oldest)? {
+            IDEMPOTENCY_KEYS.remove(storage, (&sender, &key));
+        }
+        IDEMPOTENCY_LOG.remove(storage, oldest);
+    }
+    Ok(true)
+}
+
+/// Saves the trackers of a path, recording the change
+pub fn save_trackers(
+    storage: &mut dyn Storage,
+    path: &Path,
+    trackers: &[RateLimit],
+) -> StdResult<()> {
+    RATE_LIMIT_TRACKERS.save(storage, path.into(), &trackers.to_vec())?;
+    record_change(storage, path.into())?;
+    Ok(())
+}
+
+/// Removes the trackers of a path, recording the change
+pub fn remove_trackers(storage: &mut dyn Storage, path: &Path) -> StdResult<()> {
+    RATE_LIMIT_TRACKERS.remove(storage, path.into());
+    record_change(storage, path.into())?;
+    Ok(())
+}
+
+/// CHANNEL_ASSETS lists the denoms a contract accepts on a channel. Once set,
+/// packets of any other denom on the channel are rejected, whatever the quotas.
+/// Denoms are stored normalized
+pub const CHANNEL_ASSETS: Map<(&Addr, &str), Vec<String>> = Map::new("channel_assets");
+
+/// MAX_CHANNEL_ASSETS bounds the allowed denoms of a channel, as the whole list
+/// is read on every packet of the channel
+pub const MAX_CHANNEL_ASSETS: usize = 50;
+
+/// CHANNEL_DEFAULT_QUOTAS maps (contract, channel_id) to the quotas inherited by
+/// the denoms of the channel that don't have a path of their own.
+///
+/// The first packet of such a denom stores the inherited quotas as the trackers
+/// of its path, so later changes to the defaults don't affect it.
+pub const CHANNEL_DEFAULT_QUOTAS: Map<(Addr, String), Vec<QuotaMsg>> =
+    Map::new("channel_default_quotas");
+
+/// CHANNEL_TRACKERS maps (contract, channel_id) to the flows of all the denoms
+/// of the channel against its default quotas, when the quota hierarchy is
+/// enforced. The quotas that keep their name keep their flows when the defaults
+/// change, and undone and failed sends are given back to them
+pub const CHANNEL_TRACKERS: Map<(Addr, String), Vec<RateLimit>> = Map::new("channel_trackers");
+
+/// HistoryEntry is the archived flow of a quota for a finished period. The
+/// index is sequential for each path, across all of its quotas.
+#[cw_serde]
+pub struct HistoryEntry {
+    pub index: u64,
+    pub quota: String,
+    pub inflow: Uint256,
+    pub outflow: Uint256,
+    pub period_end: Timestamp,
+    #[serde(default)]
+    pub stats: FlowStats,
+}
+
+/// HISTORY_LENGTH is the number of archived periods kept for each path
+pub const HISTORY_LENGTH: usize = 20;
+
+/// HISTORY keeps the last HISTORY_LENGTH finished periods of each path, oldest
+/// first. Periods are archived when they roll over, either on traffic or on Tick.
+pub const HISTORY: Map<(Addr, String, String), Vec<HistoryEntry>> = Map::new("history");
+
+/// Rejection is a packet of a path refused by one of its quotas
+#[cw_serde]
+pub struct Rejection {
+    pub at: Timestamp,
+    pub amount: Uint128,
+    pub direction: FlowType,
+    pub quota: String,
+    pub sender: Option<String>,
+}
+
+/// REJECTIONS_LENGTH is the number of rejections kept for each path
+pub const REJECTIONS_LENGTH: usize = 20;
+
+/// REJECTIONS keeps the last REJECTIONS_LENGTH rejections of each path answered
+/// with data, oldest first
+pub const REJECTIONS: Map<PathKey, Vec<Rejection>> = Map::new("rejections");
+
+/// BlockRejections counts the rejections of a path answered in the block at
+/// height
+#[cw_serde]
+pub struct BlockRejections {
+    pub height: u64,
+    pub count: u32,
+}
+
+/// BLOCK_REJECTIONS is the rejection count of each path in the last block it
+/// had rejections in, for `Config::max_rejections_per_block`
+pub const BLOCK_REJECTIONS: Map<PathKey, BlockRejections> = Map::new("block_rejections");
+
+/// TICK_CURSOR is the last path visited by a Tick that didn't name its paths,
+/// so consecutive ticks walk through all the trackers
+pub const TICK_CURSOR: Item<(Addr, String, String)> = Item::new("tick_cursor");
+
+/// AuditAction is the kind of change recorded in the audit log. Adding a path
+/// that already exists replaces its quotas and is recorded as EditQuota
+#[cw_serde]
+pub enum AuditAction {
+    AddPath,
+    EditQuota,
+    RemovePath,
+    Reset,
+    MigratePath,
+    RenameQuota,
+}
+
+/// AuditEntry records who changed a path, when and, optionally, why. Changes
+/// made through sudo are recorded with the address of this contract as actor
+#[cw_serde]
+pub struct AuditEntry {
+    pub index: u64,
+    pub action: AuditAction,
+    pub actor: Addr,
+    pub at: Timestamp,
+    pub memo: Option<String>,
+}
+
+/// PATH_AUDIT_LOG is the append-only log of changes to each path, keyed by the
+/// path and a sequential index. Entries are kept after the path is removed
+pub const PATH_AUDIT_LOG: Map<((Addr, String, String), u64), AuditEntry> =
+    Map::new("path_audit_log");
+
+/// CLASS_TRACKERS holds the extra quotas of a path applying only to one class of
+/// packets or of receivers, keyed by the path and the class name. They are
+/// checked after the main quotas of the path, so they can only be stricter.
+pub const CLASS_TRACKERS: Map<(PathKey, String), Vec<RateLimit>> =
+    Map::new(CLASS_TRACKERS_NAMESPACE);
+
+/// TAG_TRACKERS holds the quotas of a path applying only to the transfers
+/// tagged by the bridge, keyed by the path and the tag. They override the
+/// quotas of the path: tagged transfers are only accounted on the quotas of
+/// their tag, untagged ones and tags without quotas on the ones of the path
+pub const TAG_TRACKERS: Map<(PathKey, String), Vec<RateLimit>> = Map::new("tag_flow");
+
+/// CHAIN_TRACKERS maps (contract, chain_id, denom) to the flows of the denom
+/// over all the paths of the contract whose counterparty is on that chain,
+/// whatever their channel, so the exposure to a single remote chain can be
+/// capped. Paths are linked to a chain by the counterparty of their config, and
+/// the quotas are checked on top of the quotas of the path
+pub const CHAIN_TRACKERS: Map<(Addr, String, String), Vec<RateLimit>> = Map::new("chain_flow");
+
+/// PathKey is the storage key of a path: (contract, channel_id, denom)
+pub type PathKey = (Addr, String, String);
+
+/// PATH_REDIRECTS points the key of a migrated path to the key it was moved
+/// to, so that queries against the old key keep working
+pub const PATH_REDIRECTS: Map<PathKey, PathKey> = Map::new("path_redirects");
+
+/// Keys the path by the key spec, then follows the redirects left by
+/// migrations, unless the path was configured again after being migrated
+pub fn resolve_path(storage: &dyn Storage, path: Path) -> StdResult<Path> {
+    let path = load_key_spec(storage)?.path(&path.contract, &path.channel, &path.denom);
+    let mut key: PathKey = path.into();
+    while !RATE_LIMIT_TRACKERS.has(storage, key.clone()) {
+        match PATH_REDIRECTS.may_load(storage, key.clone())? {
+            Some(next) => key = next,
+            None => break,
+        }
+    }
+    let (contract, channel, denom) = key;
+    Ok(Path::new(&contract, channel, denom))
+}
+
+/// QUARANTINE holds the raw bytes of tracker entries that could not be
+/// deserialized. They are moved here the first time a packet touches the path
+/// so the owner can inspect and purge them.
+pub const QUARANTINE: Map<(Addr, String, String), Binary> = Map::new("quarantine");
+
+/// PathConfig holds the per-path settings that are not part of the quotas.
+///
+/// When escrow is enabled, SendPacket must carry the transferred funds and its
+/// sequence. The funds are held by the contract until the send is acknowledged
+/// or undone, and are then released to the bridge. Funds sent to paths without
+/// escrow are refused.
+///
+/// evaluation_mode decides whether a transfer must fit in all the quotas of
+/// the path or in any one of them.
+///
+/// max_in_flight caps the value of the sends that were not acknowledged or
+/// undone yet. Only sends carrying a sequence can be settled, so only those
+/// are counted as in flight.
+///
+/// on_exceed decides whether transfers exceeding the quotas are rejected or
+/// truncated to the amount the quotas still allow.
+///
+/// reject_with_data answers the sends rejected by the quotas of the path with
+/// a `TransferData` accepting nothing, as truncated ones are, instead of an
+/// error. Only bridges reading the response data can set it, as the others
+/// would go ahead with the send. Receives keep failing. Rejections returned as
+/// errors revert along with their transaction, so only the ones answered with
+/// data are recorded, escalated or bounded by max_rejections_per_block.
+///
+/// on_reject_contract is notified of the sends rejected by the quotas of the
+/// path with a `RejectHookMsg`. An error would revert the notification, so it
+/// requires reject_with_data.
+///
+/// counterparty links the path to its other half, when the contract is also
+/// deployed on the counterparty chain.
+///
+/// escalation reacts to streaks of rejected sends, see `EscalationPolicy`.
+///
+/// tags label the path for the observers querying it through a `PathFilter`.
+///
+/// local_channel is the port and channel of this chain the packets of the path
+/// go through. The direction of the packets carrying their endpoints is checked
+/// against it, and inferred from it for ProcessPacket. Packets sent without
+/// their endpoints are trusted to be in the direction they're sent as, so the
+/// bridge must supply them for the direction to be verified.
+///
+/// fee_bps is the fee charged on the transfers the quotas accept, in basis
+/// points of the accepted amount, see `FeeAccount`. It's set by the owner with
+/// SetPathFee, SetPathConfig and RemovePath keep it.
+#[cw_serde]
+#[derive(Default)]
+pub struct PathConfig {
+    #[serde(default)]
+    pub escrow: bool,
+    #[serde(default)]
+    pub evaluation_mode: EvaluationMode,
+    #[serde(default)]
+    pub max_in_flight: Option<Uint256>,
+    #[serde(default)]
+    pub on_exceed: OnExceed,
+    #[serde(default)]
+    pub reject_with_data: bool,
+    #[serde(default)]
+    pub on_reject_contract: Option<Addr>,
+    #[serde(default)]
+    pub counterparty: Option<CounterpartyPath>,
+    #[serde(default)]
+    pub escalation: Option<EscalationPolicy>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub local_channel: Option<LocalChannel>,
+    #[serde(default)]
+    pub fee_bps: u16,
+}
+
+impl PathConfig {
+    /// Whether the transfers in `direction` exceeding the quotas are truncated.
+    /// Receives can't be split by the bridge, so they're always rejected
+    pub fn truncates(&self, direction: &FlowType) -> bool {
+        matches!(self.on_exceed, OnExceed::Truncate) && matches!(direction, FlowType::Out)
+    }
+}
+
+/// MAX_FEE_BPS caps the fee of a path, at 10% of the transfers
+pub const MAX_FEE_BPS: u16 = 1_000;
+
+/// FeeAccount holds the fees of the paths of a bridge contract for a denom.
+/// Fees are owed by the bridge, which deducts them from the transfers, until
+/// it pays them with PayFees. Its sends of the denom are refused meanwhile.
+/// Paid fees are collected by the contract until the owner withdraws them.
+/// Fees of pre-approved transfers are waived, and the ones of sends undone
+/// later are kept
+#[cw_serde]
+#[derive(Default)]
+pub struct FeeAccount {
+    pub owed: Uint128,
+    pub collected: Uint128,
+}
+
+/// FEES maps (bridge contract, denom) to its fee account
+pub const FEES: Map<(&Addr, &str), FeeAccount> = Map::new("fees");
+
+/// LocalChannel is the end of an IBC channel on this chain
+#[cw_serde]
+pub struct LocalChannel {
+    pub port_id: String,
+    pub channel_id: String,
+}
+
+/// EscalationPolicy escalates once `threshold` sends of a path were rejected
+/// with data in a row within `window` seconds of the first one: an
+/// `escalation` event is emitted, the path is paused if `freeze` is set, and
+/// the alert contract is sent an `AlertMsg`. A failing alert is reported by the
+/// reply entry point instead of reverting the rejection
+#[cw_serde]
+pub struct EscalationPolicy {
+    pub threshold: u32,
+    pub window: u64,
+    #[serde(default)]
+    pub freeze: bool,
+    #[serde(default)]
+    pub alert_contract: Option<Addr>,
+}
+
+/// Streak counts the sends of a path rejected in a row since `started_at`. It
+/// escalates at most once, and is cleared by the next transfer the quotas
+/// accept
+#[cw_serde]
+pub struct Streak {
+    pub count: u32,
+    pub started_at: Timestamp,
+    pub last_at: Timestamp,
+    pub escalated: bool,
+}
+
+/// STREAKS maps each path to its current streak of rejected sends
+pub const STREAKS: Map<PathKey, Streak> = Map::new("streaks");
+
+/// PathFilter selects paths by one of their tags, their channel or their denom
+#[cw_serde]
+pub enum PathFilter {
+    Tag { tag: String },
+    Channel { channel_id: String },
+    Denom { denom: String },
+}
+
+impl PathFilter {
+    pub fn matches(&self, path: &PathKey, config: Option<&PathConfig>) -> bool {
+        let (_, channel_id, denom) = path;
+        match self {
+            PathFilter::Tag { tag } => config.is_some_and(|config| config.tags.contains(tag)),
+            PathFilter::Channel {
+                channel_id: channel,
+            } => channel == channel_id,
+            PathFilter::Denom { denom: filtered } => filtered == denom,
+        }
+    }
+}
+
+/// StoredFilter is a filter saved by an observer, who alone can replace it
+#[cw_serde]
+pub struct StoredFilter {
+    pub owner: Addr,
+    pub filter: PathFilter,
+}
+
+/// FILTERS maps the id of a filter to the filter
+pub const FILTERS: Map<&str, StoredFilter> = Map::new("filters");
+
+/// CounterpartyPath is the path of the contract deployed on the counterparty
+/// chain that the packets of a path are accounted on over there. Its state is
+/// pushed by the relayer with SyncCounterpartyState, so our outflow can be
+/// compared with its inflow and the other way round
+#[cw_serde]
+pub struct CounterpartyPath {
+    pub chain_id: String,
+    pub contract: String,
+    pub channel_id: String,
+    pub denom: String,
+    pub relayer: Addr,
+}
+
+/// CounterpartyState is the last usage of the counterparty path synced by the
+/// relayer. reported_at is the time of the counterparty chain the usage was
+/// read at, and synced_at the time it reached this contract
+#[cw_serde]
+pub struct CounterpartyState {
+    pub usage: Vec<QuotaUsage>,
+    pub reported_at: Timestamp,
+    pub synced_at: Timestamp,
+}
+
+/// COUNTERPARTY_STATES maps each path to the last synced state of its
+/// counterparty path
+pub const COUNTERPARTY_STATES: Map<PathKey, CounterpartyState> = Map::new("counterparty_states");
+
+/// OnExceed decides what happens to a transfer exceeding the quotas of its
+/// path. Truncated transfers are accepted for the amount that fits, which is
+/// returned in the response data as a `TransferData` so the bridge can split
+/// the transfer. Nothing fitting is still rejected, and so are receives
+#[cw_serde]
+#[derive(Default)]
+pub enum OnExceed {
+    #[default]
+    Reject,
+    Truncate,
+}
+
+/// EvaluationMode decides how the quotas of a path are combined. With Any, a
+/// generous long-window quota can let through a transfer that exceeds a strict
+/// short-window one. The transfer is accounted on every quota either way
+#[cw_serde]
+#[derive(Default)]
+pub enum EvaluationMode {
+    #[default]
+    All,
+    Any,
+}
+
+/// PATH_CONFIGS maps a path (contract, channel_id, denom) to its settings.
+/// Paths without an entry use the default settings.
+pub const PATH_CONFIGS: Map<(Addr, String, String), PathConfig> = Map::new("path_configs");
+
+/// ESCROW_BALANCES tracks the funds held for each path in escrow mode. They are
+/// keyed by the denom of the funds, even if the path isn't
+pub const ESCROW_BALANCES: Map<(Addr, String, String), Uint128> = Map::new("escrow_balances");
+
+/// Metrics holds the counters and gauges exposed by the Metrics query.
+///
+/// rejected counts the sends rejected with data on paths configured so. Other
+/// packets rejected by a quota abort the transaction, so they leave no trace in
+/// storage, and neither do the ones dropped past max_rejections_per_block.
+/// packets_undone counts the sends reverted after being processed.
+/// quarantined_paths are the paths frozen because their trackers are corrupt.
+#[cw_serde]
+#[derive(Default)]
+pub struct Metrics {
+    pub packets_in: u64,
+    pub packets_out: u64,
+    pub packets_undone: u64,
+    #[serde(default)]
+    pub rejected: u64,
+    pub paths: u64,
+    pub quarantined_paths: u64,
+}
+
+pub const METRICS: Item<Metrics> = Item::new("metrics");
+
+/// Applies a change to the metrics. Contracts instantiated before the metrics
+/// were introduced start from zero, the paths gauge being seeded on migration
+pub fn update_metrics(storage: &mut dyn Storage, f: impl FnOnce(&mut Metrics)) -> StdResult<()> {
+    let mut metrics = METRICS.may_load(storage)?.unwrap_or_default();
+    f(&mut metrics);
+    METRICS.save(storage, &metrics)
+}
+
+/// DailyStats accumulates the packets of a path over a UTC day, independently
+/// of its quotas. day is the number of days since the unix epoch. Rejected
+/// packets only count in rejections, and only when the path answers them with
+/// data, as the others abort the transaction. packets_undone counts the sends
+/// reverted after being processed.
+///
+/// last_packet is the last packet accepted on the path. It's carried over to
+/// the next day, so it stays set however long ago it was. It's kept with the
+/// stats as they are written on every packet anyway
+#[cw_serde]
+#[derive(Default)]
+pub struct DailyStats {
+    pub day: u64,
+    pub inflow: Uint256,
+    pub outflow: Uint256,
+    pub packets_in: u64,
+    pub packets_out: u64,
+    pub packets_undone: u64,
+    #[serde(default)]
+    pub rejections: u64,
+    #[serde(default)]
+    pub last_packet: Option<LastPacket>,
+}
+
+/// LastPacket is a packet accepted on a path, with the amount accounted for it
+#[cw_serde]
+pub struct LastPacket {
+    pub direction: FlowType,
+    pub amount: Uint128,
+    pub timestamp: Timestamp,
+    pub sequence: Option<u64>,
+}
+
+/// DAILY_STATS keeps the stats of the current day of each path. They are
+/// emitted in a daily_rollup event once the day is over
+pub const DAILY_STATS: Map<PathKey, DailyStats> = Map::new("daily_stats");
+
+/// PendingPeriod is the period of a quota a send was accounted in
+#[cw_serde]
+pub struct PendingPeriod {
+    pub quota: String,
+    pub period_end: Timestamp,
+}
+
+/// PendingSend records the periods a send was accounted in until it is
+/// acknowledged or undone, so undoing it can't affect a later period
+#[cw_serde]
+pub struct PendingSend {
+    pub denom: String,
+    pub amount: Uint128,
+    pub periods: Vec<PendingPeriod>,
+    /// Whether the amount was added to the in flight value of the path
+    #[serde(default)]
+    pub in_flight: bool,
+    /// Whether the amount is held in escrow until the send is settled
+    #[serde(default)]
+    pub escrowed: bool,
+    /// The tag whose quotas the send was accounted on, instead of the ones of
+    /// the path
+    #[serde(default)]
+    pub tag: Option<String>,
+    /// Whether the send went through on a pre-approval, without being
+    /// accounted on any quota
+    #[serde(default)]
+    pub pre_approved: bool,
+    /// The periods of the class quotas the send was accounted in, by class
+    #[serde(default)]
+    pub class_periods: Vec<(String, PendingPeriod)>,
+    /// The periods of the quotas of the channel the send was accounted in
+    #[serde(default)]
+    pub channel_periods: Vec<PendingPeriod>,
+    /// The periods of the quotas of the counterparty chain the send was
+    /// accounted in
+    #[serde(default)]
+    pub chain_periods: Vec<PendingPeriod>,
+    /// The periods of the degraded quotas the send was accounted in, none
+    /// unless it was sent while heartbeats were missed
+    #[serde(default)]
+    pub degraded_periods: Vec<PendingPeriod>,
+}
+
+/// PENDING_SENDS maps (contract, channel_id, sequence) to the sends that have
+/// not been acknowledged yet. Only sends carrying a sequence are recorded
+pub const PENDING_SENDS: Map<(Addr, String, u64), PendingSend> = Map::new("pending_sends");
+
+/// PENDING_ICA_SENDS maps (contract, channel_id, sequence) to the coins of an
+/// ICA packet that have not been acknowledged yet, one send per denom
+pub const PENDING_ICA_SENDS: Map<(Addr, String, u64), Vec<PendingSend>> =
+    Map::new("pending_ica_sends");
+
+/// IN_FLIGHT is the value of the pending sends of the paths with a
+/// max_in_flight configured
+pub const IN_FLIGHT: Map<PathKey, Uint256> = Map::new("in_flight");
+
+/// PENDING_IN_FLIGHT is the value of the pending sends of each path counted in
+/// its in flight value. It is kept alongside the pending sends so IN_FLIGHT
+/// can be checked without scanning them
+pub const PENDING_IN_FLIGHT: Map<PathKey, Uint256> = Map::new("pending_in_flight");
+
+/// EscrowValue is the last escrow balance of a path reported by the escrow
+/// querier, and when it was received
+#[cw_serde]
+pub struct EscrowValue {
+    pub amount: Uint128,
+    pub updated_at: Timestamp,
+}
+
+/// ESCROW_VALUES holds the escrow balances reported by the escrow querier. They
+/// take precedence over the channel value of the packets
+pub const ESCROW_VALUES: Map<PathKey, EscrowValue> = Map::new("escrow_values");
+
+/// REGISTERED_BRIDGES holds the contracts allowed to send packets without
+/// having paths yet, when the config requires registered senders
+pub const REGISTERED_BRIDGES: Map<&Addr, Empty> = Map::new("registered_bridges");
+
+/// RECEIVER_CLASSES registers the class of receiver addresses on the
+/// counterparty chains. Unregistered receivers are accounts
+pub const RECEIVER_CLASSES: Map<&str, ReceiverClass> = Map::new("receiver_classes");
+
+/// Multisig is the set of addresses managing the contract once it owns itself.
+/// Management messages are proposed by one of them and executed by the
+/// contract after `threshold` of them confirmed.
+#[cw_serde]
+pub struct Multisig {
+    pub owners: Vec<Addr>,
+    pub threshold: u32,
+}
+
+pub const MULTISIG: Item<Multisig> = Item::new("multisig");
+
+/// Proposal is a message waiting for the confirmations of the multisig owners.
+/// It can't be confirmed nor executed after it expires
+#[cw_serde]
+pub struct Proposal {
+    pub id: u64,
+    pub msg: ExecuteMsg,
+    pub proposer: Addr,
+    pub confirmations: Vec<Addr>,
+    pub expires: Timestamp,
+    pub executed: bool,
+}
+
+/// PROPOSALS maps a sequential id to each proposal
+pub const PROPOSALS: Map<u64, Proposal> = Map::new("proposals");
+
+/// PROPOSAL_COUNT is the number of proposals ever created
+pub const PROPOSAL_COUNT: Item<u64> = Item::new("proposal_count");
+
+/// MAX_PROPOSAL_EXPIRY bounds how long a proposal stays open, in seconds: 30
+/// days
+pub const MAX_PROPOSAL_EXPIRY: u64 = 30 * SECONDS_PER_DAY;
+
+/// ApproverRole is the capacity in which a transfer was pre-approved: by the
+/// contract owner, or by the multisig through an executed proposal
+#[cw_serde]
+pub enum ApproverRole {
+    Owner,
+    Multisig,
+}
+
+/// PreApproval lets a single packet of exactly `amount` through the quotas of
+/// a path until `expires`. Consumed approvals are kept, with the time they
+/// were used at, so they can be reviewed
+#[cw_serde]
+pub struct PreApproval {
+    pub id: u64,
+    pub amount: Uint128,
+    pub expires: Timestamp,
+    pub approver: Addr,
+    pub approver_role: ApproverRole,
+    pub consumed_at: Option<Timestamp>,
+}
+
+/// PRE_APPROVALS maps each path to its pre-approved transfers, by id
+pub const PRE_APPROVALS: Map<(PathKey, u64), PreApproval> = Map::new("pre_approvals");
+
+/// OPEN_PRE_APPROVALS indexes the pre-approvals not consumed yet by path and
+/// amount, so packets only look at the ones they could consume. Expired ones
+/// leave it once a packet comes across them
+pub const OPEN_PRE_APPROVALS: Map<(PathKey, u128, u64), Empty> = Map::new("open_pre_approvals");
+
+/// PRE_APPROVAL_COUNT is the number of transfers ever pre-approved
+pub const PRE_APPROVAL_COUNT: Item<u64> = Item::new("pre_approval_count");
+
+/// MAX_PRE_APPROVAL_WINDOW bounds how long a pre-approval can wait to be
+/// used, in seconds: a year
+pub const MAX_PRE_APPROVAL_WINDOW: u64 = 365 * SECONDS_PER_DAY;
+
+/// Reservation earmarks `amount` of the capacity of a path in a direction
+/// until `expires`, ahead of a planned operation. Other packets can't use it,
+/// while the packets sent by the beneficiary draw from it. What they drew is
+/// kept in `consumed`
+#[cw_serde]
+pub struct Reservation {
+    pub id: u64,
+    pub direction: FlowType,
+    pub amount: Uint128,
+    pub consumed: Uint128,
+    pub beneficiary: String,
+    pub expires: Timestamp,
+    pub reserved_by: Addr,
+    pub role: ApproverRole,
+}
+
+impl Reservation {
+    /// The part of the reservation still earmarked
+    pub fn left(&self) -> Uint128 {
+        self.amount.saturating_sub(self.consumed)
+    }
+
+    /// Whether the reservation still earmarks capacity
+    pub fn is_active(&self, now: Timestamp) -> bool {
+        now <= self.expires && !self.left().is_zero()
+    }
+
+    /// Whether the reservation still earmarks capacity in the direction
+    pub fn is_open(&self, direction: &FlowType, now: Timestamp) -> bool {
+        self.direction == *direction && self.is_active(now)
+    }
+}
+
+/// RESERVATIONS maps each path to its capacity reservations, by id
+pub const RESERVATIONS: Map<(PathKey, u64), Reservation> = Map::new("reservations");
+
+/// ACTIVE_RESERVATIONS indexes the reservations of each path that still
+/// earmark capacity, so packets don't go through the used up ones. Expired
+/// ones leave it once a packet comes across them
+pub const ACTIVE_RESERVATIONS: Map<(PathKey, u64), Empty> = Map::new("active_reservations");
+
+/// MAX_ACTIVE_RESERVATIONS bounds the active reservations of a path, as all of
+/// them are read on every packet of the path
+pub const MAX_ACTIVE_RESERVATIONS: usize = 20;
+
+/// MAX_RESERVATION_WINDOW bounds how long a reservation holds capacity back,
+/// in seconds: a year
+pub const MAX_RESERVATION_WINDOW: u64 = 365 * SECONDS_PER_DAY;
+
+/// RESERVATION_COUNT is the number of reservations ever made
+pub const RESERVATION_COUNT: Item<u64> = Item::new("reservation_count");
+
+/// PAUSED halts the processing of new packets for every path while set.
+/// It can be toggled by the owner or by chain governance through sudo.
+pub const PAUSED: Item<bool> = Item::new("paused");
+
+/// LAST_HEARTBEAT is the time of the last Heartbeat of the keeper, or of the
+/// config update setting the heartbeat policy, whichever is last
+pub const LAST_HEARTBEAT: Item<Timestamp> = Item::new("last_heartbeat");
+
+/// DEGRADED_TRACKERS holds the flows of each path under the degraded quotas of
+/// the heartbeat policy. They are created on the first send after a missed
+/// heartbeat and kept after, expiring with their periods, until the heartbeat
+/// policy changes
+pub const DEGRADED_TRACKERS: Map<PathKey, Vec<RateLimit>> = Map::new("degraded_flow");
+
+/// PAUSE_EXPIRY is when the global pause lifts by itself, if it was set with
+/// an `auto_unpause_at`. The pause is lifted by the first packet processed
+/// after it, so a forgotten pause can't hold the packets back indefinitely
+pub const PAUSE_EXPIRY: Item<Timestamp> = Item::new("pause_expiry");
+
+/// Whether the global pause holds at `now`, an expired pause counting as
+/// lifted even before a packet clears it
+pub fn is_paused(storage: &dyn Storage, now: Timestamp) -> StdResult<bool> {
+    if !PAUSED.may_load(storage)?.unwrap_or_default() {
+        return Ok(false);
+    }
+    Ok(match PAUSE_EXPIRY.may_load(storage)? {
+        Some(expiry) => now < expiry,
+        None => true,
+    })
+}
+
+/// MAINTENANCE is set by migrate while the state is being rewritten. Execute
+/// messages from anyone but the owner are rejected until it's cleared, queries
+/// keep being served
+pub const MAINTENANCE: Item<bool> = Item::new("maintenance");
+
+/// PAUSED_DIRECTIONS halts the packets flowing in a single direction, keyed by
+/// `FlowType::as_str`. It lets sends be stopped while refunds keep coming in
+pub const PAUSED_DIRECTIONS: Map<&str, Empty> = Map::new("paused_directions");
+
+/// PAUSED_CONTRACTS halts the packets of every path of a bridge contract, so a
+/// single bridge of a multi-bridge deployment can be stopped
+pub const PAUSED_CONTRACTS: Map<&Addr, Empty> = Map::new("paused_contracts");
+
+/// PAUSED_CHANNELS halts the packets of a bridge contract on a channel, keyed
+/// by the channel of its paths
+pub const PAUSED_CHANNELS: Map<(&Addr, &str), Empty> = Map::new("paused_channels");
+
+/// PAUSED_PATHS halts the packets of a single path
+pub const PAUSED_PATHS: Map<PathKey, Empty> = Map::new("paused_paths");
+
+/// PauseScope is the part of the packets a pause applies to, below the global
+/// pause
+#[cw_serde]
+pub enum PauseScope {
+    Contract { contract: Addr },
+    Channel { contract: Addr, channel_id: String },
+    Path(Path),
+}
+
+/// PACKET_SEQUENCES keeps the highest packet sequence processed for each
+/// (contract, channel_id, direction) so the same packet can't be counted twice.
+pub const PACKET_SEQUENCES: Map<(Addr, String, String), u64> = Map::new("packet_sequences");
 
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::time::ContractClock;
 
     pub const RESET_TIME_DAILY: u64 = 60 * 60 * 24;
     pub const RESET_TIME_WEEKLY: u64 = 60 * 60 * 24 * 7;
     pub const RESET_TIME_MONTHLY: u64 = 60 * 60 * 24 * 30;
 
+    const WEEKLY_NANOS: u64 = RESET_TIME_WEEKLY * NANOS_PER_SECOND;
+
+    #[test]
+    fn denom_normalization() {
+        let atom = "ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2";
+        assert_eq!(normalize_denom(atom), atom);
+        assert_eq!(normalize_denom(&atom.to_lowercase()), atom);
+        assert_eq!(
+            normalize_denom(
+                " IBC/27394fb092d2eccd56123c74f36e4c1f926001ceada9ca97ea622b25f41e5eb2"
+            ),
+            atom
+        );
+        assert_eq!(normalize_denom("transfer/channel-0/uatom"), atom);
+
+        // Native and factory denoms are left alone
+        assert_eq!(normalize_denom("uOSMO"), "uOSMO");
+        assert_eq!(
+            normalize_denom("factory/osmo1abc/Token"),
+            "factory/osmo1abc/Token"
+        );
+        assert_eq!(
+            Path::new(
+                &Addr::unchecked("contract"),
+                "channel-0",
+                "transfer/channel-0/uatom"
+            )
+            .denom,
+            atom
+        );
+    }
+
     #[test]
     fn flow() {
         let epoch = Timestamp::from_seconds(0);
-        let mut flow = Flow::new(0_u32, 0_u32, epoch, RESET_TIME_WEEKLY);
+        let mut flow = Flow::new(0_u32, 0_u32, &epoch, WEEKLY_NANOS);
 
-        assert!(!flow.is_expired(epoch));
-        assert!(!flow.is_expired(epoch.plus_seconds(RESET_TIME_DAILY)));
-        assert!(!flow.is_expired(epoch.plus_seconds(RESET_TIME_WEEKLY)));
-        assert!(flow.is_expired(epoch.plus_seconds(RESET_TIME_WEEKLY).plus_nanos(1)));
+        assert!(!flow.is_expired(&epoch));
+        assert!(!flow.is_expired(&epoch.plus_seconds(RESET_TIME_DAILY)));
+        assert!(!flow.is_expired(&epoch.plus_seconds(RESET_TIME_WEEKLY)));
+        assert!(flow.is_expired(&epoch.plus_seconds(RESET_TIME_WEEKLY).plus_nanos(1)));
 
         assert_eq!(flow.balance(), (0_u32.into(), 0_u32.into()));
         flow.add_flow(FlowType::In, 5_u32.into());
@@ -296,17 +2216,224 @@ pub mod tests {
         flow.add_flow(FlowType::Out, 2_u32.into());
         assert_eq!(flow.balance(), (3_u32.into(), 0_u32.into()));
         // Adding flow doesn't affect expiration
-        assert!(!flow.is_expired(epoch.plus_seconds(RESET_TIME_DAILY)));
+        assert!(!flow.is_expired(&epoch.plus_seconds(RESET_TIME_DAILY)));
+        assert_eq!(flow.stats.packets_in, 1);
+        assert_eq!(flow.stats.packets_out, 1);
+        assert_eq!(flow.stats.largest_transfer, Uint128::from(5_u32));
 
-        flow.expire(epoch.plus_seconds(RESET_TIME_WEEKLY), RESET_TIME_WEEKLY);
+        flow.expire(&epoch.plus_seconds(RESET_TIME_WEEKLY), WEEKLY_NANOS);
         assert_eq!(flow.balance(), (0_u32.into(), 0_u32.into()));
-        assert_eq!(flow.inflow, Uint128::from(0_u32));
-        assert_eq!(flow.outflow, Uint128::from(0_u32));
+        assert_eq!(flow.inflow, Uint256::zero());
+        assert_eq!(flow.outflow, Uint256::zero());
         assert_eq!(flow.period_end, epoch.plus_seconds(RESET_TIME_WEEKLY * 2));
+        assert_eq!(flow.stats, FlowStats::default());
 
         // Expiration has moved
-        assert!(!flow.is_expired(epoch.plus_seconds(RESET_TIME_WEEKLY).plus_nanos(1)));
-        assert!(!flow.is_expired(epoch.plus_seconds(RESET_TIME_WEEKLY * 2)));
-        assert!(flow.is_expired(epoch.plus_seconds(RESET_TIME_WEEKLY * 2).plus_nanos(1)));
+        assert!(!flow.is_expired(&epoch.plus_seconds(RESET_TIME_WEEKLY).plus_nanos(1)));
+        assert!(!flow.is_expired(&epoch.plus_seconds(RESET_TIME_WEEKLY * 2)));
+        assert!(flow.is_expired(&epoch.plus_seconds(RESET_TIME_WEEKLY * 2).plus_nanos(1)));
+    }
+
+    #[test]
+    fn flow_grace() {
+        let epoch = Timestamp::from_seconds(0);
+        let flow = Flow::new(0_u32, 0_u32, &epoch, WEEKLY_NANOS);
+        let clock = |now: Timestamp| ContractClock {
+            now,
+            grace_nanos: NANOS_PER_SECOND,
+            height: 0,
+        };
+
+        // Expired from a second before the end of the period
+        let grace_start = epoch.plus_seconds(RESET_TIME_WEEKLY - 1);
+        assert!(!flow.is_expired(&clock(grace_start)));
+        assert!(flow.is_expired(&clock(grace_start.plus_nanos(1))));
+        assert!(!flow.is_expired(&grace_start.plus_nanos(1)));
+    }
+
+    #[test] // Tests how each reset mode handles the usage of the current period
+    fn reset_modes() {
+        let epoch = Timestamp::from_seconds(0);
+        let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 100_u32.into(), 100_u32.into());
+        let limit = RateLimit {
+            quota: (&quota).into(),
+            flow: Flow::new(40_u32, 80_u32, &epoch, WEEKLY_NANOS),
+            last_reset: None,
+        };
+        // A quarter of the period is left
+        let now = epoch.plus_seconds(RESET_TIME_WEEKLY / 4 * 3);
+        let owner = Addr::unchecked("owner");
+
+        let reset = |mode: ResetMode| {
+            let mut limit = limit.clone();
+            limit.reset(&owner, &now, None, mode.clone());
+            assert_eq!(limit.flow.period_end, now.plus_seconds(RESET_TIME_WEEKLY));
+            assert_eq!(limit.last_reset.as_ref().unwrap().mode, mode);
+            (limit.flow.inflow, limit.flow.outflow)
+        };
+        assert_eq!(reset(ResetMode::Full), (Uint256::zero(), Uint256::zero()));
+        assert_eq!(
+            reset(ResetMode::ExtendOnly),
+            (Uint256::from(40_u128), Uint256::from(80_u128))
+        );
+        assert_eq!(
+            reset(ResetMode::Prorate),
+            (Uint256::from(10_u128), Uint256::from(20_u128))
+        );
+
+        // Expired periods have no time left
+        let mut expired = limit.clone();
+        let later = epoch.plus_seconds(RESET_TIME_WEEKLY * 2);
+        expired.reset(&owner, &later, None, ResetMode::Prorate);
+        assert_eq!(expired.flow.balance(), (Uint256::zero(), Uint256::zero()));
+    }
+
+    #[test] // Tests that clamping to a duration past the end of time keeps the period open
+    fn clamp_duration_saturates() {
+        let now = Timestamp::from_seconds(1_000);
+        let quota = QuotaMsg::new("weekly", RESET_TIME_WEEKLY, 100_u32.into(), 100_u32.into());
+        let mut limit = RateLimit {
+            quota: (&quota).into(),
+            flow: Flow::new(0_u32, 0_u32, &now, WEEKLY_NANOS),
+            last_reset: None,
+        };
+        limit.quota.duration_nanos = u64::MAX;
+        limit.flow.period_end = Timestamp::from_nanos(u64::MAX);
+
+        assert!(limit.clamp_duration(u64::MAX - 1, &now));
+        assert_eq!(limit.quota.duration_nanos, u64::MAX - 1);
+        assert_eq!(limit.flow.period_end, Timestamp::from_nanos(u64::MAX));
+    }
+
+    #[test] // Tests that combined quotas count both directions against the same pool
+    fn combined_quota() {
+        let epoch = Timestamp::from_seconds(0);
+        let path = Path::new(&Addr::unchecked("contract"), "channel", "denom");
+        let quota = QuotaMsg::combined("weekly", RESET_TIME_WEEKLY, 10_u32.into());
+        let mut limit = RateLimit {
+            quota: (&quota).into(),
+            flow: Flow::new(0_u32, 0_u32, &epoch, WEEKLY_NANOS),
+            last_reset: None,
+        };
+
+        limit
+            .allow_transfer(&path, &FlowType::In, 6_u32.into(), None, &epoch)
+            .unwrap();
+        // A netted quota would allow this, as it brings the balance back to zero
+        let err = limit
+            .allow_transfer(&path, &FlowType::Out, 6_u32.into(), None, &epoch)
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::RateLimitExceded { used, max, .. }
+                if used == Uint256::from(6_u128) && max == Uint256::from(10_u128)
+        ));
+
+        let mut limit = RateLimit {
+            quota: (&quota).into(),
+            flow: Flow::new(6_u32, 0_u32, &epoch, WEEKLY_NANOS),
+            last_reset: None,
+        };
+        limit
+            .allow_transfer(&path, &FlowType::Out, 4_u32.into(), None, &epoch)
+            .unwrap();
+        assert_eq!(limit.flow.total(), Uint256::from(10_u128));
+    }
+
+    #[test] // Tests that wei-scale flows accumulate past the max Uint128 without saturating
+    fn wei_scale_flows() {
+        let epoch = Timestamp::from_seconds(0);
+        let path = Path::new(&Addr::unchecked("contract"), "channel", "denom");
+        // 6 * 10^20 tokens of 18 decimals, above the max Uint128
+        let wei = Uint256::from(10_u128.pow(18));
+        let max_total = Uint256::from(6 * 10_u128.pow(20)) * wei;
+        let quota = QuotaMsg::combined("weekly", RESET_TIME_WEEKLY, max_total);
+        let mut limit = RateLimit {
+            quota: (&quota).into(),
+            flow: Flow::new(0_u32, 0_u32, &epoch, WEEKLY_NANOS),
+            last_reset: None,
+        };
+
+        let funds = Uint128::MAX / Uint128::new(2);
+        for _ in 0..3 {
+            limit
+                .allow_transfer(&path, &FlowType::In, funds, None, &epoch)
+                .unwrap();
+        }
+        let total = Uint256::from(funds) * Uint256::from(3_u8);
+        assert!(total > Uint256::from(Uint128::MAX));
+        assert_eq!(limit.flow.total(), total);
+
+        let err = limit
+            .allow_transfer(&path, &FlowType::Out, Uint128::MAX, None, &epoch)
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::RateLimitExceded { used, max, .. }
+                if used == total && max == max_total
+        ));
+
+        // Netted balances don't saturate either
+        let mut flow = Flow::new(0_u32, 0_u32, &epoch, WEEKLY_NANOS);
+        flow.add_flow(FlowType::Out, Uint128::MAX);
+        flow.add_flow(FlowType::Out, Uint128::MAX);
+        flow.add_flow(FlowType::In, Uint128::MAX);
+        assert_eq!(flow.balance_on(&FlowType::Out), Uint256::from(Uint128::MAX));
+    }
+
+    #[test] // Tests the percentage capacity on small, uneven and huge channel values
+    fn percentage_capacity() {
+        let limit = |rounding: Rounding, min_capacity: u128| PercentageLimit {
+            send: Decimal::percent(5),
+            recv: Decimal::permille(1),
+            rounding,
+            min_capacity: Uint256::from(min_capacity),
+        };
+
+        // 5% and 0.1% of 10 truncate to zero
+        assert_eq!(
+            limit(Rounding::Floor, 0).capacity(Uint128::new(10)),
+            (Uint256::zero(), Uint256::zero())
+        );
+        assert_eq!(
+            limit(Rounding::Ceil, 0).capacity(Uint128::new(10)),
+            (Uint256::from(1_u128), Uint256::from(1_u128))
+        );
+        assert_eq!(
+            limit(Rounding::Floor, 3).capacity(Uint128::new(10)),
+            (Uint256::from(3_u128), Uint256::from(3_u128))
+        );
+
+        // Exact results are not rounded up
+        assert_eq!(
+            limit(Rounding::Ceil, 0).capacity(Uint128::new(1000)),
+            (Uint256::from(1_u128), Uint256::from(50_u128))
+        );
+        assert_eq!(
+            limit(Rounding::Ceil, 0).capacity(Uint128::new(1001)),
+            (Uint256::from(2_u128), Uint256::from(51_u128))
+        );
+        assert_eq!(
+            limit(Rounding::Floor, 0).capacity(Uint128::new(1001)),
+            (Uint256::from(1_u128), Uint256::from(50_u128))
+        );
+
+        // The intermediate product doesn't overflow
+        let max = Uint256::from(Uint128::MAX);
+        assert_eq!(
+            limit(Rounding::Floor, 0).capacity(Uint128::MAX),
+            (max / Uint256::from(1000_u128), max / Uint256::from(20_u128))
+        );
+        let above_total = PercentageLimit {
+            send: Decimal::percent(200),
+            recv: Decimal::one(),
+            rounding: Rounding::Floor,
+            min_capacity: Uint256::zero(),
+        };
+        // Fractions above 100% are no longer capped to the max Uint128
+        assert_eq!(
+            above_total.capacity(Uint128::MAX),
+            (max, max * Uint256::from(2_u8))
+        );
     }
 }
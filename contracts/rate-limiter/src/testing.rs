@@ -0,0 +1,103 @@
+//! Helpers for cw-multi-test suites of contracts integrating the rate limiter.
+//! They hide the period math, so tests don't depend on how flows expire.
+
+use cosmwasm_std::{Addr, Empty, Timestamp};
+use cosmwasm_testing_util::{App, Contract, ContractWrapper};
+
+use crate::msg::{PathId, QueryMsg, RateLimitResponse, Versioned};
+
+/// The rate limiter, ready to be stored in an App
+pub fn contract() -> Box<dyn Contract<Empty>> {
+    let contract = ContractWrapper::new(
+        crate::contract::execute,
+        crate::contract::instantiate,
+        crate::contract::query,
+    )
+    .with_sudo(crate::contract::sudo)
+    .with_reply(crate::contract::reply);
+
+    Box::new(contract)
+}
+
+/// The quotas of a path, as tracked by the rate limiter at `contract`
+pub fn path_quotas(app: &App, contract: &Addr, path: &PathId) -> Vec<RateLimitResponse> {
+    let msg = QueryMsg::GetQuotas {
+        contract: path.contract.clone(),
+        channel_id: path.channel_id.clone(),
+        denom: path.denom.clone(),
+    };
+    app.wrap()
+        .query_wasm_smart::<Versioned<Vec<RateLimitResponse>>>(contract, &msg)
+        .unwrap()
+        .data
+}
+
+/// Moves the block time past the end of `n` periods of the longest quota of
+/// the path, so all of its flows are expired on the next packet. The first
+/// period is the current one. Doesn't account for a mocked contract time
+pub fn advance_period(app: &mut App, contract: &Addr, path: &PathId, n: u64) {
+    let quotas = path_quotas(app, contract, path);
+    // Quotas with split durations are measured by their longest direction
+    let longest = quotas
+        .iter()
+        .flat_map(|limit| {
+            [
+                (limit.flow.period_end, limit.quota.send_duration_nanos),
+                (limit.flow.recv_period_end, limit.quota.recv_duration_nanos),
+            ]
+        })
+        .max_by_key(|(_, duration)| *duration);
+    let Some((period_end, duration)) = longest else {
+        return;
+    };
+    if n == 0 {
+        return;
+    }
+
+    let now = app.block_info().time;
+    let end = period_end.max(now);
+    let time = end
+        .plus_nanos(duration.saturating_mul(n.saturating_sub(1)))
+        .plus_nanos(1);
+    set_block_time(app, time);
+}
+
+fn set_block_time(app: &mut App, time: Timestamp) {
+    app.update_block(|block| {
+        let seconds = time.seconds().saturating_sub(block.time.seconds());
+        block.height += (seconds / 5).max(1);
+        block.time = time;
+    });
+}
+
+/// Asserts that the result of executing a message on an App failed because a
+/// quota was exceeded, optionally checking the name of the quota
+#[macro_export]
+macro_rules! assert_rate_limited {
+    ($res:expr) => {
+        match $res {
+            Ok(_) => panic!("expected the transfer to be rate limited"),
+            Err(err) => assert!(
+                matches!(
+                    err.downcast_ref::<$crate::ContractError>(),
+                    Some($crate::ContractError::RateLimitExceded { .. })
+                ),
+                "expected a rate limit error, got {err}"
+            ),
+        }
+    };
+    ($res:expr, $quota:expr) => {
+        match $res {
+            Ok(_) => panic!("expected the transfer to be rate limited"),
+            Err(err) => assert!(
+                matches!(
+                    err.downcast_ref::<$crate::ContractError>(),
+                    Some($crate::ContractError::RateLimitExceded { quota_name, .. })
+                        if quota_name == $quota
+                ),
+                "expected a rate limit error on {}, got {err}",
+                $quota
+            ),
+        }
+    };
+}
@@ -0,0 +1,94 @@
+use cosmwasm_std::{BlockInfo, Env, StdResult, Storage, Timestamp};
+#[cfg(feature = "mock-time")]
+use cw_storage_plus::Item;
+
+use crate::state::{load_config, Config};
+
+/// TimeProvider is the source of "contract time" used for all the period
+/// calculations. In production this is always the block time, but with the
+/// `mock-time` feature tests can move it independently from the blocks.
+pub trait TimeProvider {
+    fn now(&self) -> Timestamp;
+
+    /// How long before their end periods are already considered over
+    fn grace_nanos(&self) -> u64 {
+        0
+    }
+
+    /// The height of the block being executed, zero when not known
+    fn height(&self) -> u64 {
+        0
+    }
+}
+
+/// A fixed point in time
+impl TimeProvider for Timestamp {
+    fn now(&self) -> Timestamp {
+        *self
+    }
+}
+
+/// The time of the block being executed
+impl TimeProvider for BlockInfo {
+    fn now(&self) -> Timestamp {
+        self.time
+    }
+
+    fn height(&self) -> u64 {
+        self.height
+    }
+}
+
+/// ContractClock is the time of the current call along with the expiry grace
+/// of the config and the height of the block
+pub struct ContractClock {
+    pub now: Timestamp,
+    pub grace_nanos: u64,
+    pub height: u64,
+}
+
+impl TimeProvider for ContractClock {
+    fn now(&self) -> Timestamp {
+        self.now
+    }
+
+    fn grace_nanos(&self) -> u64 {
+        self.grace_nanos
+    }
+
+    fn height(&self) -> u64 {
+        self.height
+    }
+}
+
+/// MOCK_TIME overrides the block time when set. It only exists with the
+/// `mock-time` feature and must never be enabled in production builds
+#[cfg(feature = "mock-time")]
+pub const MOCK_TIME: Item<Timestamp> = Item::new("mock_time");
+
+/// Returns the clock to use for the current call
+pub fn contract_clock(storage: &dyn Storage, env: &Env) -> StdResult<ContractClock> {
+    config_clock(storage, env, &load_config(storage)?)
+}
+
+/// Returns the clock to use for the current call, with the grace of a config
+/// the caller already loaded
+pub fn config_clock(storage: &dyn Storage, env: &Env, config: &Config) -> StdResult<ContractClock> {
+    Ok(ContractClock {
+        now: contract_time(storage, env)?,
+        grace_nanos: config.grace_nanos,
+        height: env.block.height,
+    })
+}
+
+#[cfg(not(feature = "mock-time"))]
+fn contract_time(_storage: &dyn Storage, env: &Env) -> StdResult<Timestamp> {
+    Ok(env.block.now())
+}
+
+#[cfg(feature = "mock-time")]
+fn contract_time(storage: &dyn Storage, env: &Env) -> StdResult<Timestamp> {
+    Ok(MOCK_TIME
+        .may_load(storage)?
+        .unwrap_or_else(|| env.block.now()))
+}